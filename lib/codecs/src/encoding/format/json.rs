@@ -47,12 +47,22 @@ impl JsonSerializerConfig {
 #[derive(Debug, Clone)]
 pub struct JsonSerializer {
     metric_tag_values: MetricTagValues,
+    pretty: bool,
 }
 
 impl JsonSerializer {
     /// Creates a new `JsonSerializer`.
     pub const fn new(metric_tag_values: MetricTagValues) -> Self {
-        Self { metric_tag_values }
+        Self {
+            metric_tag_values,
+            pretty: false,
+        }
+    }
+
+    /// Sets whether events are pretty-printed, over multiple lines, rather than compact.
+    pub const fn with_pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
     }
 
     /// Encode event and represent it as JSON value.
@@ -64,6 +74,18 @@ impl JsonSerializer {
         }
         .map_err(|e| e.to_string().into())
     }
+
+    fn write<W, T>(&self, writer: W, value: &T) -> serde_json::Result<()>
+    where
+        W: std::io::Write,
+        T: serde::Serialize + ?Sized,
+    {
+        if self.pretty {
+            serde_json::to_writer_pretty(writer, value)
+        } else {
+            serde_json::to_writer(writer, value)
+        }
+    }
 }
 
 impl Encoder<Event> for JsonSerializer {
@@ -72,14 +94,14 @@ impl Encoder<Event> for JsonSerializer {
     fn encode(&mut self, event: Event, buffer: &mut BytesMut) -> Result<(), Self::Error> {
         let writer = buffer.writer();
         match event {
-            Event::Log(log) => serde_json::to_writer(writer, &log),
+            Event::Log(log) => self.write(writer, &log),
             Event::Metric(mut metric) => {
                 if self.metric_tag_values == MetricTagValues::Single {
                     metric.reduce_tags_to_single();
                 }
-                serde_json::to_writer(writer, &metric)
+                self.write(writer, &metric)
             }
-            Event::Trace(trace) => serde_json::to_writer(writer, &trace),
+            Event::Trace(trace) => self.write(writer, &trace),
         }
         .map_err(Into::into)
     }
@@ -189,6 +211,19 @@ mod tests {
         assert_eq!(bytes.freeze(), serde_json::to_string(&json).unwrap());
     }
 
+    #[test]
+    fn serialize_json_log_pretty() {
+        let event = Event::Log(LogEvent::from(btreemap! {
+            "x" => Value::from("23"),
+            "a" => Value::from("0"),
+        }));
+        let mut serializer = JsonSerializerConfig::default().build().with_pretty(true);
+        let mut bytes = BytesMut::new();
+        serializer.encode(event, &mut bytes).unwrap();
+
+        assert_eq!(bytes.freeze(), "{\n  \"a\": \"0\",\n  \"x\": \"23\"\n}");
+    }
+
     #[test]
     fn serialize_metric_tags_full() {
         let bytes = serialize(
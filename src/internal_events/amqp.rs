@@ -125,6 +125,67 @@ pub mod sink {
         }
     }
 
+    #[derive(Debug)]
+    pub struct AmqpConfirmTimeout {
+        pub timeout: std::time::Duration,
+        pub retrying: bool,
+    }
+
+    impl InternalEvent for AmqpConfirmTimeout {
+        fn emit(self) {
+            if self.retrying {
+                warn!(
+                    message = "No publisher confirm received in time, publishing again.",
+                    timeout = ?self.timeout,
+                    internal_log_rate_limit = true,
+                );
+                return;
+            }
+
+            let reason = "Publisher confirm timed out.";
+            error!(message = reason,
+                   timeout = ?self.timeout,
+                   error_type = error_type::TIMED_OUT,
+                   stage = error_stage::SENDING,
+                   internal_log_rate_limit = true,
+            );
+            counter!(
+                "component_errors_total", 1,
+                "error_type" => error_type::TIMED_OUT,
+                "stage" => error_stage::SENDING,
+            );
+            emit!(ComponentEventsDropped::<UNINTENTIONAL> { count: 1, reason });
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct AmqpMessageTooLarge {
+        pub size: usize,
+        pub max: usize,
+    }
+
+    impl InternalEvent for AmqpMessageTooLarge {
+        fn emit(self) {
+            let reason = "Message exceeds the maximum message size.";
+
+            error!(message = reason,
+                   size = %self.size,
+                   max_message_bytes = %self.max,
+                   error_code = "message_too_large",
+                   error_type = error_type::ENCODER_FAILED,
+                   stage = error_stage::PROCESSING,
+                   internal_log_rate_limit = true,
+            );
+            counter!(
+                "component_errors_total", 1,
+                "error_code" => "message_too_large",
+                "error_type" => error_type::ENCODER_FAILED,
+                "stage" => error_stage::PROCESSING,
+            );
+            emit!(ComponentEventsDropped::<UNINTENTIONAL> { count: 1, reason });
+        }
+    }
+
     #[derive(Debug)]
     pub struct AmqpAcknowledgementError<'a> {
         pub error: &'a lapin::Error,
@@ -0,0 +1,230 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use metrics::counter;
+use vector_common::internal_event::{
+    error_stage, error_type, ComponentEventsDropped, UNINTENTIONAL,
+};
+use vector_core::{event::EventStatus, internal_event::InternalEvent};
+
+use crate::emit;
+
+#[derive(Debug)]
+pub struct DatadogArchivesCompressionFailed {
+    pub error: std::io::Error,
+}
+
+impl InternalEvent for DatadogArchivesCompressionFailed {
+    fn emit(self) {
+        warn!(
+            message = "Failed to compress the archive, writing it uncompressed.",
+            error = %self.error,
+            error_type = error_type::ENCODER_FAILED,
+            stage = error_stage::PROCESSING,
+            internal_log_rate_limit = true,
+        );
+        counter!("datadog_archives_uncompressed_writes_total", 1);
+    }
+}
+
+/// An event already has a field at the path of a reserved attribute it's reshaped into.
+#[derive(Debug)]
+pub struct DatadogArchivesFieldCollision {
+    pub field: String,
+}
+
+impl InternalEvent for DatadogArchivesFieldCollision {
+    fn emit(self) {
+        let reason = "Event has a field colliding with a reserved attribute.";
+        error!(
+            message = reason,
+            field = %self.field,
+            error_code = "field_collision",
+            error_type = error_type::CONVERSION_FAILED,
+            stage = error_stage::PROCESSING,
+            internal_log_rate_limit = true,
+        );
+        counter!(
+            "component_errors_total", 1,
+            "error_code" => "field_collision",
+            "error_type" => error_type::CONVERSION_FAILED,
+            "stage" => error_stage::PROCESSING,
+        );
+        emit!(ComponentEventsDropped::<UNINTENTIONAL> { count: 1, reason });
+    }
+}
+
+/// An event couldn't be encoded, and was dropped from its archive.
+#[derive(Debug)]
+pub struct DatadogArchivesEventEncodingFailed {
+    pub error: vector_common::Error,
+}
+
+impl InternalEvent for DatadogArchivesEventEncodingFailed {
+    fn emit(self) {
+        let reason = "Failed to encode the event, dropping it from the archive.";
+        error!(
+            message = reason,
+            error = %self.error,
+            error_code = "event_encoding_failed",
+            error_type = error_type::ENCODER_FAILED,
+            stage = error_stage::PROCESSING,
+            internal_log_rate_limit = true,
+        );
+        counter!(
+            "component_errors_total", 1,
+            "error_code" => "event_encoding_failed",
+            "error_type" => error_type::ENCODER_FAILED,
+            "stage" => error_stage::PROCESSING,
+        );
+        emit!(ComponentEventsDropped::<UNINTENTIONAL> { count: 1, reason });
+    }
+}
+
+/// An event has a `message` which isn't a string, and was dropped.
+#[derive(Debug)]
+pub struct DatadogArchivesNonStringMessage {
+    pub kind: &'static str,
+}
+
+impl InternalEvent for DatadogArchivesNonStringMessage {
+    fn emit(self) {
+        let reason = "Event has a message which isn't a string.";
+        error!(
+            message = reason,
+            kind = %self.kind,
+            error_code = "non_string_message",
+            error_type = error_type::CONVERSION_FAILED,
+            stage = error_stage::PROCESSING,
+            internal_log_rate_limit = true,
+        );
+        counter!(
+            "component_errors_total", 1,
+            "error_code" => "non_string_message",
+            "error_type" => error_type::CONVERSION_FAILED,
+            "stage" => error_stage::PROCESSING,
+        );
+        emit!(ComponentEventsDropped::<UNINTENTIONAL> { count: 1, reason });
+    }
+}
+
+/// An event has a timestamp too far in the future, and was dropped.
+#[derive(Debug)]
+pub struct DatadogArchivesFutureTimestamp {
+    pub timestamp: DateTime<Utc>,
+}
+
+impl InternalEvent for DatadogArchivesFutureTimestamp {
+    fn emit(self) {
+        let reason = "Event has a timestamp in the future.";
+        error!(
+            message = reason,
+            timestamp = %self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            error_code = "future_timestamp",
+            error_type = error_type::CONDITION_FAILED,
+            stage = error_stage::PROCESSING,
+            internal_log_rate_limit = true,
+        );
+        counter!(
+            "component_errors_total", 1,
+            "error_code" => "future_timestamp",
+            "error_type" => error_type::CONDITION_FAILED,
+            "stage" => error_stage::PROCESSING,
+        );
+        emit!(ComponentEventsDropped::<UNINTENTIONAL> { count: 1, reason });
+    }
+}
+
+/// Too many partitions are open, and an event was redirected to the catch-all partition.
+#[derive(Debug)]
+pub struct DatadogArchivesPartitionOverflow {
+    pub max: usize,
+}
+
+impl InternalEvent for DatadogArchivesPartitionOverflow {
+    fn emit(self) {
+        warn!(
+            message = "Too many open partitions, redirecting the event to the overflow partition.",
+            max_open_partitions = %self.max,
+            internal_log_rate_limit = true,
+        );
+        counter!("datadog_archives_partition_overflows_total", 1);
+    }
+}
+
+/// An event was rejected from its archive, and is reported along with a sample of it.
+///
+/// The error itself is reported by the event of its reason, so this doesn't count as an error.
+#[derive(Debug)]
+pub struct DatadogArchivesEventRejected {
+    pub reason: &'static str,
+    pub sample: String,
+}
+
+impl InternalEvent for DatadogArchivesEventRejected {
+    fn emit(self) {
+        warn!(
+            message = "Event rejected from the archive.",
+            reason = %self.reason,
+            sample = %self.sample,
+        );
+    }
+}
+
+/// An object was finalized, and is about to be written.
+///
+/// The counters are labeled with the partition of the object only, without its date and hour, so
+/// that their cardinality stays bounded.
+#[derive(Debug)]
+pub struct DatadogArchivesObjectFinalized {
+    pub partition: String,
+    pub events: usize,
+    pub uncompressed_bytes: usize,
+    pub compressed_bytes: Option<usize>,
+}
+
+impl InternalEvent for DatadogArchivesObjectFinalized {
+    fn emit(self) {
+        debug!(
+            message = "Object finalized.",
+            partition = %self.partition,
+            events = %self.events,
+            uncompressed_bytes = %self.uncompressed_bytes,
+            compressed_bytes = ?self.compressed_bytes,
+        );
+        counter!("datadog_archives_objects_total", 1, "partition" => self.partition.clone());
+        counter!(
+            "datadog_archives_object_events_total", self.events as u64,
+            "partition" => self.partition.clone(),
+        );
+        counter!(
+            "datadog_archives_object_uncompressed_bytes_total", self.uncompressed_bytes as u64,
+            "partition" => self.partition.clone(),
+        );
+        if let Some(compressed_bytes) = self.compressed_bytes {
+            counter!(
+                "datadog_archives_object_compressed_bytes_total", compressed_bytes as u64,
+                "partition" => self.partition,
+            );
+        }
+    }
+}
+
+/// An object was written, and is reported for the audit trail of the archives.
+#[derive(Debug, PartialEq)]
+pub struct DatadogArchivesObjectWritten {
+    pub key: String,
+    pub partition: String,
+    pub events: usize,
+    pub status: EventStatus,
+}
+
+impl InternalEvent for DatadogArchivesObjectWritten {
+    fn emit(self) {
+        info!(
+            message = "Object written.",
+            key = %self.key,
+            partition = %self.partition,
+            events = %self.events,
+            status = ?self.status,
+        );
+    }
+}
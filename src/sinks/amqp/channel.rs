@@ -0,0 +1,344 @@
+//! Keeps the channel used to publish to `AMQP` connected.
+use std::{
+    future::Future,
+    iter,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use lapin::options::ConfirmSelectOptions;
+use tokio::sync::Notify;
+use vector_config::configurable_component;
+
+use crate::amqp::AmqpConfig;
+
+/// Reconnection configuration.
+///
+/// When the connection to the AMQP server is lost, the sink waits before each attempt to
+/// reconnect. The wait doubles after each failed attempt, up to `max_backoff_ms`.
+#[configurable_component]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AmqpReconnectConfig {
+    /// The time to wait before the first attempt to reconnect, in milliseconds.
+    #[serde(default = "default_initial_backoff_ms")]
+    #[configurable(metadata(docs::type_unit = "milliseconds"))]
+    #[configurable(metadata(docs::human_name = "Initial Backoff"))]
+    pub initial_backoff_ms: u64,
+
+    /// The maximum time to wait between two attempts to reconnect, in milliseconds.
+    #[serde(default = "default_max_backoff_ms")]
+    #[configurable(metadata(docs::type_unit = "milliseconds"))]
+    #[configurable(metadata(docs::human_name = "Maximum Backoff"))]
+    pub max_backoff_ms: u64,
+
+    /// The maximum number of attempts to reconnect before giving up.
+    ///
+    /// When the sink gives up, the events being published are rejected, and the next events
+    /// to be published start a new series of attempts. By default, the sink never gives up.
+    #[configurable(metadata(docs::type_unit = "attempts"))]
+    pub max_attempts: Option<NonZeroUsize>,
+}
+
+const fn default_initial_backoff_ms() -> u64 {
+    1_000
+}
+
+const fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+impl Default for AmqpReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            max_attempts: None,
+        }
+    }
+}
+
+impl AmqpReconnectConfig {
+    /// The time to wait before each attempt to reconnect.
+    ///
+    /// The schedule ends after `max_attempts` delays, or never if it's not set.
+    pub(super) fn backoff(&self) -> impl Iterator<Item = Duration> {
+        let max_backoff = Duration::from_millis(self.max_backoff_ms);
+        let delays = iter::successors(
+            Some(Duration::from_millis(self.initial_backoff_ms).min(max_backoff)),
+            move |delay| Some(delay.saturating_mul(2).min(max_backoff)),
+        );
+
+        delays.take(self.max_attempts.map_or(usize::MAX, NonZeroUsize::get))
+    }
+}
+
+/// Calls `connect` until it succeeds, waiting before each attempt according to the backoff
+/// schedule.
+///
+/// Returns `None` when every attempt has failed.
+async fn retry_connect<T, E, F, Fut>(reconnect: &AmqpReconnectConfig, mut connect: F) -> Option<T>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    for (attempt, delay) in reconnect.backoff().enumerate() {
+        tokio::time::sleep(delay).await;
+
+        match connect().await {
+            Ok(connected) => {
+                info!(
+                    message = "Reconnected to AMQP server.",
+                    attempt = attempt + 1
+                );
+                return Some(connected);
+            }
+            Err(error) => warn!(
+                message = "Failed to reconnect to AMQP server.",
+                attempt = attempt + 1,
+                %error,
+            ),
+        }
+    }
+
+    None
+}
+
+/// Opens a channel with publisher confirms enabled.
+async fn open_channel(
+    connection: &AmqpConfig,
+) -> Result<lapin::Channel, Box<dyn std::error::Error + Send + Sync>> {
+    let (_, channel) = connection.connect().await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+    Ok(channel)
+}
+
+/// The state of the channel the sink publishes to.
+enum ChannelState {
+    /// The channel is open, though its connection may have been lost since.
+    Open,
+    /// A request is reconnecting the channel, and the others wait for it.
+    Reconnecting,
+    /// The last attempts to reconnect gave up.
+    GaveUp,
+}
+
+/// The channel the sink publishes to, which is reopened when the connection is lost.
+///
+/// A single request reconnects the channel at a time, while the others wait to be notified of the
+/// outcome. The state of the channel is only locked to check or update it, never while connecting,
+/// so that requests never block on the lock while the sink backs off between attempts.
+pub(super) struct AmqpChannel {
+    connection: AmqpConfig,
+    reconnect: AmqpReconnectConfig,
+    state: Mutex<(ChannelState, Arc<lapin::Channel>)>,
+    reconnected: Notify,
+}
+
+/// Ends the reconnection of a channel, even if the request reconnecting it is dropped, so that the
+/// requests waiting for it don't wait forever.
+struct Reconnection<'a> {
+    channel: &'a AmqpChannel,
+    reopened: Option<Arc<lapin::Channel>>,
+}
+
+impl Drop for Reconnection<'_> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().expect("poisoned lock");
+        match self.reopened.take() {
+            Some(reopened) => *state = (ChannelState::Open, reopened),
+            None => state.0 = ChannelState::GaveUp,
+        }
+        drop(state);
+        self.channel.reconnected.notify_waiters();
+    }
+}
+
+impl AmqpChannel {
+    /// Connects to the AMQP server.
+    ///
+    /// The initial connection isn't retried, so that configuration errors are reported when the
+    /// sink is built.
+    pub(super) async fn connect(
+        connection: AmqpConfig,
+        reconnect: AmqpReconnectConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let channel = open_channel(&connection).await?;
+        Ok(Self {
+            connection,
+            reconnect,
+            state: Mutex::new((ChannelState::Open, Arc::new(channel))),
+            reconnected: Notify::new(),
+        })
+    }
+
+    /// The current channel, which may not be connected.
+    pub(super) fn current(&self) -> Arc<lapin::Channel> {
+        Arc::clone(&self.state.lock().expect("poisoned lock").1)
+    }
+
+    /// A connected channel, reconnecting first if the connection was lost.
+    ///
+    /// Returns `None` when the sink gave up reconnecting, including when this request waited for
+    /// another one to reconnect.
+    pub(super) async fn ensure_connected(&self) -> Option<Arc<lapin::Channel>> {
+        let mut waited = false;
+        loop {
+            // Registered before the state is checked, so that the end of a reconnection can't be
+            // missed in between.
+            let reconnected = self.reconnected.notified();
+            {
+                let mut state = self.state.lock().expect("poisoned lock");
+                match &*state {
+                    (ChannelState::Open, channel) if channel.status().connected() => {
+                        return Some(Arc::clone(channel));
+                    }
+                    (ChannelState::Reconnecting, _) => {}
+                    (ChannelState::GaveUp, _) if waited => return None,
+                    _ => {
+                        state.0 = ChannelState::Reconnecting;
+                        break;
+                    }
+                }
+            }
+            reconnected.await;
+            waited = true;
+        }
+
+        warn!(message = "Lost connection to AMQP server, reconnecting.");
+        let mut reconnection = Reconnection {
+            channel: self,
+            reopened: None,
+        };
+        reconnection.reopened = retry_connect(&self.reconnect, || open_channel(&self.connection))
+            .await
+            .map(Arc::new);
+        reconnection.reopened.clone()
+    }
+
+    pub(super) const fn max_attempts(&self) -> Option<NonZeroUsize> {
+        self.reconnect.max_attempts
+    }
+}
+
+/// The channels the sink publishes to, which are used in turn.
+///
+/// Each channel has a connection of its own, which is reopened independently of the others, and
+/// receives the publisher confirms of the messages published to it.
+pub(super) struct ChannelPool<T = AmqpChannel> {
+    channels: Vec<T>,
+    next: AtomicUsize,
+}
+
+impl ChannelPool {
+    /// Connects `size` channels to the AMQP server.
+    pub(super) async fn connect(
+        connection: AmqpConfig,
+        reconnect: AmqpReconnectConfig,
+        size: NonZeroUsize,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut channels = Vec::with_capacity(size.get());
+        for _ in 0..size.get() {
+            channels.push(AmqpChannel::connect(connection.clone(), reconnect.clone()).await?);
+        }
+        Ok(Self::new(channels))
+    }
+}
+
+impl<T> ChannelPool<T> {
+    pub(super) fn new(channels: Vec<T>) -> Self {
+        assert!(!channels.is_empty(), "A channel pool can't be empty.");
+        Self {
+            channels,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The channel to publish the next message to, in round-robin order.
+    pub(super) fn next(&self) -> &T {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.channels.len();
+        &self.channels[index]
+    }
+
+    /// The first channel of the pool, which the healthcheck is run against.
+    pub(super) fn first(&self) -> &T {
+        &self.channels[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconnect(max_attempts: Option<usize>) -> AmqpReconnectConfig {
+        AmqpReconnectConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            max_attempts: max_attempts.and_then(NonZeroUsize::new),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let delays = reconnect(None)
+            .backoff()
+            .take(7)
+            .map(|delay| delay.as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1_000, 1_000, 1_000]);
+    }
+
+    #[test]
+    fn backoff_ends_after_max_attempts() {
+        assert_eq!(reconnect(Some(3)).backoff().count(), 3);
+
+        // The initial backoff is capped by the maximum backoff as well.
+        let config = AmqpReconnectConfig {
+            initial_backoff_ms: 5_000,
+            ..reconnect(Some(2))
+        };
+        assert_eq!(
+            config.backoff().collect::<Vec<_>>(),
+            vec![Duration::from_millis(1_000); 2]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let attempts = AtomicUsize::new(0);
+        let result = retry_connect(&reconnect(Some(4)), || {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            async { Err::<(), _>("connection refused") }
+        })
+        .await;
+
+        assert!(result.is_none());
+        assert_eq!(attempts.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_retrying_once_connected() {
+        let attempts = AtomicUsize::new(0);
+        let result = retry_connect(&reconnect(Some(4)), || {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            async move {
+                if attempt < 2 {
+                    Err("connection refused")
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result, Some(2));
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+}
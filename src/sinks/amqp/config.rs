@@ -2,9 +2,13 @@
 use crate::{amqp::AmqpConfig, sinks::prelude::*};
 use codecs::TextSerializerConfig;
 use lapin::{types::ShortString, BasicProperties};
-use std::sync::Arc;
+use std::{
+    num::{NonZeroU64, NonZeroUsize},
+    sync::Arc,
+    time::Duration,
+};
 
-use super::sink::AmqpSink;
+use super::{channel::AmqpReconnectConfig, sink::AmqpSink};
 
 /// AMQP properties configuration.
 #[configurable_component]
@@ -18,10 +22,29 @@ pub struct AmqpPropertiesConfig {
     /// Content-Encoding for the AMQP messages.
     #[configurable(derived)]
     pub(crate) content_encoding: Option<String>,
+
+    /// Priority for the AMQP messages.
+    #[configurable(derived)]
+    pub(crate) priority: Option<AmqpPriority>,
+
+    /// App-Id for the AMQP messages, identifying the application which published them.
+    ///
+    /// Either a constant, or a template rendering the App-Id of each message. Messages whose App-Id
+    /// fails to render are published without one.
+    #[configurable(metadata(docs::examples = "vector", docs::examples = "{{ service }}"))]
+    pub(crate) app_id: Option<Template>,
+
+    /// Type for the AMQP messages, such as the name of the event they carry.
+    ///
+    /// Either a constant, or a template rendering the Type of each message. Messages whose Type
+    /// fails to render are published without one.
+    #[serde(rename = "type")]
+    #[configurable(metadata(docs::examples = "log", docs::examples = "{{ event_type }}"))]
+    pub(crate) message_type: Option<Template>,
 }
 
 impl AmqpPropertiesConfig {
-    pub(super) fn build(&self) -> BasicProperties {
+    pub(super) fn build(&self, event: &Event) -> BasicProperties {
         let mut prop = BasicProperties::default();
         if let Some(content_type) = &self.content_type {
             prop = prop.with_content_type(ShortString::from(content_type.clone()));
@@ -29,10 +52,78 @@ impl AmqpPropertiesConfig {
         if let Some(content_encoding) = &self.content_encoding {
             prop = prop.with_content_encoding(ShortString::from(content_encoding.clone()));
         }
+        if let Some(priority) = self.priority.as_ref().and_then(|p| p.render(event)) {
+            prop = prop.with_priority(priority);
+        }
+        if let Some(app_id) = render_property(self.app_id.as_ref(), event, "properties.app_id") {
+            prop = prop.with_app_id(app_id);
+        }
+        if let Some(message_type) =
+            render_property(self.message_type.as_ref(), event, "properties.type")
+        {
+            prop = prop.with_kind(message_type);
+        }
         prop
     }
 }
 
+/// Renders a string property of the message of `event`.
+fn render_property(
+    template: Option<&Template>,
+    event: &Event,
+    field: &'static str,
+) -> Option<ShortString> {
+    template?
+        .render_string(event)
+        .map_err(|error| {
+            emit!(TemplateRenderingError {
+                error,
+                field: Some(field),
+                drop_event: false,
+            })
+        })
+        .ok()
+        .map(ShortString::from)
+}
+
+/// Priority for the AMQP messages, used by priority queues.
+///
+/// Priorities are clamped to the range `0` to `255`. Messages whose priority isn't a number are
+/// published without a priority.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(untagged)]
+pub enum AmqpPriority {
+    /// The same priority for all messages.
+    Constant(i64),
+
+    /// A template rendering the priority of each message, such as `{{ priority }}`.
+    Template(Template),
+}
+
+impl AmqpPriority {
+    /// Renders the priority of the message of `event`, if it's a number.
+    fn render(&self, event: &Event) -> Option<u8> {
+        let priority = match self {
+            AmqpPriority::Constant(priority) => *priority,
+            AmqpPriority::Template(template) => template
+                .render_string(event)
+                .map_err(|error| {
+                    emit!(TemplateRenderingError {
+                        error,
+                        field: Some("properties.priority"),
+                        drop_event: false,
+                    })
+                })
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?,
+        };
+        Some(priority.clamp(0, u8::MAX.into()) as u8)
+    }
+}
+
 /// Configuration for the `amqp` sink.
 ///
 /// Supports AMQP version 0.9.1
@@ -51,6 +142,46 @@ pub struct AmqpSinkConfig {
     #[serde(flatten)]
     pub(crate) connection: AmqpConfig,
 
+    #[configurable(derived)]
+    #[serde(default)]
+    pub(crate) reconnect: AmqpReconnectConfig,
+
+    /// The time to wait for the publisher confirm of a message, in milliseconds.
+    ///
+    /// A broker that never confirms a message would otherwise leave its events pending forever.
+    /// When no confirm is received in time, the message is published again up to
+    /// `confirm_retries` times, after which its events are rejected. By default, the sink waits
+    /// for confirms indefinitely.
+    #[configurable(metadata(docs::type_unit = "milliseconds"))]
+    #[configurable(metadata(docs::examples = 30000))]
+    pub(crate) confirm_timeout_ms: Option<NonZeroU64>,
+
+    /// The number of times a message is published again when its publisher confirm times out.
+    ///
+    /// The broker may still have received the message, so retrying can deliver it more than once.
+    /// Only applies when `confirm_timeout_ms` is set.
+    #[configurable(metadata(docs::type_unit = "retries"))]
+    pub(crate) confirm_retries: Option<NonZeroUsize>,
+
+    /// The maximum size of the encoded body of a message, in bytes.
+    ///
+    /// Brokers refuse messages over their maximum message size. Messages whose body exceeds this
+    /// size are dropped, and their events rejected, rather than being published. By default, the
+    /// size of the messages isn't checked.
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 134217728))]
+    pub(crate) max_message_bytes: Option<NonZeroUsize>,
+
+    /// The number of channels to publish messages to.
+    ///
+    /// Messages are published to the channels in turn, each of which has a connection of its own
+    /// and receives the publisher confirms of its messages. A single channel waits for the confirm
+    /// of each message in order, so more channels raise the throughput of brokers with slow
+    /// confirms. By default, a single channel is used.
+    #[configurable(metadata(docs::type_unit = "channels"))]
+    #[configurable(metadata(docs::examples = 4))]
+    pub(crate) channel_pool_size: Option<NonZeroUsize>,
+
     #[configurable(derived)]
     pub(crate) encoding: EncodingConfig,
 
@@ -71,11 +202,31 @@ impl Default for AmqpSinkConfig {
             properties: None,
             encoding: TextSerializerConfig::default().into(),
             connection: AmqpConfig::default(),
+            reconnect: AmqpReconnectConfig::default(),
+            confirm_timeout_ms: None,
+            confirm_retries: None,
+            max_message_bytes: None,
+            channel_pool_size: None,
             acknowledgements: AcknowledgementsConfig::default(),
         }
     }
 }
 
+impl AmqpSinkConfig {
+    pub(super) fn confirm_timeout(&self) -> Option<Duration> {
+        self.confirm_timeout_ms
+            .map(|timeout| Duration::from_millis(timeout.get()))
+    }
+
+    pub(super) fn confirm_retries(&self) -> usize {
+        self.confirm_retries.map_or(0, NonZeroUsize::get)
+    }
+
+    pub(super) fn channel_pool_size(&self) -> NonZeroUsize {
+        self.channel_pool_size.unwrap_or(NonZeroUsize::MIN)
+    }
+}
+
 impl GenerateConfig for AmqpSinkConfig {
     fn generate_config() -> toml::Value {
         toml::from_str(
@@ -92,7 +243,7 @@ impl GenerateConfig for AmqpSinkConfig {
 impl SinkConfig for AmqpSinkConfig {
     async fn build(&self, _cx: SinkContext) -> crate::Result<(VectorSink, Healthcheck)> {
         let sink = AmqpSink::new(self.clone()).await?;
-        let hc = healthcheck(Arc::clone(&sink.channel)).boxed();
+        let hc = healthcheck(sink.channels.first().current()).boxed();
         Ok((VectorSink::from_event_streamsink(sink), hc))
     }
 
@@ -123,3 +274,100 @@ pub(super) async fn healthcheck(channel: Arc<lapin::Channel>) -> crate::Result<(
 pub fn generate_config() {
     crate::test_util::test_generate_config::<AmqpSinkConfig>();
 }
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::event::LogEvent;
+
+    fn priority(config: &str, event: &Event) -> Option<u8> {
+        let config: AmqpPropertiesConfig = toml::from_str(config).unwrap();
+        config.build(event).priority().as_ref().copied()
+    }
+
+    fn event(severity: impl Into<Value>) -> Event {
+        let mut log = LogEvent::from("test message");
+        log.insert("severity", severity.into());
+        log.into()
+    }
+
+    #[test]
+    fn sets_constant_priority() {
+        assert_eq!(priority("priority = 5", &event(1)), Some(5));
+        assert_eq!(priority("priority = 300", &event(1)), Some(255));
+        assert_eq!(priority("priority = -1", &event(1)), Some(0));
+        assert_eq!(priority("", &event(1)), None);
+    }
+
+    #[test]
+    fn sets_priority_from_event_field() {
+        let config = r#"priority = "{{ severity }}""#;
+
+        assert_eq!(priority(config, &event(7)), Some(7));
+        assert_eq!(priority(config, &event("3")), Some(3));
+        assert_eq!(priority(config, &event(1000)), Some(255));
+        assert_eq!(priority(config, &event(-20)), Some(0));
+    }
+
+    fn string_properties(config: &str, event: &Event) -> (Option<String>, Option<String>) {
+        let config: AmqpPropertiesConfig = toml::from_str(config).unwrap();
+        let properties = config.build(event);
+        (
+            properties
+                .app_id()
+                .as_ref()
+                .map(|app_id| app_id.as_str().to_owned()),
+            properties
+                .kind()
+                .as_ref()
+                .map(|kind| kind.as_str().to_owned()),
+        )
+    }
+
+    #[test]
+    fn sets_constant_app_id_and_type() {
+        let config = indoc! {r#"
+            app_id = "vector"
+            type = "log"
+        "#};
+
+        assert_eq!(
+            string_properties(config, &event(1)),
+            (Some("vector".to_owned()), Some("log".to_owned()))
+        );
+        assert_eq!(string_properties("", &event(1)), (None, None));
+    }
+
+    #[test]
+    fn sets_app_id_and_type_from_event_fields() {
+        let config = indoc! {r#"
+            app_id = "{{ service }}"
+            type = "{{ event_type }}"
+        "#};
+        let mut log = LogEvent::from("test message");
+        log.insert("service", "checkout");
+        log.insert("event_type", "order.created");
+
+        assert_eq!(
+            string_properties(config, &log.into()),
+            (
+                Some("checkout".to_owned()),
+                Some("order.created".to_owned())
+            )
+        );
+        assert_eq!(string_properties(config, &event(1)), (None, None));
+    }
+
+    #[test]
+    fn omits_non_numeric_priority() {
+        let config = r#"priority = "{{ severity }}""#;
+
+        assert_eq!(priority(config, &event("high")), None);
+        assert_eq!(
+            priority(config, &LogEvent::from("no severity").into()),
+            None
+        );
+    }
+}
@@ -1,5 +1,6 @@
 //! `AMQP` sink.
 //! Handles version AMQP 0.9.1 which is used by RabbitMQ.
+mod channel;
 mod config;
 mod encoder;
 mod request_builder;
@@ -1,16 +1,21 @@
 //! The main tower service that takes the request created by the request builder
 //! and sends it to `AMQP`.
 use crate::{
-    internal_events::sink::{AmqpAcknowledgementError, AmqpDeliveryError},
+    internal_events::sink::{AmqpAcknowledgementError, AmqpConfirmTimeout, AmqpDeliveryError},
     sinks::prelude::*,
 };
 use bytes::Bytes;
 use futures::future::BoxFuture;
-use lapin::{options::BasicPublishOptions, BasicProperties};
+use lapin::{options::BasicPublishOptions, publisher_confirm::Confirmation, BasicProperties};
 use snafu::Snafu;
+
+use super::channel::{AmqpChannel, ChannelPool};
 use std::{
+    future::Future,
+    num::NonZeroUsize,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 /// The request contains the data to send to `AMQP` together
@@ -45,6 +50,11 @@ impl AmqpRequest {
             event_json_size,
         }
     }
+
+    /// The size of the body of the message, in bytes.
+    pub(super) fn body_len(&self) -> usize {
+        self.body.len()
+    }
 }
 
 impl Finalizable for AmqpRequest {
@@ -79,9 +89,71 @@ impl DriverResponse for AmqpResponse {
     }
 }
 
+/// A channel of the pool, which messages are published to and their publisher confirms received on.
+#[async_trait]
+pub(super) trait Publisher: Send + Sync + 'static {
+    /// The channel once connected.
+    type Connected: Send + Sync;
+
+    /// A connected channel, reconnecting first if the connection was lost.
+    async fn connected(&self) -> Result<Self::Connected, AmqpError>;
+
+    /// Publishes the message of `req` to `channel`, and waits for its publisher confirm.
+    async fn publish(
+        &self,
+        channel: &Self::Connected,
+        req: &AmqpRequest,
+    ) -> Result<Confirmation, AmqpError>;
+}
+
+#[async_trait]
+impl Publisher for AmqpChannel {
+    type Connected = Arc<lapin::Channel>;
+
+    async fn connected(&self) -> Result<Self::Connected, AmqpError> {
+        self.ensure_connected()
+            .await
+            .ok_or_else(|| AmqpError::AmqpReconnectFailed {
+                attempts: self.max_attempts().map_or(0, NonZeroUsize::get),
+            })
+    }
+
+    async fn publish(
+        &self,
+        channel: &Self::Connected,
+        req: &AmqpRequest,
+    ) -> Result<Confirmation, AmqpError> {
+        // Publisher confirms were enabled when the channel was opened.
+        let fut = channel
+            .basic_publish(
+                &req.exchange,
+                &req.routing_key,
+                BasicPublishOptions::default(),
+                req.body.as_ref(),
+                req.properties.clone(),
+            )
+            .await;
+
+        match fut {
+            Ok(result) => result.await.map_err(|error| {
+                // TODO: In due course the caller could emit these on error.
+                emit!(AmqpAcknowledgementError { error: &error });
+                AmqpError::AmqpAcknowledgementFailed { error }
+            }),
+            Err(error) => {
+                // TODO: In due course the caller could emit these on error.
+                emit!(AmqpDeliveryError { error: &error });
+                Err(AmqpError::AmqpDeliveryFailed { error })
+            }
+        }
+    }
+}
+
 /// The tower service that handles the actual sending of data to `AMQP`.
-pub(super) struct AmqpService {
-    pub(super) channel: Arc<lapin::Channel>,
+pub(super) struct AmqpService<P = AmqpChannel> {
+    pub(super) channels: Arc<ChannelPool<P>>,
+    pub(super) confirm_timeout: Option<Duration>,
+    pub(super) confirm_retries: usize,
 }
 
 #[derive(Debug, Snafu)]
@@ -91,9 +163,48 @@ pub(super) enum AmqpError {
 
     #[snafu(display("Failed AMQP request: {}", error))]
     AmqpDeliveryFailed { error: lapin::Error },
+
+    #[snafu(display("Failed to reconnect to AMQP after {} attempts", attempts))]
+    AmqpReconnectFailed { attempts: usize },
+
+    #[snafu(display("No publisher confirm received from AMQP within {:?}", timeout))]
+    AmqpConfirmTimedOut { timeout: Duration },
 }
 
-impl Service<AmqpRequest> for AmqpService {
+/// Publishes a message until its confirmation is received.
+///
+/// When no confirmation is received within `confirm_timeout`, the message is published again, up
+/// to `confirm_retries` times, after which the publish fails.
+async fn publish_confirmed<F, Fut>(
+    confirm_timeout: Option<Duration>,
+    confirm_retries: usize,
+    mut publish: F,
+) -> Result<Confirmation, AmqpError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Confirmation, AmqpError>>,
+{
+    let Some(timeout) = confirm_timeout else {
+        return publish().await;
+    };
+
+    let mut retries = 0;
+    loop {
+        match tokio::time::timeout(timeout, publish()).await {
+            Ok(result) => return result,
+            Err(_) => {
+                let retrying = retries < confirm_retries;
+                emit!(AmqpConfirmTimeout { timeout, retrying });
+                if !retrying {
+                    return Err(AmqpError::AmqpConfirmTimedOut { timeout });
+                }
+                retries += 1;
+            }
+        }
+    }
+}
+
+impl<P: Publisher> Service<AmqpRequest> for AmqpService<P> {
     type Response = AmqpResponse;
 
     type Error = AmqpError;
@@ -105,50 +216,255 @@ impl Service<AmqpRequest> for AmqpService {
     }
 
     fn call(&mut self, req: AmqpRequest) -> Self::Future {
-        let channel = Arc::clone(&self.channel);
+        let channels = Arc::clone(&self.channels);
+        let confirm_timeout = self.confirm_timeout;
+        let confirm_retries = self.confirm_retries;
 
         Box::pin(async move {
-            channel
-                .confirm_select(lapin::options::ConfirmSelectOptions::default())
-                .await
-                .unwrap();
+            // The confirm of the message is received on the channel it's published to.
+            let publisher = channels.next();
+            let channel = publisher.connected().await?;
 
             let byte_size = req.body.len();
-            let fut = channel
-                .basic_publish(
-                    &req.exchange,
-                    &req.routing_key,
-                    BasicPublishOptions::default(),
-                    req.body.as_ref(),
-                    req.properties,
-                )
-                .await;
-
-            match fut {
-                Ok(result) => match result.await {
-                    Ok(lapin::publisher_confirm::Confirmation::Nack(_)) => {
-                        warn!("Received Negative Acknowledgement from AMQP server.");
-                        Ok(AmqpResponse {
-                            json_size: req.event_json_size,
-                            byte_size,
-                        })
-                    }
-                    Err(error) => {
-                        // TODO: In due course the caller could emit these on error.
-                        emit!(AmqpAcknowledgementError { error: &error });
-                        Err(AmqpError::AmqpAcknowledgementFailed { error })
-                    }
-                    Ok(_) => Ok(AmqpResponse {
-                        json_size: req.event_json_size,
-                        byte_size,
-                    }),
-                },
-                Err(error) => {
-                    // TODO: In due course the caller could emit these on error.
-                    emit!(AmqpDeliveryError { error: &error });
-                    Err(AmqpError::AmqpDeliveryFailed { error })
+            let publish = || publisher.publish(&channel, &req);
+            let confirmation = publish_confirmed(confirm_timeout, confirm_retries, publish).await?;
+            if let Confirmation::Nack(_) = confirmation {
+                warn!("Received Negative Acknowledgement from AMQP server.");
+            }
+            Ok(AmqpResponse {
+                json_size: req.event_json_size,
+                byte_size,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::{future, stream};
+    use vector_core::event::{BatchNotifier, BatchStatus, BatchStatusReceiver, EventFinalizer};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A channel which leaves its first messages unconfirmed, or which fails to reconnect or to
+    /// publish.
+    struct TestChannel {
+        connects: bool,
+        delivers: bool,
+        unconfirmed: usize,
+        published: AtomicUsize,
+    }
+
+    impl TestChannel {
+        /// A channel which only confirms the messages published after the first `unconfirmed`.
+        const fn new(unconfirmed: usize) -> Self {
+            Self {
+                connects: true,
+                delivers: true,
+                unconfirmed,
+                published: AtomicUsize::new(0),
+            }
+        }
+
+        const fn disconnected() -> Self {
+            Self {
+                connects: false,
+                ..Self::new(0)
+            }
+        }
+
+        /// A channel which connects, but fails to publish messages.
+        const fn undeliverable() -> Self {
+            Self {
+                delivers: false,
+                ..Self::new(0)
+            }
+        }
+
+        fn published(&self) -> usize {
+            self.published.load(Ordering::Relaxed)
+        }
+    }
+
+    #[async_trait]
+    impl Publisher for TestChannel {
+        type Connected = ();
+
+        async fn connected(&self) -> Result<(), AmqpError> {
+            if self.connects {
+                Ok(())
+            } else {
+                Err(AmqpError::AmqpReconnectFailed { attempts: 3 })
+            }
+        }
+
+        async fn publish(&self, _: &(), _: &AmqpRequest) -> Result<Confirmation, AmqpError> {
+            if !self.delivers {
+                return Err(AmqpError::AmqpDeliveryFailed {
+                    error: lapin::Error::ChannelsLimitReached,
+                });
+            }
+            if self.published.fetch_add(1, Ordering::Relaxed) < self.unconfirmed {
+                future::pending().await
+            } else {
+                Ok(Confirmation::NotRequested)
+            }
+        }
+    }
+
+    fn request() -> (AmqpRequest, BatchStatusReceiver) {
+        let (batch, receiver) = BatchNotifier::new_with_receiver();
+        let request = AmqpRequest::new(
+            Bytes::from_static(b"test message"),
+            "exchange".to_owned(),
+            "routing_key".to_owned(),
+            BasicProperties::default(),
+            EventFinalizers::new(EventFinalizer::new(batch)),
+            RequestMetadata::new(1, 12, 12, 12, JsonSize::new(12)),
+            JsonSize::new(12),
+        );
+        (request, receiver)
+    }
+
+    /// Sends `requests` through the service, publishing to `channels`.
+    async fn send(
+        channels: Vec<TestChannel>,
+        confirm_timeout: Option<Duration>,
+        confirm_retries: usize,
+        requests: Vec<AmqpRequest>,
+    ) -> Arc<ChannelPool<TestChannel>> {
+        let channels = Arc::new(ChannelPool::new(channels));
+        let service = AmqpService {
+            channels: Arc::clone(&channels),
+            confirm_timeout,
+            confirm_retries,
+        };
+
+        stream::iter(requests)
+            .into_driver(service)
+            .run()
+            .await
+            .unwrap();
+        channels
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_without_confirm() {
+        let result = publish_confirmed(Some(TIMEOUT), 0, future::pending).await;
+
+        assert!(matches!(
+            result,
+            Err(AmqpError::AmqpConfirmTimedOut { timeout }) if timeout == TIMEOUT
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn publishes_again_after_confirm_timeout() {
+        let attempts = AtomicUsize::new(0);
+        let result = publish_confirmed(Some(TIMEOUT), 2, || {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            async move {
+                if attempt < 2 {
+                    future::pending().await
+                } else {
+                    Ok(Confirmation::NotRequested)
                 }
             }
         })
+        .await;
+
+        assert!(matches!(result, Ok(Confirmation::NotRequested)));
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_confirm_retries() {
+        let attempts = AtomicUsize::new(0);
+        let result = publish_confirmed(Some(TIMEOUT), 2, || {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            future::pending()
+        })
+        .await;
+
+        assert!(matches!(result, Err(AmqpError::AmqpConfirmTimedOut { .. })));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rejects_events_when_no_confirm_arrives() {
+        let channel = TestChannel::new(usize::MAX);
+        let (request, receiver) = request();
+
+        send(vec![channel], Some(TIMEOUT), 0, vec![request]).await;
+
+        assert_eq!(receiver.await, BatchStatus::Rejected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delivers_events_confirmed_after_a_timeout() {
+        let channels = vec![TestChannel::new(1)];
+        let (request, receiver) = request();
+
+        let channels = send(channels, Some(TIMEOUT), 2, vec![request]).await;
+
+        assert_eq!(receiver.await, BatchStatus::Delivered);
+        assert_eq!(channels.first().published(), 2);
+    }
+
+    #[tokio::test]
+    async fn resolves_the_finalizers_of_each_channel() {
+        // The first channel confirms its messages, the second one fails to reconnect.
+        let channels = vec![TestChannel::new(0), TestChannel::disconnected()];
+        let (requests, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| request()).unzip();
+
+        send(channels, None, 0, requests).await;
+
+        let statuses = future::join_all(receivers).await;
+        assert_eq!(
+            statuses,
+            vec![
+                BatchStatus::Delivered,
+                BatchStatus::Rejected,
+                BatchStatus::Delivered,
+                BatchStatus::Rejected,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_events_which_fail_to_publish() {
+        let (request, receiver) = request();
+
+        send(vec![TestChannel::undeliverable()], None, 0, vec![request]).await;
+
+        assert_eq!(receiver.await, BatchStatus::Rejected);
+    }
+
+    #[tokio::test]
+    async fn publishes_to_channels_in_turn() {
+        let channels = (0..3).map(|_| TestChannel::new(0)).collect();
+        let (requests, _receivers): (Vec<_>, Vec<_>) = (0..30).map(|_| request()).unzip();
+
+        let channels = send(channels, None, 0, requests).await;
+
+        assert_eq!(
+            (0..3)
+                .map(|_| channels.next().published())
+                .collect::<Vec<_>>(),
+            vec![10, 10, 10]
+        );
+    }
+
+    #[tokio::test]
+    async fn publishes_to_a_single_channel() {
+        let (requests, _receivers): (Vec<_>, Vec<_>) = (0..5).map(|_| request()).unzip();
+
+        let channels = send(vec![TestChannel::new(0)], None, 0, requests).await;
+
+        assert_eq!(channels.first().published(), 5);
     }
 }
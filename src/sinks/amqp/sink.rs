@@ -1,15 +1,16 @@
 //! The sink for the `AMQP` sink that wires together the main stream that takes the
 //! event and sends it to `AMQP`.
-use crate::sinks::prelude::*;
-use lapin::{options::ConfirmSelectOptions, BasicProperties};
+use crate::{internal_events::sink::AmqpMessageTooLarge, sinks::prelude::*};
+use lapin::BasicProperties;
 use serde::Serialize;
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
 
 use super::{
+    channel::ChannelPool,
     config::{AmqpPropertiesConfig, AmqpSinkConfig},
     encoder::AmqpEncoder,
     request_builder::AmqpRequestBuilder,
-    service::AmqpService,
+    service::{AmqpRequest, AmqpService},
     BuildError,
 };
 
@@ -46,40 +47,43 @@ impl EstimatedJsonEncodedSizeOf for AmqpEvent {
 }
 
 pub(super) struct AmqpSink {
-    pub(super) channel: Arc<lapin::Channel>,
+    pub(super) channels: Arc<ChannelPool>,
     exchange: Template,
     routing_key: Option<Template>,
     properties: Option<AmqpPropertiesConfig>,
     transformer: Transformer,
     encoder: crate::codecs::Encoder<()>,
+    confirm_timeout: Option<Duration>,
+    confirm_retries: usize,
+    max_message_bytes: Option<NonZeroUsize>,
 }
 
 impl AmqpSink {
     pub(super) async fn new(config: AmqpSinkConfig) -> crate::Result<Self> {
-        let (_, channel) = config
-            .connection
-            .connect()
-            .await
-            .map_err(|e| BuildError::AmqpCreateFailed { source: e })?;
-
-        channel
-            .confirm_select(ConfirmSelectOptions::default())
-            .await
-            .map_err(|e| BuildError::AmqpCreateFailed {
-                source: Box::new(e),
-            })?;
-
+        let channels = ChannelPool::connect(
+            config.connection,
+            config.reconnect,
+            config.channel_pool_size(),
+        )
+        .await
+        .map_err(|e| BuildError::AmqpCreateFailed { source: e })?;
+
+        let confirm_timeout = config.confirm_timeout();
+        let confirm_retries = config.confirm_retries();
         let transformer = config.encoding.transformer();
         let serializer = config.encoding.build()?;
         let encoder = crate::codecs::Encoder::<()>::new(serializer);
 
         Ok(AmqpSink {
-            channel: Arc::new(channel),
+            channels: Arc::new(channels),
             exchange: config.exchange,
             routing_key: config.routing_key,
             properties: config.properties,
             transformer,
             encoder,
+            confirm_timeout,
+            confirm_retries,
+            max_message_bytes: config.max_message_bytes,
         })
     }
 
@@ -114,7 +118,7 @@ impl AmqpSink {
 
         let properties = match &self.properties {
             None => BasicProperties::default(),
-            Some(prop) => prop.build(),
+            Some(prop) => prop.build(&event),
         };
 
         Some(AmqpEvent {
@@ -133,20 +137,22 @@ impl AmqpSink {
             },
         };
         let service = ServiceBuilder::new().service(AmqpService {
-            channel: Arc::clone(&self.channel),
+            channels: Arc::clone(&self.channels),
+            confirm_timeout: self.confirm_timeout,
+            confirm_retries: self.confirm_retries,
         });
 
         input
             .filter_map(|event| std::future::ready(self.make_amqp_event(event)))
             .request_builder(None, request_builder)
-            .filter_map(|request| async move {
-                match request {
+            .filter_map(|request| {
+                std::future::ready(match request {
                     Err(e) => {
                         error!("Failed to build AMQP request: {:?}.", e);
                         None
                     }
-                    Ok(req) => Some(req),
-                }
+                    Ok(req) => check_message_size(req, self.max_message_bytes),
+                })
             })
             .into_driver(service)
             .protocol("amqp_0_9_1")
@@ -161,3 +167,81 @@ impl StreamSink<Event> for AmqpSink {
         self.run_inner(input).await
     }
 }
+
+/// Drops a request whose message exceeds `max_message_bytes`, which the broker would refuse.
+///
+/// The events of the message are rejected, and an error is reported.
+fn check_message_size(
+    mut request: AmqpRequest,
+    max_message_bytes: Option<NonZeroUsize>,
+) -> Option<AmqpRequest> {
+    let Some(max) = max_message_bytes else {
+        return Some(request);
+    };
+
+    let size = request.body_len();
+    if size <= max.get() {
+        return Some(request);
+    }
+    request
+        .take_finalizers()
+        .update_status(EventStatus::Rejected);
+    emit!(AmqpMessageTooLarge {
+        size,
+        max: max.get(),
+    });
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use codecs::TextSerializerConfig;
+    use vector_common::finalization::{BatchNotifier, BatchStatus};
+
+    use super::*;
+
+    fn request(message: &str, batch: &BatchNotifier) -> AmqpRequest {
+        let request_builder = AmqpRequestBuilder {
+            encoder: AmqpEncoder {
+                encoder: crate::codecs::Encoder::<()>::new(
+                    TextSerializerConfig::default().build().into(),
+                ),
+                transformer: Default::default(),
+            },
+        };
+        let event = AmqpEvent {
+            event: LogEvent::from(message).with_batch_notifier(batch).into(),
+            exchange: "vector".to_owned(),
+            routing_key: String::new(),
+            properties: BasicProperties::default(),
+        };
+
+        let (metadata, request_metadata_builder, event) = request_builder.split_input(event);
+        let payload = request_builder.encode_events(event).unwrap();
+        let request_metadata = request_metadata_builder.build(&payload);
+        request_builder.build_request(metadata, request_metadata, payload)
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let (batch, mut receiver) = BatchNotifier::new_with_receiver();
+        let request = request("a message over the maximum size", &batch);
+        drop(batch);
+
+        assert!(check_message_size(request, NonZeroUsize::new(10)).is_none());
+        assert_eq!(receiver.try_recv(), Ok(BatchStatus::Rejected));
+    }
+
+    #[test]
+    fn passes_messages_within_the_maximum_size() {
+        let (batch, mut receiver) = BatchNotifier::new_with_receiver();
+        let request = request("a message", &batch);
+        drop(batch);
+
+        let request = check_message_size(request, NonZeroUsize::new(9)).unwrap();
+        assert_eq!(request.body_len(), 9);
+        let request = check_message_size(request, None).unwrap();
+        drop(request);
+        assert_eq!(receiver.try_recv(), Ok(BatchStatus::Delivered));
+    }
+}
@@ -102,6 +102,9 @@ impl RequestBuilder<(S3PartitionKey, Vec<Event>)> for S3RequestOptions {
             request_metadata,
             content_encoding: self.compression.content_encoding(),
             options: s3_options,
+            user_metadata: None,
+            content_disposition: None,
+            content_language: None,
         }
     }
 }
@@ -96,6 +96,9 @@ impl RequestBuilder<(String, Vec<Event>)> for AzureBlobRequestOptions {
             content_type: self.compression.content_type(),
             metadata: azure_metadata,
             request_metadata,
+            user_metadata: None,
+            content_disposition: None,
+            content_language: None,
         }
     }
 }
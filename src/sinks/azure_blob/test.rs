@@ -1,16 +1,22 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 use chrono::Utc;
 use codecs::{
     encoding::{Framer, FramingConfig},
     NewlineDelimitedEncoder, TextSerializerConfig,
 };
+use http::StatusCode;
 use vector_core::partition::Partitioner;
 
 use super::config::AzureBlobSinkConfig;
 use super::request_builder::AzureBlobRequestOptions;
 use crate::codecs::EncodingConfigWithFraming;
 use crate::event::{Event, LogEvent};
-use crate::sinks::util::{request_builder::RequestBuilder, Compression};
+use crate::sinks::{
+    azure_common::config::{http_error, AzureBlobRetryLogic},
+    util::{request_builder::RequestBuilder, retries::RetryLogic, Compression},
+};
 use crate::{codecs::Encoder, sinks::util::request_builder::EncodeResult};
 
 fn default_config(encoding: EncodingConfigWithFraming) -> AzureBlobSinkConfig {
@@ -216,3 +222,54 @@ fn azure_blob_build_request_with_uuid() {
     assert_eq!(request.content_encoding, None);
     assert_eq!(request.content_type, "text/plain");
 }
+
+#[tokio::test]
+async fn azure_blob_retries_throttled_and_failed_requests() {
+    let logic = AzureBlobRetryLogic;
+
+    let error = http_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        &[
+            ("x-ms-error-code", "ServerBusy"),
+            ("x-ms-retry-after-ms", "250"),
+        ],
+    )
+    .await;
+    assert!(logic.is_retriable_error(&error));
+    assert_eq!(
+        logic.error_retry_after(&error),
+        Some(Duration::from_millis(250))
+    );
+
+    let error = http_error(StatusCode::TOO_MANY_REQUESTS, &[]).await;
+    assert!(logic.is_retriable_error(&error));
+    assert_eq!(logic.error_retry_after(&error), None);
+
+    let error = http_error(StatusCode::INTERNAL_SERVER_ERROR, &[]).await;
+    assert!(logic.is_retriable_error(&error));
+}
+
+#[tokio::test]
+async fn azure_blob_does_not_retry_client_errors() {
+    let logic = AzureBlobRetryLogic;
+
+    let error = http_error(
+        StatusCode::FORBIDDEN,
+        &[("x-ms-error-code", "AuthenticationFailed")],
+    )
+    .await;
+    assert!(!logic.is_retriable_error(&error));
+
+    let error = http_error(
+        StatusCode::NOT_FOUND,
+        &[("x-ms-error-code", "ContainerNotFound")],
+    )
+    .await;
+    assert!(!logic.is_retriable_error(&error));
+
+    let error = azure_core::Error::new(
+        azure_core::error::ErrorKind::Io,
+        std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset"),
+    );
+    assert!(!logic.is_retriable_error(&error));
+}
@@ -1,9 +1,12 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use azure_core::{error::HttpError, RetryOptions};
 use azure_identity::{AutoRefreshingTokenCredential, DefaultAzureCredential};
 use azure_storage::{prelude::*, CloudLocation, ConnectionString};
-use azure_storage_blobs::{blob::operations::PutBlockBlobResponse, prelude::*};
+use azure_storage_blobs::{
+    blob::operations::{PutBlockBlobResponse, PutBlockListResponse},
+    prelude::*,
+};
 use bytes::Bytes;
 use futures::FutureExt;
 use http::StatusCode;
@@ -26,6 +29,12 @@ pub struct AzureBlobRequest {
     pub content_type: &'static str,
     pub metadata: AzureBlobMetadata,
     pub request_metadata: RequestMetadata,
+    /// User-defined metadata for the created blob, sent as `x-ms-meta-*` headers.
+    pub user_metadata: Option<HashMap<String, String>>,
+    /// The `Content-Disposition` of the created blob.
+    pub content_disposition: Option<String>,
+    /// The `Content-Language` of the created blob.
+    pub content_language: Option<String>,
 }
 
 impl Finalizable for AzureBlobRequest {
@@ -48,22 +57,91 @@ pub struct AzureBlobMetadata {
     pub finalizers: EventFinalizers,
 }
 
+/// The header in which Azure Storage requests a delay before retrying, in milliseconds.
+const RETRY_AFTER_MS: &str = "x-ms-retry-after-ms";
+
+/// The error code of Azure Storage when the server is throttling requests.
+const SERVER_BUSY: &str = "ServerBusy";
+
 #[derive(Debug, Clone)]
 pub struct AzureBlobRetryLogic;
 
 impl RetryLogic for AzureBlobRetryLogic {
-    type Error = HttpError;
+    type Error = azure_core::Error;
     type Response = AzureBlobResponse;
 
     fn is_retriable_error(&self, error: &Self::Error) -> bool {
-        error.status().is_server_error()
-            || StatusCode::TOO_MANY_REQUESTS.as_u16() == Into::<u16>::into(error.status())
+        error.downcast_ref::<HttpError>().map_or(false, |error| {
+            is_retriable(error.status().into(), error.error_code())
+        })
     }
+
+    fn error_retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        // The SDK doesn't expose the headers of failed responses, but lists them, one per line,
+        // when the error is formatted.
+        let error = error.downcast_ref::<HttpError>()?;
+        parse_retry_after_ms(&format!("{:#}", error))
+    }
+}
+
+/// Builds the error the SDK returns for a failed response with the given status and headers.
+#[cfg(test)]
+pub async fn http_error(
+    status: StatusCode,
+    headers: &[(&'static str, &'static str)],
+) -> azure_core::Error {
+    let mut response_headers = azure_core::headers::Headers::new();
+    for (name, value) in headers {
+        response_headers.insert(*name, *value);
+    }
+    let status = azure_core::StatusCode::try_from(status.as_u16()).expect("valid status code");
+    let response = azure_core::Response::new(
+        status,
+        response_headers,
+        Box::pin(azure_core::BytesStream::new(Bytes::new())),
+    );
+    let error = HttpError::new(response).await;
+    let kind = azure_core::error::ErrorKind::http_response(
+        status,
+        error.error_code().map(ToOwned::to_owned),
+    );
+    azure_core::Error::new(kind, error)
+}
+
+/// Whether a request failing with the given status and error code should be retried.
+fn is_retriable(status: u16, error_code: Option<&str>) -> bool {
+    StatusCode::from_u16(status).map_or(false, |status| status.is_server_error())
+        || status == StatusCode::TOO_MANY_REQUESTS.as_u16()
+        || error_code == Some(SERVER_BUSY)
+}
+
+/// Parses the delay requested with `x-ms-retry-after-ms` from the headers of a failed response,
+/// listed as `name:value`.
+fn parse_retry_after_ms(headers: &str) -> Option<Duration> {
+    headers.lines().find_map(|line| {
+        let (name, value) = line.trim().split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case(RETRY_AFTER_MS) {
+            return None;
+        }
+        value
+            .trim()
+            .trim_end_matches(',')
+            .parse()
+            .ok()
+            .map(Duration::from_millis)
+    })
+}
+
+/// The response to the upload of a blob, which depends on whether it was uploaded in blocks.
+#[derive(Debug)]
+pub enum AzureBlobUploadResponse {
+    Blob(PutBlockBlobResponse),
+    BlockList(PutBlockListResponse),
 }
 
 #[derive(Debug)]
 pub struct AzureBlobResponse {
-    pub inner: PutBlockBlobResponse,
+    pub inner: AzureBlobUploadResponse,
     pub count: usize,
     pub events_byte_size: JsonSize,
     pub byte_size: usize,
@@ -186,3 +264,53 @@ pub fn build_client(
     }
     Ok(std::sync::Arc::new(client))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_server_busy_and_throttling() {
+        assert!(is_retriable(503, Some("ServerBusy")));
+        assert!(is_retriable(500, Some("InternalError")));
+        assert!(is_retriable(429, None));
+        assert!(!is_retriable(404, Some("BlobNotFound")));
+    }
+
+    #[test]
+    fn does_not_retry_authentication_errors() {
+        assert!(!is_retriable(403, Some("AuthenticationFailed")));
+        assert!(!is_retriable(403, None));
+    }
+
+    #[tokio::test]
+    async fn parses_retry_after_ms() {
+        let error = http_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &[
+                ("x-ms-error-code", "ServerBusy"),
+                ("x-ms-retry-after-ms", "1500"),
+                ("date", "Mon, 23 Aug 2021 16:00:27 GMT"),
+            ],
+        )
+        .await;
+        assert_eq!(
+            AzureBlobRetryLogic.error_retry_after(&error),
+            Some(Duration::from_millis(1500))
+        );
+
+        let error = http_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &[("x-ms-retry-after-ms", "soon")],
+        )
+        .await;
+        assert_eq!(AzureBlobRetryLogic.error_retry_after(&error), None);
+
+        let error = http_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &[("retry-after", "2"), ("x-ms-error-code", "ServerBusy")],
+        )
+        .await;
+        assert_eq!(AzureBlobRetryLogic.error_retry_after(&error), None);
+    }
+}
@@ -1,25 +1,114 @@
 use std::{
+    num::NonZeroUsize,
+    ops::Range,
     result::Result as StdResult,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
-use azure_storage_blobs::prelude::*;
+use azure_core::request_options::Metadata;
+use azure_storage_blobs::{blob::BlobBlockType, prelude::*};
+use bytes::Bytes;
 use futures::future::BoxFuture;
 use tower::Service;
 use tracing::Instrument;
 
-use crate::sinks::azure_common::config::{AzureBlobRequest, AzureBlobResponse};
+use crate::sinks::{
+    azure_common::config::{AzureBlobRequest, AzureBlobResponse, AzureBlobUploadResponse},
+    util::retries::ExponentialBackoff,
+};
+
+/// The number of times the upload of a single block is attempted, when blobs are uploaded in blocks.
+const BLOCK_ATTEMPTS: usize = 3;
 
 #[derive(Clone)]
 pub(crate) struct AzureBlobService {
     client: Arc<ContainerClient>,
+    block_size: Option<NonZeroUsize>,
 }
 
 impl AzureBlobService {
     pub fn new(client: Arc<ContainerClient>) -> AzureBlobService {
-        AzureBlobService { client }
+        AzureBlobService {
+            client,
+            block_size: None,
+        }
+    }
+
+    /// Uploads blobs larger than `block_size` bytes in blocks of that size.
+    ///
+    /// Each block is retried on its own, so that a failure doesn't require uploading the whole blob
+    /// again. Smaller blobs are still uploaded in a single request.
+    pub const fn with_block_size(mut self, block_size: Option<NonZeroUsize>) -> Self {
+        self.block_size = block_size;
+        self
+    }
+}
+
+/// The byte ranges of the blocks a blob of `len` bytes is uploaded in.
+fn block_ranges(len: usize, block_size: NonZeroUsize) -> Vec<Range<usize>> {
+    let block_size = block_size.get();
+    (0..len)
+        .step_by(block_size)
+        .map(|start| start..len.min(start + block_size))
+        .collect()
+}
+
+/// The delays between the attempts to upload a block.
+///
+/// Blocks are mostly retried when the storage account is throttled, so attempts back off rather
+/// than being retried right away.
+fn block_backoff() -> ExponentialBackoff {
+    ExponentialBackoff::from_millis(2)
+        .factor(250)
+        .max_delay(Duration::from_secs(5))
+}
+
+/// The ID of the block at `index`.
+///
+/// The IDs of the blocks of a blob must all have the same length.
+fn block_id(index: usize) -> Bytes {
+    Bytes::from(format!("block-{:010}", index))
+}
+
+/// Stages the blocks of `data`, and returns the list of blocks to commit.
+async fn put_blocks(
+    client: &BlobClient,
+    data: &Bytes,
+    block_size: NonZeroUsize,
+) -> StdResult<BlockList, azure_core::Error> {
+    let mut blocks = Vec::new();
+    for (index, range) in block_ranges(data.len(), block_size).into_iter().enumerate() {
+        let id = BlockId::new(block_id(index));
+        let mut attempt = 1;
+        let mut backoff = block_backoff();
+        loop {
+            match client
+                .put_block(id.clone(), data.slice(range.clone()))
+                .into_future()
+                .await
+            {
+                Ok(_) => break,
+                Err(error) if attempt < BLOCK_ATTEMPTS => {
+                    let delay = backoff.next().expect("backoff never ends");
+                    warn!(
+                        message = "Failed to upload block, retrying.",
+                        block = index,
+                        attempt,
+                        delay_ms = %delay.as_millis(),
+                        %error,
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        blocks.push(BlobBlockType::new_uncommitted(id));
     }
+
+    Ok(BlockList { blocks })
 }
 
 impl Service<AzureBlobRequest> for AzureBlobService {
@@ -41,19 +130,72 @@ impl Service<AzureBlobRequest> for AzureBlobService {
                 .client
                 .blob_client(request.metadata.partition_key.as_str());
             let byte_size = request.blob_data.len();
-            let blob = client
-                .put_block_blob(request.blob_data)
-                .content_type(request.content_type);
-            let blob = match request.content_encoding {
-                Some(encoding) => blob.content_encoding(encoding),
-                None => blob,
-            };
-
-            let result = blob
-                .into_future()
+            let metadata = request.user_metadata.map(|user_metadata| {
+                let mut metadata = Metadata::new();
+                for (key, value) in user_metadata {
+                    metadata.insert(key, value);
+                }
+                metadata
+            });
+
+            let result = match this
+                .block_size
+                .filter(|block_size| byte_size > block_size.get())
+            {
+                Some(block_size) => async {
+                    let block_list = put_blocks(&client, &request.blob_data, block_size).await?;
+                    let blob = client
+                        .put_block_list(block_list)
+                        .content_type(request.content_type);
+                    let blob = match request.content_encoding {
+                        Some(encoding) => blob.content_encoding(encoding),
+                        None => blob,
+                    };
+                    let blob = match request.content_disposition {
+                        Some(content_disposition) => blob.content_disposition(content_disposition),
+                        None => blob,
+                    };
+                    let blob = match request.content_language {
+                        Some(content_language) => blob.content_language(content_language),
+                        None => blob,
+                    };
+                    let blob = match metadata {
+                        Some(metadata) => blob.metadata(metadata),
+                        None => blob,
+                    };
+                    blob.into_future().await
+                }
                 .instrument(info_span!("request").or_current())
                 .await
-                .map_err(|err| err.into());
+                .map(AzureBlobUploadResponse::BlockList),
+                None => {
+                    let blob = client
+                        .put_block_blob(request.blob_data)
+                        .content_type(request.content_type);
+                    let blob = match request.content_encoding {
+                        Some(encoding) => blob.content_encoding(encoding),
+                        None => blob,
+                    };
+                    let blob = match request.content_disposition {
+                        Some(content_disposition) => blob.content_disposition(content_disposition),
+                        None => blob,
+                    };
+                    let blob = match request.content_language {
+                        Some(content_language) => blob.content_language(content_language),
+                        None => blob,
+                    };
+                    let blob = match metadata {
+                        Some(metadata) => blob.metadata(metadata),
+                        None => blob,
+                    };
+
+                    blob.into_future()
+                        .instrument(info_span!("request").or_current())
+                        .await
+                        .map(AzureBlobUploadResponse::Blob)
+                }
+            }
+            .map_err(|err| err.into());
 
             result.map(|inner| AzureBlobResponse {
                 inner,
@@ -64,3 +206,45 @@ impl Service<AzureBlobRequest> for AzureBlobService {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_size(size: usize) -> NonZeroUsize {
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    #[test]
+    fn splits_blobs_into_blocks() {
+        assert_eq!(block_ranges(10, block_size(4)), vec![0..4, 4..8, 8..10]);
+        assert_eq!(block_ranges(8, block_size(4)), vec![0..4, 4..8]);
+        assert_eq!(block_ranges(3, block_size(4)), vec![0..3]);
+        assert!(block_ranges(0, block_size(4)).is_empty());
+    }
+
+    #[test]
+    fn counts_blocks_of_a_full_batch() {
+        // A full 100MB batch, uploaded in blocks of 8MiB.
+        let ranges = block_ranges(100_000_000, block_size(8 * 1024 * 1024));
+        assert_eq!(ranges.len(), 12);
+        assert_eq!(
+            ranges.last().unwrap().len(),
+            100_000_000 - 11 * 8 * 1024 * 1024
+        );
+    }
+
+    #[test]
+    fn backs_off_between_block_attempts() {
+        assert_eq!(
+            block_backoff().take(BLOCK_ATTEMPTS - 1).collect::<Vec<_>>(),
+            [Duration::from_millis(500), Duration::from_secs(1)]
+        );
+        assert_eq!(block_backoff().nth(10), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn block_ids_have_the_same_length() {
+        assert_eq!(block_id(0).len(), block_id(123_456).len());
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    cmp,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use tower::Service;
+use vector_config::configurable_component;
+
+use super::sink::BatchSizing;
+use crate::{
+    event::Event,
+    sinks::util::retries::{RetryAction, RetryLogic},
+};
+
+const fn default_min_bytes() -> usize {
+    10_000_000
+}
+
+const fn default_throttle_threshold() -> usize {
+    3
+}
+
+const fn default_recovery_threshold() -> usize {
+    10
+}
+
+/// Adaptive batch sizing configuration.
+///
+/// When enabled, the effective batch size is temporarily reduced while the storage service is
+/// throttling or failing requests, and is ramped back up once requests succeed again. This avoids
+/// queueing up many maximum-sized batches against a backend that is recovering from an outage.
+#[configurable_component]
+#[derive(Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveBatchSizeConfig {
+    /// Whether or not adaptive batch sizing is enabled.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The smallest size, in bytes, that a batch can be reduced to.
+    #[serde(default = "default_min_bytes")]
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    pub min_bytes: usize,
+
+    /// The number of consecutive throttled requests after which the batch size is halved.
+    #[serde(default = "default_throttle_threshold")]
+    pub throttle_threshold: usize,
+
+    /// The number of consecutive successful requests after which the batch size is doubled.
+    ///
+    /// The batch size is never raised above the default batch size.
+    #[serde(default = "default_recovery_threshold")]
+    pub recovery_threshold: usize,
+}
+
+impl Default for AdaptiveBatchSizeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_bytes: default_min_bytes(),
+            throttle_threshold: default_throttle_threshold(),
+            recovery_threshold: default_recovery_threshold(),
+        }
+    }
+}
+
+impl AdaptiveBatchSizeConfig {
+    /// Builds the shared batch size state, if adaptive batch sizing is enabled.
+    pub(super) fn build(&self, max_bytes: usize) -> Option<Arc<AdaptiveBatchSize>> {
+        self.enabled
+            .then(|| Arc::new(AdaptiveBatchSize::new(max_bytes, self)))
+    }
+}
+
+#[derive(Debug)]
+struct AdaptiveBatchSizeState {
+    limit: usize,
+    consecutive_throttles: usize,
+    consecutive_successes: usize,
+}
+
+/// Tracks the effective batch size based on the responses from the storage service.
+#[derive(Debug)]
+pub struct AdaptiveBatchSize {
+    max_bytes: usize,
+    min_bytes: usize,
+    throttle_threshold: usize,
+    recovery_threshold: usize,
+    state: Mutex<AdaptiveBatchSizeState>,
+}
+
+impl AdaptiveBatchSize {
+    fn new(max_bytes: usize, config: &AdaptiveBatchSizeConfig) -> Self {
+        Self {
+            max_bytes,
+            min_bytes: cmp::min(config.min_bytes, max_bytes),
+            throttle_threshold: cmp::max(config.throttle_threshold, 1),
+            recovery_threshold: cmp::max(config.recovery_threshold, 1),
+            state: Mutex::new(AdaptiveBatchSizeState {
+                limit: max_bytes,
+                consecutive_throttles: 0,
+                consecutive_successes: 0,
+            }),
+        }
+    }
+
+    /// The current effective batch size limit, in bytes.
+    pub fn limit(&self) -> usize {
+        self.state.lock().expect("poisoned lock").limit
+    }
+
+    /// Records a request which was throttled, or otherwise failed in a retriable way.
+    pub fn record_throttle(&self) {
+        let mut state = self.state.lock().expect("poisoned lock");
+        state.consecutive_successes = 0;
+        state.consecutive_throttles += 1;
+        if state.consecutive_throttles >= self.throttle_threshold {
+            state.consecutive_throttles = 0;
+            state.limit = cmp::max(state.limit / 2, self.min_bytes);
+        }
+    }
+
+    /// Records a request which succeeded.
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().expect("poisoned lock");
+        state.consecutive_throttles = 0;
+        if state.limit >= self.max_bytes {
+            return;
+        }
+        state.consecutive_successes += 1;
+        if state.consecutive_successes >= self.recovery_threshold {
+            state.consecutive_successes = 0;
+            state.limit = cmp::min(state.limit.saturating_mul(2), self.max_bytes);
+        }
+    }
+
+    /// Splits a batch into batches which fit within the current effective limit.
+    ///
+    /// The size of events is accounted for in the same way as the batcher does, so a batch is left
+    /// intact whenever the limit is at its maximum.
+    pub fn split(&self, events: Vec<Event>, batch_sizing: BatchSizing) -> Vec<Vec<Event>> {
+        let limit = self.limit();
+        if limit >= self.max_bytes {
+            return vec![events];
+        }
+
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        for event in events {
+            let event_size = batch_sizing.size_of(&event);
+            if !batch.is_empty() && batch_size + event_size > limit {
+                batches.push(std::mem::take(&mut batch));
+                batch_size = 0;
+            }
+            batch_size += event_size;
+            batch.push(event);
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+}
+
+/// Feeds the outcome of every request attempt into the adaptive batch size.
+///
+/// Responses and errors are classified with the backend's retry logic, so anything that would be
+/// retried counts as throttling.
+#[derive(Clone)]
+pub struct AdaptiveBatchSizeService<S, L> {
+    inner: S,
+    logic: L,
+    batch_size: Option<Arc<AdaptiveBatchSize>>,
+}
+
+impl<S, L> AdaptiveBatchSizeService<S, L> {
+    pub const fn new(inner: S, logic: L, batch_size: Option<Arc<AdaptiveBatchSize>>) -> Self {
+        Self {
+            inner,
+            logic,
+            batch_size,
+        }
+    }
+}
+
+impl<S, L, Request> Service<Request> for AdaptiveBatchSizeService<S, L>
+where
+    S: Service<Request>,
+    S::Response: Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<crate::Error>,
+    L: RetryLogic<Response = S::Response>,
+{
+    type Response = S::Response;
+    type Error = crate::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let future = self.inner.call(request);
+        let logic = self.logic.clone();
+        let batch_size = self.batch_size.clone();
+
+        Box::pin(async move {
+            let result: Result<S::Response, crate::Error> = future.await.map_err(Into::into);
+
+            if let Some(batch_size) = batch_size {
+                let throttled = match &result {
+                    Ok(response) => match logic.should_retry_response(response) {
+                        RetryAction::Retry(_) => Some(true),
+                        RetryAction::Successful => Some(false),
+                        RetryAction::DontRetry(_) => None,
+                    },
+                    Err(error) => error
+                        .downcast_ref::<L::Error>()
+                        .filter(|error| logic.is_retriable_error(error))
+                        .map(|_| true),
+                };
+                match throttled {
+                    Some(true) => batch_size.record_throttle(),
+                    Some(false) => batch_size.record_success(),
+                    None => {}
+                }
+            }
+
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vector_core::ByteSizeOf;
+
+    use super::*;
+    use crate::event::LogEvent;
+
+    fn batch_size(max_bytes: usize, min_bytes: usize) -> AdaptiveBatchSize {
+        AdaptiveBatchSize::new(
+            max_bytes,
+            &AdaptiveBatchSizeConfig {
+                enabled: true,
+                min_bytes,
+                throttle_threshold: 2,
+                recovery_threshold: 3,
+            },
+        )
+    }
+
+    #[test]
+    fn shrinks_on_sustained_throttling() {
+        let batch_size = batch_size(1000, 200);
+
+        // A single throttled request isn't enough to shrink the batch size.
+        batch_size.record_throttle();
+        assert_eq!(batch_size.limit(), 1000);
+
+        // Nor is a throttled request after a success.
+        batch_size.record_success();
+        batch_size.record_throttle();
+        assert_eq!(batch_size.limit(), 1000);
+
+        batch_size.record_throttle();
+        assert_eq!(batch_size.limit(), 500);
+
+        batch_size.record_throttle();
+        batch_size.record_throttle();
+        assert_eq!(batch_size.limit(), 250);
+
+        // The batch size never goes below the configured minimum.
+        for _ in 0..10 {
+            batch_size.record_throttle();
+        }
+        assert_eq!(batch_size.limit(), 200);
+    }
+
+    #[test]
+    fn ramps_up_after_recovery() {
+        let batch_size = batch_size(1000, 100);
+        for _ in 0..4 {
+            batch_size.record_throttle();
+        }
+        assert_eq!(batch_size.limit(), 250);
+
+        batch_size.record_success();
+        batch_size.record_success();
+        assert_eq!(batch_size.limit(), 250);
+
+        // A throttled request resets the recovery.
+        batch_size.record_throttle();
+        batch_size.record_success();
+        batch_size.record_success();
+        assert_eq!(batch_size.limit(), 250);
+
+        batch_size.record_success();
+        assert_eq!(batch_size.limit(), 500);
+
+        for _ in 0..3 {
+            batch_size.record_success();
+        }
+        assert_eq!(batch_size.limit(), 1000);
+
+        // The batch size never goes above the maximum.
+        for _ in 0..10 {
+            batch_size.record_success();
+        }
+        assert_eq!(batch_size.limit(), 1000);
+    }
+
+    #[test]
+    fn splits_batches_to_the_effective_limit() {
+        let events = (0..10)
+            .map(|i| Event::Log(LogEvent::from(format!("test message {}", i))))
+            .collect::<Vec<_>>();
+        let event_size = events[0].size_of();
+        let max_bytes = event_size * 10;
+
+        let batch_size = batch_size(max_bytes, 0);
+        let batches = batch_size.split(events.clone(), BatchSizing::InMemory);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 10);
+
+        batch_size.record_throttle();
+        batch_size.record_throttle();
+        assert_eq!(batch_size.limit(), max_bytes / 2);
+
+        let batches = batch_size.split(events, BatchSizing::InMemory);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 10);
+        assert!(batches
+            .iter()
+            .all(|batch| batch.iter().map(ByteSizeOf::size_of).sum::<usize>() <= max_bytes / 2));
+    }
+}
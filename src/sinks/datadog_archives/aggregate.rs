@@ -0,0 +1,409 @@
+use std::{io, mem, num::NonZeroUsize};
+
+use async_stream::stream;
+use futures::{FutureExt, Stream, StreamExt};
+use lookup::path;
+use serde_json::json;
+use vector_core::event::{Event, Value};
+
+use super::sink::BatchSizing;
+use crate::sinks::util::encoding::Encoder;
+
+/// A partition whose small batches can be aggregated with those of other partitions.
+pub trait AggregatePartition: Sized {
+    /// Aggregates the batches of several partitions into the batch of a single object.
+    ///
+    /// The events of each partition are marked with it, so that the object is written with its
+    /// manifest.
+    fn aggregate(batches: Vec<(Self, Vec<Event>)>) -> (Self, Vec<Event>);
+
+    /// Whether the events of the partition are errors.
+    ///
+    /// The batches of error partitions are only aggregated with each other, so that error events
+    /// and regular events are never written to the same object.
+    fn errors(&self) -> bool;
+}
+
+/// Marks the events of an aggregated object with the partition they belong to.
+pub fn mark_partition(events: &mut [Event], partition: &str) {
+    for event in events {
+        if let Event::Log(log) = event {
+            log.metadata_mut()
+                .value_mut()
+                .insert(path!("datadog_archives", "partition"), partition);
+        }
+    }
+}
+
+/// The partition the event was marked with, if it's part of an aggregated object.
+fn marked_partition(event: &Event) -> Option<&Value> {
+    event.maybe_as_log().and_then(|log| {
+        log.metadata()
+            .value()
+            .get(path!("datadog_archives", "partition"))
+    })
+}
+
+/// Aggregates batches smaller than a threshold into multi-partition objects.
+///
+/// Partitions receiving few events are written to tiny objects, one per partition and per batch
+/// timeout. Batches smaller than `threshold` bytes are instead held, and aggregated with the small
+/// batches of other partitions until they reach the threshold together. The small batches of error
+/// partitions are held apart from the others, and only aggregated with each other.
+pub struct Aggregator<K> {
+    threshold: usize,
+    /// The small batches being held, of regular partitions and of error partitions respectively.
+    pending: [PendingBatches<K>; 2],
+}
+
+/// Small batches held to be aggregated together.
+struct PendingBatches<K> {
+    batches: Vec<(K, Vec<Event>)>,
+    bytes: usize,
+}
+
+impl<K: AggregatePartition> PendingBatches<K> {
+    const fn new() -> Self {
+        Self {
+            batches: Vec::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Removes the batches being held, aggregated if there are several of them.
+    fn take(&mut self) -> Option<(K, Vec<Event>)> {
+        self.bytes = 0;
+        let mut batches = mem::take(&mut self.batches);
+        match batches.len() {
+            0 | 1 => batches.pop(),
+            _ => Some(K::aggregate(batches)),
+        }
+    }
+}
+
+impl<K: AggregatePartition> Aggregator<K> {
+    pub const fn new(threshold: NonZeroUsize) -> Self {
+        Self {
+            threshold: threshold.get(),
+            pending: [PendingBatches::new(), PendingBatches::new()],
+        }
+    }
+
+    /// Adds a batch of `size` bytes.
+    ///
+    /// Returns the batches to write, which are the batch itself if it's large enough, or the
+    /// aggregate of the small batches if they're now large enough together.
+    pub fn push(&mut self, key: K, batch: Vec<Event>, size: usize) -> Option<(K, Vec<Event>)> {
+        if size >= self.threshold {
+            return Some((key, batch));
+        }
+
+        let pending = &mut self.pending[usize::from(key.errors())];
+        pending.batches.push((key, batch));
+        pending.bytes += size;
+        if pending.bytes >= self.threshold {
+            pending.take()
+        } else {
+            None
+        }
+    }
+
+    /// Removes the batches being held, aggregated if there are several of them, with those of
+    /// error partitions aggregated apart.
+    pub fn flush(&mut self) -> Vec<(K, Vec<Event>)> {
+        self.pending
+            .iter_mut()
+            .filter_map(PendingBatches::take)
+            .collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending
+            .iter()
+            .all(|pending| pending.batches.is_empty())
+    }
+}
+
+/// Applies `aggregator` to a stream of batches.
+///
+/// Small batches which are ready together, such as those flushed by the batcher when their
+/// timeouts expire at once, are aggregated. The batches being held are flushed as soon as no other
+/// batch is ready, so that aggregation never delays a batch.
+pub fn aggregate_batches<'a, K>(
+    batches: impl Stream<Item = (K, Vec<Event>)> + Send + 'a,
+    mut aggregator: Aggregator<K>,
+    batch_sizing: BatchSizing,
+) -> impl Stream<Item = (K, Vec<Event>)> + Send + 'a
+where
+    K: AggregatePartition + Send + 'a,
+{
+    let mut batches = Box::pin(batches);
+
+    stream! {
+        loop {
+            let batch = if aggregator.is_empty() {
+                batches.next().await
+            } else {
+                match batches.next().now_or_never() {
+                    Some(batch) => batch,
+                    None => {
+                        for batch in aggregator.flush() {
+                            yield batch;
+                        }
+                        continue;
+                    }
+                }
+            };
+
+            match batch {
+                None => {
+                    for batch in aggregator.flush() {
+                        yield batch;
+                    }
+                    break;
+                }
+                Some((key, batch)) => {
+                    let size = batch.iter().map(|event| batch_sizing.size_of(event)).sum();
+                    if let Some(batch) = aggregator.push(key, batch, size) {
+                        yield batch;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Encodes the events of an object, along with its manifest if it's aggregated.
+///
+/// The first line of an aggregated object is its manifest, which lists the partitions of the
+/// object in order. Each of them has the offset and the length of its records, in bytes of the
+/// uncompressed object from the end of the manifest line, and its number of events.
+pub fn encode_with_manifest<E>(
+    encoder: &E,
+    events: Vec<Event>,
+    writer: &mut dyn io::Write,
+) -> io::Result<usize>
+where
+    E: Encoder<Vec<Event>> + ?Sized,
+{
+    if events.first().and_then(marked_partition).is_none() {
+        return encoder.encode_input(events, writer);
+    }
+
+    let mut records = Vec::new();
+    let mut partitions = Vec::new();
+    let mut events = events.into_iter().peekable();
+    while let Some(first) = events.next() {
+        let partition = marked_partition(&first).cloned();
+        let mut run = vec![first];
+        while let Some(event) =
+            events.next_if(|event| marked_partition(event) == partition.as_ref())
+        {
+            run.push(event);
+        }
+
+        let count = run.len();
+        let offset = records.len();
+        let length = encoder.encode_input(run, &mut records)?;
+        partitions.push(json!({
+            "partition": partition.map(|partition| partition.to_string_lossy().into_owned()),
+            "offset": offset,
+            "length": length,
+            "count": count,
+        }));
+    }
+
+    let mut manifest = serde_json::to_vec(&json!({ "manifest": { "partitions": partitions } }))?;
+    manifest.push(b'\n');
+    writer.write_all(&manifest)?;
+    writer.write_all(&records)?;
+
+    Ok(manifest.len() + records.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+    use crate::event::LogEvent;
+
+    impl AggregatePartition for &'static str {
+        fn aggregate(batches: Vec<(Self, Vec<Event>)>) -> (Self, Vec<Event>) {
+            let batches_are_errors = batches.iter().all(|(partition, _)| partition.errors());
+            let events = batches
+                .into_iter()
+                .flat_map(|(partition, mut events)| {
+                    mark_partition(&mut events, partition);
+                    events
+                })
+                .collect();
+            let aggregate = if batches_are_errors {
+                "error_aggregate"
+            } else {
+                "aggregate"
+            };
+            (aggregate, events)
+        }
+
+        fn errors(&self) -> bool {
+            self.starts_with("error")
+        }
+    }
+
+    /// Encodes the message of each event on a line of its own.
+    struct MessageEncoder;
+
+    impl Encoder<Vec<Event>> for MessageEncoder {
+        fn encode_input(&self, input: Vec<Event>, writer: &mut dyn io::Write) -> io::Result<usize> {
+            let mut written = 0;
+            for event in input {
+                let line = format!(
+                    "{}\n",
+                    event.as_log().get("message").unwrap().to_string_lossy()
+                );
+                writer.write_all(line.as_bytes())?;
+                written += line.len();
+            }
+            Ok(written)
+        }
+    }
+
+    fn events(messages: &[&str]) -> Vec<Event> {
+        messages
+            .iter()
+            .map(|message| Event::Log(LogEvent::from(*message)))
+            .collect()
+    }
+
+    fn aggregator() -> Aggregator<&'static str> {
+        Aggregator::new(NonZeroUsize::new(100).unwrap())
+    }
+
+    #[test]
+    fn writes_large_batches_on_their_own() {
+        let mut aggregator = aggregator();
+
+        let (key, batch) = aggregator.push("a", events(&["a1"]), 100).unwrap();
+        assert_eq!(key, "a");
+        assert_eq!(batch.len(), 1);
+        assert!(aggregator.flush().is_empty());
+    }
+
+    #[test]
+    fn aggregates_small_batches_until_the_threshold() {
+        let mut aggregator = aggregator();
+
+        assert!(aggregator.push("a", events(&["a1", "a2"]), 40).is_none());
+        assert!(aggregator.push("b", events(&["b1"]), 40).is_none());
+        let (key, batch) = aggregator.push("c", events(&["c1"]), 20).unwrap();
+        assert_eq!(key, "aggregate");
+        assert_eq!(batch.len(), 4);
+        assert_eq!(
+            batch.iter().map(marked_partition).collect::<Vec<_>>(),
+            vec![
+                Some(&Value::from("a")),
+                Some(&Value::from("a")),
+                Some(&Value::from("b")),
+                Some(&Value::from("c")),
+            ]
+        );
+        assert!(aggregator.flush().is_empty());
+    }
+
+    #[test]
+    fn doesnt_aggregate_a_single_small_batch() {
+        let mut aggregator = aggregator();
+
+        assert!(aggregator.push("a", events(&["a1"]), 10).is_none());
+        let (key, batch) = aggregator.flush().pop().unwrap();
+        assert_eq!(key, "a");
+        assert!(marked_partition(&batch[0]).is_none());
+    }
+
+    #[test]
+    fn aggregates_error_batches_apart() {
+        let mut aggregator = aggregator();
+
+        assert!(aggregator.push("a", events(&["a1"]), 40).is_none());
+        assert!(aggregator
+            .push("error_a", events(&["error_a1"]), 40)
+            .is_none());
+        assert!(aggregator.push("b", events(&["b1"]), 40).is_none());
+        assert!(aggregator
+            .push("error_b", events(&["error_b1"]), 40)
+            .is_none());
+
+        // The regular batches reach the threshold on their own.
+        let (key, batch) = aggregator.push("c", events(&["c1"]), 40).unwrap();
+        assert_eq!(key, "aggregate");
+        assert_eq!(
+            batch.iter().map(marked_partition).collect::<Vec<_>>(),
+            vec![
+                Some(&Value::from("a")),
+                Some(&Value::from("b")),
+                Some(&Value::from("c")),
+            ]
+        );
+
+        let flushed = aggregator
+            .flush()
+            .into_iter()
+            .map(|(key, batch)| (key, batch.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(flushed, vec![("error_aggregate", 2)]);
+    }
+
+    #[tokio::test]
+    async fn aggregates_batches_which_are_ready_together() {
+        let batches = stream::iter(vec![
+            ("a", events(&["a1"])),
+            ("b", events(&["b1", "b2"])),
+            ("c", events(&["c1"])),
+        ]);
+        let aggregator = Aggregator::new(NonZeroUsize::new(usize::MAX).unwrap());
+        let aggregated = aggregate_batches(batches, aggregator, BatchSizing::InMemory)
+            .map(|(key, batch)| (key, batch.len()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(aggregated, vec![("aggregate", 4)]);
+    }
+
+    #[test]
+    fn writes_the_manifest_of_aggregated_objects() {
+        let (_, batch) = <&str>::aggregate(vec![
+            ("a", events(&["first", "second"])),
+            ("b", events(&["third"])),
+        ]);
+        let mut payload = Vec::new();
+        let written = encode_with_manifest(&MessageEncoder, batch, &mut payload).unwrap();
+        assert_eq!(written, payload.len());
+
+        let (manifest, records) =
+            payload.split_at(payload.iter().position(|&b| b == b'\n').unwrap() + 1);
+        let manifest = serde_json::from_slice::<serde_json::Value>(manifest).unwrap();
+        let partitions = manifest["manifest"]["partitions"].as_array().unwrap();
+        assert_eq!(partitions.len(), 2);
+
+        let slice = |partition: &serde_json::Value| {
+            let offset = partition["offset"].as_u64().unwrap() as usize;
+            let length = partition["length"].as_u64().unwrap() as usize;
+            std::str::from_utf8(&records[offset..offset + length]).unwrap()
+        };
+        assert_eq!(partitions[0]["partition"], "a");
+        assert_eq!(partitions[0]["count"], 2);
+        assert_eq!(slice(&partitions[0]), "first\nsecond\n");
+        assert_eq!(partitions[1]["partition"], "b");
+        assert_eq!(partitions[1]["count"], 1);
+        assert_eq!(slice(&partitions[1]), "third\n");
+    }
+
+    #[test]
+    fn writes_objects_which_arent_aggregated_as_is() {
+        let mut payload = Vec::new();
+        encode_with_manifest(&MessageEncoder, events(&["first"]), &mut payload).unwrap();
+
+        assert_eq!(payload, b"first\n");
+    }
+}
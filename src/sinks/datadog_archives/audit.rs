@@ -0,0 +1,164 @@
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tower::Service;
+use vector_common::request_metadata::MetaDescriptive;
+use vector_core::stream::DriverResponse;
+
+use super::{concurrency::partition_of, sidecar::SidecarRequest};
+use crate::internal_events::DatadogArchivesObjectWritten;
+
+/// The object written by a request, to report once it's written.
+#[derive(Debug)]
+struct PendingAudit {
+    key: String,
+    events: usize,
+}
+
+impl PendingAudit {
+    /// The object written by `request`, if it's an archive.
+    ///
+    /// Sidecar objects and success markers hold no events, and aren't reported.
+    fn of<R: SidecarRequest + MetaDescriptive>(request: &R) -> Option<Self> {
+        let events = request.get_metadata().event_count();
+        (events > 0).then(|| Self {
+            key: request.key().to_owned(),
+            events,
+        })
+    }
+
+    /// The report of the object, if the request writing it succeeded.
+    fn written<T: DriverResponse, E>(
+        self,
+        result: &Result<T, E>,
+    ) -> Option<DatadogArchivesObjectWritten> {
+        let response = result.as_ref().ok()?;
+        Some(DatadogArchivesObjectWritten {
+            partition: partition_of(&self.key).to_owned(),
+            key: self.key,
+            events: self.events,
+            status: response.event_status(),
+        })
+    }
+}
+
+/// Reports each archive successfully written, along with the status of its events.
+///
+/// The report is an internal log, so that the archives written can be audited through the
+/// `internal_logs` source. Requests which fail aren't reported, and those which are retried are
+/// only reported once they succeed.
+#[derive(Clone)]
+pub struct AuditService<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S> AuditService<S> {
+    pub const fn new(inner: S, enabled: bool) -> Self {
+        Self { inner, enabled }
+    }
+}
+
+impl<S, Request> Service<Request> for AuditService<S>
+where
+    S: Service<Request>,
+    S::Response: DriverResponse + Send + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+    Request: SidecarRequest + MetaDescriptive,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let pending = self.enabled.then(|| PendingAudit::of(&request)).flatten();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let result = future.await;
+            if let Some(written) = pending.and_then(|pending| pending.written(&result)) {
+                emit!(written);
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::HeaderValue;
+    use vector_common::{json_size::JsonSize, request_metadata::RequestMetadata};
+    use vector_core::{
+        event::{EventFinalizers, EventStatus},
+        internal_event::CountByteSize,
+    };
+
+    use super::*;
+    use crate::sinks::gcs_common::service::{GcsRequest, GcsRequestSettings};
+
+    struct Response(EventStatus);
+
+    impl DriverResponse for Response {
+        fn event_status(&self) -> EventStatus {
+            self.0
+        }
+
+        fn events_sent(&self) -> CountByteSize {
+            CountByteSize(0, JsonSize::zero())
+        }
+    }
+
+    fn request(key: &str, events: usize) -> GcsRequest {
+        GcsRequest {
+            key: key.to_owned(),
+            body: Bytes::from_static(b"archive"),
+            settings: GcsRequestSettings {
+                acl: None,
+                content_type: HeaderValue::from_static("application/json"),
+                content_encoding: None,
+                storage_class: HeaderValue::from_static("STANDARD"),
+                headers: Vec::new(),
+            },
+            finalizers: EventFinalizers::default(),
+            metadata: RequestMetadata::new(events, 7, 7, 7, JsonSize::new(7)),
+        }
+    }
+
+    #[test]
+    fn reports_written_archives() {
+        let pending = PendingAudit::of(&request("dt=20210823/hour=16/archive.json.gz", 3));
+
+        assert_eq!(
+            pending
+                .unwrap()
+                .written(&Ok::<_, ()>(Response(EventStatus::Delivered))),
+            Some(DatadogArchivesObjectWritten {
+                key: "dt=20210823/hour=16/archive.json.gz".to_owned(),
+                partition: "dt=20210823/hour=16".to_owned(),
+                events: 3,
+                status: EventStatus::Delivered,
+            })
+        );
+    }
+
+    #[test]
+    fn doesnt_report_failed_writes() {
+        let pending = PendingAudit::of(&request("dt=20210823/hour=16/archive.json.gz", 3));
+
+        assert_eq!(
+            pending.unwrap().written(&Err::<Response, _>("timed out")),
+            None
+        );
+    }
+
+    #[test]
+    fn doesnt_report_objects_without_events() {
+        assert!(PendingAudit::of(&request("dt=20210823/hour=16/_SUCCESS", 0)).is_none());
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures::Stream;
+use pin_project::pin_project;
+use vector_common::finalization::BatchNotifier;
+
+use crate::event::Event;
+
+/// The bytes of events buffered by the batcher, across all partitions.
+#[derive(Debug)]
+struct BufferState {
+    max_bytes: usize,
+    buffered: usize,
+    waker: Option<Waker>,
+}
+
+impl BufferState {
+    /// Whether an event of `size` bytes can be buffered.
+    ///
+    /// An event is always admitted when nothing is buffered, so that an event larger than the limit
+    /// doesn't stall the sink.
+    const fn admits(&self, size: usize) -> bool {
+        self.buffered == 0 || self.buffered.saturating_add(size) <= self.max_bytes
+    }
+}
+
+/// Limits the bytes of events buffered by the batcher, across all partitions.
+///
+/// Events are only pulled from the input once there's room for them. Their bytes are released once
+/// they're finalized, when the request writing them completes or when they're dropped, since
+/// batches flushed by the batcher may still be held further down the sink. Until then, the batcher
+/// still flushes the batches whose timeout expires, and the requests of the batches flushed before
+/// complete, which makes room for more events.
+#[derive(Clone, Debug)]
+pub struct BufferLimit(Arc<Mutex<BufferState>>);
+
+impl BufferLimit {
+    pub fn new(max_bytes: NonZeroUsize) -> Self {
+        Self(Arc::new(Mutex::new(BufferState {
+            max_bytes: max_bytes.get(),
+            buffered: 0,
+            waker: None,
+        })))
+    }
+
+    /// Limits the items pulled from `items`, given the size of each item.
+    pub fn limit<S, F>(&self, items: S, size_of: F) -> LimitedStream<S, F>
+    where
+        S: Stream,
+        F: Fn(&S::Item) -> usize,
+    {
+        LimitedStream {
+            items,
+            size_of,
+            limit: self.clone(),
+            pending: None,
+        }
+    }
+
+    /// Releases the `size` bytes of `events` once they're finalized.
+    ///
+    /// A batch notifier is added to the finalizers of the events, so that their bytes stay buffered
+    /// while they're held past the batcher, such as for the grace period of their partition or to be
+    /// aggregated with other partitions, and until the request writing them completes.
+    pub fn release_when_finalized(&self, events: Vec<Event>, size: usize) -> Vec<Event> {
+        let (notifier, receiver) = BatchNotifier::new_with_receiver();
+        let events = events
+            .into_iter()
+            .map(|event| event.with_batch_notifier(&notifier))
+            .collect();
+
+        let limit = self.clone();
+        tokio::spawn(async move {
+            receiver.await;
+            limit.release(size);
+        });
+        events
+    }
+
+    /// Releases the bytes of events which are no longer buffered.
+    pub fn release(&self, size: usize) {
+        let mut state = self.0.lock().expect("poisoned lock");
+        state.buffered = state.buffered.saturating_sub(size);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Buffers `size` bytes if there's room for them, or registers the task to be woken up once
+    /// there may be.
+    fn try_buffer(&self, size: usize, cx: &Context<'_>) -> bool {
+        let mut state = self.0.lock().expect("poisoned lock");
+        if state.admits(size) {
+            state.buffered += size;
+            true
+        } else {
+            state.waker = Some(cx.waker().clone());
+            false
+        }
+    }
+}
+
+/// A stream whose items are only yielded once the buffer limit has room for them.
+#[pin_project]
+pub struct LimitedStream<S: Stream, F> {
+    #[pin]
+    items: S,
+    size_of: F,
+    limit: BufferLimit,
+    pending: Option<(S::Item, usize)>,
+}
+
+impl<S, F> Stream for LimitedStream<S, F>
+where
+    S: Stream,
+    F: Fn(&S::Item) -> usize,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let (item, size) = match this.pending.take() {
+            Some(pending) => pending,
+            None => match this.items.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let size = (this.size_of)(&item);
+                    (item, size)
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            },
+        };
+
+        if this.limit.try_buffer(size, cx) {
+            Poll::Ready(Some(item))
+        } else {
+            *this.pending = Some((item, size));
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+    use futures::{stream, StreamExt};
+    use vector_common::finalization::{EventStatus, Finalizable};
+
+    use super::*;
+    use crate::{
+        event::LogEvent,
+        sinks::datadog_archives::grace_period::{ClosingPartition, GracePeriod},
+    };
+
+    /// A partition ending an hour from now.
+    #[derive(Debug, Eq, Hash, PartialEq)]
+    struct NextHour;
+
+    impl ClosingPartition for NextHour {
+        fn end(&self) -> Option<DateTime<Utc>> {
+            Some(Utc::now() + chrono::Duration::hours(1))
+        }
+    }
+
+    fn state(max_bytes: usize, buffered: usize) -> BufferState {
+        BufferState {
+            max_bytes,
+            buffered,
+            waker: None,
+        }
+    }
+
+    #[test]
+    fn admits_events_within_the_limit() {
+        // Partitions buffering 40 and 50 bytes, out of 100.
+        let state = state(100, 40 + 50);
+
+        assert!(state.admits(0));
+        assert!(state.admits(10));
+        assert!(!state.admits(11));
+    }
+
+    #[test]
+    fn admits_any_event_when_nothing_is_buffered() {
+        assert!(state(100, 0).admits(1_000));
+        assert!(!state(100, 1).admits(1_000));
+    }
+
+    #[tokio::test]
+    async fn applies_backpressure_until_bytes_are_released() {
+        let limit = BufferLimit::new(NonZeroUsize::new(100).unwrap());
+        let mut items = limit.limit(stream::iter(vec![60, 30, 20]), |size| *size);
+
+        assert_eq!(items.next().await, Some(60));
+        assert_eq!(items.next().await, Some(30));
+        // 20 more bytes would exceed the limit.
+        assert!(futures::poll!(items.next()).is_pending());
+
+        // Flushing the first partition makes room for the last event.
+        limit.release(60);
+        assert_eq!(items.next().await, Some(20));
+        assert_eq!(items.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn holds_bytes_until_events_are_finalized() {
+        let limit = BufferLimit::new(NonZeroUsize::new(100).unwrap());
+        let mut items = limit.limit(stream::iter(vec![60, 50]), |size| *size);
+        assert_eq!(items.next().await, Some(60));
+
+        // The batch of the first event leaves the batcher, but is held for the grace period of its
+        // partition.
+        let events = limit.release_when_finalized(vec![Event::Log(LogEvent::from("first"))], 60);
+        let mut grace_period = GracePeriod::new(Duration::from_secs(600), 100);
+        assert!(grace_period
+            .push(NextHour, events, 60, Utc::now())
+            .is_none());
+        tokio::task::yield_now().await;
+        assert!(futures::poll!(items.next()).is_pending());
+
+        // Writing the batch makes room for the next event.
+        let (_, mut events) = grace_period.drain().pop().unwrap();
+        events
+            .take_finalizers()
+            .update_status(EventStatus::Delivered);
+        drop(events);
+        assert_eq!(items.next().await, Some(50));
+        assert_eq!(items.next().await, None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::HashMap, hash::Hash, num::NonZeroUsize, time::Duration};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use tokio::time::{interval, Instant};
+
+/// How often batches which were carried over are checked against the maximum age.
+const EXPIRATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The events carried over for a partition.
+struct Carried<T> {
+    items: Vec<T>,
+    since: Instant,
+}
+
+/// Carries batches with too few events over to the next batch of the same partition.
+///
+/// The batcher flushes each partition when its batch is full or when its timeout expires. A batch
+/// flushed with fewer than `min_events` events, which only happens on timeout, is held back and
+/// merged with the next batch of its partition instead of being written on its own. Events are
+/// never held back for longer than `max_age`, counted from the flush of the first batch that was
+/// held back.
+pub struct CarryOver<K, T> {
+    min_events: usize,
+    max_age: Duration,
+    carried: HashMap<K, Carried<T>>,
+}
+
+impl<K, T> CarryOver<K, T>
+where
+    K: Eq + Hash,
+{
+    pub fn new(min_events: NonZeroUsize, max_age: Duration) -> Self {
+        Self {
+            min_events: min_events.get(),
+            max_age,
+            carried: HashMap::new(),
+        }
+    }
+
+    /// Adds a batch flushed by the batcher to the events carried over for its partition.
+    ///
+    /// Returns the events to write for the partition, if they're now enough, or if they have been
+    /// carried over for too long.
+    pub fn push(&mut self, key: K, batch: Vec<T>, now: Instant) -> Option<Vec<T>> {
+        let mut carried = self.carried.remove(&key).unwrap_or(Carried {
+            items: Vec::new(),
+            since: now,
+        });
+        carried.items.extend(batch);
+
+        if carried.items.len() >= self.min_events || self.is_expired(&carried, now) {
+            Some(carried.items)
+        } else {
+            self.carried.insert(key, carried);
+            None
+        }
+    }
+
+    /// Removes the events which have been carried over for too long.
+    pub fn expired(&mut self, now: Instant) -> Vec<(K, Vec<T>)> {
+        let (expired, carried) = std::mem::take(&mut self.carried)
+            .into_iter()
+            .partition::<HashMap<_, _>, _>(|(_, carried)| self.is_expired(carried, now));
+        self.carried = carried;
+
+        expired
+            .into_iter()
+            .map(|(key, carried)| (key, carried.items))
+            .collect()
+    }
+
+    /// Removes all of the events carried over.
+    pub fn drain(&mut self) -> Vec<(K, Vec<T>)> {
+        self.carried
+            .drain()
+            .map(|(key, carried)| (key, carried.items))
+            .collect()
+    }
+
+    fn is_expired(&self, carried: &Carried<T>, now: Instant) -> bool {
+        now.saturating_duration_since(carried.since) >= self.max_age
+    }
+}
+
+/// Applies `carry_over` to a stream of batches.
+///
+/// Events still carried over when the stream ends are flushed, so that none are lost on shutdown.
+pub fn carry_over_batches<'a, K, T>(
+    batches: impl Stream<Item = (K, Vec<T>)> + Send + 'a,
+    mut carry_over: CarryOver<K, T>,
+) -> impl Stream<Item = (K, Vec<T>)> + Send + 'a
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    T: Send + 'a,
+{
+    let mut batches = Box::pin(batches);
+    let mut expiration_check = interval(EXPIRATION_CHECK_INTERVAL);
+
+    stream! {
+        let mut output = Vec::new();
+        let mut done = false;
+        while !done {
+            tokio::select! {
+                _ = expiration_check.tick() => {
+                    output.extend(carry_over.expired(Instant::now()));
+                },
+                batch = batches.next() => {
+                    match batch {
+                        None => {
+                            output.extend(carry_over.drain());
+                            done = true;
+                        }
+                        Some((key, batch)) => {
+                            if let Some(batch) = carry_over.push(key.clone(), batch, Instant::now()) {
+                                output.push((key, batch));
+                            }
+                        }
+                    }
+                }
+            };
+            for batch in output.drain(..) {
+                yield batch;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    fn carry_over() -> CarryOver<&'static str, u32> {
+        CarryOver::new(NonZeroUsize::new(5).unwrap(), Duration::from_secs(3600))
+    }
+
+    /// The time of the given timeout tick of the batcher, 15 minutes apart.
+    fn tick(start: Instant, tick: u32) -> Instant {
+        start + Duration::from_secs(900) * tick
+    }
+
+    #[test]
+    fn flushes_batches_with_enough_events() {
+        let mut carry_over = carry_over();
+        let start = Instant::now();
+
+        assert_eq!(
+            carry_over.push("a", vec![1, 2, 3, 4, 5], tick(start, 1)),
+            Some(vec![1, 2, 3, 4, 5])
+        );
+        assert!(carry_over.drain().is_empty());
+    }
+
+    #[test]
+    fn carries_small_batches_over_until_enough_events() {
+        let mut carry_over = carry_over();
+        let start = Instant::now();
+
+        assert_eq!(carry_over.push("a", vec![1, 2], tick(start, 1)), None);
+        assert_eq!(carry_over.push("b", vec![10], tick(start, 1)), None);
+        assert_eq!(carry_over.push("a", vec![3, 4], tick(start, 2)), None);
+        assert_eq!(
+            carry_over.push("a", vec![5], tick(start, 3)),
+            Some(vec![1, 2, 3, 4, 5])
+        );
+
+        // Other partitions are carried over independently.
+        assert_eq!(carry_over.drain(), vec![("b", vec![10])]);
+    }
+
+    #[test]
+    fn flushes_small_batches_after_max_age() {
+        let mut carry_over = carry_over();
+        let start = Instant::now();
+
+        assert_eq!(carry_over.push("a", vec![1], tick(start, 1)), None);
+        assert_eq!(carry_over.push("a", vec![2], tick(start, 4)), None);
+        // An hour after the first batch was carried over.
+        assert_eq!(
+            carry_over.push("a", vec![3], tick(start, 5)),
+            Some(vec![1, 2, 3])
+        );
+
+        // Batches which aren't followed by any other are flushed by the expiration check.
+        assert_eq!(carry_over.push("b", vec![10], tick(start, 5)), None);
+        assert!(carry_over.expired(tick(start, 8)).is_empty());
+        assert_eq!(carry_over.expired(tick(start, 9)), vec![("b", vec![10])]);
+        assert!(carry_over.drain().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flushes_carried_over_batches_when_the_stream_ends() {
+        let batches = stream::iter(vec![
+            ("a", vec![1, 2]),
+            ("a", vec![3, 4, 5]),
+            ("b", vec![10]),
+        ]);
+        let flushed = carry_over_batches(batches, carry_over())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(flushed, vec![("a", vec![1, 2, 3, 4, 5]), ("b", vec![10])]);
+    }
+}
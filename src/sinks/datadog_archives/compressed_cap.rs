@@ -0,0 +1,193 @@
+use std::num::NonZeroUsize;
+
+use crate::sinks::util::RequestBuilder;
+
+/// Builds the requests of a batch, splitting it until the payload of each is within `max_bytes`.
+///
+/// The batch is encoded and compressed as a whole first. When its payload, as it's sent, exceeds
+/// `max_bytes`, the batch is split in two halves which are encoded again, recursively. A copy of
+/// the batch is held while it's encoded, so that it can be split. A single event whose payload
+/// exceeds `max_bytes` is still written on its own.
+pub fn build_capped_requests<RB, K, T>(
+    request_builder: &RB,
+    key: K,
+    batch: Vec<T>,
+    max_bytes: NonZeroUsize,
+) -> Vec<Result<RB::Request, RB::Error>>
+where
+    RB: RequestBuilder<(K, Vec<T>)>,
+    K: Clone,
+    T: Clone,
+{
+    let mut requests = Vec::new();
+    // The halves of a split batch are pushed in reverse, so that they're built in order.
+    let mut pending = vec![batch];
+    while let Some(batch) = pending.pop() {
+        let splittable = (batch.len() > 1).then(|| batch.clone());
+        let (metadata, request_metadata_builder, events) =
+            request_builder.split_input((key.clone(), batch));
+        let payload = match request_builder.encode_events(events) {
+            Ok(payload) => payload,
+            Err(error) => {
+                requests.push(Err(error));
+                continue;
+            }
+        };
+
+        let size = payload
+            .compressed_byte_size
+            .unwrap_or(payload.uncompressed_byte_size);
+        match splittable {
+            Some(mut first) if size > max_bytes.get() => {
+                debug!(
+                    message = "Splitting batch over the maximum compressed size.",
+                    size,
+                    max_bytes = max_bytes.get(),
+                    events = first.len(),
+                );
+                // The finalizers of the discarded request are shared with the copy of the batch.
+                drop(metadata);
+                let second = first.split_off(first.len() / 2);
+                pending.push(second);
+                pending.push(first);
+            }
+            _ => {
+                let request_metadata = request_metadata_builder.build(&payload);
+                requests.push(Ok(request_builder.build_request(
+                    metadata,
+                    request_metadata,
+                    payload,
+                )));
+            }
+        }
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use bytes::Bytes;
+    use vector_common::request_metadata::RequestMetadata;
+
+    use super::*;
+    use crate::{
+        event::{Event, LogEvent},
+        sinks::util::{
+            encoding::Encoder, metadata::RequestMetadataBuilder, request_builder::EncodeResult,
+            Compression,
+        },
+    };
+
+    /// Encodes the message of each event on a line of its own.
+    struct MessageEncoder;
+
+    impl Encoder<Vec<Event>> for MessageEncoder {
+        fn encode_input(&self, input: Vec<Event>, writer: &mut dyn io::Write) -> io::Result<usize> {
+            let mut written = 0;
+            for event in input {
+                let line = format!(
+                    "{}\n",
+                    event.as_log().get("message").unwrap().to_string_lossy()
+                );
+                writer.write_all(line.as_bytes())?;
+                written += line.len();
+            }
+            Ok(written)
+        }
+    }
+
+    /// Builds requests holding the key and the payload of each object.
+    struct TestRequestBuilder;
+
+    impl RequestBuilder<(&'static str, Vec<Event>)> for TestRequestBuilder {
+        type Metadata = &'static str;
+        type Events = Vec<Event>;
+        type Encoder = MessageEncoder;
+        type Payload = Bytes;
+        type Request = (&'static str, Bytes);
+        type Error = io::Error;
+
+        fn compression(&self) -> Compression {
+            Compression::None
+        }
+
+        fn encoder(&self) -> &Self::Encoder {
+            &MessageEncoder
+        }
+
+        fn split_input(
+            &self,
+            (key, events): (&'static str, Vec<Event>),
+        ) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
+            (key, RequestMetadataBuilder::from_events(&events), events)
+        }
+
+        fn build_request(
+            &self,
+            key: Self::Metadata,
+            _request_metadata: RequestMetadata,
+            payload: EncodeResult<Self::Payload>,
+        ) -> Self::Request {
+            (key, payload.into_payload())
+        }
+    }
+
+    fn events(messages: &[&str]) -> Vec<Event> {
+        messages
+            .iter()
+            .map(|message| Event::Log(LogEvent::from(*message)))
+            .collect()
+    }
+
+    fn build(batch: Vec<Event>, max_bytes: usize) -> Vec<(&'static str, Bytes)> {
+        build_capped_requests(
+            &TestRequestBuilder,
+            "partition",
+            batch,
+            NonZeroUsize::new(max_bytes).unwrap(),
+        )
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn builds_a_single_object_within_the_cap() {
+        let requests = build(events(&["first", "second"]), 100);
+
+        assert_eq!(
+            requests,
+            vec![("partition", Bytes::from("first\nsecond\n"))]
+        );
+    }
+
+    #[test]
+    fn splits_objects_over_the_cap() {
+        // 4 lines of 6 bytes, in objects of at most 20 bytes.
+        let requests = build(events(&["aaaaa", "bbbbb", "ccccc", "ddddd"]), 20);
+
+        assert_eq!(
+            requests,
+            vec![
+                ("partition", Bytes::from("aaaaa\nbbbbb\n")),
+                ("partition", Bytes::from("ccccc\nddddd\n")),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_recursively_down_to_single_events() {
+        let requests = build(events(&["aaaaa", "bbbbb", "ccccc"]), 4);
+
+        assert_eq!(
+            requests,
+            vec![
+                ("partition", Bytes::from("aaaaa\n")),
+                ("partition", Bytes::from("bbbbb\n")),
+                ("partition", Bytes::from("ccccc\n")),
+            ]
+        );
+    }
+}
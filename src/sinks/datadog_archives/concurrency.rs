@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{buffer::Buffer, util::Either, Service};
+
+use super::sidecar::SidecarRequest;
+
+/// The number of requests which can be waiting for their partition to have an upload slot.
+///
+/// Requests are only pulled from the batches once there's room for them, so that the batches of a
+/// busy partition apply backpressure rather than piling up.
+const PENDING_REQUESTS: usize = 64;
+
+/// The partition of an object, which is the directory it's written to.
+pub(super) fn partition_of(key: &str) -> &str {
+    key.rsplit_once('/').map_or("", |(directory, _)| directory)
+}
+
+/// The upload slots of each partition.
+#[derive(Clone, Debug)]
+struct PartitionLimit {
+    max: usize,
+    partitions: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl PartitionLimit {
+    fn new(max: NonZeroUsize) -> Self {
+        Self {
+            max: max.get(),
+            partitions: Arc::default(),
+        }
+    }
+
+    /// Waits for an upload slot of the partition.
+    async fn acquire(&self, partition: String) -> PartitionPermit {
+        let semaphore = {
+            let mut partitions = self.partitions.lock().expect("poisoned lock");
+            let semaphore = partitions
+                .entry(partition.clone())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max)));
+            Arc::clone(semaphore)
+        };
+        let permit = semaphore
+            .acquire_owned()
+            .await
+            .expect("partition semaphore is never closed");
+
+        PartitionPermit {
+            limit: self.clone(),
+            partition,
+            permit: Some(permit),
+        }
+    }
+
+    /// The number of uploads in flight for the partition.
+    #[cfg(test)]
+    fn in_flight(&self, partition: &str) -> usize {
+        let partitions = self.partitions.lock().expect("poisoned lock");
+        partitions
+            .get(partition)
+            .map_or(0, |semaphore| self.max - semaphore.available_permits())
+    }
+}
+
+/// An upload slot of a partition, which is released when dropped.
+struct PartitionPermit {
+    limit: PartitionLimit,
+    partition: String,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for PartitionPermit {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+
+        // Partitions are forgotten once no upload is in flight or waiting for them, so that the
+        // slots of past partitions don't accumulate.
+        let mut partitions = self.limit.partitions.lock().expect("poisoned lock");
+        if partitions
+            .get(&self.partition)
+            .map_or(false, |semaphore| Arc::strong_count(semaphore) == 1)
+        {
+            partitions.remove(&self.partition);
+        }
+    }
+}
+
+/// Limits the number of uploads in flight for each partition.
+///
+/// The limit is applied in front of the request settings, so that the time spent waiting for an
+/// upload slot doesn't count towards the request timeout or the adaptive concurrency.
+pub struct PartitionConcurrencyService<S, Request>
+where
+    S: Service<Request>,
+{
+    inner: Buffer<S, Request>,
+    limit: PartitionLimit,
+}
+
+/// Wraps the service with a limit of `max` uploads in flight per partition, if any.
+pub fn limit_partition_concurrency<S, Request>(
+    service: S,
+    max: Option<NonZeroUsize>,
+) -> Either<PartitionConcurrencyService<S, Request>, S>
+where
+    S: Service<Request> + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<crate::Error> + Send + Sync,
+    Request: Send + 'static,
+{
+    match max {
+        Some(max) => Either::A(PartitionConcurrencyService {
+            inner: Buffer::new(service, PENDING_REQUESTS),
+            limit: PartitionLimit::new(max),
+        }),
+        None => Either::B(service),
+    }
+}
+
+impl<S, Request> Service<Request> for PartitionConcurrencyService<S, Request>
+where
+    S: Service<Request> + Send + 'static,
+    S::Future: Send,
+    S::Response: Send + 'static,
+    S::Error: Into<crate::Error> + Send + Sync,
+    Request: SidecarRequest + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = crate::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The buffer was readied by `poll_ready`, so it's the one which has to be called.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limit = self.limit.clone();
+        let partition = partition_of(request.key()).to_owned();
+
+        Box::pin(async move {
+            let _permit = limit.acquire(partition).await;
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::future::join_all;
+
+    use super::*;
+
+    #[test]
+    fn partitions_by_directory() {
+        assert_eq!(
+            partition_of("audit/dt=20210823/hour=16/archive_1.json.gz"),
+            "audit/dt=20210823/hour=16"
+        );
+        assert_eq!(partition_of("archive_1.json.gz"), "");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_in_flight_uploads_within_the_cap() {
+        let limit = PartitionLimit::new(NonZeroUsize::new(2).unwrap());
+        let max_in_flight = Arc::new(Mutex::new(HashMap::<String, usize>::new()));
+
+        let uploads = (0..10).map(|i| {
+            let limit = limit.clone();
+            let max_in_flight = Arc::clone(&max_in_flight);
+            let partition = if i % 2 == 0 { "dt=1" } else { "dt=2" };
+            async move {
+                let _permit = limit.acquire(partition.to_owned()).await;
+                let in_flight = limit.in_flight(partition);
+                {
+                    let mut max_in_flight = max_in_flight.lock().unwrap();
+                    let max = max_in_flight.entry(partition.to_owned()).or_default();
+                    *max = (*max).max(in_flight);
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        join_all(uploads).await;
+
+        // Each partition had as many uploads in flight as allowed, and no more.
+        let max_in_flight = max_in_flight.lock().unwrap();
+        assert_eq!(max_in_flight.get("dt=1"), Some(&2));
+        assert_eq!(max_in_flight.get("dt=2"), Some(&2));
+
+        // Partitions without uploads are forgotten.
+        assert!(limit.partitions.lock().unwrap().is_empty());
+        assert_eq!(limit.in_flight("dt=1"), 0);
+    }
+}
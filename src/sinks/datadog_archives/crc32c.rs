@@ -0,0 +1,22 @@
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+/// The value of the `x-goog-hash` header carrying the CRC32C checksum of `bytes`.
+///
+/// GCS expects the checksum as the base64 encoding of its big-endian bytes.
+pub fn gcs_crc32c_hash(bytes: &[u8]) -> String {
+    format!(
+        "crc32c={}",
+        BASE64_STANDARD.encode(::crc32c::crc32c(bytes).to_be_bytes())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_the_checksum_for_gcs() {
+        // The CRC32C of `123456789` is `0xE3069283`.
+        assert_eq!(gcs_crc32c_hash(b"123456789"), "crc32c=4waSgw==");
+    }
+}
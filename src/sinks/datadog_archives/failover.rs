@@ -0,0 +1,203 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use tower::Service;
+
+use crate::sinks::{
+    s3_common::service::S3Request,
+    util::retries::{RetryAction, RetryLogic},
+};
+
+/// The default number of consecutive failed writes after which the sink fails over.
+pub const DEFAULT_FAILOVER_AFTER: usize = 5;
+
+#[derive(Debug, Default)]
+struct FailoverState {
+    consecutive_failures: usize,
+    failed_over: bool,
+}
+
+/// Tracks the writes to the primary region, to decide when to fail over to the secondary one.
+#[derive(Debug)]
+pub struct Failover {
+    failover_after: usize,
+    state: Mutex<FailoverState>,
+}
+
+impl Failover {
+    pub fn new(failover_after: NonZeroUsize) -> Self {
+        Self {
+            failover_after: failover_after.get(),
+            state: Mutex::new(FailoverState::default()),
+        }
+    }
+
+    /// Whether the writes are redirected to the secondary region.
+    pub fn failed_over(&self) -> bool {
+        self.state.lock().expect("poisoned lock").failed_over
+    }
+
+    /// Records the outcome of a write to the primary region.
+    ///
+    /// Returns whether the write triggered the failover, which happens once `failover_after`
+    /// writes failed in a row.
+    pub fn record(&self, failed: bool) -> bool {
+        let mut state = self.state.lock().expect("poisoned lock");
+        if state.failed_over {
+            return false;
+        }
+        if !failed {
+            state.consecutive_failures = 0;
+            return false;
+        }
+
+        state.consecutive_failures += 1;
+        state.failed_over = state.consecutive_failures >= self.failover_after;
+        state.failed_over
+    }
+}
+
+/// Redirects the writes to a secondary region once the primary one persistently fails.
+///
+/// Responses and errors are classified with the backend's retry logic, so writes which would be
+/// retried count as failures of the primary region. Once the sink fails over, every write,
+/// including the retries of the writes which failed, goes to the secondary region until the sink is
+/// restarted.
+#[derive(Clone)]
+pub struct FailoverService<S, L> {
+    primary: S,
+    secondary: Option<S>,
+    secondary_bucket: Option<String>,
+    logic: L,
+    failover: Arc<Failover>,
+}
+
+impl<S, L> FailoverService<S, L> {
+    /// A service writing to the primary region only.
+    pub fn new(primary: S, logic: L) -> Self {
+        Self {
+            primary,
+            secondary: None,
+            secondary_bucket: None,
+            logic,
+            failover: Arc::new(Failover::new(
+                NonZeroUsize::new(DEFAULT_FAILOVER_AFTER).expect("failover threshold is non-zero"),
+            )),
+        }
+    }
+
+    /// Fails over to `secondary` after `failover_after` consecutive failed writes, writing to
+    /// `bucket` instead of the configured one if set.
+    pub fn with_secondary(
+        mut self,
+        secondary: S,
+        bucket: Option<String>,
+        failover_after: NonZeroUsize,
+    ) -> Self {
+        self.secondary = Some(secondary);
+        self.secondary_bucket = bucket;
+        self.failover = Arc::new(Failover::new(failover_after));
+        self
+    }
+}
+
+impl<S, L> Service<S3Request> for FailoverService<S, L>
+where
+    S: Service<S3Request>,
+    S::Future: Send + 'static,
+    L: RetryLogic<Response = S::Response, Error = S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.secondary {
+            Some(secondary) if self.failover.failed_over() => secondary.poll_ready(cx),
+            _ => self.primary.poll_ready(cx),
+        }
+    }
+
+    fn call(&mut self, mut request: S3Request) -> Self::Future {
+        let Some(secondary) = &mut self.secondary else {
+            return Box::pin(self.primary.call(request));
+        };
+        if self.failover.failed_over() {
+            if let Some(bucket) = &self.secondary_bucket {
+                request.bucket = bucket.clone();
+            }
+            return Box::pin(secondary.call(request));
+        }
+
+        let future = self.primary.call(request);
+        let logic = self.logic.clone();
+        let failover = Arc::clone(&self.failover);
+
+        Box::pin(async move {
+            let result = future.await;
+            let failed = match &result {
+                Ok(response) => {
+                    matches!(logic.should_retry_response(response), RetryAction::Retry(_))
+                }
+                Err(error) => logic.is_retriable_error(error),
+            };
+            if failover.record(failed) {
+                warn!(
+                    message = "Writes to the primary region persistently failed, failing over to the secondary region.",
+                    failures = failover.failover_after,
+                );
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failover(failover_after: usize) -> Failover {
+        Failover::new(NonZeroUsize::new(failover_after).unwrap())
+    }
+
+    #[test]
+    fn fails_over_after_consecutive_failures() {
+        let failover = failover(3);
+
+        assert!(!failover.record(true));
+        assert!(!failover.record(true));
+        assert!(!failover.failed_over());
+        assert!(failover.record(true));
+        assert!(failover.failed_over());
+
+        // The failover is only triggered once, and isn't reverted.
+        assert!(!failover.record(true));
+        assert!(!failover.record(false));
+        assert!(failover.failed_over());
+    }
+
+    #[test]
+    fn successes_reset_the_failures() {
+        let failover = failover(3);
+
+        for _ in 0..5 {
+            assert!(!failover.record(true));
+            assert!(!failover.record(true));
+            assert!(!failover.record(false));
+        }
+        assert!(!failover.failed_over());
+    }
+
+    #[test]
+    fn fails_over_on_the_first_failure() {
+        let failover = failover(1);
+
+        assert!(!failover.record(false));
+        assert!(failover.record(true));
+        assert!(failover.failed_over());
+    }
+}
@@ -0,0 +1,391 @@
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use http::{Method, Request, Uri};
+use hyper::Body;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use tower::Service;
+
+use super::GcsAclGrant;
+use crate::{
+    gcp::GcpAuthenticator,
+    http::{HttpClient, HttpError},
+    sinks::gcs_common::service::{GcsRequest, GcsRequestSettings, GcsResponse, GcsService},
+};
+
+/// The prefix of the name of the temporary objects written by atomic uploads.
+const TEMP_OBJECT_PREFIX: &str = ".tmp_";
+
+/// The key of the temporary object an archive is uploaded to, before being copied to its key.
+///
+/// The temporary object is written next to the archive, with its name prefixed by `.tmp_`, so that
+/// it's never mistaken for an archive.
+fn temp_key(key: &str) -> String {
+    match key.rsplit_once('/') {
+        Some((directory, name)) => format!("{}/{}{}", directory, TEMP_OBJECT_PREFIX, name),
+        None => format!("{}{}", TEMP_OBJECT_PREFIX, key),
+    }
+}
+
+/// Uploads archives to GCS.
+///
+/// When atomic uploads are enabled, each archive is first uploaded to a temporary object, which is
+/// then copied to the key of the archive and deleted. The copy only completes once the whole archive
+/// has been written, so consumers listing the bucket never observe a partially written archive.
+///
+/// When explicit ACL grants are set, they're added to the ACL of each archive once it's written.
+#[derive(Clone, Debug)]
+pub struct DatadogGcsService {
+    inner: GcsService,
+    finalizer: Option<GcsFinalizer>,
+    grants: Option<GcsGrants>,
+}
+
+impl DatadogGcsService {
+    pub const fn new(inner: GcsService) -> Self {
+        Self {
+            inner,
+            finalizer: None,
+            grants: None,
+        }
+    }
+
+    /// Adds `grants` to the ACL of the archives written to `bucket`, whose URL is `base_url`.
+    pub fn with_grants(
+        mut self,
+        client: HttpClient,
+        bucket: &str,
+        base_url: &str,
+        auth: GcpAuthenticator,
+        grants: &[GcsAclGrant],
+    ) -> Self {
+        // The ACL entries are added through the JSON API, which is served on the same host as the
+        // XML API the archives are written with.
+        let host = base_url
+            .strip_suffix(&format!("{}/", bucket))
+            .expect("base URL doesn't end with the bucket");
+        self.grants = Some(GcsGrants {
+            client,
+            objects_url: format!("{}storage/v1/b/{}/o/", host, bucket),
+            auth,
+            bodies: grants
+                .iter()
+                .map(|grant| {
+                    serde_json::json!({
+                        "entity": grant.entity,
+                        "role": grant.role.as_str(),
+                    })
+                    .to_string()
+                    .into()
+                })
+                .collect(),
+        });
+        self
+    }
+
+    /// Uploads archives through temporary objects.
+    pub fn with_atomic_uploads(
+        mut self,
+        client: HttpClient,
+        bucket: String,
+        base_url: String,
+        auth: GcpAuthenticator,
+    ) -> Self {
+        self.finalizer = Some(GcsFinalizer {
+            client,
+            bucket,
+            base_url,
+            auth,
+        });
+        self
+    }
+}
+
+/// Moves temporary objects to their final key.
+#[derive(Clone, Debug)]
+struct GcsFinalizer {
+    client: HttpClient,
+    bucket: String,
+    base_url: String,
+    auth: GcpAuthenticator,
+}
+
+impl GcsFinalizer {
+    /// Builds the requests which finalize an archive uploaded to `temp_key`, in the order in which
+    /// they are sent: the temporary object is copied to `key`, and then deleted.
+    fn build_requests(
+        &self,
+        temp_key: &str,
+        key: &str,
+        settings: &GcsRequestSettings,
+    ) -> [Request<Body>; 2] {
+        let uri = |key: &str| {
+            format!("{}{}", self.base_url, key)
+                .parse::<Uri>()
+                .expect("invalid object key")
+        };
+
+        // Copying doesn't carry the ACL and storage class of the source object over.
+        let mut copy = Request::builder()
+            .method(Method::PUT)
+            .uri(uri(key))
+            .header("content-length", "0")
+            .header(
+                "x-goog-copy-source",
+                format!("/{}/{}", self.bucket, temp_key),
+            )
+            .header("x-goog-storage-class", settings.storage_class.clone());
+        if let Some(acl) = &settings.acl {
+            copy = copy.header("x-goog-acl", acl.clone());
+        }
+        let mut copy = copy.body(Body::empty()).expect("invalid copy request");
+        self.auth.apply(&mut copy);
+
+        let mut delete = Request::builder()
+            .method(Method::DELETE)
+            .uri(uri(temp_key))
+            .body(Body::empty())
+            .expect("invalid delete request");
+        self.auth.apply(&mut delete);
+
+        [copy, delete]
+    }
+}
+
+/// Adds explicit grants to the ACL of archives.
+///
+/// GCS only applies predefined ACLs to objects as they're written, so each grant is added to the
+/// ACL of an archive once it's written, through the JSON API, which keeps the other entries.
+#[derive(Clone, Debug)]
+struct GcsGrants {
+    client: HttpClient,
+    objects_url: String,
+    auth: GcpAuthenticator,
+    /// The JSON bodies of the requests adding each grant.
+    bodies: Vec<Bytes>,
+}
+
+impl GcsGrants {
+    /// Builds the requests which add each grant to the ACL of the archive written to `key`.
+    fn build_requests(&self, key: &str) -> Vec<Request<Body>> {
+        let uri = format!(
+            "{}{}/acl",
+            self.objects_url,
+            utf8_percent_encode(key, NON_ALPHANUMERIC)
+        )
+        .parse::<Uri>()
+        .expect("invalid object key");
+
+        self.bodies
+            .iter()
+            .map(|body| {
+                let mut request = Request::builder()
+                    .method(Method::POST)
+                    .uri(uri.clone())
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.clone()))
+                    .expect("invalid grant request");
+                self.auth.apply(&mut request);
+                request
+            })
+            .collect()
+    }
+}
+
+impl DatadogGcsService {
+    /// Writes the archive of `request`, through a temporary object with atomic uploads.
+    fn upload(
+        &mut self,
+        mut request: GcsRequest,
+    ) -> BoxFuture<'static, Result<GcsResponse, HttpError>> {
+        let Some(finalizer) = &self.finalizer else {
+            return self.inner.call(request);
+        };
+
+        let key = std::mem::take(&mut request.key);
+        let temp_key = temp_key(&key);
+        let [copy, delete] = finalizer.build_requests(&temp_key, &key, &request.settings);
+        let client = finalizer.client.clone();
+        let metadata = request.metadata;
+
+        request.key = temp_key.clone();
+        let upload = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = upload.await?;
+            if !response.inner.status().is_success() {
+                return Ok(response);
+            }
+
+            let response = client.send(copy).await?;
+            if response.status().is_success() {
+                // The archive is complete at this point, so a temporary object which can't be
+                // deleted is only left behind.
+                match client.send(delete).await {
+                    Ok(delete) if delete.status().is_success() => {}
+                    Ok(delete) => warn!(
+                        message = "Failed to delete temporary object.",
+                        key = %temp_key,
+                        status = %delete.status(),
+                    ),
+                    Err(error) => warn!(
+                        message = "Failed to delete temporary object.",
+                        key = %temp_key,
+                        %error,
+                    ),
+                }
+            }
+
+            Ok(GcsResponse {
+                inner: response,
+                metadata,
+            })
+        })
+    }
+}
+
+impl Service<GcsRequest> for DatadogGcsService {
+    type Response = GcsResponse;
+    type Error = HttpError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: GcsRequest) -> Self::Future {
+        let Some(grants) = &self.grants else {
+            return self.upload(request);
+        };
+
+        let requests = grants.build_requests(&request.key);
+        let client = grants.client.clone();
+        let upload = self.upload(request);
+
+        Box::pin(async move {
+            let response = upload.await?;
+            if !response.inner.status().is_success() {
+                return Ok(response);
+            }
+
+            // An archive missing any of its grants is written again, so that they're all added.
+            for request in requests {
+                let grant = client.send(request).await?;
+                if !grant.status().is_success() {
+                    return Ok(GcsResponse {
+                        inner: grant,
+                        metadata: response.metadata,
+                    });
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::HeaderValue;
+
+    use super::*;
+    use crate::{sinks::datadog_archives::GcsAclRole, tls::TlsSettings};
+
+    #[test]
+    fn derives_temp_key_next_to_the_archive() {
+        assert_eq!(
+            temp_key("audit/dt=20210823/hour=16/archive_1.json.gz"),
+            "audit/dt=20210823/hour=16/.tmp_archive_1.json.gz"
+        );
+        assert_eq!(temp_key("archive_1.json.gz"), ".tmp_archive_1.json.gz");
+    }
+
+    #[tokio::test]
+    async fn finalizes_by_copying_then_deleting() {
+        let finalizer = GcsFinalizer {
+            client: HttpClient::new(TlsSettings::default(), &Default::default()).unwrap(),
+            bucket: "dd-logs".to_owned(),
+            base_url: "https://storage.googleapis.com/dd-logs/".to_owned(),
+            auth: GcpAuthenticator::None,
+        };
+        let settings = GcsRequestSettings {
+            acl: Some(HeaderValue::from_static("private")),
+            content_type: HeaderValue::from_static("application/x-ndjson"),
+            content_encoding: Some(HeaderValue::from_static("gzip")),
+            storage_class: HeaderValue::from_static("COLDLINE"),
+            headers: Vec::new(),
+        };
+
+        let key = "dt=20210823/hour=16/archive_1.json.gz";
+        let [copy, delete] = finalizer.build_requests(&temp_key(key), key, &settings);
+
+        assert_eq!(copy.method(), Method::PUT);
+        assert_eq!(
+            copy.uri(),
+            "https://storage.googleapis.com/dd-logs/dt=20210823/hour=16/archive_1.json.gz"
+        );
+        assert_eq!(
+            copy.headers()["x-goog-copy-source"],
+            "/dd-logs/dt=20210823/hour=16/.tmp_archive_1.json.gz"
+        );
+        assert_eq!(copy.headers()["x-goog-acl"], "private");
+        assert_eq!(copy.headers()["x-goog-storage-class"], "COLDLINE");
+
+        assert_eq!(delete.method(), Method::DELETE);
+        assert_eq!(
+            delete.uri(),
+            "https://storage.googleapis.com/dd-logs/dt=20210823/hour=16/.tmp_archive_1.json.gz"
+        );
+    }
+
+    #[tokio::test]
+    async fn adds_each_grant_to_the_acl() {
+        let client = HttpClient::new(TlsSettings::default(), &Default::default()).unwrap();
+        let service = DatadogGcsService::new(GcsService::new(
+            client.clone(),
+            "https://storage.googleapis.com/dd-logs/".to_owned(),
+            GcpAuthenticator::None,
+        ))
+        .with_grants(
+            client,
+            "dd-logs",
+            "https://storage.googleapis.com/dd-logs/",
+            GcpAuthenticator::None,
+            &[
+                GcsAclGrant {
+                    entity: "user-reader@example.com".to_owned(),
+                    role: GcsAclRole::Reader,
+                },
+                GcsAclGrant {
+                    entity: "allAuthenticatedUsers".to_owned(),
+                    role: GcsAclRole::Owner,
+                },
+            ],
+        );
+
+        let requests = service
+            .grants
+            .unwrap()
+            .build_requests("dt=20210823/hour=16/archive_1.json.gz");
+
+        let mut bodies = Vec::new();
+        for request in requests {
+            assert_eq!(request.method(), Method::POST);
+            assert_eq!(
+                request.uri(),
+                "https://storage.googleapis.com/storage/v1/b/dd-logs/o/\
+                 dt%3D20210823%2Fhour%3D16%2Farchive%5F1%2Ejson%2Egz/acl"
+            );
+            assert_eq!(request.headers()["content-type"], "application/json");
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            bodies.push(serde_json::from_slice::<serde_json::Value>(&body).unwrap());
+        }
+        assert_eq!(
+            bodies,
+            vec![
+                serde_json::json!({"entity": "user-reader@example.com", "role": "READER"}),
+                serde_json::json!({"entity": "allAuthenticatedUsers", "role": "OWNER"}),
+            ]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use tokio::time::interval;
+
+/// How often held partitions are checked against the end of their grace period.
+const CLOSE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A partition covering a period of time, after which it receives no more events but late ones.
+pub trait ClosingPartition {
+    /// The end of the period covered by the partition, if any.
+    fn end(&self) -> Option<DateTime<Utc>>;
+}
+
+/// Holds the batches of each partition until its grace period has elapsed.
+///
+/// The batches flushed by the batcher for a partition are held back and merged, until
+/// `grace_period` has elapsed past the end of the partition. The partition is then considered
+/// closed, and its events are written at once, so that late events don't end up in many small
+/// objects overlapping the others. The events held for a partition are written early when they'd
+/// otherwise exceed `max_bytes`, so that objects stay within the batch size limit. Batches of
+/// partitions which are already closed, or which don't cover a period of time, are written right
+/// away.
+pub struct GracePeriod<K, T> {
+    grace_period: chrono::Duration,
+    max_bytes: usize,
+    held: HashMap<K, HeldPartition<T>>,
+}
+
+/// The events held for a partition.
+struct HeldPartition<T> {
+    closes_at: DateTime<Utc>,
+    batch: Vec<T>,
+    bytes: usize,
+}
+
+impl<K, T> GracePeriod<K, T>
+where
+    K: ClosingPartition + Eq + Hash,
+{
+    pub fn new(grace_period: Duration, max_bytes: usize) -> Self {
+        Self {
+            grace_period: chrono::Duration::from_std(grace_period)
+                .unwrap_or_else(|_| chrono::Duration::max_value()),
+            max_bytes,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Adds a batch of `size` bytes flushed by the batcher to the events held for its partition.
+    ///
+    /// Returns the batch to write right away, which is either the batch itself if its partition
+    /// isn't held, or the events held so far for its partition if the batch doesn't fit with them.
+    pub fn push(
+        &mut self,
+        key: K,
+        batch: Vec<T>,
+        size: usize,
+        now: DateTime<Utc>,
+    ) -> Option<(K, Vec<T>)> {
+        let closes_at = match key
+            .end()
+            .and_then(|end| end.checked_add_signed(self.grace_period))
+        {
+            Some(closes_at) if now < closes_at => closes_at,
+            _ => return Some((key, batch)),
+        };
+
+        match self.held.get_mut(&key) {
+            Some(held) if held.bytes.saturating_add(size) > self.max_bytes => {
+                held.bytes = size;
+                Some((key, std::mem::replace(&mut held.batch, batch)))
+            }
+            Some(held) => {
+                held.bytes += size;
+                held.batch.extend(batch);
+                None
+            }
+            None => {
+                self.held.insert(
+                    key,
+                    HeldPartition {
+                        closes_at,
+                        batch,
+                        bytes: size,
+                    },
+                );
+                None
+            }
+        }
+    }
+
+    /// Removes the events of the partitions whose grace period has elapsed.
+    pub fn closed(&mut self, now: DateTime<Utc>) -> Vec<(K, Vec<T>)> {
+        let (closed, held) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition::<HashMap<_, _>, _>(|(_, held)| held.closes_at <= now);
+        self.held = held;
+
+        closed
+            .into_iter()
+            .map(|(key, held)| (key, held.batch))
+            .collect()
+    }
+
+    /// Removes all of the events being held.
+    pub fn drain(&mut self) -> Vec<(K, Vec<T>)> {
+        self.held
+            .drain()
+            .map(|(key, held)| (key, held.batch))
+            .collect()
+    }
+}
+
+/// Applies `grace_period` to a stream of batches, given the size of each item.
+///
+/// Events still held when the stream ends are flushed, so that none are lost on shutdown.
+pub fn grace_period_batches<'a, K, T, F>(
+    batches: impl Stream<Item = (K, Vec<T>)> + Send + 'a,
+    mut grace_period: GracePeriod<K, T>,
+    size_of: F,
+) -> impl Stream<Item = (K, Vec<T>)> + Send + 'a
+where
+    K: ClosingPartition + Eq + Hash + Send + 'a,
+    T: Send + 'a,
+    F: Fn(&T) -> usize + Send + 'a,
+{
+    let mut batches = Box::pin(batches);
+    let mut close_check = interval(CLOSE_CHECK_INTERVAL);
+
+    stream! {
+        let mut output = Vec::new();
+        let mut done = false;
+        while !done {
+            tokio::select! {
+                _ = close_check.tick() => {
+                    output.extend(grace_period.closed(Utc::now()));
+                },
+                batch = batches.next() => {
+                    match batch {
+                        None => {
+                            output.extend(grace_period.drain());
+                            done = true;
+                        }
+                        Some((key, batch)) => {
+                            let size = batch.iter().map(&size_of).sum();
+                            output.extend(grace_period.push(key, batch, size, Utc::now()));
+                        }
+                    }
+                }
+            };
+            for batch in output.drain(..) {
+                yield batch;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// A partition of the given hour of 2021-08-23, if any.
+    #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+    struct Hour(Option<u32>);
+
+    impl ClosingPartition for Hour {
+        fn end(&self) -> Option<DateTime<Utc>> {
+            self.0
+                .map(|hour| Utc.with_ymd_and_hms(2021, 8, 23, hour + 1, 0, 0).unwrap())
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2021, 8, 23, hour, minute, 0).unwrap()
+    }
+
+    /// A grace period of 10 minutes, holding up to 3 items of one byte each per partition.
+    fn grace_period() -> GracePeriod<Hour, u32> {
+        GracePeriod::new(Duration::from_secs(600), 3)
+    }
+
+    #[test]
+    fn holds_partitions_until_the_grace_period_elapses() {
+        let mut grace_period = grace_period();
+
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![1, 2], 2, at(16, 30)),
+            None
+        );
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![3], 1, at(17, 5)),
+            None
+        );
+        assert!(grace_period.closed(at(17, 0)).is_empty());
+        assert!(grace_period.closed(at(17, 9)).is_empty());
+
+        assert_eq!(
+            grace_period.closed(at(17, 10)),
+            vec![(Hour(Some(16)), vec![1, 2, 3])]
+        );
+        assert!(grace_period.drain().is_empty());
+    }
+
+    #[test]
+    fn writes_closed_partitions_right_away() {
+        let mut grace_period = grace_period();
+
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![1], 1, at(17, 10)),
+            Some((Hour(Some(16)), vec![1]))
+        );
+        assert_eq!(
+            grace_period.push(Hour(None), vec![2], 1, at(16, 30)),
+            Some((Hour(None), vec![2]))
+        );
+        assert!(grace_period.drain().is_empty());
+    }
+
+    #[test]
+    fn closes_partitions_independently() {
+        let mut grace_period = grace_period();
+
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![1], 1, at(16, 59)),
+            None
+        );
+        assert_eq!(
+            grace_period.push(Hour(Some(17)), vec![2], 1, at(17, 1)),
+            None
+        );
+
+        assert_eq!(
+            grace_period.closed(at(17, 15)),
+            vec![(Hour(Some(16)), vec![1])]
+        );
+        assert_eq!(grace_period.drain(), vec![(Hour(Some(17)), vec![2])]);
+    }
+
+    #[test]
+    fn writes_held_events_before_exceeding_the_batch_size() {
+        let mut grace_period = grace_period();
+
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![1, 2], 2, at(16, 30)),
+            None
+        );
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![3], 1, at(16, 45)),
+            None
+        );
+        assert_eq!(
+            grace_period.push(Hour(Some(16)), vec![4, 5], 2, at(17, 5)),
+            Some((Hour(Some(16)), vec![1, 2, 3]))
+        );
+
+        assert_eq!(
+            grace_period.closed(at(17, 10)),
+            vec![(Hour(Some(16)), vec![4, 5])]
+        );
+        assert!(grace_period.drain().is_empty());
+    }
+}
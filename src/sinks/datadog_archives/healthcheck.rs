@@ -0,0 +1,228 @@
+use std::{sync::Arc, time::Duration};
+
+use aws_sdk_s3::{types::ByteStream, Client as S3Client};
+use aws_smithy_client::SdkError;
+use azure_core::error::HttpError;
+use azure_storage_blobs::prelude::ContainerClient;
+use bytes::Bytes;
+use futures::FutureExt;
+use http::{StatusCode, Uri};
+use hyper::Body;
+use snafu::Snafu;
+use vector_config::configurable_component;
+
+use crate::{
+    gcp::GcpAuthenticator,
+    http::HttpClient,
+    sinks::{azure_common, gcs_common, s3_common, Healthcheck},
+};
+
+/// The name of the object written by the `write` healthcheck.
+const PROBE_OBJECT_NAME: &str = ".vector-healthcheck";
+
+/// How the healthcheck verifies access to the bucket.
+///
+/// The healthcheck can be disabled altogether with the `healthcheck.enabled` option.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthcheckMode {
+    /// Checks that the bucket exists and is accessible, by reading its metadata.
+    #[default]
+    Read,
+
+    /// Checks that objects can be written to the bucket, by uploading an empty object.
+    ///
+    /// The object is named `.vector-healthcheck`, and is written under the `key_prefix`. This is
+    /// useful with credentials that can write objects, but can't read the metadata of the bucket.
+    Write,
+}
+
+/// The healthcheck of a backend didn't complete in time.
+#[derive(Debug, Snafu)]
+#[snafu(display("Healthcheck of {} timed out after {:?}", service, timeout))]
+pub(super) struct HealthcheckTimeout {
+    service: String,
+    timeout: Duration,
+}
+
+/// Fails the healthcheck of `service` if it doesn't complete within `timeout`.
+pub(super) fn with_timeout(
+    healthcheck: Healthcheck,
+    service: &str,
+    timeout: Duration,
+) -> Healthcheck {
+    let service = service.to_owned();
+    async move {
+        tokio::time::timeout(timeout, healthcheck)
+            .await
+            .unwrap_or_else(|_| Err(HealthcheckTimeout { service, timeout }.into()))
+    }
+    .boxed()
+}
+
+/// The key of the object written by the `write` healthcheck.
+pub(super) fn probe_key(key_prefix: Option<&str>) -> String {
+    match key_prefix {
+        Some(key_prefix) if !key_prefix.is_empty() => {
+            format!("{}/{}", key_prefix.trim_end_matches('/'), PROBE_OBJECT_NAME)
+        }
+        _ => PROBE_OBJECT_NAME.to_owned(),
+    }
+}
+
+pub(super) fn build_s3_healthcheck(
+    mode: HealthcheckMode,
+    bucket: String,
+    key: String,
+    client: S3Client,
+) -> crate::Result<Healthcheck> {
+    if mode == HealthcheckMode::Read {
+        return s3_common::config::build_healthcheck(bucket, client);
+    }
+
+    let healthcheck = async move {
+        let result = client
+            .put_object()
+            .bucket(bucket.clone())
+            .key(key)
+            .body(ByteStream::from(Bytes::new()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(match error {
+                SdkError::ServiceError { err: _, raw } => match raw.http().status() {
+                    StatusCode::FORBIDDEN => {
+                        s3_common::config::HealthcheckError::InvalidCredentials.into()
+                    }
+                    StatusCode::NOT_FOUND => {
+                        s3_common::config::HealthcheckError::UnknownBucket { bucket }.into()
+                    }
+                    status => s3_common::config::HealthcheckError::UnknownStatus { status }.into(),
+                },
+                error => error.into(),
+            }),
+        }
+    };
+
+    Ok(healthcheck.boxed())
+}
+
+pub(super) fn build_gcs_healthcheck(
+    mode: HealthcheckMode,
+    bucket: String,
+    key: String,
+    client: HttpClient,
+    base_url: String,
+    auth: GcpAuthenticator,
+) -> crate::Result<Healthcheck> {
+    if mode == HealthcheckMode::Read {
+        return gcs_common::config::build_healthcheck(bucket, client, base_url, auth);
+    }
+
+    let healthcheck = async move {
+        let uri = format!("{}{}", base_url, key).parse::<Uri>()?;
+        let mut request = http::Request::put(uri)
+            .header("content-length", "0")
+            .body(Body::empty())?;
+
+        auth.apply(&mut request);
+
+        let not_found_error = gcs_common::config::GcsError::BucketNotFound { bucket }.into();
+
+        let response = client.send(request).await?;
+        gcs_common::config::healthcheck_response(response, not_found_error)
+    };
+
+    Ok(healthcheck.boxed())
+}
+
+pub(super) fn build_azure_healthcheck(
+    mode: HealthcheckMode,
+    container_name: String,
+    key: String,
+    client: Arc<ContainerClient>,
+) -> crate::Result<Healthcheck> {
+    if mode == HealthcheckMode::Read {
+        return azure_common::config::build_healthcheck(container_name, client);
+    }
+
+    let healthcheck = async move {
+        let response = client
+            .blob_client(key)
+            .put_block_blob(Bytes::new())
+            .into_future()
+            .await;
+
+        let resp: crate::Result<()> = match response {
+            Ok(_) => Ok(()),
+            Err(reason) => Err(match reason.downcast_ref::<HttpError>() {
+                Some(err) => match StatusCode::from_u16(err.status().into()) {
+                    Ok(StatusCode::FORBIDDEN) => {
+                        Box::new(azure_common::config::HealthcheckError::InvalidCredentials)
+                    }
+                    Ok(StatusCode::NOT_FOUND) => {
+                        Box::new(azure_common::config::HealthcheckError::UnknownContainer {
+                            container: container_name,
+                        })
+                    }
+                    Ok(status) => {
+                        Box::new(azure_common::config::HealthcheckError::Unknown { status })
+                    }
+                    Err(_) => "unknown status code".into(),
+                },
+                _ => reason.into(),
+            }),
+        };
+        resp
+    };
+
+    Ok(healthcheck.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_key_is_under_the_key_prefix() {
+        assert_eq!(probe_key(None), ".vector-healthcheck");
+        assert_eq!(probe_key(Some("")), ".vector-healthcheck");
+        assert_eq!(probe_key(Some("audit")), "audit/.vector-healthcheck");
+        assert_eq!(probe_key(Some("audit/")), "audit/.vector-healthcheck");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_healthchecks_which_time_out() {
+        let healthcheck = with_timeout(
+            futures::future::pending().boxed(),
+            "aws_s3",
+            Duration::from_secs(5),
+        );
+
+        let error = healthcheck.await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Healthcheck of aws_s3 timed out after 5s"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_the_result_of_healthchecks_which_complete_in_time() {
+        let passing = with_timeout(
+            futures::future::ok(()).boxed(),
+            "aws_s3",
+            Duration::from_secs(5),
+        );
+        assert!(passing.await.is_ok());
+
+        let failing = with_timeout(
+            futures::future::err("access denied".into()).boxed(),
+            "azure_blob",
+            Duration::from_secs(5),
+        );
+        assert_eq!(failing.await.unwrap_err().to_string(), "access denied");
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// Records the key of each object as its request is built, one key per line of a local file.
+///
+/// This is a diagnostic aid, to see which objects a run writes without listing the bucket. Keys are
+/// appended to the file as the requests are built, before they're sent, so that the keys of objects
+/// whose writes fail are recorded as well. The file is created if it doesn't exist, and appended to
+/// otherwise.
+#[derive(Debug)]
+pub struct KeyInventory {
+    file: Mutex<File>,
+}
+
+impl KeyInventory {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends `key` to the inventory.
+    ///
+    /// Failing to record a key doesn't affect the write of the object, and is only logged.
+    pub fn record(&self, key: &str) {
+        let mut file = self.file.lock().expect("poisoned lock");
+        if let Err(error) = writeln!(file, "{}", key) {
+            warn!(
+                message = "Couldn't record the key of the object in the inventory.",
+                key,
+                %error,
+                internal_log_rate_limit = true,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::temp_file;
+
+    #[test]
+    fn records_keys_on_their_own_line() {
+        let path = temp_file();
+        let inventory = KeyInventory::open(&path).unwrap();
+
+        inventory.record("dt=20210823/hour=16/archive_1.json.gz");
+        inventory.record("dt=20210823/hour=17/archive_2.json.gz");
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "dt=20210823/hour=16/archive_1.json.gz\ndt=20210823/hour=17/archive_2.json.gz\n"
+        );
+    }
+
+    #[test]
+    fn appends_to_an_existing_inventory() {
+        let path = temp_file();
+        fs::write(&path, "dt=20210823/hour=15/archive_0.json.gz\n").unwrap();
+
+        KeyInventory::open(&path)
+            .unwrap()
+            .record("dt=20210823/hour=16/archive_1.json.gz");
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "dt=20210823/hour=15/archive_0.json.gz\ndt=20210823/hour=16/archive_1.json.gz\n"
+        );
+    }
+}
@@ -0,0 +1,7697 @@
+// NOTE: We intentionally do not assert/verify that `datadog_archives` meets the component specification because it
+// derives all of its capabilities from existing sink implementations which themselves are tested. We probably _should_
+// also verify it here, but for now, this is a punt to avoid having to add a bunch of specific integration tests that
+// exercise all possible configurations of the sink.
+
+mod adaptive;
+mod aggregate;
+mod audit;
+mod buffer_limit;
+mod carry_over;
+mod compressed_cap;
+mod concurrency;
+mod crc32c;
+mod failover;
+mod gcs;
+mod grace_period;
+mod healthcheck;
+mod inventory;
+mod partition_cap;
+mod partitioner;
+mod region;
+mod shutdown_order;
+mod sidecar;
+mod sink;
+mod success_marker;
+mod tee;
+mod ulid;
+
+pub use adaptive::AdaptiveBatchSizeConfig;
+pub use healthcheck::HealthcheckMode;
+pub use partitioner::{FutureTimestamps, PartitionFallback, PartitionTime, SourceIndexConfig};
+pub use sink::BatchSizing;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    io::{self, Write},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use azure_storage_blobs::prelude::ContainerClient;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use codecs::{
+    encoding::Framer, JsonSerializerConfig, NewlineDelimitedEncoder, RawMessageSerializerConfig,
+};
+use futures::{future, FutureExt, TryFutureExt};
+use goauth::scopes::Scope;
+use http::header::{HeaderName, HeaderValue, CONTENT_DISPOSITION, CONTENT_LANGUAGE};
+use http::Uri;
+use lookup::{event_path, metadata_path, PathPrefix};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use snafu::Snafu;
+use tokio_util::codec::Encoder as _;
+use tower::ServiceBuilder;
+use uuid::Uuid;
+use vector_common::{request_metadata::RequestMetadata, TimeZone};
+use vector_config::{configurable_component, NamedComponent};
+use vector_core::{
+    config::{log_schema, AcknowledgementsConfig},
+    event::{Event, EventFinalizers, Finalizable, LogEvent, Value},
+    internal_event::{ComponentEventsDropped, INTENTIONAL},
+    partition::Partitioner,
+    schema, EstimatedJsonEncodedSizeOf,
+};
+use vrl::value::Kind;
+
+use crate::{
+    aws::{AwsAuthentication, RegionOrEndpoint},
+    codecs::{Encoder, Transformer},
+    config::{GenerateConfig, Input, SinkConfig, SinkContext},
+    gcp::{GcpAuthConfig, GcpAuthenticator},
+    http::{get_http_scheme_from_uri, HttpClient},
+    internal_events::{
+        DatadogArchivesCompressionFailed, DatadogArchivesEventEncodingFailed,
+        DatadogArchivesEventRejected, DatadogArchivesFieldCollision,
+        DatadogArchivesFutureTimestamp, DatadogArchivesNonStringMessage,
+        DatadogArchivesObjectFinalized,
+    },
+    serde::json::to_string,
+    sinks::{
+        azure_common::{
+            self,
+            config::{AzureBlobMetadata, AzureBlobRequest, AzureBlobRetryLogic},
+            service::AzureBlobService,
+        },
+        gcs_common::{
+            self,
+            config::{GcsPredefinedAcl, GcsRetryLogic, GcsStorageClass, BASE_URL},
+            service::{GcsRequest, GcsRequestSettings, GcsService},
+        },
+        s3_common::{
+            self,
+            config::{
+                create_service, S3CannedAcl, S3RetryLogic, S3ServerSideEncryption, S3StorageClass,
+            },
+            partitioner::S3PartitionKey,
+            service::{S3Metadata, S3Request, S3Service, MIN_PART_SIZE},
+        },
+        util::{
+            encoding::write_all, metadata::RequestMetadataBuilder, request_builder::EncodeResult,
+            BatchConfig, Compression, Compressor, RequestBuilder, ServiceBuilderExt,
+            SinkBatchSettings, TowerRequestConfig,
+        },
+        VectorSink,
+    },
+    template::Template,
+    tls::{TlsConfig, TlsSettings},
+};
+
+use self::{
+    adaptive::AdaptiveBatchSizeService,
+    aggregate::encode_with_manifest,
+    concurrency::limit_partition_concurrency,
+    crc32c::gcs_crc32c_hash,
+    failover::{FailoverService, DEFAULT_FAILOVER_AFTER},
+    gcs::DatadogGcsService,
+    inventory::KeyInventory,
+    partitioner::{
+        hash_bucket_width, is_future, parse_partition_date, unsortable_time_segments,
+        ArchivePartition, DatadogArchivesPartitioner,
+    },
+    region::RegionMismatch,
+    sink::DatadogArchivesSink,
+    tee::TeeSink,
+    ulid::UlidGenerator,
+};
+
+const DEFAULT_COMPRESSION: Compression = Compression::gzip_default();
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DatadogArchivesDefaultBatchSettings;
+
+/// We should avoid producing many small batches - this might slow down Log Rehydration,
+/// these values are similar with how DataDog's Log Archives work internally:
+/// batch size - 100mb
+/// batch timeout - 15min
+impl SinkBatchSettings for DatadogArchivesDefaultBatchSettings {
+    const MAX_EVENTS: Option<usize> = None;
+    const MAX_BYTES: Option<usize> = Some(100_000_000);
+    const TIMEOUT_SECS: f64 = 900.0;
+}
+/// Configuration for the `datadog_archives` sink.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DatadogArchivesSinkConfig {
+    /// The name of the object storage service to use.
+    // TODO: This should really be an enum.
+    pub service: String,
+
+    /// The names of additional object storage services to write the archives to.
+    ///
+    /// Every event is archived to `service` and to each of these services, which is useful when
+    /// migrating between services, or for redundancy. Events are only acknowledged once they have
+    /// been archived to all of the services.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "gcp_cloud_storage"))]
+    pub services: Vec<String>,
+
+    /// The name of the bucket to store the archives in.
+    pub bucket: String,
+
+    /// A prefix to apply to all object keys.
+    ///
+    /// Prefixes are useful for partitioning objects, such as by creating an object key that
+    /// stores objects under a particular directory. If using a prefix for this purpose, it must end
+    /// in `/` to act as a directory path. A trailing `/` is **not** automatically added.
+    pub key_prefix: Option<String>,
+
+    /// Whether or not to include the hostname of the Vector instance in the name of each object.
+    ///
+    /// This makes it possible to trace an archive back to the instance which wrote it, when several
+    /// instances write to the same bucket and prefix. The hostname is added before the unique
+    /// identifier in the object name, such as `archive_<hostname>_<uuid>.json.gz`.
+    #[serde(default)]
+    pub include_hostname: bool,
+
+    /// The maximum number of segments in the object keys.
+    ///
+    /// The segments of a key are the parts separated by the `key_separator`, including the
+    /// `key_prefix` and the name of the object. The longest key the configuration can produce is
+    /// checked against this limit when the sink is built, and the sink fails to start if it's
+    /// exceeded. The length of that key is always checked against the maximum key length of the
+    /// storage backends, which is 1024 bytes. The `source` of the events, with
+    /// `partition_by_source`, isn't known in advance and isn't accounted for in the length.
+    #[configurable(metadata(docs::type_unit = "segments"))]
+    pub max_key_segments: Option<NonZeroUsize>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub object_id: ObjectIdFormat,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub partition_key_casing: PartitionKeyCasing,
+
+    /// Whether or not to include a sequence number in the name of each object.
+    ///
+    /// Objects are numbered in the order in which they are built, from zero when the sink starts,
+    /// and the sequence number is added before the hostname and the unique identifier in the
+    /// object name, such as `archive_00000000000000000042_<uuid>.json.gz`. It's zero-padded so
+    /// that the objects of a partition are listed in order. A gap in the sequence indicates an
+    /// object which couldn't be written.
+    #[serde(default)]
+    pub sequence_numbers: bool,
+
+    /// Whether or not to sanitize object keys, for storage backends with restrictive key rules.
+    ///
+    /// When enabled, object keys are lowercased, and any character which isn't an ASCII letter, an
+    /// ASCII digit, or one of `/`, `-`, `_`, `.`, and `=` is replaced with `_`. This applies to
+    /// the whole key, including the `key_prefix`.
+    #[serde(default)]
+    pub sanitize_keys: bool,
+
+    /// Whether or not to collapse double slashes (`//`) in object keys into a single slash.
+    ///
+    /// When disabled, the `key_prefix` is used exactly as configured, so a prefix such as `audit/`
+    /// results in keys such as `audit//dt=20230801/hour=12/archive_<uuid>.json.gz`. This allows
+    /// for intentionally empty path segments.
+    #[serde(default = "crate::serde::default_true")]
+    pub collapse_slashes: bool,
+
+    /// The separator between the segments of object keys.
+    ///
+    /// The separator joins the `key_prefix`, the segments of the partition, and the name of each
+    /// object, for backends with a flat namespace. Doubled separators are collapsed along with
+    /// double slashes. The `key_prefix` is used as configured, and isn't split on slashes.
+    ///
+    /// Any other separator than `/` can't be used along with `max_concurrency_per_partition`,
+    /// `success_markers`, `content_disposition`, `checksums`, or `audit_writes`, which rely on the
+    /// directories of the keys.
+    #[serde(default = "default_key_separator")]
+    #[configurable(metadata(docs::examples = "_"))]
+    pub key_separator: String,
+
+    /// Whether or not to partition the archives by the `source` of the events.
+    ///
+    /// When enabled, the objects of each hour are further split into one directory per source, such
+    /// as `dt=20230801/hour=12/source=nginx/`. Events without a `source` are archived under
+    /// `source=unknown/`.
+    #[serde(default)]
+    pub partition_by_source: bool,
+
+    /// The number of hash buckets to spread the objects of each partition across.
+    ///
+    /// When set, a `bucket=NN/` segment is prepended to the directory of each object, such as
+    /// `bucket=07/dt=20230801/hour=12/`, so that the requests are evenly distributed across key
+    /// prefixes rather than all going to the prefix of the current hour. The bucket of each event is
+    /// a stable hash of its `message`, and is zero-padded to at least two digits. Each bucket is a
+    /// separate partition, so the number of objects written grows with the number of buckets.
+    #[configurable(metadata(docs::type_unit = "buckets"))]
+    pub hash_buckets: Option<NonZeroU32>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub source_index: Option<SourceIndexConfig>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub partition_by: PartitionTime,
+
+    /// The time zone to partition the objects by, such as `Europe/Paris`, or `local` for the system
+    /// time zone.
+    ///
+    /// By default, the `dt=` and `hour=` segments of the keys are in UTC. When set, they're in the
+    /// given time zone instead, so that the objects of a day are those of a local business day. The
+    /// `date` of the events is still in UTC. The grace period of the partitions is still applied as
+    /// if their hours were in UTC.
+    #[configurable(metadata(docs::examples = "Europe/Paris", docs::examples = "local"))]
+    pub partition_timezone: Option<TimeZone>,
+
+    /// The separator between the year, the month, and the day of the `dt=` segment of the keys.
+    ///
+    /// By default, the components of the date aren't separated, as in `dt=20210823`. With a
+    /// separator of `-`, the segment is `dt=2021-08-23` instead. The separator is made of `-`, `_`,
+    /// and `.` characters, so that the keys remain legal for every storage backend.
+    #[configurable(metadata(docs::examples = "-"))]
+    pub partition_date_separator: Option<String>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub partition_fallback: PartitionFallback,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub future_timestamps: FutureTimestamps,
+
+    /// How far in the future the timestamp of an event can be before `future_timestamps` applies.
+    ///
+    /// This leaves room for the clock of the sources to be slightly ahead of this one.
+    #[serde(default = "default_future_timestamp_tolerance_secs")]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub future_timestamp_tolerance_secs: u64,
+
+    /// Whether or not to write error events to separate objects.
+    ///
+    /// When enabled, events whose `status` is an error status, such as `error` or `critical`, are
+    /// written to their own objects named `errors_<uuid>.json.gz`, next to the regular archives of
+    /// the same partition. This makes it quicker to review the errors of an incident.
+    #[serde(default)]
+    pub route_errors: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+
+    /// The maximum number of objects uploaded concurrently to the same partition.
+    ///
+    /// Concurrent uploads can complete out of order, so that the objects of a partition aren't
+    /// listed in the order in which their events were received. Setting this to `1` preserves that
+    /// order within each partition, while different partitions are still uploaded concurrently. By
+    /// default, only `request.concurrency` limits the uploads.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "requests"))]
+    pub max_concurrency_per_partition: Option<NonZeroUsize>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub adaptive_batch_size: AdaptiveBatchSizeConfig,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub batch_sizing: BatchSizing,
+
+    /// The minimum number of events written to an object when the batch timeout expires.
+    ///
+    /// Batches flushed on timeout with fewer events are carried over, and merged with the next
+    /// batch of the same partition instead of being written to a near-empty object. Carried over
+    /// events are written regardless once `max_carry_over_secs` have elapsed.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "events"))]
+    pub min_events_per_object: Option<NonZeroUsize>,
+
+    /// The maximum time for which events are carried over by `min_events_per_object`.
+    #[serde(default = "default_max_carry_over_secs")]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub max_carry_over_secs: u64,
+
+    /// The maximum number of bytes of events buffered across all partitions.
+    ///
+    /// Each partition buffers its events until its batch is full or its timeout expires, so many
+    /// concurrent partitions can buffer many batches at once. Events remain buffered until the
+    /// objects holding them are written, including while they're held by
+    /// `min_events_per_object`, `aggregate_below_bytes`, or `partition_grace_period_secs`. When
+    /// this limit is reached, no more events are accepted until objects are written, which applies
+    /// backpressure to the sources. Events are measured according to `batch_sizing`. By default,
+    /// there is no limit.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    pub max_buffered_bytes: Option<NonZeroUsize>,
+
+    /// The maximum number of partitions buffered at once.
+    ///
+    /// Each partition buffers its events in a batch of its own until the batch is flushed. Once
+    /// this limit is reached, events of any other partition are written to a catch-all `overflow`
+    /// partition instead, and a warning is logged. This guards against partitioning by a field
+    /// with unexpectedly high cardinality. By default, there is no limit.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "partitions"))]
+    pub max_open_partitions: Option<NonZeroUsize>,
+
+    /// The size below which batches are aggregated with those of other partitions.
+    ///
+    /// Partitions receiving few events are otherwise written to tiny objects, one per partition and
+    /// per batch timeout. Batches smaller than this, which are flushed at the same time, are instead
+    /// written together to a single object in the directory shared by their partitions, until they
+    /// reach this size together. The first line of these objects is a manifest listing their
+    /// partitions, with the offset and the length of the records of each partition, in bytes of
+    /// the uncompressed object following the manifest. Batches are measured according to
+    /// `batch_sizing`. By default, batches are never aggregated.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    pub aggregate_below_bytes: Option<NonZeroUsize>,
+
+    /// The time to wait past the end of each partition before writing its events.
+    ///
+    /// When set, the events of each partition are held until its hour has ended and this grace
+    /// period has elapsed, and are then written together, in objects of up to `batch.max_bytes`.
+    /// This avoids writing late events to many small objects overlapping the others, but holds up
+    /// to an hour of events in memory, which counts against `max_buffered_bytes`. Late events of
+    /// partitions which are already closed are written right away. By default, events are written
+    /// as soon as their batch is flushed.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub partition_grace_period_secs: Option<u64>,
+
+    /// Whether or not to write the objects flushed on shutdown in the order of their partition.
+    ///
+    /// On shutdown, the events still buffered are flushed for all partitions at once, in an
+    /// arbitrary order. When enabled, they're written in the order of the hour of their partition
+    /// instead, so that the last objects written are those of the most recent partitions, which
+    /// consumers tailing the bucket in creation order expect. Partitions without a date are written
+    /// first. The objects flushed on shutdown are written one at a time, so that they also
+    /// complete in that order, at the cost of a slower shutdown.
+    #[serde(default)]
+    pub ordered_shutdown: bool,
+
+    /// The maximum size of each object once compressed.
+    ///
+    /// Batches are limited by their size before compression, but some storage pipelines are
+    /// rather limited by the size of the objects as they're written. When set, a batch whose
+    /// compressed payload exceeds this size is split in two, and each half is compressed again,
+    /// until all of the objects are within the limit. A single event which exceeds it on its own is
+    /// still written. This holds a copy of each batch while it's encoded, and skips the sequence
+    /// number of each discarded payload when `sequence_numbers` is enabled.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 10000000))]
+    pub max_compressed_bytes: Option<NonZeroUsize>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub healthcheck_mode: HealthcheckMode,
+
+    /// The time allowed for the healthcheck of each backend to complete, in seconds.
+    ///
+    /// A healthcheck which doesn't complete in time fails, naming the backend that timed out. This
+    /// keeps a hung endpoint from stalling the healthcheck until the global healthcheck timeout.
+    #[serde(default = "default_healthcheck_timeout_secs")]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub healthcheck_timeout_secs: u64,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub aws_s3: Option<S3Config>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub azure_blob: Option<AzureBlobConfig>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub gcp_cloud_storage: Option<GcsConfig>,
+
+    /// Whether or not to accept unknown fields in the `aws_s3`, `azure_blob`, and
+    /// `gcp_cloud_storage` options.
+    ///
+    /// By default, the sink fails to build if these options have a field this version of Vector
+    /// doesn't know of, which catches typos. When enabled, such fields are ignored with a warning
+    /// instead, so that a configuration using options added in a later version can still be
+    /// loaded by older versions. The other options of the sink are always checked strictly.
+    #[serde(default)]
+    pub lenient_parsing: bool,
+
+    #[configurable(derived)]
+    tls: Option<TlsConfig>,
+
+    /// Whether or not to terminate the last record of each object with a newline.
+    ///
+    /// Records are always separated by newlines, but by default the final record of an object is not
+    /// followed by one. Some strict newline-delimited JSON parsers require the trailing newline.
+    #[serde(default)]
+    pub trailing_newline: bool,
+
+    /// Whether or not to start each object with a header line holding its number of records.
+    ///
+    /// The header is a line of its own before the records, such as `{"header":{"count":42}}`, so
+    /// that consumers can check that they read every record of an object. It's the only line
+    /// with a top-level `header` field. The records are buffered uncompressed until the object is
+    /// complete. Only applies to the `datadog` and `minimal_dd` formats. In aggregated objects,
+    /// the records of each partition have a header of their own, after the manifest.
+    #[serde(default)]
+    pub count_header: bool,
+
+    /// Whether or not to pretty-print the archived events as JSON, over multiple lines.
+    ///
+    /// This makes small archives easier to inspect by hand, but makes them larger. Pretty-printed
+    /// records are still separated by newlines, but span multiple lines, so archives written this
+    /// way aren't newline-delimited JSON, and can't be rehydrated by Datadog. This has no effect
+    /// with the `passthrough` format.
+    #[serde(default)]
+    pub pretty_json: bool,
+
+    /// Whether or not to encode integers outside of the safe JSON range as strings.
+    ///
+    /// Many JSON consumers parse numbers as double-precision floats, which silently lose precision
+    /// for integers beyond 2^53. When enabled, any such integer is written as a string instead, so
+    /// that its exact value survives the round-trip.
+    #[serde(default)]
+    pub stringify_large_integers: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub tags_format: TagsFormat,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub tags_input_format: TagsInputFormat,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub host_mapping: HostMapping,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub field_collision: FieldCollision,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub existing_attributes: ExistingAttributes,
+
+    /// The maximum number of custom attributes of each event.
+    ///
+    /// Custom attributes are the fields of the events which are moved under `attributes`. Events
+    /// with more attributes are handled according to `attributes_overflow`. By default, there is no
+    /// limit.
+    #[configurable(metadata(docs::type_unit = "attributes"))]
+    pub max_attributes: Option<NonZeroUsize>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub attributes_overflow: AttributesOverflow,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub non_string_message: NonStringMessage,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub encode_failure: EncodeFailure,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub value_fallback: ValueFallback,
+
+    /// The field to deduplicate the events of each object by.
+    ///
+    /// When set, only the first event of each object with a given value of this field is archived,
+    /// and the following ones are dropped. Events without the field are all archived. Dropped
+    /// events are acknowledged along with the rest of their object. Events are only deduplicated
+    /// within an object, and not across objects.
+    #[configurable(metadata(docs::examples = "message", docs::examples = "request_id"))]
+    pub dedup_by: Option<String>,
+
+    /// Whether to report each event rejected from its archive, along with a sample of the event.
+    ///
+    /// Events which are dropped because they can't be reshaped or encoded are reported with the
+    /// reason they were rejected, and their first kilobyte as JSON, in addition to the usual error.
+    /// The reports are internal log events, which can be routed elsewhere with the `internal_logs`
+    /// source. Each rejected event is copied before being archived, which is costly.
+    #[serde(default)]
+    pub emit_rejected_events: bool,
+
+    /// Whether to report a rollup of each object as it's finalized.
+    ///
+    /// Each object written is counted, along with its events and its size before and after
+    /// compression, in the `datadog_archives_objects_total`, `datadog_archives_object_events_total`,
+    /// `datadog_archives_object_uncompressed_bytes_total` and
+    /// `datadog_archives_object_compressed_bytes_total` internal metrics. The metrics are labeled with
+    /// the `partition` of the object, without its `dt` and `hour` segments, so that their cardinality
+    /// is bounded by the other partitions.
+    #[serde(default)]
+    pub emit_object_rollups: bool,
+
+    /// Whether to log each archive written, for an audit trail of the archives.
+    ///
+    /// Once an archive is written, an `Object written.` internal log is emitted with its `key`, its
+    /// `partition`, its number of `events`, and the `status` its events are finalized with. The
+    /// logs can be routed with the `internal_logs` source. Checksum sidecars and success markers
+    /// aren't logged.
+    #[serde(default)]
+    pub audit_writes: bool,
+
+    /// The path of a local file to record the key of each object written in.
+    ///
+    /// This is a diagnostic aid for test environments, to see which objects a run writes without
+    /// inspecting the bucket. Each key is appended on a line of its own as the request writing the
+    /// object is built, so that the keys of objects whose writes fail are recorded as well. This
+    /// includes the keys of checksum sidecars and success markers. The file is created if it
+    /// doesn't exist, and is never truncated.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "/tmp/datadog_archives_inventory.log"))]
+    pub inventory_path: Option<PathBuf>,
+
+    /// Whether to stamp each object with the size of its payload before compression, in bytes.
+    ///
+    /// The size lets consumers allocate the buffer to decompress an object into up front. For S3
+    /// and Azure Blob Storage, this sets the `uncompressed_size` metadata of the objects. For GCS,
+    /// this sets their `uncompressed-size` custom metadata.
+    #[serde(default)]
+    pub uncompressed_size_metadata: bool,
+
+    /// The field to take the `date` of each event from, in preference to its timestamp.
+    ///
+    /// This is useful when events carry both the time at which they were ingested and the time at
+    /// which they happened. The field must hold a timestamp, and is removed from the event. When it's
+    /// missing, or doesn't hold a timestamp, the `date` is taken from the timestamp of the event as
+    /// usual, which is otherwise kept as an attribute. Objects are still partitioned by the
+    /// timestamp of the events.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "event_time"))]
+    pub timestamp_field: Option<String>,
+
+    /// The field to which the key of the object each event is written to is added.
+    ///
+    /// This makes the archives self-describing, since each record carries the key of its object.
+    /// With the `datadog` format, the field is archived within the `attributes`. It's ignored by
+    /// the `passthrough` format. With `uncompressed_fallback`, records of archives written
+    /// uncompressed still carry the key with the `.gz` extension.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "_archive_key"))]
+    pub object_key_field: Option<String>,
+
+    /// The interval, in seconds, at which the random part of the event IDs is re-randomized.
+    ///
+    /// Event IDs are made of a timestamp, of random bytes, and of a sequence number. By default, the
+    /// random bytes are generated once at startup. When set, they are re-randomized once per
+    /// interval, which keeps the IDs of long-lived instances well-distributed.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub id_rotation_interval_secs: Option<NonZeroU64>,
+
+    /// A list of fields whose values are redacted before being archived.
+    ///
+    /// The value of each matching field is replaced with `[REDACTED]`. Fields are matched by their
+    /// path, before being reshaped, and reserved attributes such as `message` can be redacted too. A
+    /// trailing `*` matches any field whose path starts with what precedes it.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "password"))]
+    #[configurable(metadata(docs::examples = "message.authorization"))]
+    #[configurable(metadata(docs::examples = "secret_*"))]
+    pub redact_fields: Vec<String>,
+
+    /// Default values of reserved attributes, for events which lack them.
+    ///
+    /// Rehydration works best when reserved attributes such as `service` and `source` are always
+    /// present. When an event lacks one of these attributes once reshaped, or when it's `null` or an
+    /// empty string, it's set to the configured value. Events which have the attribute are left
+    /// untouched. Only the reserved attributes other than `_id` and `date`, which are always set,
+    /// can be given a default.
+    #[serde(default)]
+    #[configurable(metadata(
+        docs::additional_props_description = "The default value of a reserved attribute."
+    ))]
+    #[configurable(metadata(docs::examples = "reserved_attribute_defaults_examples()"))]
+    pub reserved_attribute_defaults: BTreeMap<String, String>,
+
+    /// The statuses to normalize the `status` reserved attribute of the events into.
+    ///
+    /// Sources often use their own severities, such as `WARNING`, `fatal`, or syslog levels like
+    /// `3`, which Datadog doesn't recognize once rehydrated. When the `status` of an event, as a
+    /// string, is one of the configured values, it's replaced with the matching Datadog status.
+    /// Other values are left as is. The mapping is case-sensitive, and applies before the default
+    /// of the `status`, if any, is set.
+    #[serde(default)]
+    #[configurable(metadata(
+        docs::additional_props_description = "The Datadog status the value is normalized into."
+    ))]
+    #[configurable(metadata(docs::examples = "status_mapping_examples()"))]
+    pub status_mapping: BTreeMap<String, DatadogStatus>,
+
+    /// Whether or not to stamp each record with the version of the schema it was reshaped into.
+    ///
+    /// The version is added as the `_schema_version` top-level field of each record. It's managed by
+    /// the sink, and changes whenever the way events are reshaped changes, so that consumers can
+    /// tell how to migrate old archives. A `_schema_version` field of the events themselves is
+    /// archived within the `attributes`, unless the format is `minimal_dd`, where it's overwritten.
+    #[serde(default)]
+    pub include_schema_version: bool,
+
+    /// Whether or not to stamp each object with a hash of the sink configuration.
+    ///
+    /// The SHA-256 hash of the configuration is added to the object metadata, under the
+    /// `vector-config-hash` key. This makes it possible to tell which version of the configuration
+    /// produced an archive. The hash only depends on the configuration of this sink.
+    #[serde(default)]
+    pub include_config_hash: bool,
+
+    /// Whether or not to set the `Content-Disposition` of each object.
+    ///
+    /// When enabled, objects are written with `Content-Disposition: attachment`, and with the name
+    /// of the object as the filename, such as `archive_<uuid>.json.gz`. This makes browsers save the
+    /// archives under their own name when they are downloaded directly.
+    #[serde(default)]
+    pub content_disposition: bool,
+
+    /// The `Content-Language` of each object.
+    ///
+    /// The language of the events, such as `en-US`, for archives of events written in a given
+    /// language. This is only used as metadata of the objects, and doesn't change their content.
+    #[configurable(metadata(docs::examples = "en-US"))]
+    #[configurable(metadata(docs::examples = "de, en"))]
+    pub content_language: Option<String>,
+
+    /// Whether to stamp each object with the time of its latest event.
+    ///
+    /// On GCS, this sets the `customTime` of the objects, which lifecycle rules can act on. On S3
+    /// and Azure Blob Storage, this sets the `custom_time` metadata of the objects. Retention can
+    /// then be based on the time of the events rather than on the time at which the objects were
+    /// written. Objects whose events have no timestamp aren't stamped.
+    #[serde(default)]
+    pub custom_time: bool,
+
+    /// Whether or not to write archives uncompressed when their compression fails.
+    ///
+    /// Compression rarely fails, but can when memory runs out while compressing a large batch.
+    /// When enabled, such a batch is written uncompressed instead of being failed, to an object
+    /// without the `.gz` extension and without a `Content-Encoding`. This buffers each archive in
+    /// full before compressing it, which uses more memory.
+    #[serde(default)]
+    pub uncompressed_fallback: bool,
+
+    /// The expected ratio of the compressed size of an archive to the size of its events.
+    ///
+    /// The buffers archives are encoded and compressed into are allocated upfront, from the
+    /// estimated JSON size of their events, scaled by this ratio once compressed. This avoids
+    /// reallocating and copying them repeatedly as they grow, which is costly for large batches. A
+    /// larger ratio allocates more memory than needed for archives which compress well, and a
+    /// smaller one reallocates more often for archives which don't. The ratio is clamped between
+    /// `0` and `1`.
+    #[serde(default = "default_compression_presize_ratio")]
+    #[configurable(metadata(docs::examples = 0.2))]
+    pub compression_presize_ratio: f64,
+
+    /// Whether or not to write the checksum of each archive to a sidecar object.
+    ///
+    /// When enabled, each archive is followed by an object with the same key and a `.sha256`
+    /// extension, holding the hex-encoded SHA-256 of the archive as it was written, in the format of
+    /// `sha256sum`. This makes it possible to verify the integrity of the archives independently of
+    /// the storage backend.
+    #[serde(default)]
+    pub checksums: bool,
+
+    /// Whether or not to write a `_SUCCESS` marker object to each partition once it's complete.
+    ///
+    /// Data lake engines, such as Hive and Spark, look for these markers to know when a partition
+    /// can be read. A partition is complete once an archive is written for a later hour, and its
+    /// marker is then written as an empty object named `_SUCCESS` in its directory. Partitions
+    /// receiving late events are marked again at the next hour. Partitions written with the
+    /// `literal` partition fallback are never marked.
+    ///
+    /// Requests are sent concurrently, so a marker may be written before the last archives of its
+    /// partition are complete.
+    #[serde(default)]
+    pub success_markers: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub envelope: Option<ArchiveEnvelopeConfig>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub format: ArchiveFormat,
+
+    #[configurable(derived)]
+    #[serde(
+        default,
+        skip_serializing_if = "crate::serde::skip_serializing_if_default"
+    )]
+    pub encoding: Transformer,
+
+    #[configurable(derived)]
+    #[serde(
+        default,
+        deserialize_with = "crate::serde::bool_or_struct",
+        skip_serializing_if = "crate::serde::skip_serializing_if_default"
+    )]
+    acknowledgements: AcknowledgementsConfig,
+}
+
+/// The format of the unique identifier in the name of each object.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectIdFormat {
+    /// A random UUID (version 4), such as `archive_3f8f1e4a-7c57-4e0a-8b9a-2c0d0b2c3e6f.json.gz`.
+    #[default]
+    Uuid,
+
+    /// A [ULID][ulid], such as `archive_01H7ZK2Q4X3M5N6P7R8S9T0V1W.json.gz`.
+    ///
+    /// ULIDs start with the time at which the object was written, so the objects of a partition
+    /// are listed in the order in which they were written.
+    ///
+    /// [ulid]: https://github.com/ulid/spec
+    Ulid,
+}
+
+/// The casing of the names of the `dt` and `hour` segments of the object keys.
+///
+/// The casing is applied to the segments of the whole key, after it's sanitized with
+/// `sanitize_keys`.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionKeyCasing {
+    /// The segments are named in lowercase, such as `dt=20230801/hour=12/`.
+    #[default]
+    Lowercase,
+
+    /// The segments are named in uppercase, such as `DT=20230801/HOUR=12/`, as expected by some
+    /// Hive-style consumers.
+    Uppercase,
+}
+
+/// The format of the archived events.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    /// Events are reshaped into the Datadog log archive format.
+    ///
+    /// This is required for the archives to be rehydrated by Datadog.
+    #[default]
+    Datadog,
+
+    /// Events are archived as-is, as newline-delimited JSON.
+    ///
+    /// Only the `encoding` options are applied to the events. Archives written in this format
+    /// can't be rehydrated by Datadog.
+    Verbatim,
+
+    /// The `message` of each event is archived as raw bytes, one per line.
+    ///
+    /// This is meant for events from byte-oriented sources, whose `message` already is the payload
+    /// to archive. The rest of the event is discarded, and objects are named with a `.log.gz`
+    /// extension. Archives written in this format can't be rehydrated by Datadog.
+    Passthrough,
+
+    /// Events are archived as-is, with only the `_id` and `date` attributes added.
+    ///
+    /// The `_id` and `date` attributes are generated as in the `datadog` format, but the rest of the
+    /// event is left untouched: `message` and `host` aren't moved, and custom attributes aren't
+    /// nested under `attributes`. The `timestamp` is kept alongside `date`.
+    MinimalDd,
+}
+
+/// The format of the `tags` reserved attribute.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagsFormat {
+    /// Tags are archived as they are, usually as an array of `key:value` strings.
+    #[default]
+    Array,
+
+    /// Tags are archived as an object, such as `{"env": "prod"}` for `["env:prod"]`.
+    ///
+    /// Tags without a value, such as `beta`, are mapped to an empty string. If any of the tags isn't
+    /// a string, or has an empty key, the tags are archived as they are.
+    Map,
+}
+
+/// The format of the `tags` of the events, as received by the sink.
+///
+/// Datadog expects the `tags` to be an array of `key:value` strings. Tags in another format are
+/// converted into such an array, before `tags_format` applies.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagsInputFormat {
+    /// Tags are expected to be an array of `key:value` strings, and are archived as they are.
+    #[default]
+    Array,
+
+    /// Tags are expected to be an object, such as `{"env": "prod"}`, which is converted into an
+    /// array, such as `["env:prod"]`.
+    ///
+    /// Tags whose value is empty or `null` are mapped to their key alone. Tags which aren't an
+    /// object are archived as they are.
+    Map,
+
+    /// The format of the tags is detected for each event.
+    ///
+    /// Objects are converted as with `map`, and strings of comma-separated tags, such as
+    /// `env:prod,team:logs`, are split into an array. Arrays are archived as they are.
+    Auto,
+}
+
+impl TagsInputFormat {
+    /// Converts the tags of an event into an array of `key:value` strings, or returns `None` if
+    /// they're archived as they are.
+    fn to_array(self, tags: &Value) -> Option<Value> {
+        match (self, tags) {
+            (Self::Map | Self::Auto, Value::Object(map)) => Some(
+                map.iter()
+                    .map(|(key, value)| match value {
+                        Value::Null => Value::from(key.as_str()),
+                        Value::Bytes(bytes) if bytes.is_empty() => Value::from(key.as_str()),
+                        value => Value::from(format!("{}:{}", key, value.to_string_lossy())),
+                    })
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            (Self::Auto, Value::Bytes(bytes)) => Some(
+                String::from_utf8_lossy(bytes)
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(Value::from)
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// How the host of each event is mapped to the archived event.
+///
+/// The host is read from the field with the `host` meaning, or from the `host_key` of the Global Log
+/// Schema.
+#[configurable_component]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+#[configurable(metadata(docs::enum_tag_description = "How the host of each event is mapped."))]
+pub enum HostMapping {
+    /// The host is moved to the `host` reserved attribute.
+    #[default]
+    Move,
+
+    /// The host is copied to the `host` reserved attribute, and kept at its original location.
+    ///
+    /// A host under a path other than `host` is then also archived within the `attributes`.
+    Copy,
+
+    /// The host is moved to a custom key, and archived within the `attributes`.
+    ///
+    /// The `host` reserved attribute is then left unset.
+    Rename {
+        /// The key to which the host is moved, such as `hostname`.
+        #[configurable(metadata(docs::examples = "hostname"))]
+        key: String,
+    },
+}
+
+/// How a reserved attribute set from a field with a meaning, such as `host`, is handled when the
+/// event already has a field at the path of that attribute.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldCollision {
+    /// The existing field is overwritten.
+    #[default]
+    Overwrite,
+
+    /// The existing field is kept, and the field with the meaning is left at its original location.
+    ///
+    /// Unless it's a reserved attribute itself, the field with the meaning is then archived within
+    /// the `attributes`.
+    KeepExisting,
+
+    /// The event is dropped, and an error is reported.
+    Error,
+}
+
+/// How a pre-existing `attributes` field of an event is handled.
+///
+/// The custom attributes of each event are nested under the `attributes` field, which the event
+/// may already have, such as when it was received from another Datadog Archives pipeline.
+#[configurable_component]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+#[configurable(metadata(
+    docs::enum_tag_description = "How a pre-existing `attributes` field is handled."
+))]
+pub enum ExistingAttributes {
+    /// The pre-existing field is discarded, and replaced by the custom attributes.
+    Overwrite,
+
+    /// The fields of the pre-existing object are merged with the custom attributes.
+    ///
+    /// The other fields of the event take precedence over those of the pre-existing object with
+    /// the same key. A pre-existing field which isn't an object is nested under `attributes`, as it
+    /// is with `nest_under`.
+    Merge,
+
+    /// The pre-existing field is nested as a custom attribute of its own.
+    ///
+    /// This is the default, with the field being archived under `attributes.attributes`.
+    NestUnder {
+        /// The key of the custom attribute under which the pre-existing field is nested.
+        ///
+        /// It overwrites any other field of the event with the same key.
+        #[configurable(metadata(docs::examples = "original_attributes"))]
+        key: String,
+    },
+}
+
+impl Default for ExistingAttributes {
+    fn default() -> Self {
+        Self::NestUnder {
+            key: "attributes".to_string(),
+        }
+    }
+}
+
+/// How the custom attributes of an event beyond `max_attributes` are handled.
+///
+/// Attributes are kept in alphabetical order, so the same attributes are kept for events with the
+/// same fields.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributesOverflow {
+    /// The attributes beyond the limit are dropped.
+    #[default]
+    Drop,
+
+    /// The attributes beyond the limit are collapsed into a single `_overflow` attribute.
+    ///
+    /// The `_overflow` attribute holds the collapsed attributes, encoded as a JSON object, and
+    /// counts towards the limit.
+    Collapse,
+}
+
+/// How an event whose `message` isn't a string is handled.
+///
+/// This applies to the `message` of the events once it's moved to the `message` reserved
+/// attribute. A `message` which is `null` is left as is.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NonStringMessage {
+    /// The `message` is archived as is.
+    #[default]
+    Leave,
+
+    /// The `message` is converted into a string.
+    ///
+    /// Objects and arrays are encoded as JSON, and other values are formatted as text.
+    Stringify,
+
+    /// The event is dropped, and an error is reported.
+    Error,
+}
+
+/// How an event which can't be encoded is handled.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodeFailure {
+    /// The whole object fails, and none of its events are archived.
+    #[default]
+    FailBatch,
+
+    /// The event is dropped, and an error is reported, while the rest of the object is archived.
+    ///
+    /// The event is acknowledged along with the rest of the object.
+    SkipEvent,
+}
+
+/// How values which JSON can't represent faithfully are encoded.
+///
+/// These are infinite floats, which are otherwise encoded as `null`, and bytes which aren't valid
+/// UTF-8, whose invalid sequences are otherwise replaced. The policy is applied to each value of
+/// the events, at any depth, right before they're encoded. It doesn't apply to the `passthrough`
+/// format.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueFallback {
+    /// The values are encoded as is.
+    #[default]
+    Leave,
+
+    /// The values are removed from their object or array.
+    DropField,
+
+    /// The values are converted into strings.
+    ///
+    /// Infinite floats become `inf` or `-inf`, and the invalid sequences of bytes are replaced.
+    Stringify,
+
+    /// Bytes are encoded as base64 strings, and infinite floats are converted into strings.
+    Base64,
+}
+
+/// A status of Datadog logs.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatadogStatus {
+    /// The `emergency` status.
+    Emergency,
+
+    /// The `alert` status.
+    Alert,
+
+    /// The `critical` status.
+    Critical,
+
+    /// The `error` status.
+    Error,
+
+    /// The `warn` status.
+    Warn,
+
+    /// The `notice` status.
+    Notice,
+
+    /// The `info` status.
+    Info,
+
+    /// The `debug` status.
+    Debug,
+
+    /// The `ok` status.
+    Ok,
+}
+
+impl DatadogStatus {
+    /// The value of the `status` reserved attribute.
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Emergency => "emergency",
+            Self::Alert => "alert",
+            Self::Critical => "critical",
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Notice => "notice",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Ok => "ok",
+        }
+    }
+}
+
+impl ArchiveFormat {
+    /// The extension of the objects, before compression.
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Datadog | Self::Verbatim | Self::MinimalDd => "json",
+            Self::Passthrough => "log",
+        }
+    }
+}
+
+const fn default_future_timestamp_tolerance_secs() -> u64 {
+    300
+}
+
+const fn default_max_carry_over_secs() -> u64 {
+    3600
+}
+
+const fn default_compression_presize_ratio() -> f64 {
+    0.2
+}
+
+fn reserved_attribute_defaults_examples() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("service".to_owned(), "unknown".to_owned()),
+        ("source".to_owned(), "vector".to_owned()),
+    ])
+}
+
+fn status_mapping_examples() -> BTreeMap<String, DatadogStatus> {
+    BTreeMap::from([
+        ("WARNING".to_owned(), DatadogStatus::Warn),
+        ("fatal".to_owned(), DatadogStatus::Critical),
+        ("3".to_owned(), DatadogStatus::Error),
+    ])
+}
+
+fn default_key_separator() -> String {
+    "/".to_owned()
+}
+
+const fn default_healthcheck_timeout_secs() -> u64 {
+    5
+}
+
+fn default_envelope_key() -> String {
+    "log".to_owned()
+}
+
+fn default_envelope_ingested_at_key() -> String {
+    "ingested_at".to_owned()
+}
+
+/// Envelope configuration.
+///
+/// When set, each reshaped event is wrapped in an envelope, such as
+/// `{"log": {...}, "ingested_at": "2023-08-01T12:00:00.000Z"}`, for downstream systems which
+/// expect it. Archives written with an envelope can't be rehydrated by Datadog.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveEnvelopeConfig {
+    /// The key under which the reshaped event is nested.
+    #[serde(default = "default_envelope_key")]
+    pub key: String,
+
+    /// The key of the time at which the event was archived.
+    #[serde(default = "default_envelope_ingested_at_key")]
+    pub ingested_at_key: String,
+}
+
+impl Default for ArchiveEnvelopeConfig {
+    fn default() -> Self {
+        Self {
+            key: default_envelope_key(),
+            ingested_at_key: default_envelope_ingested_at_key(),
+        }
+    }
+}
+
+fn default_retention_tag_key() -> String {
+    "expires".to_owned()
+}
+
+/// Retention tag configuration.
+///
+/// The tag holds the date on which the events of an object expire, computed from the date of the
+/// partition of the object. Lifecycle rules of the bucket can then key on it, so that a single
+/// bucket can hold archives with different retention periods.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionTagConfig {
+    /// The key of the tag.
+    #[serde(default = "default_retention_tag_key")]
+    pub key: String,
+
+    /// The number of days for which the events are retained.
+    ///
+    /// The value of the tag is the date of the partition plus this number of days, such as
+    /// `2021-09-22` for the `/dt=20210823/hour=16/` partition when retained for 30 days.
+    #[configurable(metadata(docs::examples = 30))]
+    pub days: NonZeroU32,
+}
+
+impl RetentionTagConfig {
+    /// The value of the tag for the objects of the given partition.
+    ///
+    /// Partitions without a date, such as those of the `literal` partition fallback, aren't tagged.
+    fn value_for(&self, partition_prefix: &str) -> Option<String> {
+        let date = partition_prefix
+            .split('/')
+            .find_map(|segment| segment.strip_prefix("dt="))
+            .and_then(parse_partition_date)?;
+        let expires = date.checked_add_signed(Duration::days(i64::from(self.days.get())))?;
+        Some(expires.format("%Y-%m-%d").to_string())
+    }
+}
+
+/// S3-specific configuration options.
+#[configurable_component]
+#[derive(Clone, Debug, Default)]
+pub struct S3Config {
+    #[serde(flatten)]
+    pub options: S3Options,
+
+    #[serde(flatten)]
+    pub region: RegionOrEndpoint,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub auth: AwsAuthentication,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub region_mismatch: RegionMismatch,
+
+    /// The size above which archives are uploaded with a multipart upload, in bytes.
+    ///
+    /// Archives larger than this are uploaded in parts of `part_size` bytes, instead of in a single
+    /// request. Each part is retried on its own, so that a failure doesn't require uploading the
+    /// whole archive again. By default, archives are always uploaded in a single request.
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 16777216))]
+    pub multipart_threshold: Option<NonZeroUsize>,
+
+    /// The size of the parts of multipart uploads, in bytes.
+    ///
+    /// It must be at least 5 MiB, which is the minimum size of the parts allowed by S3. Parts are
+    /// made larger for archives which would otherwise have more than 10,000 parts. Only applies
+    /// when `multipart_threshold` is set. Defaults to 8 MiB.
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 8388608))]
+    pub part_size: Option<NonZeroUsize>,
+
+    /// The region to write the archives to once the configured one persistently fails.
+    ///
+    /// After `failover_after` consecutive writes fail in a way which would be retried, every write,
+    /// including the retries of the failed ones, goes to this region instead, until Vector is
+    /// restarted. The same credentials are used in both regions. By default, the sink never fails
+    /// over.
+    #[configurable(metadata(docs::examples = "us-west-2"))]
+    pub failover_region: Option<String>,
+
+    /// The endpoint to write the archives to once the configured one persistently fails.
+    ///
+    /// It can be set along with, or instead of, `failover_region`. Without `failover_region`, the
+    /// configured region is used with this endpoint.
+    #[configurable(metadata(docs::examples = "http://127.0.0.0:5000/path/to/service"))]
+    #[configurable(metadata(docs::advanced))]
+    pub failover_endpoint: Option<String>,
+
+    /// The bucket to write the archives to once the sink failed over.
+    ///
+    /// The names of buckets are unique across regions, so the failover region usually holds a
+    /// replica of the bucket under another name. Defaults to `bucket`.
+    #[configurable(metadata(docs::examples = "my-bucket-replica"))]
+    pub failover_bucket: Option<String>,
+
+    /// The number of consecutive failed writes after which the sink fails over.
+    ///
+    /// Only applies when `failover_region` or `failover_endpoint` is set. Defaults to 5.
+    #[configurable(metadata(docs::type_unit = "writes"))]
+    pub failover_after: Option<NonZeroUsize>,
+
+    /// The fields which aren't known to this version of Vector.
+    ///
+    /// They're rejected when the sink is built, unless `lenient_parsing` is enabled.
+    #[serde(flatten)]
+    #[configurable(metadata(docs::hidden))]
+    pub unknown_fields: BTreeMap<String, toml::Value>,
+}
+
+/// The default size of the parts of multipart uploads.
+const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+impl S3Config {
+    /// Configures `service` to upload large archives in parts if `multipart_threshold` is set.
+    fn multipart(&self, service: S3Service) -> Result<S3Service, ConfigError> {
+        Ok(service.with_multipart(self.multipart_threshold, self.part_size()?))
+    }
+
+    /// The region to fail over to, if any.
+    fn failover_region(&self) -> Option<RegionOrEndpoint> {
+        if self.failover_region.is_none() && self.failover_endpoint.is_none() {
+            return None;
+        }
+        Some(RegionOrEndpoint {
+            region: self
+                .failover_region
+                .clone()
+                .or_else(|| self.region.region.clone()),
+            endpoint: self.failover_endpoint.clone(),
+        })
+    }
+
+    fn failover_after(&self) -> NonZeroUsize {
+        self.failover_after.unwrap_or_else(|| {
+            NonZeroUsize::new(DEFAULT_FAILOVER_AFTER).expect("failover threshold is non-zero")
+        })
+    }
+
+    /// The size of the parts of multipart uploads, which must be allowed by S3.
+    fn part_size(&self) -> Result<NonZeroUsize, ConfigError> {
+        let part_size = self.part_size.unwrap_or_else(|| {
+            NonZeroUsize::new(DEFAULT_PART_SIZE).expect("part size is non-zero")
+        });
+        if part_size.get() < MIN_PART_SIZE {
+            return Err(ConfigError::S3PartTooSmall {
+                part_size: part_size.get(),
+                min: MIN_PART_SIZE,
+            });
+        }
+        Ok(part_size)
+    }
+}
+
+/// S3-specific bucket/object options.
+#[configurable_component]
+#[derive(Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct S3Options {
+    /// Canned ACL to apply to the created objects.
+    ///
+    /// For more information, see [Canned ACL][canned_acl].
+    ///
+    /// [canned_acl]: https://docs.aws.amazon.com/AmazonS3/latest/dev/acl-overview.html#canned-acl
+    pub acl: Option<S3CannedAcl>,
+
+    /// Grants `READ`, `READ_ACP`, and `WRITE_ACP` permissions on the created objects to the named [grantee].
+    ///
+    /// This allows the grantee to read the created objects and their metadata, as well as read and
+    /// modify the ACL on the created objects.
+    ///
+    /// [grantee]: https://docs.aws.amazon.com/AmazonS3/latest/dev/acl-overview.html#specifying-grantee
+    pub grant_full_control: Option<String>,
+
+    /// Grants `READ` permissions on the created objects to the named [grantee].
+    ///
+    /// This allows the grantee to read the created objects and their metadata.
+    ///
+    /// [grantee]: https://docs.aws.amazon.com/AmazonS3/latest/dev/acl-overview.html#specifying-grantee
+    pub grant_read: Option<String>,
+
+    /// Grants `READ_ACP` permissions on the created objects to the named [grantee].
+    ///
+    /// This allows the grantee to read the ACL on the created objects.
+    ///
+    /// [grantee]: https://docs.aws.amazon.com/AmazonS3/latest/dev/acl-overview.html#specifying-grantee
+    pub grant_read_acp: Option<String>,
+
+    /// Grants `WRITE_ACP` permissions on the created objects to the named [grantee].
+    ///
+    /// This allows the grantee to modify the ACL on the created objects.
+    ///
+    /// [grantee]: https://docs.aws.amazon.com/AmazonS3/latest/dev/acl-overview.html#specifying-grantee
+    pub grant_write_acp: Option<String>,
+
+    /// The Server-side Encryption algorithm used when storing these objects.
+    pub server_side_encryption: Option<S3ServerSideEncryption>,
+
+    /// Specifies the ID of the AWS Key Management Service (AWS KMS) symmetrical customer managed
+    /// customer master key (CMK) that is used for the created objects.
+    ///
+    /// Only applies when `server_side_encryption` is configured to use KMS.
+    ///
+    /// If not specified, Amazon S3 uses the AWS managed CMK in AWS to protect the data.
+    pub ssekms_key_id: Option<String>,
+
+    /// Whether or not to use an S3 Bucket Key for the created objects.
+    ///
+    /// Bucket Keys reduce the number of requests made to AWS KMS, and therefore its cost, when
+    /// writing many objects. Only applies when `server_side_encryption` is configured to use KMS.
+    ///
+    /// For more information, see [Reducing the cost of SSE-KMS with Amazon S3 Bucket Keys][bucket_keys].
+    ///
+    /// [bucket_keys]: https://docs.aws.amazon.com/AmazonS3/latest/userguide/bucket-key.html
+    pub bucket_key_enabled: Option<bool>,
+
+    /// The storage class for the created objects.
+    ///
+    /// For more information, see [Using Amazon S3 storage classes][storage_classes].
+    ///
+    /// [storage_classes]: https://docs.aws.amazon.com/AmazonS3/latest/dev/storage-class-intro.html
+    pub storage_class: S3StorageClass,
+
+    /// The tag-set for the object.
+    #[configurable(metadata(docs::additional_props_description = "A single tag."))]
+    pub tags: Option<BTreeMap<String, String>>,
+
+    /// A tag computed from the partition of the object, added to the tag-set of the object.
+    ///
+    /// It takes precedence over any tag with the same key in `tags`.
+    pub retention_tag: Option<RetentionTagConfig>,
+
+    /// Whether or not to add the partition of each object to its user metadata.
+    ///
+    /// When enabled, the date and the hour of the partition are added as the `dt` and `hour` user
+    /// metadata, sent as the `x-amz-meta-dt` and `x-amz-meta-hour` headers, so that consumers can
+    /// filter objects on their metadata rather than parse their keys. Partitions without a date or
+    /// an hour, such as those of the `literal` partition fallback, only get the metadata they have.
+    /// Other metadata with the same keys take precedence.
+    #[serde(default)]
+    pub partition_metadata: bool,
+}
+
+/// ABS-specific configuration options.
+#[configurable_component]
+#[derive(Clone, Debug, Default)]
+pub struct AzureBlobConfig {
+    /// The Azure Blob Storage Account connection string.
+    ///
+    /// Authentication with access key is the only supported authentication method.
+    pub connection_string: String,
+
+    /// The size of the blocks large archives are uploaded in, in bytes.
+    ///
+    /// Archives larger than this are uploaded as a list of blocks, instead of in a single request.
+    /// Each block is retried on its own, so that a failure doesn't require uploading the whole
+    /// archive again. By default, archives are always uploaded in a single request.
+    #[serde(default)]
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 8388608))]
+    pub block_size: Option<NonZeroUsize>,
+
+    /// The fields which aren't known to this version of Vector.
+    ///
+    /// They're rejected when the sink is built, unless `lenient_parsing` is enabled.
+    #[serde(flatten)]
+    #[configurable(metadata(docs::hidden))]
+    pub unknown_fields: BTreeMap<String, toml::Value>,
+}
+
+/// GCS-specific configuration options.
+#[configurable_component]
+#[derive(Clone, Debug, Default)]
+pub struct GcsConfig {
+    #[configurable(derived)]
+    acl: Option<GcsPredefinedAcl>,
+
+    #[configurable(derived)]
+    storage_class: Option<GcsStorageClass>,
+
+    /// The set of metadata `key:value` pairs for the created objects.
+    ///
+    /// For more information, see [Custom metadata][custom_metadata].
+    ///
+    /// [custom_metadata]: https://cloud.google.com/storage/docs/metadata#custom-metadata
+    #[configurable(metadata(docs::additional_props_description = "A key/value pair."))]
+    metadata: Option<HashMap<String, String>>,
+
+    /// Explicit ACL grants to apply to the created objects.
+    ///
+    /// This allows granting access to specific users, service accounts, or groups. It can't be
+    /// used together with `acl`, and requires fine-grained access control on the bucket.
+    ///
+    /// GCS only applies predefined ACLs to objects as they're written, so each grant is added to
+    /// the ACL of the objects afterwards, with one more request per grant and object.
+    #[serde(default)]
+    grants: Vec<GcsAclGrant>,
+
+    /// Whether or not to make each archive appear atomically.
+    ///
+    /// When enabled, each archive is first uploaded to a temporary object next to it, named
+    /// `.tmp_archive_<uuid>.json.gz`, which is then copied to the key of the archive and deleted.
+    /// Consumers listing the bucket then never observe a partially written archive, at the cost of
+    /// two more requests per archive.
+    #[serde(default)]
+    atomic_uploads: bool,
+
+    /// Whether or not to send the CRC32C checksum of each archive along with its upload.
+    ///
+    /// When enabled, the checksum of the payload is sent in the `x-goog-hash` header, and GCS
+    /// rejects the upload if the object it received doesn't match it, so that corruption in transit
+    /// is detected and the upload retried.
+    #[serde(default)]
+    crc32c: bool,
+
+    /// The name of a Private Service Connect endpoint to write the objects through.
+    ///
+    /// When set, both the objects and the healthcheck are sent to the
+    /// `https://storage-<private_endpoint>.p.googleapis.com/` host of the endpoint, instead of the
+    /// public `https://storage.googleapis.com/` one. The name is made of 1 to 20 lowercase letters
+    /// and digits, starting with a letter.
+    ///
+    /// For more information, see [Private Service Connect][psc].
+    ///
+    /// [psc]: https://cloud.google.com/vpc/docs/configure-private-service-connect-apis
+    #[configurable(metadata(docs::examples = "archives"))]
+    private_endpoint: Option<String>,
+
+    #[serde(flatten)]
+    auth: GcpAuthConfig,
+
+    /// The fields which aren't known to this version of Vector.
+    ///
+    /// They're rejected when the sink is built, unless `lenient_parsing` is enabled.
+    #[serde(flatten)]
+    #[configurable(metadata(docs::hidden))]
+    unknown_fields: BTreeMap<String, toml::Value>,
+}
+
+impl GcsConfig {
+    /// The URL of `bucket`, on the host of the private endpoint if any.
+    fn base_url(&self, bucket: &str) -> Result<String, ConfigError> {
+        match &self.private_endpoint {
+            None => Ok(format!("{}{}/", BASE_URL, bucket)),
+            Some(endpoint) => {
+                let mut chars = endpoint.chars();
+                let valid = endpoint.len() <= 20
+                    && chars.next().map_or(false, |c| c.is_ascii_lowercase())
+                    && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+                if !valid {
+                    return Err(ConfigError::InvalidGcsPrivateEndpoint {
+                        endpoint: endpoint.clone(),
+                    });
+                }
+                Ok(format!(
+                    "https://storage-{}.p.googleapis.com/{}/",
+                    endpoint, bucket
+                ))
+            }
+        }
+    }
+
+    /// The explicit ACL grants to apply to the created objects, once checked.
+    fn acl_grants(&self) -> Result<&[GcsAclGrant], ConfigError> {
+        if self.grants.is_empty() {
+            return Ok(&[]);
+        }
+        if self.acl.is_some() {
+            return Err(ConfigError::ConflictingGcsAcl);
+        }
+
+        match self.grants.iter().find(|grant| !grant.has_valid_entity()) {
+            Some(grant) => Err(ConfigError::InvalidGcsGrant {
+                entity: grant.entity.clone(),
+            }),
+            None => Ok(&self.grants),
+        }
+    }
+}
+
+/// An explicit ACL grant for objects created in GCS.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GcsAclGrant {
+    /// The entity to grant the role to.
+    ///
+    /// This is `user-<email>` or `group-<email>` for a user, service account, or group,
+    /// `domain-<domain>` for a Google Workspace domain, `project-<team>-<project number>` for the
+    /// owners, editors, or viewers of a project, or either of `allUsers` and
+    /// `allAuthenticatedUsers`.
+    ///
+    /// For more information, see [Access control lists][gcs_acl_scopes].
+    ///
+    /// [gcs_acl_scopes]: https://cloud.google.com/storage/docs/access-control/lists#scopes
+    #[configurable(metadata(
+        docs::examples = "user-archives-reader@my-project.iam.gserviceaccount.com"
+    ))]
+    #[configurable(metadata(docs::examples = "group-security@example.com"))]
+    #[configurable(metadata(docs::examples = "allAuthenticatedUsers"))]
+    entity: String,
+
+    #[configurable(derived)]
+    role: GcsAclRole,
+}
+
+impl GcsAclGrant {
+    fn has_valid_entity(&self) -> bool {
+        matches!(self.entity.as_str(), "allUsers" | "allAuthenticatedUsers")
+            || ["user-", "group-", "domain-", "project-"]
+                .iter()
+                .any(|scope| {
+                    self.entity
+                        .strip_prefix(scope)
+                        .map_or(false, |name| !name.is_empty())
+                })
+    }
+}
+
+/// The role granted by an explicit GCS ACL grant.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GcsAclRole {
+    /// The entity can read the created objects and their metadata.
+    Reader,
+
+    /// The entity can read and modify the created objects, their metadata, and their ACL.
+    Owner,
+}
+
+impl GcsAclRole {
+    /// The name of the role in the JSON API.
+    const fn as_str(self) -> &'static str {
+        match self {
+            GcsAclRole::Reader => "READER",
+            GcsAclRole::Owner => "OWNER",
+        }
+    }
+}
+
+impl GenerateConfig for DatadogArchivesSinkConfig {
+    fn generate_config() -> toml::Value {
+        toml::Value::try_from(Self {
+            service: "".to_owned(),
+            services: Vec::new(),
+            bucket: "".to_owned(),
+            key_prefix: None,
+            include_hostname: false,
+            object_id: Default::default(),
+            partition_key_casing: Default::default(),
+            sequence_numbers: false,
+            sanitize_keys: false,
+            collapse_slashes: true,
+            key_separator: default_key_separator(),
+            partition_by_source: false,
+            hash_buckets: None,
+            source_index: None,
+            partition_by: Default::default(),
+            partition_timezone: None,
+            partition_date_separator: None,
+            partition_fallback: Default::default(),
+            future_timestamps: Default::default(),
+            future_timestamp_tolerance_secs: default_future_timestamp_tolerance_secs(),
+            route_errors: false,
+            request: TowerRequestConfig::default(),
+            max_concurrency_per_partition: None,
+            adaptive_batch_size: Default::default(),
+            batch_sizing: Default::default(),
+            min_events_per_object: None,
+            max_carry_over_secs: default_max_carry_over_secs(),
+            max_key_segments: None,
+            max_buffered_bytes: None,
+            max_open_partitions: None,
+            aggregate_below_bytes: None,
+            partition_grace_period_secs: None,
+            ordered_shutdown: false,
+            max_compressed_bytes: None,
+            healthcheck_mode: Default::default(),
+            healthcheck_timeout_secs: default_healthcheck_timeout_secs(),
+            aws_s3: None,
+            gcp_cloud_storage: None,
+            lenient_parsing: false,
+            tls: None,
+            azure_blob: None,
+            trailing_newline: false,
+            count_header: false,
+            pretty_json: false,
+            stringify_large_integers: false,
+            tags_format: Default::default(),
+            tags_input_format: Default::default(),
+            host_mapping: Default::default(),
+            field_collision: Default::default(),
+            existing_attributes: Default::default(),
+            max_attributes: None,
+            attributes_overflow: Default::default(),
+            non_string_message: Default::default(),
+            encode_failure: Default::default(),
+            value_fallback: Default::default(),
+            dedup_by: None,
+            emit_rejected_events: false,
+            emit_object_rollups: false,
+            audit_writes: false,
+            inventory_path: None,
+            uncompressed_size_metadata: false,
+            timestamp_field: None,
+            object_key_field: None,
+            id_rotation_interval_secs: None,
+            redact_fields: Vec::new(),
+            reserved_attribute_defaults: BTreeMap::new(),
+            status_mapping: BTreeMap::new(),
+            include_schema_version: false,
+            include_config_hash: false,
+            content_disposition: false,
+            content_language: None,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            checksums: false,
+            success_markers: false,
+            envelope: None,
+            format: Default::default(),
+            encoding: Default::default(),
+            acknowledgements: Default::default(),
+        })
+        .unwrap()
+    }
+}
+
+#[derive(Debug, Snafu, PartialEq)]
+enum ConfigError {
+    #[snafu(display("Unsupported service: {}", service))]
+    UnsupportedService { service: String },
+    #[snafu(display("Duplicate service: {}", service))]
+    DuplicateService { service: String },
+    #[snafu(display("Unsupported storage class: {}", storage_class))]
+    UnsupportedStorageClass { storage_class: String },
+    #[snafu(display(
+        "Bucket {} is in region {}, but the configured region is {}",
+        bucket,
+        region,
+        configured
+    ))]
+    BucketRegionMismatch {
+        bucket: String,
+        region: String,
+        configured: String,
+    },
+    #[snafu(display(
+        "S3 part size of {} bytes is less than the minimum of {}",
+        part_size,
+        min
+    ))]
+    S3PartTooSmall { part_size: usize, min: usize },
+    #[snafu(display("`{}` isn't a reserved attribute which can have a default", attribute))]
+    UnsupportedReservedAttributeDefault { attribute: String },
+    #[snafu(display(
+        "Unknown field `{}.{}`, which is either misspelled or not supported by this version of Vector. Set `lenient_parsing` to ignore it",
+        config,
+        field
+    ))]
+    UnknownField { config: &'static str, field: String },
+    #[snafu(display("GCS `acl` and `grants` can't be set at the same time"))]
+    ConflictingGcsAcl,
+    #[snafu(display(
+        "Invalid GCS grant entity `{}`, which must be `user-<email>`, `group-<email>`, \
+         `domain-<domain>`, `project-<team>-<project number>`, `allUsers`, or \
+         `allAuthenticatedUsers`",
+        entity
+    ))]
+    InvalidGcsGrant { entity: String },
+    #[snafu(display("Invalid GCS private endpoint name: {}", endpoint))]
+    InvalidGcsPrivateEndpoint { endpoint: String },
+    #[snafu(display(
+        "Object keys can be {} bytes long, more than the maximum of {}: {}",
+        length,
+        max,
+        key
+    ))]
+    ObjectKeyTooLong {
+        key: String,
+        length: usize,
+        max: usize,
+    },
+    #[snafu(display(
+        "Object keys can have {} segments, more than the maximum of {}: {}",
+        segments,
+        max,
+        key
+    ))]
+    TooManyKeySegments {
+        key: String,
+        segments: usize,
+        max: usize,
+    },
+    #[snafu(display(
+        "Invalid partition date separator, only `-`, `_`, and `.` are allowed: {:?}",
+        separator
+    ))]
+    InvalidDateSeparator { separator: String },
+    #[snafu(display(
+        "`{}` can't be used with a `key_separator` other than `/`: {:?}",
+        option,
+        separator
+    ))]
+    UnsupportedKeySeparator {
+        option: &'static str,
+        separator: String,
+    },
+    #[snafu(display("Couldn't open the inventory {:?}: {}", path, reason))]
+    InventoryUnavailable { path: PathBuf, reason: String },
+}
+
+const KEY_TEMPLATE: &str = "/dt=%Y%m%d/hour=%H/";
+
+/// Warns of the time segments of a key template which don't sort in the order of their time.
+fn check_key_template(template: &Template) {
+    for segment in unsortable_time_segments(template) {
+        warn!(
+            message = "Object keys won't be listed in the order of their time.",
+            template = %template,
+            %segment,
+        );
+    }
+}
+
+/// The attribute holding the attributes collapsed by `AttributesOverflow::Collapse`.
+const OVERFLOW_ATTRIBUTE: &str = "_overflow";
+
+/// The field holding the version of the schema of the records, with `include_schema_version`.
+const SCHEMA_VERSION_FIELD: &str = "_schema_version";
+
+/// The version of the schema the events are reshaped into.
+///
+/// It must be incremented whenever a change to `DatadogArchivesEncoding::reshape` changes the
+/// records of existing configurations.
+const SCHEMA_VERSION: i64 = 1;
+
+/// The maximum length of the object keys, which is the same for all of the storage backends.
+const MAX_KEY_LENGTH: usize = 1024;
+
+/// The hostname in the object keys previewed by `DatadogArchivesSinkConfig::preview_key`.
+const PREVIEW_HOSTNAME: &str = "hostname";
+
+/// The metadata key of the custom time of S3 and Azure Blob Storage objects.
+const CUSTOM_TIME_METADATA_KEY: &str = "custom_time";
+
+/// The metadata key of the size of the payload of S3 and Azure Blob Storage objects before
+/// compression.
+const UNCOMPRESSED_SIZE_METADATA_KEY: &str = "uncompressed_size";
+
+/// The header setting the size of the payload of GCS objects before compression.
+const GCS_UNCOMPRESSED_SIZE_HEADER: &str = "x-goog-meta-uncompressed-size";
+
+/// The header setting the custom time of GCS objects.
+const GCS_CUSTOM_TIME_HEADER: &str = "x-goog-custom-time";
+
+/// The header carrying the checksums GCS validates uploads against.
+const GCS_HASH_HEADER: &str = "x-goog-hash";
+
+/// The header setting the number of events of GCS objects.
+const GCS_EVENT_COUNT_HEADER: &str = "x-goog-meta-event-count";
+
+/// The header setting the time of the earliest event of GCS objects.
+const GCS_TIME_MIN_HEADER: &str = "x-goog-meta-time-min";
+
+/// The header setting the time of the latest event of GCS objects.
+const GCS_TIME_MAX_HEADER: &str = "x-goog-meta-time-max";
+
+impl DatadogArchivesSinkConfig {
+    /// Builds the sink, encoding the archives with `encoder` rather than the configured `format`.
+    ///
+    /// This reuses the partitioning, batching, and storage backends of the sink with a custom
+    /// encoding of the archives. The `encoding` options and the options of the `datadog` format
+    /// only apply to the built-in encoders, and are up to `encoder` otherwise.
+    pub async fn build_with_encoder(
+        &self,
+        cx: SinkContext,
+        encoder: Arc<dyn ArchiveEncoder>,
+    ) -> crate::Result<(VectorSink, super::Healthcheck)> {
+        self.build_sink(cx, Some(encoder)).await
+    }
+
+    async fn build_sink(
+        &self,
+        cx: SinkContext,
+        encoder: Option<Arc<dyn ArchiveEncoder>>,
+    ) -> crate::Result<(VectorSink, super::Healthcheck)> {
+        self.check_unknown_fields()?;
+        self.check_reserved_attribute_defaults()?;
+        self.check_date_separator()?;
+        self.check_key_separator()?;
+        check_key_template(&self.key_template());
+        self.check_object_keys(&self.build_object_key_generator()?)?;
+
+        if self.services.is_empty() {
+            return self
+                .build_service_sink(&self.service, &cx, encoder.as_ref())
+                .await;
+        }
+
+        let mut services = vec![self.service.as_str()];
+        for service in &self.services {
+            if services.contains(&service.as_str()) {
+                return Err(Box::new(ConfigError::DuplicateService {
+                    service: service.clone(),
+                }));
+            }
+            services.push(service);
+        }
+
+        let mut sinks = Vec::with_capacity(services.len());
+        let mut healthchecks = Vec::with_capacity(services.len());
+        for service in services {
+            let (sink, healthcheck) = self
+                .build_service_sink(service, &cx, encoder.as_ref())
+                .await?;
+            sinks.push(sink);
+            healthchecks.push(healthcheck);
+        }
+
+        let sink = VectorSink::Stream(Box::new(TeeSink::new(sinks)));
+        let healthcheck = future::try_join_all(healthchecks).map_ok(|_| ()).boxed();
+        Ok((sink, healthcheck))
+    }
+
+    /// Builds the sink writing to `service`, whose healthcheck fails if it doesn't complete within
+    /// the `healthcheck_timeout_secs`.
+    async fn build_service_sink(
+        &self,
+        service: &str,
+        cx: &SinkContext,
+        encoder: Option<&Arc<dyn ArchiveEncoder>>,
+    ) -> crate::Result<(VectorSink, super::Healthcheck)> {
+        let (sink, healthcheck) = match service {
+            "aws_s3" => {
+                let s3_config = self.aws_s3.as_ref().expect("s3 config wasn't provided");
+                let mut service =
+                    create_service(&s3_config.region, &s3_config.auth, &cx.proxy, &self.tls)
+                        .await?;
+                if let Some(bucket_region) = region::check_bucket_region(
+                    s3_config.region_mismatch,
+                    &self.bucket,
+                    &s3_config.region,
+                    &service.client(),
+                )
+                .await?
+                {
+                    service = create_service(&bucket_region, &s3_config.auth, &cx.proxy, &self.tls)
+                        .await?;
+                }
+                let service = s3_config.multipart(service)?;
+                let client = service.client();
+                let mut service = FailoverService::new(service, S3RetryLogic);
+                if let Some(failover_region) = s3_config.failover_region() {
+                    let secondary =
+                        create_service(&failover_region, &s3_config.auth, &cx.proxy, &self.tls)
+                            .await?;
+                    service = service.with_secondary(
+                        s3_config.multipart(secondary)?,
+                        s3_config.failover_bucket.clone(),
+                        s3_config.failover_after(),
+                    );
+                }
+                let svc = self
+                    .build_s3_sink(&s3_config.options, service, encoder)
+                    .map_err(|error| error.to_string())?;
+                let healthcheck = healthcheck::build_s3_healthcheck(
+                    self.healthcheck_mode,
+                    self.bucket.clone(),
+                    healthcheck::probe_key(self.key_prefix.as_deref()),
+                    client,
+                )?;
+                (svc, healthcheck)
+            }
+            "azure_blob" => {
+                let azure_config = self
+                    .azure_blob
+                    .as_ref()
+                    .expect("azure blob config wasn't provided");
+                let client = azure_common::config::build_client(
+                    Some(azure_config.connection_string.clone()),
+                    None,
+                    self.bucket.clone(),
+                    None,
+                )?;
+                let svc = self
+                    .build_azure_sink(Arc::<ContainerClient>::clone(&client), encoder)
+                    .map_err(|error| error.to_string())?;
+                let healthcheck = healthcheck::build_azure_healthcheck(
+                    self.healthcheck_mode,
+                    self.bucket.clone(),
+                    healthcheck::probe_key(self.key_prefix.as_deref()),
+                    client,
+                )?;
+                (svc, healthcheck)
+            }
+            "gcp_cloud_storage" => {
+                let gcs_config = self
+                    .gcp_cloud_storage
+                    .as_ref()
+                    .expect("gcs config wasn't provided");
+                let auth = gcs_config.auth.build(Scope::DevStorageReadWrite).await?;
+                let base_url = gcs_config.base_url(&self.bucket)?;
+                let tls = TlsSettings::from_options(&self.tls)?;
+                let client = HttpClient::new(tls, cx.proxy())?;
+                let healthcheck = healthcheck::build_gcs_healthcheck(
+                    self.healthcheck_mode,
+                    self.bucket.clone(),
+                    healthcheck::probe_key(self.key_prefix.as_deref()),
+                    client.clone(),
+                    base_url.clone(),
+                    auth.clone(),
+                )?;
+                let sink = self
+                    .build_gcs_sink(client, base_url, auth, encoder)
+                    .map_err(|error| error.to_string())?;
+                (sink, healthcheck)
+            }
+
+            service => {
+                return Err(Box::new(ConfigError::UnsupportedService {
+                    service: service.to_owned(),
+                }))
+            }
+        };
+
+        let healthcheck =
+            healthcheck::with_timeout(healthcheck, service, self.healthcheck_timeout());
+        Ok((sink, healthcheck))
+    }
+
+    fn build_s3_sink(
+        &self,
+        s3_options: &S3Options,
+        service: FailoverService<S3Service, S3RetryLogic>,
+        encoder: Option<&Arc<dyn ArchiveEncoder>>,
+    ) -> crate::Result<VectorSink> {
+        match s3_options.storage_class {
+            class @ S3StorageClass::DeepArchive | class @ S3StorageClass::Glacier => {
+                return Err(Box::new(ConfigError::UnsupportedStorageClass {
+                    storage_class: format!("{:?}", class),
+                }));
+            }
+            _ => (),
+        }
+
+        let batcher_settings = BatchConfig::<DatadogArchivesDefaultBatchSettings>::default()
+            .into_batcher_settings()
+            .expect("invalid batch settings");
+        let adaptive_batch_size = self.adaptive_batch_size.build(batcher_settings.size_limit);
+
+        // we use lower default limits, because we send 100mb batches,
+        // thus no need of the higher number of outgoing requests
+        let request_limits = self.request.unwrap_with(&Default::default());
+        let service = ServiceBuilder::new()
+            .settings(request_limits, S3RetryLogic)
+            .service(AdaptiveBatchSizeService::new(
+                service,
+                S3RetryLogic,
+                adaptive_batch_size.clone(),
+            ));
+        let service = limit_partition_concurrency(service, self.max_concurrency_per_partition);
+
+        let partitioner = self.build_partitioner();
+
+        let s3_config = self
+            .aws_s3
+            .as_ref()
+            .expect("s3 config wasn't provided")
+            .clone();
+        let request_builder = DatadogS3RequestBuilder::new(
+            self.bucket.clone(),
+            self.build_object_key_generator()?,
+            s3_config,
+            self.archive_encoder(encoder),
+        )
+        .with_object_metadata(self.build_object_metadata())
+        .with_content_disposition(self.content_disposition)
+        .with_content_language(self.content_language.clone())
+        .with_custom_time(self.custom_time)
+        .with_uncompressed_fallback(self.uncompressed_fallback)
+        .with_compression_presize_ratio(self.compression_presize_ratio)
+        .with_object_rollups(self.emit_object_rollups)
+        .with_uncompressed_size(self.uncompressed_size_metadata);
+
+        let sink = DatadogArchivesSink::new(
+            service,
+            request_builder,
+            partitioner,
+            batcher_settings,
+            None,
+        )
+        .with_adaptive_batch_size(adaptive_batch_size)
+        .with_batch_sizing(self.batch_sizing)
+        .with_checksums(self.checksums)
+        .with_success_markers(self.success_markers)
+        .with_min_events_per_object(self.min_events_per_object, self.max_carry_over())
+        .with_max_buffered_bytes(self.max_buffered_bytes)
+        .with_max_open_partitions(self.max_open_partitions)
+        .with_aggregate_below_bytes(self.aggregate_below_bytes)
+        .with_partition_grace_period(self.partition_grace_period())
+        .with_ordered_shutdown(self.ordered_shutdown)
+        .with_max_compressed_bytes(self.max_compressed_bytes)
+        .with_audit_writes(self.audit_writes)
+        .with_inventory(self.build_inventory()?);
+
+        Ok(VectorSink::from_event_streamsink(sink))
+    }
+
+    pub fn build_gcs_sink(
+        &self,
+        client: HttpClient,
+        base_url: String,
+        auth: GcpAuthenticator,
+        encoder: Option<&Arc<dyn ArchiveEncoder>>,
+    ) -> crate::Result<VectorSink> {
+        let request = self.request.unwrap_with(&Default::default());
+        let protocol = get_http_scheme_from_uri(&base_url.parse::<Uri>()?);
+
+        let batcher_settings = BatchConfig::<DatadogArchivesDefaultBatchSettings>::default()
+            .into_batcher_settings()
+            .expect("invalid batch settings");
+        let adaptive_batch_size = self.adaptive_batch_size.build(batcher_settings.size_limit);
+
+        let gcs_config = self
+            .gcp_cloud_storage
+            .as_ref()
+            .expect("gcs config wasn't provided")
+            .clone();
+
+        let mut service = DatadogGcsService::new(GcsService::new(
+            client.clone(),
+            base_url.clone(),
+            auth.clone(),
+        ));
+        let grants = gcs_config.acl_grants()?;
+        if !grants.is_empty() {
+            service = service.with_grants(
+                client.clone(),
+                &self.bucket,
+                &base_url,
+                auth.clone(),
+                grants,
+            );
+        }
+        if gcs_config.atomic_uploads {
+            service = service.with_atomic_uploads(client, self.bucket.clone(), base_url, auth);
+        }
+        let svc = ServiceBuilder::new()
+            .settings(request, GcsRetryLogic)
+            .service(AdaptiveBatchSizeService::new(
+                service,
+                GcsRetryLogic,
+                adaptive_batch_size.clone(),
+            ));
+        let svc = limit_partition_concurrency(svc, self.max_concurrency_per_partition);
+
+        let acl = gcs_config
+            .acl
+            .map(|acl| HeaderValue::from_str(&to_string(acl)).unwrap());
+        let storage_class = gcs_config.storage_class.unwrap_or_default();
+        let storage_class = HeaderValue::from_str(&to_string(storage_class)).unwrap();
+        let metadata = self.build_gcs_headers(&gcs_config)?;
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: self.bucket.clone(),
+            object_key: self.build_object_key_generator()?,
+            acl,
+            storage_class,
+            metadata,
+            encoding: self.archive_encoder(encoder),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: self.content_disposition,
+            custom_time: self.custom_time,
+            uncompressed_fallback: self.uncompressed_fallback,
+            compression_presize_ratio: self.compression_presize_ratio,
+            object_rollups: self.emit_object_rollups,
+            uncompressed_size: self.uncompressed_size_metadata,
+            crc32c: gcs_config.crc32c,
+        };
+
+        let partitioner = self.build_partitioner();
+
+        let sink = DatadogArchivesSink::new(
+            svc,
+            request_builder,
+            partitioner,
+            batcher_settings,
+            Some(protocol),
+        )
+        .with_adaptive_batch_size(adaptive_batch_size)
+        .with_batch_sizing(self.batch_sizing)
+        .with_checksums(self.checksums)
+        .with_success_markers(self.success_markers)
+        .with_min_events_per_object(self.min_events_per_object, self.max_carry_over())
+        .with_max_buffered_bytes(self.max_buffered_bytes)
+        .with_max_open_partitions(self.max_open_partitions)
+        .with_aggregate_below_bytes(self.aggregate_below_bytes)
+        .with_partition_grace_period(self.partition_grace_period())
+        .with_ordered_shutdown(self.ordered_shutdown)
+        .with_max_compressed_bytes(self.max_compressed_bytes)
+        .with_audit_writes(self.audit_writes)
+        .with_inventory(self.build_inventory()?);
+
+        Ok(VectorSink::from_event_streamsink(sink))
+    }
+
+    fn build_azure_sink(
+        &self,
+        client: Arc<ContainerClient>,
+        encoder: Option<&Arc<dyn ArchiveEncoder>>,
+    ) -> crate::Result<VectorSink> {
+        let block_size = self
+            .azure_blob
+            .as_ref()
+            .and_then(|azure_config| azure_config.block_size);
+        let batcher_settings = BatchConfig::<DatadogArchivesDefaultBatchSettings>::default()
+            .into_batcher_settings()
+            .expect("invalid batch settings");
+        let adaptive_batch_size = self.adaptive_batch_size.build(batcher_settings.size_limit);
+
+        let request_limits = self.request.unwrap_with(&Default::default());
+        let service = ServiceBuilder::new()
+            .settings(request_limits, AzureBlobRetryLogic)
+            .service(AdaptiveBatchSizeService::new(
+                AzureBlobService::new(client).with_block_size(block_size),
+                AzureBlobRetryLogic,
+                adaptive_batch_size.clone(),
+            ));
+        let service = limit_partition_concurrency(service, self.max_concurrency_per_partition);
+
+        let partitioner = self.build_partitioner();
+        let request_builder = DatadogAzureRequestBuilder {
+            container_name: self.bucket.clone(),
+            object_key: self.build_object_key_generator()?,
+            object_metadata: self.build_object_metadata(),
+            encoding: self.archive_encoder(encoder),
+            content_disposition: self.content_disposition,
+            content_language: self.content_language.clone(),
+            custom_time: self.custom_time,
+            uncompressed_fallback: self.uncompressed_fallback,
+            compression_presize_ratio: self.compression_presize_ratio,
+            object_rollups: self.emit_object_rollups,
+            uncompressed_size: self.uncompressed_size_metadata,
+        };
+
+        let sink = DatadogArchivesSink::new(
+            service,
+            request_builder,
+            partitioner,
+            batcher_settings,
+            Some("https"),
+        )
+        .with_adaptive_batch_size(adaptive_batch_size)
+        .with_batch_sizing(self.batch_sizing)
+        .with_checksums(self.checksums)
+        .with_success_markers(self.success_markers)
+        .with_min_events_per_object(self.min_events_per_object, self.max_carry_over())
+        .with_max_buffered_bytes(self.max_buffered_bytes)
+        .with_max_open_partitions(self.max_open_partitions)
+        .with_aggregate_below_bytes(self.aggregate_below_bytes)
+        .with_partition_grace_period(self.partition_grace_period())
+        .with_ordered_shutdown(self.ordered_shutdown)
+        .with_max_compressed_bytes(self.max_compressed_bytes)
+        .with_audit_writes(self.audit_writes)
+        .with_inventory(self.build_inventory()?);
+
+        Ok(VectorSink::from_event_streamsink(sink))
+    }
+
+    fn max_carry_over(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.max_carry_over_secs)
+    }
+
+    fn partition_grace_period(&self) -> Option<std::time::Duration> {
+        self.partition_grace_period_secs
+            .map(std::time::Duration::from_secs)
+    }
+
+    fn healthcheck_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.healthcheck_timeout_secs)
+    }
+
+    /// The template of the time segments of the keys, with the date separator if any.
+    fn key_template(&self) -> Template {
+        let template = match &self.partition_date_separator {
+            None => KEY_TEMPLATE.to_owned(),
+            Some(separator) => format!("/dt=%Y{0}%m{0}%d/hour=%H/", separator),
+        };
+        Template::try_from(template).expect("invalid object key format")
+    }
+
+    /// Checks that the options relying on the directories of the keys are only used with `/` as
+    /// the `key_separator`.
+    fn check_key_separator(&self) -> Result<(), ConfigError> {
+        if self.key_separator == "/" {
+            return Ok(());
+        }
+
+        let options = [
+            (
+                "max_concurrency_per_partition",
+                self.max_concurrency_per_partition.is_some(),
+            ),
+            ("success_markers", self.success_markers),
+            ("content_disposition", self.content_disposition),
+            ("checksums", self.checksums),
+            ("audit_writes", self.audit_writes),
+        ];
+        match options.into_iter().find(|(_, enabled)| *enabled) {
+            Some((option, _)) => Err(ConfigError::UnsupportedKeySeparator {
+                option,
+                separator: self.key_separator.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_date_separator(&self) -> Result<(), ConfigError> {
+        match &self.partition_date_separator {
+            Some(separator)
+                if separator.is_empty()
+                    || !separator.chars().all(|c| matches!(c, '-' | '_' | '.')) =>
+            {
+                Err(ConfigError::InvalidDateSeparator {
+                    separator: separator.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn build_partitioner(&self) -> DatadogArchivesPartitioner {
+        DatadogArchivesPartitioner::new(self.key_template(), self.partition_by_source)
+            .with_partition_time(self.partition_by)
+            .with_fallback(self.partition_fallback.clone())
+            .with_error_routing(self.route_errors)
+            .with_source_index(self.source_index.clone())
+            .with_hash_buckets(self.hash_buckets)
+            .with_timezone(self.partition_timezone)
+            .with_future_timestamps(self.future_timestamps, self.future_timestamp_tolerance())
+    }
+
+    fn future_timestamp_tolerance(&self) -> Duration {
+        // Durations are held in milliseconds, so the tolerance is capped to fit.
+        let secs = self
+            .future_timestamp_tolerance_secs
+            .min(i64::MAX as u64 / 1000);
+        Duration::seconds(secs as i64)
+    }
+
+    fn build_object_key_generator(&self) -> crate::Result<ObjectKeyGenerator> {
+        let hostname = if self.include_hostname {
+            Some(crate::get_hostname()?)
+        } else {
+            None
+        };
+
+        Ok(self.object_key_generator(hostname))
+    }
+
+    fn object_key_generator(&self, hostname: Option<String>) -> ObjectKeyGenerator {
+        ObjectKeyGenerator {
+            key_prefix: self.key_prefix.clone(),
+            hostname,
+            sanitize: self.sanitize_keys,
+            preserve_slashes: !self.collapse_slashes,
+            separator: Some(self.key_separator.clone()),
+            format: self.format,
+            ulid: (self.object_id == ObjectIdFormat::Ulid).then(Default::default),
+            sequence: self.sequence_numbers.then(Default::default),
+            key_field: self.object_key_field.clone(),
+            casing: self.partition_key_casing,
+        }
+    }
+
+    /// Previews the key of an object holding events from the given time.
+    ///
+    /// The key is built the same way as by the sink, except that the unique identifier and the
+    /// sequence number of the object are replaced by placeholders made of zeros, and the
+    /// hostname, if it's included, by `hostname`. Events are assumed to have no `source` and not
+    /// to be errors.
+    pub fn preview_key(&self, timestamp: DateTime<Utc>) -> String {
+        let mut log = LogEvent::default();
+        if let Some(timestamp_key) = log_schema().timestamp_key() {
+            log.insert((PathPrefix::Event, timestamp_key), timestamp);
+        }
+        log.insert(metadata_path!("vector", "ingest_timestamp"), timestamp);
+        // Events can only be missing a timestamp when the global timestamp key is disabled.
+        let partition_key = self
+            .build_partitioner()
+            .partition(&Event::Log(log))
+            .map_or_else(
+                || timestamp.format(self.key_template().get_ref()).to_string(),
+                |partition| partition.prefix,
+            );
+
+        let object_key =
+            self.object_key_generator(self.include_hostname.then(|| PREVIEW_HOSTNAME.to_owned()));
+        let sequence = self.sequence_numbers.then_some(0);
+        object_key.key(&partition_key, "archive", sequence, self.placeholder_id())
+    }
+
+    /// A placeholder for the unique identifier of an object, made of zeros.
+    fn placeholder_id(&self) -> String {
+        match self.object_id {
+            ObjectIdFormat::Uuid => Uuid::nil().to_string(),
+            ObjectIdFormat::Ulid => "0".repeat(26),
+        }
+    }
+
+    /// The longest object keys produced by `object_key` for each kind of partition, leaving the
+    /// `source` of the events aside.
+    fn longest_object_keys(&self, object_key: &ObjectKeyGenerator) -> Vec<String> {
+        let mut partitions = vec![Utc::now().format(self.key_template().get_ref()).to_string()];
+        if let PartitionFallback::Literal { prefix } = &self.partition_fallback {
+            partitions.push(prefix.clone());
+        }
+        let name = if self.route_errors {
+            "errors"
+        } else {
+            "archive"
+        };
+        let id = self.placeholder_id();
+        let sequence = self.sequence_numbers.then_some(0);
+
+        partitions
+            .into_iter()
+            .map(|mut partition| {
+                if let Some(source_index) = &self.source_index {
+                    partition = format!("/index={}{}", source_index.default_index, partition);
+                }
+                if let Some(buckets) = self.hash_buckets {
+                    partition = format!(
+                        "/bucket={}{}",
+                        "0".repeat(hash_bucket_width(buckets)),
+                        partition
+                    );
+                }
+                if self.partition_by_source {
+                    partition.push_str("source=unknown/");
+                }
+                object_key.key(&partition, name, sequence, id.clone())
+            })
+            .collect()
+    }
+
+    /// Checks that only reserved attributes which aren't always set are given a default.
+    fn check_reserved_attribute_defaults(&self) -> Result<(), ConfigError> {
+        match self.reserved_attribute_defaults.keys().find(|attribute| {
+            !RESERVED_ATTRIBUTES.contains(&attribute.as_str())
+                || ["_id", "date"].contains(&attribute.as_str())
+        }) {
+            Some(attribute) => Err(ConfigError::UnsupportedReservedAttributeDefault {
+                attribute: attribute.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the service options for fields this version doesn't know of.
+    ///
+    /// Unknown fields are an error, unless `lenient_parsing` is enabled, in which case they're
+    /// ignored with a warning.
+    fn check_unknown_fields(&self) -> Result<(), ConfigError> {
+        let unknown_fields = [
+            ("aws_s3", self.aws_s3.as_ref().map(|s3| &s3.unknown_fields)),
+            (
+                "azure_blob",
+                self.azure_blob.as_ref().map(|azure| &azure.unknown_fields),
+            ),
+            (
+                "gcp_cloud_storage",
+                self.gcp_cloud_storage
+                    .as_ref()
+                    .map(|gcs| &gcs.unknown_fields),
+            ),
+        ];
+
+        for (config, fields) in unknown_fields {
+            for field in fields.into_iter().flat_map(BTreeMap::keys) {
+                if !self.lenient_parsing {
+                    return Err(ConfigError::UnknownField {
+                        config,
+                        field: field.clone(),
+                    });
+                }
+                warn!(
+                    message = "Ignoring unknown field.",
+                    field = %format!("{}.{}", config, field),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the object keys produced by `object_key` are within the limits of the storage
+    /// backends and of `max_key_segments`.
+    fn check_object_keys(&self, object_key: &ObjectKeyGenerator) -> Result<(), ConfigError> {
+        for key in self.longest_object_keys(object_key) {
+            if key.len() > MAX_KEY_LENGTH {
+                return Err(ConfigError::ObjectKeyTooLong {
+                    length: key.len(),
+                    max: MAX_KEY_LENGTH,
+                    key,
+                });
+            }
+
+            let segments = key.split(self.key_separator.as_str()).count();
+            match self.max_key_segments {
+                Some(max) if segments > max.get() => {
+                    return Err(ConfigError::TooManyKeySegments {
+                        segments,
+                        max: max.get(),
+                        key,
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the inventory to record the key of each object in, if any.
+    fn build_inventory(&self) -> Result<Option<Arc<KeyInventory>>, ConfigError> {
+        self.inventory_path
+            .as_ref()
+            .map(|path| {
+                KeyInventory::open(path).map(Arc::new).map_err(|error| {
+                    ConfigError::InventoryUnavailable {
+                        path: path.clone(),
+                        reason: error.to_string(),
+                    }
+                })
+            })
+            .transpose()
+    }
+
+    /// The metadata added to every object.
+    fn build_object_metadata(&self) -> BTreeMap<String, String> {
+        let mut metadata = BTreeMap::new();
+        if self.include_config_hash {
+            metadata.insert("vector-config-hash".to_owned(), self.config_hash());
+        }
+        metadata
+    }
+
+    /// The headers added to every object written to GCS.
+    fn build_gcs_headers(
+        &self,
+        gcs_config: &GcsConfig,
+    ) -> crate::Result<Vec<(HeaderName, HeaderValue)>> {
+        let mut headers = gcs_config
+            .metadata
+            .as_ref()
+            .map(|metadata| {
+                metadata
+                    .iter()
+                    .map(make_header)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .unwrap_or_else(|| Ok(vec![]))?;
+        for (key, value) in self.build_object_metadata() {
+            headers.push(make_header((&format!("x-goog-meta-{}", key), &value))?);
+        }
+        if let Some(content_language) = &self.content_language {
+            headers.push(make_header((
+                &CONTENT_LANGUAGE.as_str().to_owned(),
+                content_language,
+            ))?);
+        }
+        Ok(headers)
+    }
+
+    /// A hash of the configuration, which is stable across restarts.
+    fn config_hash(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("config should serialize to JSON");
+        sort_json_value(&mut value);
+        let json = serde_json::to_string(&value).expect("config should serialize to JSON");
+
+        format!("{:x}", Sha256::digest(json.as_bytes()))
+    }
+
+    /// The encoder of the archives, which is the custom `encoder` if any.
+    fn archive_encoder(
+        &self,
+        encoder: Option<&Arc<dyn ArchiveEncoder>>,
+    ) -> Box<dyn ArchiveEncoder> {
+        match encoder {
+            Some(encoder) => Box::new(Arc::clone(encoder)),
+            None => self.build_encoder(),
+        }
+    }
+
+    fn build_encoder(&self) -> Box<dyn ArchiveEncoder> {
+        match self.format {
+            ArchiveFormat::Datadog => Box::new(self.build_encoding()),
+            ArchiveFormat::MinimalDd => {
+                let mut encoding = self.build_encoding();
+                encoding.minimal = true;
+                Box::new(encoding)
+            }
+            ArchiveFormat::Verbatim => Box::new(VerbatimEncoding {
+                encoder: ndjson_encoder(self.encoding.clone(), self.pretty_json),
+                trailing_newline: self.trailing_newline,
+                encode_failure: self.encode_failure,
+                value_fallback: self.value_fallback,
+                dedup_by: self.dedup_by.clone(),
+                emit_rejected_events: self.emit_rejected_events,
+            }),
+            ArchiveFormat::Passthrough => Box::new(VerbatimEncoding {
+                encoder: passthrough_encoder(self.encoding.clone()),
+                trailing_newline: self.trailing_newline,
+                encode_failure: self.encode_failure,
+                value_fallback: ValueFallback::Leave,
+                dedup_by: self.dedup_by.clone(),
+                emit_rejected_events: self.emit_rejected_events,
+            }),
+        }
+    }
+
+    fn build_encoding(&self) -> DatadogArchivesEncoding {
+        let mut encoding = DatadogArchivesEncoding::new(self.encoding.clone());
+        if self.pretty_json {
+            encoding.encoder = ndjson_encoder(self.encoding.clone(), true);
+        }
+        encoding.trailing_newline = self.trailing_newline;
+        encoding.count_header = self.count_header;
+        encoding.stringify_large_integers = self.stringify_large_integers;
+        encoding.tags_format = self.tags_format;
+        encoding.tags_input_format = self.tags_input_format;
+        encoding.host_mapping = self.host_mapping.clone();
+        encoding.field_collision = self.field_collision;
+        encoding.existing_attributes = self.existing_attributes.clone();
+        encoding.max_attributes = self.max_attributes;
+        encoding.attributes_overflow = self.attributes_overflow;
+        encoding.non_string_message = self.non_string_message;
+        encoding.future_timestamps = self.future_timestamps;
+        encoding.future_timestamp_tolerance = self.future_timestamp_tolerance();
+        encoding.encode_failure = self.encode_failure;
+        encoding.value_fallback = self.value_fallback;
+        encoding.dedup_by = self.dedup_by.clone();
+        encoding.emit_rejected_events = self.emit_rejected_events;
+        encoding.timestamp_field = self.timestamp_field.clone();
+        encoding.id_rotation_interval_millis = self
+            .id_rotation_interval_secs
+            .map(|secs| secs.get().saturating_mul(1000));
+        encoding.redact_fields = self.redact_fields.clone();
+        encoding.reserved_attribute_defaults = self.reserved_attribute_defaults.clone();
+        encoding.status_mapping = self.status_mapping.clone();
+        encoding.include_schema_version = self.include_schema_version;
+        encoding.envelope = self.envelope.clone();
+        encoding
+    }
+}
+
+/// An encoder for the contents of an archive.
+///
+/// The archives are encoded by the encoder of the configured `format`, unless the sink is built
+/// with a custom one by [`DatadogArchivesSinkConfig::build_with_encoder`].
+pub trait ArchiveEncoder:
+    crate::sinks::util::encoding::Encoder<Vec<Event>> + fmt::Debug + Send + Sync
+{
+    /// The MIME type of the encoded archive, before compression.
+    fn content_type(&self) -> &'static str;
+}
+
+impl crate::sinks::util::encoding::Encoder<Vec<Event>> for Box<dyn ArchiveEncoder> {
+    fn encode_input(&self, input: Vec<Event>, writer: &mut dyn Write) -> io::Result<usize> {
+        (**self).encode_input(input, writer)
+    }
+}
+
+impl crate::sinks::util::encoding::Encoder<Vec<Event>> for Arc<dyn ArchiveEncoder> {
+    fn encode_input(&self, input: Vec<Event>, writer: &mut dyn Write) -> io::Result<usize> {
+        (**self).encode_input(input, writer)
+    }
+}
+
+impl ArchiveEncoder for Arc<dyn ArchiveEncoder> {
+    fn content_type(&self) -> &'static str {
+        (**self).content_type()
+    }
+}
+
+fn ndjson_encoder(transformer: Transformer, pretty: bool) -> (Transformer, Encoder<Framer>) {
+    (
+        transformer,
+        Encoder::<Framer>::new(
+            NewlineDelimitedEncoder::new().into(),
+            JsonSerializerConfig::default()
+                .build()
+                .with_pretty(pretty)
+                .into(),
+        ),
+    )
+}
+
+fn passthrough_encoder(transformer: Transformer) -> (Transformer, Encoder<Framer>) {
+    (
+        transformer,
+        Encoder::<Framer>::new(
+            NewlineDelimitedEncoder::new().into(),
+            RawMessageSerializerConfig::new().build().into(),
+        ),
+    )
+}
+
+/// Encodes events as newline-delimited records, optionally terminating the last record with a
+/// newline.
+///
+/// Events are pulled from the iterator and written one at a time, so only a single encoded record is
+/// buffered besides the writer itself, which is the compressor of the payload. Events which were
+/// dropped before being encoded are `None`. Returns the number of bytes written, and the number of
+/// records.
+fn encode_newline_delimited<I>(
+    encoder: &(Transformer, Encoder<Framer>),
+    trailing_newline: bool,
+    encode_failure: EncodeFailure,
+    value_fallback: ValueFallback,
+    emit_rejected_events: bool,
+    events: I,
+    writer: &mut dyn Write,
+) -> io::Result<(usize, usize)>
+where
+    I: ExactSizeIterator<Item = Option<Event>>,
+{
+    let (transformer, encoder) = encoder;
+    let mut encoder = encoder.clone();
+    let mut n_events_pending = events.len();
+    let mut written = 0;
+
+    let batch_prefix = encoder.batch_prefix();
+    write_all(writer, n_events_pending, batch_prefix)?;
+    written += batch_prefix.len();
+
+    let mut records = 0;
+    let mut bytes = BytesMut::new();
+    for event in events {
+        let Some(mut event) = event else {
+            n_events_pending -= 1;
+            continue;
+        };
+
+        transformer.transform(&mut event);
+        if let Event::Log(log) = &mut event {
+            if value_fallback != ValueFallback::Leave {
+                apply_value_fallback(value_fallback, log.value_mut());
+            }
+        }
+        bytes.clear();
+        let original = (emit_rejected_events && encode_failure == EncodeFailure::SkipEvent)
+            .then(|| event.clone());
+        if let Err(error) = encoder.serialize(event, &mut bytes) {
+            match encode_failure {
+                EncodeFailure::FailBatch => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+                }
+                EncodeFailure::SkipEvent => {
+                    emit!(DatadogArchivesEventEncodingFailed { error });
+                    if let Some(original) = original {
+                        emit!(rejected_event("event_encoding_failed", &original));
+                    }
+                    n_events_pending -= 1;
+                    continue;
+                }
+            }
+        }
+
+        // Records are serialized without the framing delimiter, which is only written once the
+        // next record is known to be encodable, so that a skipped last event doesn't leave a
+        // trailing delimiter behind.
+        if records > 0 {
+            write_all(writer, n_events_pending, b"\n")?;
+            written += 1;
+        }
+        write_all(writer, n_events_pending, &bytes)?;
+        written += bytes.len();
+        records += 1;
+        n_events_pending -= 1;
+    }
+
+    let batch_suffix = encoder.batch_suffix();
+    write_all(writer, 0, batch_suffix)?;
+    written += batch_suffix.len();
+
+    // Newlines are only placed between records, so the last one has to be terminated here.
+    if trailing_newline && written > 0 {
+        write_all(writer, 0, b"\n")?;
+        written += 1;
+    }
+
+    Ok((written, records))
+}
+
+/// Applies `fallback` to the values of `value` which JSON can't represent faithfully.
+///
+/// Returns whether `value` itself is to be dropped.
+fn apply_value_fallback(fallback: ValueFallback, value: &mut Value) -> bool {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !apply_value_fallback(fallback, value));
+            false
+        }
+        Value::Array(array) => {
+            array.retain_mut(|value| !apply_value_fallback(fallback, value));
+            false
+        }
+        Value::Float(float) if !float.is_finite() => match fallback {
+            ValueFallback::Leave => false,
+            ValueFallback::DropField => true,
+            ValueFallback::Stringify | ValueFallback::Base64 => {
+                *value = Value::from(float.to_string());
+                false
+            }
+        },
+        Value::Bytes(bytes) if std::str::from_utf8(bytes).is_err() => match fallback {
+            ValueFallback::Leave => false,
+            ValueFallback::DropField => true,
+            ValueFallback::Stringify => {
+                *value = Value::from(String::from_utf8_lossy(bytes).into_owned());
+                false
+            }
+            ValueFallback::Base64 => {
+                *value = Value::from(BASE64_STANDARD.encode(bytes));
+                false
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Drops the events whose `dedup_by` field has the same value as that of an earlier event.
+///
+/// The finalizers of the batch are taken before it's encoded, so the dropped events are
+/// acknowledged along with the object.
+fn dedup_events(events: Vec<Event>, dedup_by: Option<&str>) -> Vec<Event> {
+    let Some(field) = dedup_by else {
+        return events;
+    };
+
+    let mut keys = HashSet::new();
+    let count = events.len();
+    let events = events
+        .into_iter()
+        .filter(|event| {
+            event
+                .maybe_as_log()
+                .and_then(|log| log.get(field))
+                .map_or(true, |key| keys.insert(key.to_string()))
+        })
+        .collect::<Vec<_>>();
+
+    let duplicates = count - events.len();
+    if duplicates > 0 {
+        emit!(ComponentEventsDropped::<INTENTIONAL> {
+            count: duplicates,
+            reason: "Duplicate event in the object.",
+        });
+    }
+    events
+}
+
+/// Archives events without reshaping them.
+#[derive(Debug)]
+struct VerbatimEncoding {
+    encoder: (Transformer, Encoder<Framer>),
+    trailing_newline: bool,
+    encode_failure: EncodeFailure,
+    value_fallback: ValueFallback,
+    dedup_by: Option<String>,
+    emit_rejected_events: bool,
+}
+
+impl crate::sinks::util::encoding::Encoder<Vec<Event>> for VerbatimEncoding {
+    fn encode_input(&self, input: Vec<Event>, writer: &mut dyn Write) -> io::Result<usize> {
+        let input = dedup_events(input, self.dedup_by.as_deref());
+        encode_newline_delimited(
+            &self.encoder,
+            self.trailing_newline,
+            self.encode_failure,
+            self.value_fallback,
+            self.emit_rejected_events,
+            input.into_iter().map(Some),
+            writer,
+        )
+        .map(|(written, _)| written)
+    }
+}
+
+impl ArchiveEncoder for VerbatimEncoding {
+    fn content_type(&self) -> &'static str {
+        self.encoder.1.content_type()
+    }
+}
+
+/// The value which redacted fields are replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+/// The largest integer which can be represented exactly as a double-precision float.
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+const RESERVED_ATTRIBUTES: [&str; 10] = [
+    "_id", "date", "message", "host", "source", "service", "status", "tags", "trace_id", "span_id",
+];
+
+#[derive(Debug)]
+struct DatadogArchivesEncoding {
+    encoder: (Transformer, Encoder<Framer>),
+    reserved_attributes: HashSet<&'static str>,
+    id_rnd_bytes: AtomicU64,
+    id_rnd_window: AtomicI64,
+    id_rotation_interval_millis: Option<u64>,
+    id_seq_number: AtomicU32,
+    trailing_newline: bool,
+    stringify_large_integers: bool,
+    tags_format: TagsFormat,
+    tags_input_format: TagsInputFormat,
+    host_mapping: HostMapping,
+    field_collision: FieldCollision,
+    existing_attributes: ExistingAttributes,
+    max_attributes: Option<NonZeroUsize>,
+    attributes_overflow: AttributesOverflow,
+    non_string_message: NonStringMessage,
+    future_timestamps: FutureTimestamps,
+    future_timestamp_tolerance: Duration,
+    encode_failure: EncodeFailure,
+    value_fallback: ValueFallback,
+    dedup_by: Option<String>,
+    emit_rejected_events: bool,
+    count_header: bool,
+    timestamp_field: Option<String>,
+    redact_fields: Vec<String>,
+    reserved_attribute_defaults: BTreeMap<String, String>,
+    status_mapping: BTreeMap<String, DatadogStatus>,
+    include_schema_version: bool,
+    envelope: Option<ArchiveEnvelopeConfig>,
+    minimal: bool,
+}
+
+impl DatadogArchivesEncoding {
+    /// Generates a unique event ID compatible with DD:
+    /// - 18 bytes;
+    /// - first 6 bytes represent a "now" timestamp in millis;
+    /// - the rest 12 bytes can be just any sequence unique for a given timestamp.
+    ///
+    /// To generate unique-ish trailing 12 bytes we use random 8 bytes, generated at startup
+    /// and optionally re-randomized periodically, and a rolling-over 4-bytes sequence number.
+    fn generate_log_id(&self) -> String {
+        self.generate_log_id_at(Utc::now())
+    }
+
+    fn generate_log_id_at(&self, now: DateTime<Utc>) -> String {
+        let mut id = BytesMut::with_capacity(18);
+        // timestamp in millis - 6 bytes
+        id.put_int(now.timestamp_millis(), 6);
+
+        // 8 random bytes
+        id.put_u64(self.id_rnd_bytes_at(now));
+
+        // 4 bytes for the counter should be more than enough - it should be unique for 1 millisecond only
+        let id_seq_number = self.id_seq_number.fetch_add(1, Ordering::Relaxed);
+        id.put_u32(id_seq_number);
+
+        BASE64_STANDARD.encode(id.freeze())
+    }
+
+    /// The random bytes of the IDs, which are re-randomized whenever a new rotation interval starts.
+    fn id_rnd_bytes_at(&self, now: DateTime<Utc>) -> u64 {
+        if let Some(interval_millis) = self.id_rotation_interval_millis {
+            let window = now.timestamp_millis() / interval_millis as i64;
+            let current = self.id_rnd_window.load(Ordering::Relaxed);
+            // Only the thread which moves the window forward re-randomizes the bytes.
+            if window != current
+                && self
+                    .id_rnd_window
+                    .compare_exchange(current, window, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            {
+                self.id_rnd_bytes
+                    .store(thread_rng().gen(), Ordering::Relaxed);
+            }
+        }
+        self.id_rnd_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Replaces the values of the fields matching `redact_fields`.
+    fn redact(&self, log_event: &mut LogEvent) {
+        for field in &self.redact_fields {
+            match field.strip_suffix('*') {
+                Some(prefix) => {
+                    let paths = log_event
+                        .all_fields()
+                        .map(|fields| {
+                            fields
+                                .map(|(path, _)| path)
+                                .filter(|path| path.starts_with(prefix))
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+                    for path in paths {
+                        log_event.insert(path.as_str(), REDACTED);
+                    }
+                }
+                None => {
+                    if log_event.contains(field.as_str()) {
+                        log_event.insert(field.as_str(), REDACTED);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets the reserved attributes the event lacks to their configured default.
+    fn set_reserved_attribute_defaults(&self, log_event: &mut LogEvent) {
+        for (attribute, default) in &self.reserved_attribute_defaults {
+            let lacking = match log_event.get(attribute.as_str()) {
+                None | Some(Value::Null) => true,
+                Some(Value::Bytes(bytes)) => bytes.is_empty(),
+                Some(_) => false,
+            };
+            if lacking {
+                log_event.insert(attribute.as_str(), default.clone());
+            }
+        }
+    }
+
+    /// Replaces the `status` of the event with the Datadog status it's mapped to, if any.
+    fn normalize_status(&self, log_event: &mut LogEvent) {
+        let Some(status) = log_event.get("status") else {
+            return;
+        };
+        if let Some(normalized) = self.status_mapping.get(status.to_string_lossy().as_ref()) {
+            log_event.insert("status", normalized.as_str());
+        }
+    }
+
+    /// Removes the `timestamp_field` of the event, if it holds a timestamp.
+    fn take_timestamp_field(&self, log_event: &mut LogEvent) -> Option<Value> {
+        let field = self.timestamp_field.as_deref()?;
+        log_event.get(field)?.as_timestamp()?;
+        log_event.remove(field)
+    }
+
+    /// Wraps the reshaped event in the configured envelope, if any.
+    fn wrap(&self, log_event: &mut LogEvent) {
+        if let Some(envelope) = &self.envelope {
+            let value = std::mem::replace(log_event.value_mut(), Value::Object(BTreeMap::new()));
+            let ingested_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+            *log_event.value_mut() = Value::Object(BTreeMap::from([
+                (envelope.key.clone(), value),
+                (envelope.ingested_at_key.clone(), Value::from(ingested_at)),
+            ]));
+        }
+    }
+}
+
+impl DatadogArchivesEncoding {
+    pub fn new(transformer: Transformer) -> Self {
+        Self {
+            encoder: ndjson_encoder(transformer, false),
+            reserved_attributes: RESERVED_ATTRIBUTES.iter().copied().collect(),
+            id_rnd_bytes: AtomicU64::new(thread_rng().gen()),
+            id_rnd_window: AtomicI64::new(0),
+            id_rotation_interval_millis: None,
+            id_seq_number: AtomicU32::new(0),
+            trailing_newline: false,
+            count_header: false,
+            stringify_large_integers: false,
+            tags_format: Default::default(),
+            tags_input_format: Default::default(),
+            host_mapping: Default::default(),
+            field_collision: Default::default(),
+            existing_attributes: Default::default(),
+            max_attributes: None,
+            attributes_overflow: Default::default(),
+            non_string_message: Default::default(),
+            future_timestamps: Default::default(),
+            future_timestamp_tolerance: Duration::seconds(
+                default_future_timestamp_tolerance_secs() as i64,
+            ),
+            encode_failure: Default::default(),
+            value_fallback: Default::default(),
+            dedup_by: None,
+            emit_rejected_events: false,
+            timestamp_field: None,
+            redact_fields: Vec::new(),
+            reserved_attribute_defaults: BTreeMap::new(),
+            status_mapping: BTreeMap::new(),
+            include_schema_version: false,
+            envelope: None,
+            minimal: false,
+        }
+    }
+}
+
+impl DatadogArchivesEncoding {
+    /// Reshapes the event, or emits the error it was rejected for.
+    ///
+    /// Rejected events are dropped, and reported along with a sample of the original event if
+    /// `emit_rejected_events` is enabled.
+    fn reshape_or_reject(
+        &self,
+        mut event: Event,
+    ) -> Result<Event, Option<DatadogArchivesEventRejected>> {
+        let original = self.emit_rejected_events.then(|| event.clone());
+        match self.reshape(event.as_mut_log()) {
+            Ok(()) => Ok(event),
+            Err(error) => {
+                let reason = error.reason();
+                error.emit();
+                Err(original.map(|original| rejected_event(reason, &original)))
+            }
+        }
+    }
+
+    /// Applies the following transformations to align event's schema with DD:
+    /// - (required) `_id` is generated in the sink(format described below);
+    /// - (required) `date` is set from the `timestamp_field`, the `timestamp` meaning or Global Log Schema mapping, or to the current time if missing;
+    /// - `message`,`host` are set from the corresponding meanings or Global Log Schema mappings;
+    /// - `source`, `service`, `status`, `tags` and other reserved attributes are left as is;
+    /// - the rest of the fields is moved to `attributes`.
+    ///
+    /// In `minimal` mode, only `_id` and `date` are added, and the timestamp is left in place.
+    // TODO: All reserved attributes could have specific meanings, rather than specific paths
+    fn reshape(&self, log_event: &mut LogEvent) -> Result<(), ReshapeError> {
+        self.redact(log_event);
+
+        log_event.insert("_id", self.generate_log_id());
+
+        let timestamp = if self.minimal {
+            self.timestamp_field
+                .as_deref()
+                .and_then(|field| log_event.get(field))
+                .filter(|value| value.as_timestamp().is_some())
+                .or_else(|| log_event.get_timestamp())
+                .cloned()
+        } else {
+            self.take_timestamp_field(log_event)
+                .or_else(|| log_event.remove_timestamp())
+        };
+        let date = match timestamp.as_ref().and_then(Value::as_timestamp) {
+            Some(timestamp) => self.handle_future_timestamp(*timestamp)?,
+            None => Utc::now(),
+        };
+        log_event.insert("date", date.to_rfc3339_opts(SecondsFormat::Millis, true));
+
+        if !self.minimal {
+            self.move_attributes(log_event)?;
+        }
+        self.normalize_status(log_event);
+        self.set_reserved_attribute_defaults(log_event);
+        if self.include_schema_version {
+            log_event.insert(SCHEMA_VERSION_FIELD, SCHEMA_VERSION);
+        }
+
+        if self.stringify_large_integers {
+            stringify_large_integers(log_event.value_mut());
+        }
+
+        self.wrap(log_event);
+        Ok(())
+    }
+
+    /// Moves `message` and `host` to their reserved attributes, and the custom attributes under
+    /// `attributes`.
+    fn move_attributes(&self, log_event: &mut LogEvent) -> Result<(), ReshapeError> {
+        if let Some(message_path) = log_event.message_path() {
+            if self.may_set(log_event, &message_path, "message")? {
+                log_event.rename_key(message_path.as_str(), event_path!("message"));
+            }
+            self.handle_non_string_message(log_event)?;
+        }
+
+        if let Some(host_path) = log_event.host_path() {
+            match &self.host_mapping {
+                HostMapping::Move => {
+                    if self.may_set(log_event, &host_path, "host")? {
+                        log_event.rename_key(host_path.as_str(), event_path!("host"));
+                    }
+                }
+                HostMapping::Copy => {
+                    if self.may_set(log_event, &host_path, "host")? {
+                        if let Some(host) = log_event.get(host_path.as_str()).cloned() {
+                            log_event.insert(event_path!("host"), host);
+                        }
+                    }
+                }
+                HostMapping::Rename { key } => {
+                    if self.may_set(log_event, &host_path, key)? {
+                        log_event.rename_key(host_path.as_str(), key.as_str());
+                    }
+                }
+            }
+        }
+
+        let mut attributes = BTreeMap::new();
+        let existing_attributes = log_event.remove("attributes");
+
+        let custom_attributes = if let Some(map) = log_event.as_map() {
+            map.keys()
+                .filter(|&path| !self.reserved_attributes.contains(path.as_str()))
+                .map(|v| v.to_owned())
+                .collect()
+        } else {
+            vec![]
+        };
+
+        for path in custom_attributes {
+            if let Some(value) = log_event.remove(path.as_str()) {
+                attributes.insert(path, value);
+            }
+        }
+        if let Some(existing_attributes) = existing_attributes {
+            self.handle_existing_attributes(&mut attributes, existing_attributes);
+        }
+        self.limit_attributes(&mut attributes);
+        log_event.insert("attributes", attributes);
+
+        if let Some(tags) = log_event
+            .get("tags")
+            .and_then(|tags| self.tags_input_format.to_array(tags))
+        {
+            log_event.insert("tags", tags);
+        }
+        if self.tags_format == TagsFormat::Map {
+            if let Some(tags) = log_event.get("tags").and_then(tags_to_map) {
+                log_event.insert("tags", tags);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the `existing_attributes` policy to a pre-existing `attributes` field.
+    fn handle_existing_attributes(
+        &self,
+        attributes: &mut BTreeMap<String, Value>,
+        existing: Value,
+    ) {
+        match (&self.existing_attributes, existing) {
+            (ExistingAttributes::Overwrite, _) => {}
+            (ExistingAttributes::Merge, Value::Object(existing)) => {
+                for (key, value) in existing {
+                    attributes.entry(key).or_insert(value);
+                }
+            }
+            (ExistingAttributes::Merge, existing) => {
+                attributes.insert("attributes".to_string(), existing);
+            }
+            (ExistingAttributes::NestUnder { key }, existing) => {
+                attributes.insert(key.clone(), existing);
+            }
+        }
+    }
+
+    /// Applies the `future_timestamps` policy to the timestamp of the event, returning its `date`.
+    fn handle_future_timestamp(
+        &self,
+        timestamp: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, DatadogArchivesFutureTimestamp> {
+        let now = Utc::now();
+        if !is_future(timestamp, now, self.future_timestamp_tolerance) {
+            return Ok(timestamp);
+        }
+
+        match self.future_timestamps {
+            FutureTimestamps::Leave => Ok(timestamp),
+            FutureTimestamps::Clamp => Ok(now),
+            FutureTimestamps::Reject => Err(DatadogArchivesFutureTimestamp { timestamp }),
+        }
+    }
+
+    /// Applies the `non_string_message` policy to the `message` reserved attribute.
+    fn handle_non_string_message(
+        &self,
+        log_event: &mut LogEvent,
+    ) -> Result<(), DatadogArchivesNonStringMessage> {
+        let Some(message) = log_event.get(event_path!("message")) else {
+            return Ok(());
+        };
+        if message.is_bytes() || message.is_null() {
+            return Ok(());
+        }
+
+        match self.non_string_message {
+            NonStringMessage::Leave => Ok(()),
+            NonStringMessage::Stringify => {
+                let message = match message {
+                    Value::Object(_) | Value::Array(_) => {
+                        serde_json::to_string(message).expect("message should serialize to JSON")
+                    }
+                    message => message.to_string_lossy().into_owned(),
+                };
+                log_event.insert(event_path!("message"), message);
+                Ok(())
+            }
+            NonStringMessage::Error => Err(DatadogArchivesNonStringMessage {
+                kind: message.kind_str(),
+            }),
+        }
+    }
+
+    /// Limits the custom attributes to `max_attributes`, according to the `attributes_overflow`
+    /// policy.
+    fn limit_attributes(&self, attributes: &mut BTreeMap<String, Value>) {
+        let Some(max) = self.max_attributes.map(NonZeroUsize::get) else {
+            return;
+        };
+        if attributes.len() <= max {
+            return;
+        }
+
+        match self.attributes_overflow {
+            AttributesOverflow::Drop => {
+                let first_dropped = attributes.keys().nth(max).cloned().expect("over the limit");
+                attributes.split_off(&first_dropped);
+            }
+            AttributesOverflow::Collapse => {
+                // The collapsed attributes make room for the `_overflow` attribute.
+                let first_collapsed = attributes
+                    .keys()
+                    .nth(max - 1)
+                    .cloned()
+                    .expect("over the limit");
+                let overflow = attributes.split_off(&first_collapsed);
+                let overflow =
+                    serde_json::to_string(&overflow).expect("attributes should serialize to JSON");
+                attributes.insert(OVERFLOW_ATTRIBUTE.to_owned(), Value::from(overflow));
+            }
+        }
+    }
+
+    /// Whether the field at `path` may be written to `key`, according to the `field_collision`
+    /// policy.
+    fn may_set(
+        &self,
+        log_event: &LogEvent,
+        path: &str,
+        key: &str,
+    ) -> Result<bool, DatadogArchivesFieldCollision> {
+        if path.trim_start_matches('.') == key || !log_event.contains(key) {
+            return Ok(true);
+        }
+
+        match self.field_collision {
+            FieldCollision::Overwrite => Ok(true),
+            FieldCollision::KeepExisting => Ok(false),
+            FieldCollision::Error => Err(DatadogArchivesFieldCollision {
+                field: key.to_owned(),
+            }),
+        }
+    }
+}
+
+impl crate::sinks::util::encoding::Encoder<Vec<Event>> for DatadogArchivesEncoding {
+    fn encode_input(&self, input: Vec<Event>, writer: &mut dyn Write) -> io::Result<usize> {
+        // Events are reshaped right before being encoded, so that the reshaped batch is never held
+        // in memory as a whole.
+        let events = dedup_events(input, self.dedup_by.as_deref())
+            .into_iter()
+            .map(|event| match self.reshape_or_reject(event) {
+                Ok(event) => Some(event),
+                Err(rejected) => {
+                    if let Some(rejected) = rejected {
+                        emit!(rejected);
+                    }
+                    None
+                }
+            });
+        if !self.count_header {
+            return encode_newline_delimited(
+                &self.encoder,
+                self.trailing_newline,
+                self.encode_failure,
+                self.value_fallback,
+                self.emit_rejected_events,
+                events,
+                writer,
+            )
+            .map(|(written, _)| written);
+        }
+
+        // The records are buffered, so that the header can hold their count.
+        let mut records = Vec::new();
+        let (written, count) = encode_newline_delimited(
+            &self.encoder,
+            self.trailing_newline,
+            self.encode_failure,
+            self.value_fallback,
+            self.emit_rejected_events,
+            events,
+            &mut records,
+        )?;
+        let mut header = serde_json::to_vec(&serde_json::json!({ "header": { "count": count } }))?;
+        header.push(b'\n');
+        write_all(writer, count, &header)?;
+        write_all(writer, count, &records)?;
+
+        Ok(header.len() + written)
+    }
+}
+
+/// Why an event couldn't be reshaped, in which case it's dropped.
+#[derive(Debug)]
+enum ReshapeError {
+    FieldCollision(DatadogArchivesFieldCollision),
+    NonStringMessage(DatadogArchivesNonStringMessage),
+    FutureTimestamp(DatadogArchivesFutureTimestamp),
+}
+
+impl ReshapeError {
+    /// The reason the event is rejected, which is the error code of the error.
+    const fn reason(&self) -> &'static str {
+        match self {
+            Self::FieldCollision(_) => "field_collision",
+            Self::NonStringMessage(_) => "non_string_message",
+            Self::FutureTimestamp(_) => "future_timestamp",
+        }
+    }
+
+    fn emit(self) {
+        match self {
+            Self::FieldCollision(error) => emit!(error),
+            Self::NonStringMessage(error) => emit!(error),
+            Self::FutureTimestamp(error) => emit!(error),
+        }
+    }
+}
+
+/// The maximum size of the samples of rejected events.
+const MAX_REJECTED_SAMPLE_BYTES: usize = 1024;
+
+/// The report of an event rejected from its archive for the given reason.
+fn rejected_event(reason: &'static str, event: &Event) -> DatadogArchivesEventRejected {
+    let mut sample = event
+        .maybe_as_log()
+        .and_then(|log| serde_json::to_string(log.value()).ok())
+        .unwrap_or_else(|| format!("{:?}", event));
+    if sample.len() > MAX_REJECTED_SAMPLE_BYTES {
+        let mut end = MAX_REJECTED_SAMPLE_BYTES;
+        while !sample.is_char_boundary(end) {
+            end -= 1;
+        }
+        sample.truncate(end);
+    }
+
+    DatadogArchivesEventRejected { reason, sample }
+}
+
+impl From<DatadogArchivesFieldCollision> for ReshapeError {
+    fn from(error: DatadogArchivesFieldCollision) -> Self {
+        Self::FieldCollision(error)
+    }
+}
+
+impl From<DatadogArchivesNonStringMessage> for ReshapeError {
+    fn from(error: DatadogArchivesNonStringMessage) -> Self {
+        Self::NonStringMessage(error)
+    }
+}
+
+impl From<DatadogArchivesFutureTimestamp> for ReshapeError {
+    fn from(error: DatadogArchivesFutureTimestamp) -> Self {
+        Self::FutureTimestamp(error)
+    }
+}
+
+impl ArchiveEncoder for DatadogArchivesEncoding {
+    fn content_type(&self) -> &'static str {
+        self.encoder.1.content_type()
+    }
+}
+
+/// Replaces any integer which can't be represented exactly by a double-precision float with its
+/// string representation.
+fn stringify_large_integers(value: &mut Value) {
+    match value {
+        Value::Integer(integer) if !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(integer) => {
+            *value = Value::from(integer.to_string());
+        }
+        Value::Object(map) => map.values_mut().for_each(stringify_large_integers),
+        Value::Array(array) => array.iter_mut().for_each(stringify_large_integers),
+        _ => {}
+    }
+}
+
+/// Parses an array of `key:value` tags into an object, or returns `None` if the tags can't be parsed.
+fn tags_to_map(tags: &Value) -> Option<Value> {
+    let Value::Array(tags) = tags else {
+        return None;
+    };
+
+    let mut map = BTreeMap::new();
+    for tag in tags {
+        let Value::Bytes(tag) = tag else {
+            return None;
+        };
+        let tag = String::from_utf8_lossy(tag);
+        let (key, value) = tag.split_once(':').unwrap_or((&*tag, ""));
+        if key.is_empty() {
+            return None;
+        }
+        map.insert(key.to_owned(), Value::from(value));
+    }
+    Some(Value::Object(map))
+}
+
+#[derive(Debug)]
+struct DatadogS3RequestBuilder {
+    bucket: String,
+    object_key: ObjectKeyGenerator,
+    object_metadata: BTreeMap<String, String>,
+    config: S3Config,
+    encoding: Box<dyn ArchiveEncoder>,
+    content_disposition: bool,
+    content_language: Option<String>,
+    custom_time: bool,
+    uncompressed_fallback: bool,
+    compression_presize_ratio: f64,
+    object_rollups: bool,
+    uncompressed_size: bool,
+}
+
+impl DatadogS3RequestBuilder {
+    pub fn new(
+        bucket: String,
+        object_key: ObjectKeyGenerator,
+        config: S3Config,
+        encoding: Box<dyn ArchiveEncoder>,
+    ) -> Self {
+        Self {
+            bucket,
+            object_key,
+            object_metadata: BTreeMap::new(),
+            config,
+            encoding,
+            content_disposition: false,
+            content_language: None,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+        }
+    }
+
+    /// Adds the given metadata to every object.
+    pub fn with_object_metadata(mut self, object_metadata: BTreeMap<String, String>) -> Self {
+        self.object_metadata = object_metadata;
+        self
+    }
+
+    /// Sets the `Content-Disposition` of every object, from the name of the object.
+    pub const fn with_content_disposition(mut self, content_disposition: bool) -> Self {
+        self.content_disposition = content_disposition;
+        self
+    }
+
+    /// Sets the `Content-Language` of every object.
+    pub fn with_content_language(mut self, content_language: Option<String>) -> Self {
+        self.content_language = content_language;
+        self
+    }
+
+    /// Sets whether every object is stamped with the time of its latest event.
+    pub const fn with_custom_time(mut self, custom_time: bool) -> Self {
+        self.custom_time = custom_time;
+        self
+    }
+
+    /// Sets whether objects are written uncompressed when their compression fails.
+    pub const fn with_uncompressed_fallback(mut self, uncompressed_fallback: bool) -> Self {
+        self.uncompressed_fallback = uncompressed_fallback;
+        self
+    }
+
+    /// Sets the expected ratio of the compressed size of an object to the size of its events.
+    pub const fn with_compression_presize_ratio(mut self, compression_presize_ratio: f64) -> Self {
+        self.compression_presize_ratio = compression_presize_ratio;
+        self
+    }
+
+    /// Sets whether a rollup of each object is reported as it's finalized.
+    pub const fn with_object_rollups(mut self, object_rollups: bool) -> Self {
+        self.object_rollups = object_rollups;
+        self
+    }
+
+    /// Sets whether every object is stamped with the size of its payload before compression.
+    pub const fn with_uncompressed_size(mut self, uncompressed_size: bool) -> Self {
+        self.uncompressed_size = uncompressed_size;
+        self
+    }
+}
+
+impl RequestBuilder<(ArchivePartition, Vec<Event>)> for DatadogS3RequestBuilder {
+    type Metadata = (S3Metadata, Option<DateTime<Utc>>);
+    type Events = Vec<Event>;
+    type Encoder = Box<dyn ArchiveEncoder>;
+    type Payload = Bytes;
+    type Request = S3Request;
+    type Error = io::Error;
+
+    fn compression(&self) -> Compression {
+        DEFAULT_COMPRESSION
+    }
+
+    fn encoder(&self) -> &Self::Encoder {
+        &self.encoding
+    }
+
+    fn split_input(
+        &self,
+        input: (ArchivePartition, Vec<Event>),
+    ) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
+        let (partition, mut events) = input;
+        let finalizers = events.take_finalizers();
+
+        let builder = RequestMetadataBuilder::from_events(&events);
+
+        let s3metadata = S3Metadata {
+            s3_key: self.object_key.generate_for(&partition, &mut events),
+            partition_key: S3PartitionKey {
+                key_prefix: partition.prefix,
+                ssekms_key_id: None,
+            },
+            finalizers,
+        };
+        let custom_time = custom_time(self.custom_time, &events);
+
+        ((s3metadata, custom_time), builder, events)
+    }
+
+    fn encode_events(
+        &self,
+        events: Self::Events,
+    ) -> Result<EncodeResult<Self::Payload>, Self::Error> {
+        encode_archive(
+            &*self.encoding,
+            events,
+            DEFAULT_COMPRESSION,
+            self.uncompressed_fallback,
+            self.compression_presize_ratio,
+        )
+    }
+
+    fn build_request(
+        &self,
+        (mut metadata, custom_time): Self::Metadata,
+        request_metadata: RequestMetadata,
+        payload: EncodeResult<Self::Payload>,
+    ) -> Self::Request {
+        let uncompressed = self.uncompressed_fallback && payload.compressed_byte_size.is_none();
+        if uncompressed {
+            metadata.s3_key = uncompressed_key(&metadata.s3_key);
+        }
+        if self.object_rollups {
+            emit!(object_rollup(
+                &metadata.partition_key.key_prefix,
+                &request_metadata,
+                &payload
+            ));
+        }
+        let uncompressed_size = self
+            .uncompressed_size
+            .then_some(payload.uncompressed_byte_size);
+        let body = payload.into_payload();
+        trace!(
+            message = "Sending events.",
+            bytes = ?body.len(),
+            events_len = ?request_metadata.events_byte_size(),
+            bucket = ?self.bucket,
+            key = ?metadata.partition_key
+        );
+
+        let s3_options = self.config.options.clone();
+        let mut tags = s3_options.tags.unwrap_or_default();
+        if let Some(retention_tag) = &s3_options.retention_tag {
+            if let Some(value) = retention_tag.value_for(&metadata.partition_key.key_prefix) {
+                tags.insert(retention_tag.key.clone(), value);
+            }
+        }
+        let content_disposition = self
+            .content_disposition
+            .then(|| content_disposition(&metadata.s3_key));
+        let user_metadata = if s3_options.partition_metadata {
+            let mut object_metadata = partition_metadata(&metadata.partition_key.key_prefix);
+            object_metadata.extend(self.object_metadata.clone());
+            user_metadata(&object_metadata, custom_time, uncompressed_size)
+        } else {
+            user_metadata(&self.object_metadata, custom_time, uncompressed_size)
+        };
+        S3Request {
+            body,
+            bucket: self.bucket.clone(),
+            metadata,
+            request_metadata,
+            content_encoding: if uncompressed {
+                None
+            } else {
+                DEFAULT_COMPRESSION.content_encoding()
+            },
+            options: s3_common::config::S3Options {
+                acl: s3_options.acl,
+                grant_full_control: s3_options.grant_full_control,
+                grant_read: s3_options.grant_read,
+                grant_read_acp: s3_options.grant_read_acp,
+                grant_write_acp: s3_options.grant_write_acp,
+                server_side_encryption: s3_options.server_side_encryption,
+                ssekms_key_id: s3_options.ssekms_key_id,
+                bucket_key_enabled: s3_options.bucket_key_enabled.filter(|&enabled| {
+                    enabled
+                        && matches!(
+                            s3_options.server_side_encryption,
+                            Some(S3ServerSideEncryption::AwsKms)
+                        )
+                }),
+                storage_class: s3_options.storage_class,
+                tags: (!tags.is_empty()).then(|| tags.into_iter().collect()),
+                content_encoding: None,
+                content_type: uncompressed.then(|| self.encoding.content_type().to_owned()),
+            },
+            user_metadata,
+            content_disposition,
+            content_language: self.content_language.clone(),
+        }
+    }
+}
+
+/// The user metadata describing the partition of an object: the values of its `dt=` and `hour=`
+/// segments.
+fn partition_metadata(partition_prefix: &str) -> BTreeMap<String, String> {
+    partition_prefix
+        .split('/')
+        .filter_map(|segment| segment.split_once('='))
+        .filter(|(name, _)| matches!(*name, "dt" | "hour"))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+#[derive(Debug)]
+struct DatadogGcsRequestBuilder {
+    bucket: String,
+    object_key: ObjectKeyGenerator,
+    acl: Option<HeaderValue>,
+    storage_class: HeaderValue,
+    metadata: Vec<(HeaderName, HeaderValue)>,
+    encoding: Box<dyn ArchiveEncoder>,
+    compression: Compression,
+    content_disposition: bool,
+    custom_time: bool,
+    uncompressed_fallback: bool,
+    compression_presize_ratio: f64,
+    object_rollups: bool,
+    uncompressed_size: bool,
+    crc32c: bool,
+}
+
+impl RequestBuilder<(ArchivePartition, Vec<Event>)> for DatadogGcsRequestBuilder {
+    type Metadata = (String, EventFinalizers, Option<TimeBounds>, Option<String>);
+    type Events = Vec<Event>;
+    type Payload = Bytes;
+    type Request = GcsRequest;
+    type Encoder = Box<dyn ArchiveEncoder>;
+    type Error = io::Error;
+
+    fn split_input(
+        &self,
+        input: (ArchivePartition, Vec<Event>),
+    ) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
+        let (partition, mut events) = input;
+        let metadata_builder = RequestMetadataBuilder::from_events(&events);
+        let finalizers = events.take_finalizers();
+        let key = self.object_key.generate_for(&partition, &mut events);
+        let time_bounds = TimeBounds::of(&events);
+        let rollup_prefix = self.object_rollups.then_some(partition.prefix);
+
+        (
+            (key, finalizers, time_bounds, rollup_prefix),
+            metadata_builder,
+            events,
+        )
+    }
+
+    fn encode_events(
+        &self,
+        events: Self::Events,
+    ) -> Result<EncodeResult<Self::Payload>, Self::Error> {
+        encode_archive(
+            &*self.encoding,
+            events,
+            self.compression,
+            self.uncompressed_fallback,
+            self.compression_presize_ratio,
+        )
+    }
+
+    fn build_request(
+        &self,
+        dd_metadata: Self::Metadata,
+        metadata: RequestMetadata,
+        payload: EncodeResult<Self::Payload>,
+    ) -> Self::Request {
+        let (mut key, finalizers, time_bounds, rollup_prefix) = dd_metadata;
+        let uncompressed = self.uncompressed_fallback && payload.compressed_byte_size.is_none();
+        if uncompressed {
+            key = uncompressed_key(&key);
+        }
+        if let Some(prefix) = rollup_prefix {
+            emit!(object_rollup(&prefix, &metadata, &payload));
+        }
+        let uncompressed_size = self
+            .uncompressed_size
+            .then_some(payload.uncompressed_byte_size);
+
+        let body = payload.into_payload();
+
+        trace!(
+            message = "Sending events.",
+            bytes = body.len(),
+            events_len = metadata.event_count(),
+            bucket = %self.bucket,
+            ?key
+        );
+
+        let content_type = HeaderValue::from_str(self.encoding.content_type()).unwrap();
+        let content_encoding = DEFAULT_COMPRESSION
+            .content_encoding()
+            .filter(|_| !uncompressed)
+            .map(|ce| HeaderValue::from_str(&to_string(ce)).unwrap());
+
+        let mut headers = self.metadata.clone();
+        for (name, value) in gcs_batch_metadata(metadata.event_count(), time_bounds) {
+            // User-supplied metadata takes precedence.
+            if !headers.iter().any(|(existing, _)| *existing == name) {
+                headers.push((name, value));
+            }
+        }
+        if let Some(TimeBounds { max, .. }) = time_bounds.filter(|_| self.custom_time) {
+            headers.push((
+                HeaderName::from_static(GCS_CUSTOM_TIME_HEADER),
+                HeaderValue::from_str(&format_time(max))
+                    .expect("RFC 3339 times are valid header values"),
+            ));
+        }
+        if self.content_disposition {
+            match HeaderValue::from_str(&content_disposition(&key)) {
+                Ok(value) => headers.push((CONTENT_DISPOSITION, value)),
+                Err(error) => warn!(
+                    message = "Object key can't be used in the Content-Disposition header.",
+                    %error,
+                    ?key
+                ),
+            }
+        }
+        if let Some(uncompressed_size) = uncompressed_size {
+            headers.push((
+                HeaderName::from_static(GCS_UNCOMPRESSED_SIZE_HEADER),
+                HeaderValue::from(uncompressed_size),
+            ));
+        }
+        if self.crc32c {
+            headers.push((
+                HeaderName::from_static(GCS_HASH_HEADER),
+                HeaderValue::from_str(&gcs_crc32c_hash(&body))
+                    .expect("base64 checksums are valid header values"),
+            ));
+        }
+
+        GcsRequest {
+            key,
+            body,
+            finalizers,
+            settings: GcsRequestSettings {
+                acl: self.acl.clone(),
+                content_type,
+                content_encoding,
+                storage_class: self.storage_class.clone(),
+                headers,
+            },
+            metadata,
+        }
+    }
+
+    fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    fn encoder(&self) -> &Self::Encoder {
+        &self.encoding
+    }
+}
+
+/// The times of the earliest and the latest events of a batch.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct TimeBounds {
+    min: DateTime<Utc>,
+    max: DateTime<Utc>,
+}
+
+impl TimeBounds {
+    /// The time bounds of the events, if any of them has a timestamp.
+    fn of(events: &[Event]) -> Option<Self> {
+        events
+            .iter()
+            .filter_map(|event| {
+                event
+                    .maybe_as_log()?
+                    .get_timestamp()?
+                    .as_timestamp()
+                    .copied()
+            })
+            .fold(None, |bounds, timestamp| {
+                Some(match bounds {
+                    None => Self {
+                        min: timestamp,
+                        max: timestamp,
+                    },
+                    Some(Self { min, max }) => Self {
+                        min: min.min(timestamp),
+                        max: max.max(timestamp),
+                    },
+                })
+            })
+    }
+}
+
+/// The custom metadata describing the batch of a GCS object: its number of events, and the times
+/// of its earliest and latest events.
+fn gcs_batch_metadata(
+    event_count: usize,
+    time_bounds: Option<TimeBounds>,
+) -> Vec<(HeaderName, HeaderValue)> {
+    let mut headers = vec![(
+        HeaderName::from_static(GCS_EVENT_COUNT_HEADER),
+        HeaderValue::from(event_count),
+    )];
+    if let Some(TimeBounds { min, max }) = time_bounds {
+        for (name, time) in [(GCS_TIME_MIN_HEADER, min), (GCS_TIME_MAX_HEADER, max)] {
+            headers.push((
+                HeaderName::from_static(name),
+                HeaderValue::from_str(&format_time(time))
+                    .expect("RFC 3339 times are valid header values"),
+            ));
+        }
+    }
+    headers
+}
+
+/// The time of the latest event of the batch, if objects are stamped with it and any event has a
+/// timestamp.
+fn custom_time(enabled: bool, events: &[Event]) -> Option<DateTime<Utc>> {
+    enabled
+        .then(|| TimeBounds::of(events))
+        .flatten()
+        .map(|bounds| bounds.max)
+}
+
+/// The user metadata of an S3 or Azure Blob Storage object: the metadata added to every object,
+/// along with the custom time of the object, if any.
+fn user_metadata(
+    object_metadata: &BTreeMap<String, String>,
+    custom_time: Option<DateTime<Utc>>,
+    uncompressed_size: Option<usize>,
+) -> Option<HashMap<String, String>> {
+    let mut metadata = object_metadata.clone();
+    if let Some(custom_time) = custom_time {
+        metadata.insert(
+            CUSTOM_TIME_METADATA_KEY.to_owned(),
+            format_time(custom_time),
+        );
+    }
+    if let Some(uncompressed_size) = uncompressed_size {
+        metadata.insert(
+            UNCOMPRESSED_SIZE_METADATA_KEY.to_owned(),
+            uncompressed_size.to_string(),
+        );
+    }
+    (!metadata.is_empty()).then(|| metadata.into_iter().collect())
+}
+
+/// Formats a time of the object metadata, as an RFC 3339 time in UTC.
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Sorts the keys of all objects within the value, so that it serializes the same way regardless of
+/// the order in which maps were iterated.
+fn sort_json_value(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(array) => array.iter_mut().for_each(sort_json_value),
+        serde_json::Value::Object(map) => {
+            let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (key, mut value) in entries {
+                sort_json_value(&mut value);
+                map.insert(key, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Generates the object keys for the archives, from the partition key of each batch.
+#[derive(Clone, Debug, Default)]
+struct ObjectKeyGenerator {
+    key_prefix: Option<String>,
+    hostname: Option<String>,
+    sanitize: bool,
+    preserve_slashes: bool,
+    /// The separator between the segments of the keys, which is `/` if unset.
+    separator: Option<String>,
+    format: ArchiveFormat,
+    ulid: Option<Arc<UlidGenerator>>,
+    sequence: Option<Arc<AtomicU64>>,
+    /// The field to which the key of each object is added in its events, if any.
+    key_field: Option<String>,
+    casing: PartitionKeyCasing,
+}
+
+impl ObjectKeyGenerator {
+    /// Generates the key of an object of the given partition.
+    fn generate(&self, partition: &ArchivePartition) -> String {
+        let name = if partition.errors {
+            "errors"
+        } else {
+            "archive"
+        };
+        self.generate_named(&partition.prefix, name)
+    }
+
+    /// Generates the key of an object of the given partition, and adds it to the events written to
+    /// the object if `key_field` is set.
+    fn generate_for(&self, partition: &ArchivePartition, events: &mut [Event]) -> String {
+        let key = self.generate(partition);
+        if let Some(key_field) = &self.key_field {
+            for event in events {
+                event.as_mut_log().insert(key_field.as_str(), key.clone());
+            }
+        }
+        key
+    }
+
+    fn generate_named(&self, partition_key: &str, name: &str) -> String {
+        let id = match &self.ulid {
+            Some(ulid) => ulid.generate(),
+            None => Uuid::new_v4().to_string(),
+        };
+        let sequence = self
+            .sequence
+            .as_ref()
+            .map(|sequence| sequence.fetch_add(1, Ordering::Relaxed));
+        self.key(partition_key, name, sequence, id)
+    }
+
+    /// The key of the object with the given sequence number and identifier.
+    fn key(&self, partition_key: &str, name: &str, sequence: Option<u64>, id: String) -> String {
+        let filename = match &self.hostname {
+            Some(hostname) => format!("{}_{}", hostname, id),
+            None => id,
+        };
+        let filename = match sequence {
+            Some(sequence) => format!("{:020}_{}", sequence, filename),
+            None => filename,
+        };
+
+        let key_prefix = self.key_prefix.as_deref().unwrap_or_default();
+        let separator = self.separator.as_deref().unwrap_or("/");
+        let partition_key = partition_key.replace('/', separator);
+        let key = if self.preserve_slashes {
+            // The partition key already starts and ends with a separator.
+            format!(
+                "{}{}{}_{}.{}.gz",
+                key_prefix,
+                partition_key,
+                name,
+                filename,
+                self.format.extension()
+            )
+        } else {
+            format!(
+                "{}{separator}{}{separator}{}_{}.{}.gz",
+                key_prefix,
+                partition_key,
+                name,
+                filename,
+                self.format.extension()
+            )
+            .replace(&separator.repeat(2), separator)
+        };
+
+        let key = if self.sanitize {
+            sanitize_object_key(&key)
+        } else {
+            key
+        };
+
+        match self.casing {
+            PartitionKeyCasing::Lowercase => key,
+            PartitionKeyCasing::Uppercase => uppercase_time_segments(&key, separator),
+        }
+    }
+}
+
+/// Uppercases the names of the `dt` and `hour` segments of `key`.
+fn uppercase_time_segments(key: &str, separator: &str) -> String {
+    key.split(separator)
+        .map(|segment| {
+            ["dt=", "hour="]
+                .into_iter()
+                .find_map(|name| {
+                    segment
+                        .strip_prefix(name)
+                        .map(|value| format!("{}{}", name.to_ascii_uppercase(), value))
+                })
+                .unwrap_or_else(|| segment.to_owned())
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// The `Content-Disposition` of an object, which is downloaded under the last segment of its key.
+fn content_disposition(key: &str) -> String {
+    let filename = key.rsplit('/').next().unwrap_or(key);
+    format!(
+        "attachment; filename=\"{}\"",
+        filename.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// The capacity to allocate upfront for a payload holding `uncompressed_size` bytes of events.
+fn payload_capacity(
+    uncompressed_size: usize,
+    compression: Compression,
+    presize_ratio: f64,
+) -> usize {
+    if compression.is_compressed() {
+        (uncompressed_size as f64 * presize_ratio.clamp(0.0, 1.0)) as usize
+    } else {
+        uncompressed_size
+    }
+}
+
+/// The partition an object is rolled up under, from its prefix.
+///
+/// The `dt` and `hour` segments are left out, so that there are as many rollups as there are
+/// partitions of each hour, rather than a new one every hour.
+fn rollup_partition(prefix: &str) -> String {
+    let segments = prefix
+        .split('/')
+        .filter(|segment| {
+            !segment.is_empty() && !segment.starts_with("dt=") && !segment.starts_with("hour=")
+        })
+        .collect::<Vec<_>>();
+    if segments.is_empty() {
+        "/".to_owned()
+    } else {
+        format!("/{}/", segments.join("/"))
+    }
+}
+
+/// The rollup of an object in the partition of `prefix`, as it's finalized.
+fn object_rollup(
+    prefix: &str,
+    request_metadata: &RequestMetadata,
+    payload: &EncodeResult<Bytes>,
+) -> DatadogArchivesObjectFinalized {
+    DatadogArchivesObjectFinalized {
+        partition: rollup_partition(prefix),
+        events: request_metadata.event_count(),
+        uncompressed_bytes: payload.uncompressed_byte_size,
+        compressed_bytes: payload.compressed_byte_size,
+    }
+}
+
+/// The key of an object which is written uncompressed, after its compression failed.
+fn uncompressed_key(key: &str) -> String {
+    key.strip_suffix(".gz").unwrap_or(key).to_owned()
+}
+
+/// Encodes the events of an archive, and compresses them.
+///
+/// With `uncompressed_fallback`, the events are encoded in full before being compressed, so that
+/// the archive can still be written uncompressed if its compression fails. An uncompressed archive
+/// is returned as an uncompressed `EncodeResult`.
+///
+/// The buffers are pre-sized from the estimated JSON size of the events, scaled by
+/// `presize_ratio` once compressed, so that they aren't reallocated repeatedly as they grow.
+fn encode_archive(
+    encoder: &dyn ArchiveEncoder,
+    events: Vec<Event>,
+    compression: Compression,
+    uncompressed_fallback: bool,
+    presize_ratio: f64,
+) -> io::Result<EncodeResult<Bytes>> {
+    if !uncompressed_fallback || !compression.is_compressed() {
+        let estimated_size = events.estimated_json_encoded_size_of().get();
+        let mut compressor = Compressor::with_capacity(
+            compression,
+            payload_capacity(estimated_size, compression, presize_ratio),
+        );
+        let uncompressed_byte_size = encode_with_manifest(encoder, events, &mut compressor)?;
+        let payload = compressor.finish()?.freeze();
+        return Ok(if compression.is_compressed() {
+            EncodeResult::compressed(payload, uncompressed_byte_size)
+        } else {
+            EncodeResult::uncompressed(payload)
+        });
+    }
+
+    encode_with_fallback(encoder, events, |payload| {
+        let mut compressor = Compressor::with_capacity(
+            compression,
+            payload_capacity(payload.len(), compression, presize_ratio),
+        );
+        compressor.write_all(payload)?;
+        compressor.finish()
+    })
+}
+
+fn encode_with_fallback<F>(
+    encoder: &dyn ArchiveEncoder,
+    events: Vec<Event>,
+    compress: F,
+) -> io::Result<EncodeResult<Bytes>>
+where
+    F: FnOnce(&[u8]) -> io::Result<BytesMut>,
+{
+    let mut payload = Vec::with_capacity(events.estimated_json_encoded_size_of().get());
+    encode_with_manifest(encoder, events, &mut payload)?;
+
+    match compress(&payload) {
+        Ok(compressed) => Ok(EncodeResult::compressed(compressed.freeze(), payload.len())),
+        Err(error) => {
+            emit!(DatadogArchivesCompressionFailed { error });
+            Ok(EncodeResult::uncompressed(payload.into()))
+        }
+    }
+}
+
+/// Lowercases the key, and replaces any character outside of `[a-z0-9/._=-]` with `_`.
+fn sanitize_object_key(key: &str) -> String {
+    key.chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '/' | '-' | '_' | '.' | '=') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct DatadogAzureRequestBuilder {
+    container_name: String,
+    object_key: ObjectKeyGenerator,
+    object_metadata: BTreeMap<String, String>,
+    encoding: Box<dyn ArchiveEncoder>,
+    content_disposition: bool,
+    content_language: Option<String>,
+    custom_time: bool,
+    uncompressed_fallback: bool,
+    compression_presize_ratio: f64,
+    object_rollups: bool,
+    uncompressed_size: bool,
+}
+
+impl RequestBuilder<(ArchivePartition, Vec<Event>)> for DatadogAzureRequestBuilder {
+    type Metadata = (AzureBlobMetadata, Option<DateTime<Utc>>, Option<String>);
+    type Events = Vec<Event>;
+    type Encoder = Box<dyn ArchiveEncoder>;
+    type Payload = Bytes;
+    type Request = AzureBlobRequest;
+    type Error = io::Error;
+
+    fn compression(&self) -> Compression {
+        DEFAULT_COMPRESSION
+    }
+
+    fn encoder(&self) -> &Self::Encoder {
+        &self.encoding
+    }
+
+    fn split_input(
+        &self,
+        input: (ArchivePartition, Vec<Event>),
+    ) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
+        let (partition, mut events) = input;
+        let finalizers = events.take_finalizers();
+        let metadata = AzureBlobMetadata {
+            partition_key: self.object_key.generate_for(&partition, &mut events),
+            count: events.len(),
+            byte_size: events.estimated_json_encoded_size_of(),
+            finalizers,
+        };
+        let builder = RequestMetadataBuilder::from_events(&events);
+        let custom_time = custom_time(self.custom_time, &events);
+        let rollup_prefix = self.object_rollups.then_some(partition.prefix);
+
+        ((metadata, custom_time, rollup_prefix), builder, events)
+    }
+
+    fn encode_events(
+        &self,
+        events: Self::Events,
+    ) -> Result<EncodeResult<Self::Payload>, Self::Error> {
+        encode_archive(
+            &*self.encoding,
+            events,
+            DEFAULT_COMPRESSION,
+            self.uncompressed_fallback,
+            self.compression_presize_ratio,
+        )
+    }
+
+    fn build_request(
+        &self,
+        (mut metadata, custom_time, rollup_prefix): Self::Metadata,
+        request_metadata: RequestMetadata,
+        payload: EncodeResult<Self::Payload>,
+    ) -> Self::Request {
+        let uncompressed = self.uncompressed_fallback && payload.compressed_byte_size.is_none();
+        if uncompressed {
+            metadata.partition_key = uncompressed_key(&metadata.partition_key);
+        }
+        if let Some(prefix) = rollup_prefix {
+            emit!(object_rollup(&prefix, &request_metadata, &payload));
+        }
+        let uncompressed_size = self
+            .uncompressed_size
+            .then_some(payload.uncompressed_byte_size);
+        let blob_data = payload.into_payload();
+
+        trace!(
+            message = "Sending events.",
+            bytes = ?blob_data.len(),
+            events_len = ?metadata.count,
+            container = ?self.container_name,
+            blob = ?metadata.partition_key
+        );
+
+        let content_disposition = self
+            .content_disposition
+            .then(|| content_disposition(&metadata.partition_key));
+
+        AzureBlobRequest {
+            blob_data,
+            content_encoding: if uncompressed {
+                None
+            } else {
+                DEFAULT_COMPRESSION.content_encoding()
+            },
+            content_type: if uncompressed {
+                self.encoding.content_type()
+            } else {
+                "application/gzip"
+            },
+            metadata,
+            request_metadata,
+            user_metadata: user_metadata(&self.object_metadata, custom_time, uncompressed_size),
+            content_disposition,
+            content_language: self.content_language.clone(),
+        }
+    }
+}
+
+// This is implemented manually to satisfy `SinkConfig`, because if we derive it automatically via
+// `#[configurable_component(sink("..."))]`, it would register the sink in a way that allowed it to
+// be used in `vector generate`, etc... and we don't want that.
+//
+// TODO: When the sink is fully supported and we expose it for use/within the docs, remove this.
+impl NamedComponent for DatadogArchivesSinkConfig {
+    fn get_component_name(&self) -> &'static str {
+        "datadog_archives"
+    }
+}
+
+#[async_trait::async_trait]
+impl SinkConfig for DatadogArchivesSinkConfig {
+    async fn build(&self, cx: SinkContext) -> crate::Result<(VectorSink, super::Healthcheck)> {
+        let sink_and_healthcheck = self.build_sink(cx, None).await?;
+        Ok(sink_and_healthcheck)
+    }
+
+    fn input(&self) -> Input {
+        let requirements = schema::Requirement::empty()
+            .optional_meaning("host", Kind::bytes())
+            .optional_meaning("message", Kind::bytes())
+            .optional_meaning("source", Kind::bytes())
+            .optional_meaning("service", Kind::bytes())
+            .optional_meaning("severity", Kind::bytes())
+            // TODO: A `timestamp` is required for rehydration, however today we generate a `Utc::now()`
+            // timestamp if it's not found in the event. We could require this meaning instead.
+            .optional_meaning("timestamp", Kind::timestamp())
+            .optional_meaning("trace_id", Kind::bytes());
+
+        Input::log().with_schema_requirement(requirements)
+    }
+
+    fn acknowledgements(&self) -> &AcknowledgementsConfig {
+        &self.acknowledgements
+    }
+}
+
+// Make a header pair from a key-value string pair
+fn make_header((name, value): (&String, &String)) -> crate::Result<(HeaderName, HeaderValue)> {
+    Ok((
+        HeaderName::from_bytes(name.as_bytes())?,
+        HeaderValue::from_str(value)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::print_stdout)] // tests
+
+    use std::{
+        collections::BTreeMap,
+        io::{Cursor, Read},
+    };
+
+    use chrono::DateTime;
+    use codecs::encoding::AvroSerializerConfig;
+    use indoc::indoc;
+    use lookup::owned_value_path;
+    use serde_json::json;
+    use vector_common::finalization::{BatchNotifier, BatchStatus, EventStatus};
+    use vector_core::config::LogNamespace;
+    use vrl::value;
+    use vrl::value::kind::Collection;
+
+    use super::*;
+    use crate::sinks::util::encoding::Encoder as _;
+
+    #[test]
+    fn generate_config() {
+        crate::test_util::test_generate_config::<DatadogArchivesSinkConfig>();
+    }
+
+    #[test]
+    fn encodes_event() {
+        let mut event = Event::Log(LogEvent::from("test message"));
+        let log_mut = event.as_mut_log();
+        log_mut.insert("service", "test-service");
+        log_mut.insert("not_a_reserved_attribute", "value");
+        log_mut.insert("tags", vec!["tag1:value1", "tag2:value2"]);
+        let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+        log_mut.insert("timestamp", timestamp);
+
+        let mut writer = Cursor::new(Vec::new());
+        let encoding = DatadogArchivesEncoding::new(Default::default());
+        _ = encoding.encode_input(vec![event], &mut writer);
+
+        let encoded = writer.into_inner();
+        let json: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(encoded.as_slice()).unwrap();
+
+        validate_event_id(
+            json.get("_id")
+                .expect("_id not found")
+                .as_str()
+                .expect("_id is not a string"),
+        );
+
+        assert_eq!(json.len(), 6); // _id, message, date, service, attributes
+        assert_eq!(
+            json.get("message")
+                .expect("message not found")
+                .as_str()
+                .expect("message is not a string"),
+            "test message"
+        );
+        assert_eq!(
+            json.get("date")
+                .expect("date not found")
+                .as_str()
+                .expect("date is not a string"),
+            "2021-08-23T16:00:27.879Z"
+        );
+        assert_eq!(
+            json.get("service")
+                .expect("service not found")
+                .as_str()
+                .expect("service is not a string"),
+            "test-service"
+        );
+
+        assert_eq!(
+            json.get("tags")
+                .expect("tags not found")
+                .as_array()
+                .expect("service is not an array")
+                .to_owned(),
+            vec!["tag1:value1", "tag2:value2"]
+        );
+
+        let attributes = json
+            .get("attributes")
+            .expect("attributes not found")
+            .as_object()
+            .expect("attributes is not an object");
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(
+            String::from_utf8_lossy(
+                attributes
+                    .get("not_a_reserved_attribute")
+                    .expect("not_a_reserved_attribute wasn't moved to attributes")
+                    .as_str()
+                    .expect("not_a_reserved_attribute is not a string")
+                    .as_ref()
+            ),
+            "value"
+        );
+    }
+
+    #[test]
+    fn generates_valid_key_for_an_event() {
+        let mut log = LogEvent::from("test message");
+
+        let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+        log.insert("timestamp", timestamp);
+
+        let partitioner = DatadogArchivesPartitioner::new(
+            Template::try_from(KEY_TEMPLATE).expect("invalid object key format"),
+            false,
+        );
+        let key = partitioner
+            .partition(&log.into())
+            .expect("key wasn't provided");
+
+        assert_eq!(key.prefix, "/dt=20210823/hour=16/");
+    }
+
+    #[test]
+    fn generates_valid_id() {
+        let log1 = Event::Log(LogEvent::from("test event 1"));
+        let mut writer = Cursor::new(Vec::new());
+        let encoding = DatadogArchivesEncoding::new(Default::default());
+        _ = encoding.encode_input(vec![log1], &mut writer);
+        let encoded = writer.into_inner();
+        let json: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(encoded.as_slice()).unwrap();
+        let id1 = json
+            .get("_id")
+            .expect("_id not found")
+            .as_str()
+            .expect("_id is not a string");
+        validate_event_id(id1);
+
+        // check that id is different for the next event
+        let log2 = Event::Log(LogEvent::from("test event 2"));
+        let mut writer = Cursor::new(Vec::new());
+        _ = encoding.encode_input(vec![log2], &mut writer);
+        let encoded = writer.into_inner();
+        let json: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(encoded.as_slice()).unwrap();
+        let id2 = json
+            .get("_id")
+            .expect("_id not found")
+            .as_str()
+            .expect("_id is not a string");
+        validate_event_id(id2);
+        assert_ne!(id1, id2)
+    }
+
+    #[test]
+    fn rotates_id_random_bytes() {
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.id_rotation_interval_millis = Some(3_600_000);
+
+        let decode = |id: String| {
+            let bytes = BASE64_STANDARD
+                .decode(id)
+                .expect("_id is not base64-encoded");
+            assert_eq!(bytes.len(), 18);
+            bytes
+        };
+
+        let start = DateTime::parse_from_rfc3339("2021-08-23T16:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let ids = [0, 1_000, 3_600_000, 3_601_000, 7_200_000].map(|offset| {
+            decode(encoding.generate_log_id_at(start + chrono::Duration::milliseconds(offset)))
+        });
+
+        // The random bytes only change when a new interval starts.
+        assert_eq!(ids[0][6..14], ids[1][6..14]);
+        assert_ne!(ids[1][6..14], ids[2][6..14]);
+        assert_eq!(ids[2][6..14], ids[3][6..14]);
+        assert_ne!(ids[3][6..14], ids[4][6..14]);
+
+        // The timestamp is kept, and every ID is unique.
+        assert_eq!(ids[0][..6], start.timestamp_millis().to_be_bytes()[2..]);
+        let unique = ids.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), ids.len());
+    }
+
+    #[test]
+    fn generates_date_if_missing() {
+        let log = Event::Log(LogEvent::from("test message"));
+        let mut writer = Cursor::new(Vec::new());
+        let encoding = DatadogArchivesEncoding::new(Default::default());
+        _ = encoding.encode_input(vec![log], &mut writer);
+        let encoded = writer.into_inner();
+        let json: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(encoded.as_slice()).unwrap();
+
+        let date = DateTime::parse_from_rfc3339(
+            json.get("date")
+                .expect("date not found")
+                .as_str()
+                .expect("date is not a string"),
+        )
+        .expect("date is not in an rfc3339 format");
+
+        // check that it is a recent timestamp
+        assert!(Utc::now().timestamp() - date.timestamp() < 1000);
+    }
+
+    #[test]
+    fn takes_date_from_timestamp_field() {
+        let event_time = DateTime::parse_from_rfc3339("2021-08-23T16:00:27.879Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let ingested_at = DateTime::parse_from_rfc3339("2021-08-23T16:05:00.000Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let encode = |timestamp_field: Option<&str>, event_time: Option<Value>| {
+            let mut log = LogEvent::from("test message");
+            log.insert("timestamp", ingested_at);
+            if let Some(event_time) = event_time {
+                log.insert("event_time", event_time);
+            }
+
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.timestamp_field = timestamp_field.map(str::to_owned);
+            let mut writer = Cursor::new(Vec::new());
+            encoding
+                .encode_input(vec![Event::Log(log)], &mut writer)
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        };
+
+        // The timestamp field is used in preference to the timestamp, which is kept as an attribute.
+        let json = encode(Some("event_time"), Some(event_time.into()));
+        assert_eq!(json["date"], "2021-08-23T16:00:27.879Z");
+        assert_eq!(json["attributes"]["timestamp"], "2021-08-23T16:05:00Z");
+        assert!(json["attributes"].get("event_time").is_none());
+
+        // It falls back to the timestamp when the field is missing, or doesn't hold a timestamp.
+        let json = encode(Some("event_time"), None);
+        assert_eq!(json["date"], "2021-08-23T16:05:00.000Z");
+        let json = encode(Some("event_time"), Some("yesterday".into()));
+        assert_eq!(json["date"], "2021-08-23T16:05:00.000Z");
+        assert_eq!(json["attributes"]["event_time"], "yesterday");
+
+        // The field is ignored unless configured.
+        let json = encode(None, Some(event_time.into()));
+        assert_eq!(json["date"], "2021-08-23T16:05:00.000Z");
+    }
+
+    #[test]
+    fn encodes_trailing_newline() {
+        for trailing_newline in [false, true] {
+            let events = vec![
+                Event::Log(LogEvent::from("test message 1")),
+                Event::Log(LogEvent::from("test message 2")),
+            ];
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.trailing_newline = trailing_newline;
+            let written = encoding.encode_input(events, &mut writer).unwrap();
+
+            let encoded = String::from_utf8(writer.into_inner()).unwrap();
+            assert_eq!(written, encoded.len());
+            assert_eq!(encoded.ends_with('\n'), trailing_newline);
+
+            let records = encoded.lines().collect::<Vec<_>>();
+            assert_eq!(records.len(), 2);
+            for record in records {
+                serde_json::from_str::<serde_json::Value>(record)
+                    .expect("record is not valid JSON");
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_large_integers() {
+        for stringify_large_integers in [false, true] {
+            let mut event = Event::Log(LogEvent::from("test message"));
+            let log_mut = event.as_mut_log();
+            log_mut.insert("small", 42);
+            log_mut.insert("large", MAX_SAFE_INTEGER + 1);
+            log_mut.insert("nested.negative", i64::MIN);
+            log_mut.insert("list", vec![Value::from(i64::MAX), Value::from(1)]);
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.stringify_large_integers = stringify_large_integers;
+            _ = encoding.encode_input(vec![event], &mut writer);
+
+            let json: BTreeMap<String, serde_json::Value> =
+                serde_json::from_slice(&writer.into_inner()).unwrap();
+            let attributes = json.get("attributes").unwrap();
+
+            assert_eq!(attributes["small"], json!(42));
+            assert_eq!(attributes["list"][1], json!(1));
+            if stringify_large_integers {
+                assert_eq!(attributes["large"], json!("9007199254740992"));
+                assert_eq!(
+                    attributes["nested"]["negative"],
+                    json!("-9223372036854775808")
+                );
+                assert_eq!(attributes["list"][0], json!("9223372036854775807"));
+            } else {
+                assert_eq!(attributes["large"], json!(9007199254740992_i64));
+                assert_eq!(attributes["nested"]["negative"], json!(i64::MIN));
+                assert_eq!(attributes["list"][0], json!(i64::MAX));
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_encoding_matches_batch_encoding() {
+        let events = (0..10)
+            .map(|i| {
+                let mut event = Event::Log(LogEvent::from(format!("test message {}", i)));
+                event.as_mut_log().insert("custom", i64::from(i));
+                event
+            })
+            .collect::<Vec<_>>();
+
+        let encoding = DatadogArchivesEncoding::new(Default::default());
+
+        let mut writer = Cursor::new(Vec::new());
+        _ = encoding.encode_input(events.clone(), &mut writer);
+        let streamed = writer.into_inner();
+
+        // Reshape the whole batch up front, and encode it with the generic batch encoder.
+        let mut reshaped = events;
+        for event in reshaped.iter_mut() {
+            encoding.reshape(event.as_mut_log()).unwrap();
+        }
+        let mut writer = Cursor::new(Vec::new());
+        _ = encoding.encoder.encode_input(reshaped, &mut writer);
+        let batched = writer.into_inner();
+
+        // Event IDs are unique to each encoding, so they're left out of the comparison.
+        let parse = |encoded: &[u8]| {
+            std::str::from_utf8(encoded)
+                .unwrap()
+                .split('\n')
+                .map(|line| {
+                    let mut json: BTreeMap<String, serde_json::Value> =
+                        serde_json::from_str(line).unwrap();
+                    assert!(json.remove("_id").is_some());
+                    json
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(streamed.len(), batched.len());
+        assert_eq!(parse(&streamed), parse(&batched));
+    }
+
+    #[test]
+    fn encodes_envelope() {
+        let events = (0..2)
+            .map(|i| {
+                let mut event = Event::Log(LogEvent::from(format!("test message {}", i)));
+                event.as_mut_log().insert("custom", i64::from(i));
+                event
+            })
+            .collect::<Vec<_>>();
+
+        let mut writer = Cursor::new(Vec::new());
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.trailing_newline = true;
+        encoding.envelope = Some(ArchiveEnvelopeConfig {
+            key: "record".to_owned(),
+            ..Default::default()
+        });
+        _ = encoding.encode_input(events, &mut writer);
+
+        let encoded = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(encoded.ends_with('\n'));
+
+        let lines = encoded.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        for (i, line) in lines.into_iter().enumerate() {
+            let json: BTreeMap<String, serde_json::Value> = serde_json::from_str(line).unwrap();
+            assert_eq!(
+                json.keys().collect::<Vec<_>>(),
+                vec!["ingested_at", "record"]
+            );
+            assert!(DateTime::parse_from_rfc3339(json["ingested_at"].as_str().unwrap()).is_ok());
+
+            let record = &json["record"];
+            assert_eq!(record["message"], json!(format!("test message {}", i)));
+            assert_eq!(record["attributes"]["custom"], json!(i));
+            assert!(record["_id"].is_string());
+            assert!(record["date"].is_string());
+        }
+    }
+
+    #[test]
+    fn maps_host() {
+        let encode = |host_mapping| {
+            let mut log = LogEvent::from(value!({
+                "message": "test message",
+                "resource": {"hostname": "web-1"},
+            }));
+            LogNamespace::Vector.insert_standard_vector_source_metadata(
+                &mut log,
+                "demo_logs",
+                Utc::now(),
+            );
+            let schema = schema::Definition::new_with_default_metadata(
+                Kind::object(Collection::empty()),
+                [LogNamespace::Vector],
+            )
+            .with_event_field(
+                &owned_value_path!("resource", "hostname"),
+                Kind::bytes(),
+                Some("host"),
+            );
+            log.metadata_mut().set_schema_definition(&Arc::new(schema));
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.host_mapping = host_mapping;
+            _ = encoding.encode_input(vec![Event::Log(log)], &mut writer);
+
+            let json: BTreeMap<String, serde_json::Value> =
+                serde_json::from_slice(&writer.into_inner()).unwrap();
+            (json.get("host").cloned(), json["attributes"].clone())
+        };
+
+        let (host, attributes) = encode(HostMapping::Move);
+        assert_eq!(host, Some(json!("web-1")));
+        assert_eq!(attributes["resource"], json!({}));
+
+        let (host, attributes) = encode(HostMapping::Copy);
+        assert_eq!(host, Some(json!("web-1")));
+        assert_eq!(attributes["resource"], json!({"hostname": "web-1"}));
+
+        let (host, attributes) = encode(HostMapping::Rename {
+            key: "hostname".to_owned(),
+        });
+        assert_eq!(host, None);
+        assert_eq!(attributes["hostname"], json!("web-1"));
+        assert_eq!(attributes["resource"], json!({}));
+    }
+
+    #[test]
+    fn handles_field_collisions() {
+        let encode = |field_collision| {
+            let mut log = LogEvent::from(value!({
+                "message": "test message",
+                "host": "literal-host",
+                "resource": {"hostname": "web-1"},
+            }));
+            LogNamespace::Vector.insert_standard_vector_source_metadata(
+                &mut log,
+                "demo_logs",
+                Utc::now(),
+            );
+            let schema = schema::Definition::new_with_default_metadata(
+                Kind::object(Collection::empty()),
+                [LogNamespace::Vector],
+            )
+            .with_event_field(
+                &owned_value_path!("resource", "hostname"),
+                Kind::bytes(),
+                Some("host"),
+            );
+            log.metadata_mut().set_schema_definition(&Arc::new(schema));
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.field_collision = field_collision;
+            _ = encoding.encode_input(vec![Event::Log(log)], &mut writer);
+
+            let encoded = writer.into_inner();
+            (!encoded.is_empty()).then(|| {
+                let json: BTreeMap<String, serde_json::Value> =
+                    serde_json::from_slice(&encoded).unwrap();
+                (json["host"].clone(), json["attributes"].clone())
+            })
+        };
+
+        let (host, attributes) = encode(FieldCollision::Overwrite).unwrap();
+        assert_eq!(host, json!("web-1"));
+        assert_eq!(attributes["resource"], json!({}));
+
+        let (host, attributes) = encode(FieldCollision::KeepExisting).unwrap();
+        assert_eq!(host, json!("literal-host"));
+        assert_eq!(attributes["resource"], json!({"hostname": "web-1"}));
+
+        assert!(encode(FieldCollision::Error).is_none());
+    }
+
+    #[test]
+    fn handles_existing_attributes() {
+        let encode = |existing_attributes, attributes| {
+            let log = LogEvent::from(value!({
+                "message": "test message",
+                "attributes": attributes,
+                "region": "eu",
+            }));
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.existing_attributes = existing_attributes;
+            _ = encoding.encode_input(vec![Event::Log(log)], &mut writer);
+
+            let json: BTreeMap<String, serde_json::Value> =
+                serde_json::from_slice(&writer.into_inner()).unwrap();
+            json["attributes"].clone()
+        };
+        let existing = || value!({"region": "us", "team": "core"});
+
+        assert_eq!(
+            encode(ExistingAttributes::Overwrite, existing()),
+            json!({"region": "eu"})
+        );
+        assert_eq!(
+            encode(ExistingAttributes::Merge, existing()),
+            json!({"region": "eu", "team": "core"})
+        );
+        assert_eq!(
+            encode(ExistingAttributes::Merge, value!("not an object")),
+            json!({"region": "eu", "attributes": "not an object"})
+        );
+        assert_eq!(
+            encode(ExistingAttributes::default(), existing()),
+            json!({"region": "eu", "attributes": {"region": "us", "team": "core"}})
+        );
+        assert_eq!(
+            encode(
+                ExistingAttributes::NestUnder {
+                    key: "original_attributes".to_string()
+                },
+                existing()
+            ),
+            json!({"region": "eu", "original_attributes": {"region": "us", "team": "core"}})
+        );
+    }
+
+    #[test]
+    fn parses_existing_attributes() {
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            existing_attributes.mode = "nest_under"
+            existing_attributes.key = "original_attributes"
+        "#})
+        .unwrap();
+
+        assert_eq!(
+            config.existing_attributes,
+            ExistingAttributes::NestUnder {
+                key: "original_attributes".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn reports_rejected_events() {
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.non_string_message = NonStringMessage::Error;
+        let event = Event::Log(LogEvent::from(value!({"message": 42, "service": "web"})));
+
+        // Only the error itself is reported by default.
+        assert!(matches!(
+            encoding.reshape_or_reject(event.clone()),
+            Err(None)
+        ));
+
+        encoding.emit_rejected_events = true;
+        let rejected = encoding.reshape_or_reject(event).unwrap_err().unwrap();
+        assert_eq!(rejected.reason, "non_string_message");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&rejected.sample).unwrap(),
+            json!({"message": 42, "service": "web"})
+        );
+    }
+
+    #[test]
+    fn handles_future_timestamps() {
+        let future = Utc::now() + Duration::days(365);
+        let reshape = |future_timestamps, timestamp: DateTime<Utc>| {
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.future_timestamps = future_timestamps;
+            let mut log = LogEvent::from("test message");
+            log.insert("timestamp", timestamp);
+            encoding
+                .reshape_or_reject(Event::Log(log))
+                .map(|event| {
+                    let date = event.as_log().get("date").unwrap().to_string_lossy();
+                    DateTime::parse_from_rfc3339(&date)
+                        .unwrap()
+                        .with_timezone(&Utc)
+                })
+                .map_err(|_| ())
+        };
+        let millis = |timestamp: DateTime<Utc>| timestamp.timestamp_millis();
+
+        assert_eq!(
+            reshape(FutureTimestamps::Leave, future).map(millis),
+            Ok(millis(future))
+        );
+
+        let before = Utc::now();
+        let clamped = reshape(FutureTimestamps::Clamp, future).unwrap();
+        assert!(millis(clamped) >= millis(before) && clamped <= Utc::now());
+
+        assert_eq!(reshape(FutureTimestamps::Reject, future), Err(()));
+
+        // Timestamps within the tolerance are left as is.
+        let ahead = Utc::now() + Duration::seconds(60);
+        for future_timestamps in [FutureTimestamps::Clamp, FutureTimestamps::Reject] {
+            assert_eq!(
+                reshape(future_timestamps, ahead).map(millis),
+                Ok(millis(ahead))
+            );
+        }
+    }
+
+    #[test]
+    fn reports_rejected_future_timestamps() {
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.future_timestamps = FutureTimestamps::Reject;
+        encoding.emit_rejected_events = true;
+        let mut log = LogEvent::from("test message");
+        log.insert("timestamp", Utc::now() + Duration::days(365));
+
+        let rejected = encoding
+            .reshape_or_reject(Event::Log(log))
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(rejected.reason, "future_timestamp");
+    }
+
+    #[test]
+    fn truncates_samples_of_rejected_events() {
+        // `{"message":"a` is 13 bytes long, so the limit falls in the middle of an `é`.
+        let event = Event::Log(LogEvent::from(format!("a{}", "é".repeat(1000))));
+        let rejected = rejected_event("field_collision", &event);
+
+        assert_eq!(rejected.reason, "field_collision");
+        assert_eq!(rejected.sample.len(), MAX_REJECTED_SAMPLE_BYTES - 1);
+        assert!(rejected.sample.starts_with(r#"{"message":"aéé"#));
+    }
+
+    #[test]
+    fn ignores_fields_already_at_their_reserved_attribute() {
+        // With the legacy namespace, the host is already at `host`, which isn't a collision.
+        let mut log = LogEvent::from("test message");
+        log.insert("host", "web-1");
+
+        let mut writer = Cursor::new(Vec::new());
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.field_collision = FieldCollision::Error;
+        _ = encoding.encode_input(vec![Event::Log(log)], &mut writer);
+
+        let json: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(&writer.into_inner()).unwrap();
+        assert_eq!(json["host"], json!("web-1"));
+        assert_eq!(json["message"], json!("test message"));
+    }
+
+    #[test]
+    fn encodes_tags_as_map() {
+        let encode = |tags_format, tags: Value| {
+            let mut event = Event::Log(LogEvent::from("test message"));
+            event.as_mut_log().insert("tags", tags);
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.tags_format = tags_format;
+            _ = encoding.encode_input(vec![event], &mut writer);
+
+            let json: BTreeMap<String, serde_json::Value> =
+                serde_json::from_slice(&writer.into_inner()).unwrap();
+            json["tags"].clone()
+        };
+        let tags = |tags: &[&str]| {
+            Value::from(tags.iter().map(|tag| Value::from(*tag)).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            encode(TagsFormat::Map, tags(&["a:1", "b:2"])),
+            json!({"a": "1", "b": "2"})
+        );
+        assert_eq!(
+            encode(TagsFormat::Array, tags(&["a:1", "b:2"])),
+            json!(["a:1", "b:2"])
+        );
+
+        // Only the first `:` separates the key from the value.
+        assert_eq!(
+            encode(TagsFormat::Map, tags(&["url:http://example.com", "beta"])),
+            json!({"url": "http://example.com", "beta": ""})
+        );
+
+        // Malformed tags are left as they are.
+        assert_eq!(
+            encode(TagsFormat::Map, tags(&["a:1", ":2"])),
+            json!(["a:1", ":2"])
+        );
+        assert_eq!(
+            encode(TagsFormat::Map, Value::from(vec![Value::from(1_i64)])),
+            json!([1])
+        );
+        assert_eq!(encode(TagsFormat::Map, Value::from("a:1")), json!("a:1"));
+    }
+
+    #[test]
+    fn converts_tags_into_arrays() {
+        let encode = |tags_input_format, tags: Value| {
+            let mut event = Event::Log(LogEvent::from("test message"));
+            event.as_mut_log().insert("tags", tags);
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.tags_input_format = tags_input_format;
+            _ = encoding.encode_input(vec![event], &mut writer);
+
+            let json: BTreeMap<String, serde_json::Value> =
+                serde_json::from_slice(&writer.into_inner()).unwrap();
+            json["tags"].clone()
+        };
+
+        for format in [TagsInputFormat::Map, TagsInputFormat::Auto] {
+            assert_eq!(encode(format, value!({"a": "1"})), json!(["a:1"]));
+            assert_eq!(
+                encode(format, value!({"a": 1, "beta": "", "gamma": null})),
+                json!(["a:1", "beta", "gamma"])
+            );
+            assert_eq!(
+                encode(format, value!(["a:1", "b:2"])),
+                json!(["a:1", "b:2"])
+            );
+        }
+        assert_eq!(
+            encode(TagsInputFormat::Array, value!({"a": "1"})),
+            json!({"a": "1"})
+        );
+
+        // Only `auto` splits strings of comma-separated tags.
+        assert_eq!(
+            encode(TagsInputFormat::Auto, value!("a:1, b:2,")),
+            json!(["a:1", "b:2"])
+        );
+        assert_eq!(
+            encode(TagsInputFormat::Map, value!("a:1,b:2")),
+            json!("a:1,b:2")
+        );
+    }
+
+    #[test]
+    fn encodes_passthrough_format() {
+        let raw = "<13>1 2021-08-23T16:00:27Z host app - - {\"not\": \"reshaped\"}";
+        let events = vec![
+            Event::Log(LogEvent::from(raw)),
+            Event::Log(LogEvent::from("second line")),
+        ];
+
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            format = "passthrough"
+        "#})
+        .unwrap();
+        let encoder = config.build_encoder();
+
+        let mut writer = Cursor::new(Vec::new());
+        encoder.encode_input(events, &mut writer).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            format!("{}\nsecond line", raw)
+        );
+        assert_eq!(encoder.content_type(), "text/plain");
+
+        let key = config
+            .build_object_key_generator()
+            .unwrap()
+            .generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()));
+        assert!(key.starts_with("/dt=20210823/hour=16/archive_"));
+        assert!(key.ends_with(".log.gz"));
+    }
+
+    #[test]
+    fn writes_a_count_header() {
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.count_header = true;
+        encoding.trailing_newline = true;
+        let events = ["first", "second", "third"]
+            .into_iter()
+            .map(|message| Event::Log(LogEvent::from(message)))
+            .collect();
+
+        let mut writer = Cursor::new(Vec::new());
+        let written = encoding.encode_input(events, &mut writer).unwrap();
+        let payload = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(written, payload.len());
+        assert!(payload.ends_with('\n'));
+
+        let lines = payload
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "header": { "count": 3 } }));
+        assert_eq!(
+            lines[1..]
+                .iter()
+                .map(|line| line["message"].clone())
+                .collect::<Vec<_>>(),
+            vec![json!("first"), json!("second"), json!("third")]
+        );
+    }
+
+    #[test]
+    fn encodes_pretty_json() {
+        let encode = |pretty_json| {
+            let config = DatadogArchivesSinkConfig {
+                pretty_json,
+                ..toml::from_str(indoc! {r#"
+                    service = "aws_s3"
+                    bucket = "vector-datadog-archives"
+                "#})
+                .unwrap()
+            };
+            let mut writer = Cursor::new(Vec::new());
+            _ = config.build_encoder().encode_input(
+                vec![Event::Log(LogEvent::from("test message"))],
+                &mut writer,
+            );
+            String::from_utf8(writer.into_inner()).unwrap()
+        };
+
+        let compact = encode(false);
+        assert_eq!(compact.lines().count(), 1);
+
+        let pretty = encode(true);
+        assert!(pretty.lines().count() > 1);
+        assert!(pretty.contains("\n  \"message\": \"test message\""));
+        let json: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(json["message"], json!("test message"));
+    }
+
+    #[test]
+    fn deduplicates_events_by_field() {
+        let (batch, mut receiver) = BatchNotifier::new_with_receiver();
+        let mut events = [
+            Some("a"),
+            Some("b"),
+            Some("a"),
+            None,
+            Some("b"),
+            None,
+            Some("c"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, request_id)| {
+            let mut log = LogEvent::from(format!("event {}", i)).with_batch_notifier(&batch);
+            if let Some(request_id) = request_id {
+                log.insert("request_id", request_id);
+            }
+            Event::Log(log)
+        })
+        .collect::<Vec<_>>();
+        drop(batch);
+
+        let config = DatadogArchivesSinkConfig {
+            dedup_by: Some("request_id".to_owned()),
+            ..toml::from_str(indoc! {r#"
+                service = "aws_s3"
+                bucket = "vector-datadog-archives"
+                format = "verbatim"
+            "#})
+            .unwrap()
+        };
+        let finalizers = events.take_finalizers();
+        let mut writer = Cursor::new(Vec::new());
+        config
+            .build_encoder()
+            .encode_input(events, &mut writer)
+            .unwrap();
+
+        // The first event of each key is kept, along with the events without a key.
+        let messages = String::from_utf8(writer.into_inner())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                json!("event 0"),
+                json!("event 1"),
+                json!("event 3"),
+                json!("event 5"),
+                json!("event 6"),
+            ]
+        );
+
+        // The duplicates are acknowledged along with the object.
+        finalizers.update_status(EventStatus::Delivered);
+        drop(finalizers);
+        assert_eq!(receiver.try_recv(), Ok(BatchStatus::Delivered));
+    }
+
+    #[test]
+    fn applies_the_value_fallback() {
+        let infinity = Value::Float(ordered_float::NotNan::new(f64::INFINITY).unwrap());
+        let invalid_utf8 = Value::Bytes(Bytes::from_static(b"ab\xffcd"));
+        let encode = |value_fallback| {
+            let config = DatadogArchivesSinkConfig {
+                value_fallback,
+                ..toml::from_str(indoc! {r#"
+                    service = "aws_s3"
+                    bucket = "vector-datadog-archives"
+                    format = "verbatim"
+                "#})
+                .unwrap()
+            };
+            let mut log = LogEvent::from("test message");
+            log.insert("float", infinity.clone());
+            log.insert("bytes", invalid_utf8.clone());
+            log.insert(
+                "nested",
+                Value::Array(vec![infinity.clone(), Value::from(1)]),
+            );
+
+            let mut writer = Cursor::new(Vec::new());
+            config
+                .build_encoder()
+                .encode_input(vec![Event::Log(log)], &mut writer)
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        };
+
+        let json = encode(ValueFallback::Leave);
+        assert_eq!(json["float"], json!(null));
+        assert_eq!(json["bytes"], json!("ab\u{fffd}cd"));
+        assert_eq!(json["nested"], json!([null, 1]));
+
+        let json = encode(ValueFallback::DropField);
+        assert!(json.get("float").is_none());
+        assert!(json.get("bytes").is_none());
+        assert_eq!(json["nested"], json!([1]));
+        assert_eq!(json["message"], json!("test message"));
+
+        let json = encode(ValueFallback::Stringify);
+        assert_eq!(json["float"], json!("inf"));
+        assert_eq!(json["bytes"], json!("ab\u{fffd}cd"));
+        assert_eq!(json["nested"], json!(["inf", 1]));
+
+        let json = encode(ValueFallback::Base64);
+        assert_eq!(json["float"], json!("inf"));
+        assert_eq!(json["bytes"], json!("YWL/Y2Q="));
+        assert_eq!(json["nested"], json!(["inf", 1]));
+    }
+
+    #[test]
+    fn handles_encode_failures() {
+        // Events whose `message` isn't a string can't be encoded with this schema.
+        let schema = r#"{"type": "record", "name": "log", "fields": [{"name": "message", "type": "string"}]}"#;
+        let encode = |encode_failure, messages: Vec<Value>| {
+            let encoding = VerbatimEncoding {
+                encoder: (
+                    Default::default(),
+                    Encoder::<Framer>::new(
+                        NewlineDelimitedEncoder::new().into(),
+                        AvroSerializerConfig::new(schema.to_owned())
+                            .build()
+                            .unwrap()
+                            .into(),
+                    ),
+                ),
+                trailing_newline: false,
+                encode_failure,
+                value_fallback: Default::default(),
+                dedup_by: None,
+                emit_rejected_events: false,
+            };
+            let events = messages
+                .into_iter()
+                .map(|message| {
+                    let mut log = LogEvent::default();
+                    log.insert("message", message);
+                    Event::Log(log)
+                })
+                .collect();
+            let mut writer = Cursor::new(Vec::new());
+            encoding
+                .encode_input(events, &mut writer)
+                .map(|_| writer.into_inner())
+        };
+
+        let error = encode(
+            EncodeFailure::FailBatch,
+            vec!["first".into(), 1.into(), "last".into()],
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // Strings are encoded as their zigzag-encoded length followed by their bytes.
+        assert_eq!(
+            encode(
+                EncodeFailure::SkipEvent,
+                vec!["first".into(), 1.into(), "last".into()],
+            )
+            .unwrap(),
+            b"\x0afirst\n\x08last"
+        );
+        // A skipped last event doesn't leave a trailing newline behind.
+        assert_eq!(
+            encode(
+                EncodeFailure::SkipEvent,
+                vec!["first".into(), "second".into(), 3.into()],
+            )
+            .unwrap(),
+            b"\x0afirst\n\x0csecond"
+        );
+    }
+
+    #[test]
+    fn encodes_verbatim_format() {
+        let mut event = Event::Log(LogEvent::from("test message"));
+        event
+            .as_mut_log()
+            .insert("not_a_reserved_attribute", "value");
+
+        let encoders: [Box<dyn ArchiveEncoder>; 2] = [
+            Box::new(VerbatimEncoding {
+                encoder: ndjson_encoder(Default::default(), false),
+                trailing_newline: false,
+                encode_failure: Default::default(),
+                value_fallback: Default::default(),
+                dedup_by: None,
+                emit_rejected_events: false,
+            }),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        ];
+        let [verbatim, datadog] = encoders.map(|encoder| {
+            let mut writer = Cursor::new(Vec::new());
+            encoder
+                .encode_input(vec![event.clone()], &mut writer)
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        });
+
+        assert_eq!(verbatim["message"], json!("test message"));
+        assert_eq!(verbatim["not_a_reserved_attribute"], json!("value"));
+        assert!(verbatim.get("timestamp").is_some());
+        assert!(verbatim.get("attributes").is_none());
+        assert!(verbatim.get("_id").is_none());
+
+        assert_eq!(datadog["message"], json!("test message"));
+        assert_eq!(
+            datadog["attributes"],
+            json!({"not_a_reserved_attribute": "value"})
+        );
+        assert!(datadog.get("_id").is_some());
+        assert!(datadog.get("date").is_some());
+    }
+
+    #[test]
+    fn encodes_minimal_dd_format() {
+        let mut event = Event::Log(LogEvent::from("test message"));
+        let log_mut = event.as_mut_log();
+        log_mut.insert("host", "my-host");
+        log_mut.insert("not_a_reserved_attribute", "value");
+        log_mut.insert("tags", vec!["env:prod"]);
+        log_mut.insert(
+            "timestamp",
+            DateTime::parse_from_rfc3339("2021-08-23T16:00:27.879Z")
+                .expect("invalid test case")
+                .with_timezone(&Utc),
+        );
+
+        let mut minimal = DatadogArchivesEncoding::new(Default::default());
+        minimal.minimal = true;
+        minimal.tags_format = TagsFormat::Map;
+        let encoders: [Box<dyn ArchiveEncoder>; 2] = [
+            Box::new(VerbatimEncoding {
+                encoder: ndjson_encoder(Default::default(), false),
+                trailing_newline: false,
+                encode_failure: Default::default(),
+                value_fallback: Default::default(),
+                dedup_by: None,
+                emit_rejected_events: false,
+            }),
+            Box::new(minimal),
+        ];
+        let [original, mut json] = encoders.map(|encoder| {
+            let mut writer = Cursor::new(Vec::new());
+            encoder
+                .encode_input(vec![event.clone()], &mut writer)
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        });
+
+        assert!(json.get("_id").is_some());
+        assert_eq!(json["date"], json!("2021-08-23T16:00:27.879Z"));
+
+        // Besides `_id` and `date`, the event is left untouched.
+        let json = json.as_object_mut().unwrap();
+        json.remove("_id");
+        json.remove("date");
+        assert_eq!(serde_json::Value::Object(json.clone()), original);
+    }
+
+    #[test]
+    fn handles_non_string_messages() {
+        let mut event = Event::Log(LogEvent::default());
+        event.as_mut_log().insert(
+            "message",
+            Value::from(BTreeMap::from([
+                ("level".to_owned(), Value::from("info")),
+                ("count".to_owned(), Value::from(2)),
+            ])),
+        );
+        let encode = |non_string_message| {
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.non_string_message = non_string_message;
+            let mut writer = Cursor::new(Vec::new());
+            encoding
+                .encode_input(vec![event.clone()], &mut writer)
+                .unwrap();
+            writer.into_inner()
+        };
+        let parse = |payload: Vec<u8>| {
+            serde_json::from_slice::<serde_json::Value>(&payload).unwrap()["message"].clone()
+        };
+
+        assert_eq!(
+            parse(encode(NonStringMessage::Leave)),
+            json!({"count": 2, "level": "info"})
+        );
+        assert_eq!(
+            parse(encode(NonStringMessage::Stringify)),
+            json!(r#"{"count":2,"level":"info"}"#)
+        );
+        assert!(encode(NonStringMessage::Error).is_empty());
+
+        // String messages are archived as is under every policy.
+        event.as_mut_log().insert("message", "test message");
+        for non_string_message in [NonStringMessage::Stringify, NonStringMessage::Error] {
+            assert_eq!(parse(encode(non_string_message)), json!("test message"));
+        }
+    }
+
+    #[test]
+    fn limits_attributes() {
+        let mut event = Event::Log(LogEvent::from("test message"));
+        for i in 0..1000 {
+            event
+                .as_mut_log()
+                .insert(format!("attr_{:04}", i).as_str(), i);
+        }
+        let encode = |attributes_overflow| {
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.max_attributes = NonZeroUsize::new(3);
+            encoding.attributes_overflow = attributes_overflow;
+            let mut writer = Cursor::new(Vec::new());
+            encoding
+                .encode_input(vec![event.clone()], &mut writer)
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        };
+
+        // The first attributes in alphabetical order are kept.
+        let json = encode(AttributesOverflow::Drop);
+        assert_eq!(
+            json["attributes"],
+            json!({"attr_0000": 0, "attr_0001": 1, "attr_0002": 2})
+        );
+        assert_eq!(json["message"], json!("test message"));
+
+        let json = encode(AttributesOverflow::Collapse);
+        let attributes = json["attributes"].as_object().unwrap();
+        assert_eq!(attributes.len(), 3);
+        assert_eq!(attributes["attr_0000"], json!(0));
+        assert_eq!(attributes["attr_0001"], json!(1));
+        let overflow = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(
+            attributes["_overflow"].as_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(overflow.len(), 998);
+        assert_eq!(overflow["attr_0002"], json!(2));
+        assert_eq!(overflow["attr_0999"], json!(999));
+    }
+
+    #[test]
+    fn keeps_attributes_within_the_limit() {
+        let mut event = Event::Log(LogEvent::from("test message"));
+        event.as_mut_log().insert("one", 1);
+        event.as_mut_log().insert("two", 2);
+
+        for attributes_overflow in [AttributesOverflow::Drop, AttributesOverflow::Collapse] {
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.max_attributes = NonZeroUsize::new(2);
+            encoding.attributes_overflow = attributes_overflow;
+            let mut writer = Cursor::new(Vec::new());
+            encoding
+                .encode_input(vec![event.clone()], &mut writer)
+                .unwrap();
+            let json = serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap();
+            assert_eq!(json["attributes"], json!({"one": 1, "two": 2}));
+        }
+    }
+
+    #[test]
+    fn redacts_fields() {
+        let mut event = Event::Log(LogEvent::from("test message"));
+        let log_mut = event.as_mut_log();
+        log_mut.insert("password", "hunter2");
+        log_mut.insert("secret_token", "abc");
+        log_mut.insert("secret_key.id", "def");
+        log_mut.insert("user", "alice");
+
+        let mut writer = Cursor::new(Vec::new());
+        let mut encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.redact_fields = vec![
+            "password".to_owned(),
+            "message".to_owned(),
+            "secret_*".to_owned(),
+            "missing".to_owned(),
+        ];
+        _ = encoding.encode_input(vec![event], &mut writer);
+
+        let json: serde_json::Value = serde_json::from_slice(&writer.into_inner()).unwrap();
+        assert_eq!(json["message"], json!(REDACTED));
+        assert_eq!(json["attributes"]["password"], json!(REDACTED));
+        assert_eq!(json["attributes"]["secret_token"], json!(REDACTED));
+        assert_eq!(json["attributes"]["secret_key"]["id"], json!(REDACTED));
+        assert_eq!(json["attributes"]["user"], json!("alice"));
+        assert!(json["attributes"].get("missing").is_none());
+    }
+
+    #[test]
+    fn sets_defaults_of_missing_reserved_attributes() {
+        let encode = |event: LogEvent| {
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.reserved_attribute_defaults = BTreeMap::from([
+                ("service".to_owned(), "unknown".to_owned()),
+                ("source".to_owned(), "vector".to_owned()),
+            ]);
+            _ = encoding.encode_input(vec![Event::Log(event)], &mut writer);
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        };
+
+        let json = encode(LogEvent::from("test message"));
+        assert_eq!(json["service"], json!("unknown"));
+        assert_eq!(json["source"], json!("vector"));
+
+        let mut event = LogEvent::from("test message");
+        event.insert("service", "api");
+        event.insert("source", "");
+        let json = encode(event);
+        assert_eq!(json["service"], json!("api"));
+        assert_eq!(json["source"], json!("vector"));
+        assert!(json["attributes"].get("service").is_none());
+    }
+
+    #[test]
+    fn normalizes_mapped_statuses() {
+        let encode = |status: Value| {
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.status_mapping = BTreeMap::from([
+                ("WARNING".to_owned(), DatadogStatus::Warn),
+                ("fatal".to_owned(), DatadogStatus::Critical),
+                ("3".to_owned(), DatadogStatus::Error),
+            ]);
+            let mut event = LogEvent::from("test message");
+            event.insert("status", status);
+            _ = encoding.encode_input(vec![Event::Log(event)], &mut writer);
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()["status"]
+                .clone()
+        };
+
+        assert_eq!(encode(Value::from("WARNING")), json!("warn"));
+        assert_eq!(encode(Value::from("fatal")), json!("critical"));
+        assert_eq!(encode(Value::from(3)), json!("error"));
+
+        // Values which aren't mapped, including other cases of mapped ones, are left as is.
+        assert_eq!(encode(Value::from("info")), json!("info"));
+        assert_eq!(encode(Value::from("warning")), json!("warning"));
+        assert_eq!(encode(Value::from(4)), json!(4));
+    }
+
+    #[test]
+    fn rejects_defaults_of_unsupported_attributes() {
+        let config = |attribute: &str| {
+            toml::from_str::<DatadogArchivesSinkConfig>(&format!(
+                "service = \"aws_s3\"\nbucket = \"vector-datadog-archives\"\n\
+                 [reserved_attribute_defaults]\n{} = \"default\"",
+                attribute
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(
+            config("service").check_reserved_attribute_defaults(),
+            Ok(())
+        );
+        assert_eq!(config("status").check_reserved_attribute_defaults(), Ok(()));
+        for attribute in ["date", "_id", "user"] {
+            assert_eq!(
+                config(attribute).check_reserved_attribute_defaults(),
+                Err(ConfigError::UnsupportedReservedAttributeDefault {
+                    attribute: attribute.to_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn stamps_records_with_the_schema_version() {
+        let encode = |include_schema_version, minimal| {
+            let mut event = LogEvent::from("test message");
+            event.insert("_schema_version", "custom");
+
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoding = DatadogArchivesEncoding::new(Default::default());
+            encoding.include_schema_version = include_schema_version;
+            encoding.minimal = minimal;
+            _ = encoding.encode_input(vec![Event::Log(event)], &mut writer);
+            serde_json::from_slice::<serde_json::Value>(&writer.into_inner()).unwrap()
+        };
+
+        let json = encode(true, false);
+        assert_eq!(json["_schema_version"], json!(SCHEMA_VERSION));
+        assert_eq!(json["attributes"]["_schema_version"], json!("custom"));
+        assert_eq!(encode(true, true)["_schema_version"], json!(SCHEMA_VERSION));
+
+        let json = encode(false, false);
+        assert!(json.get("_schema_version").is_none());
+        assert_eq!(json["attributes"]["_schema_version"], json!("custom"));
+
+        // The field doesn't collide with any reserved attribute.
+        assert!(!RESERVED_ATTRIBUTES.contains(&SCHEMA_VERSION_FIELD));
+    }
+
+    #[test]
+    fn projects_encoded_size_of_batch() {
+        let events = (0..100)
+            .map(|i| {
+                let mut log = LogEvent::from(format!(
+                    "GET /api/v1/users/{} HTTP/1.1 200 512 \"Mozilla/5.0 (X11; Linux x86_64)\"",
+                    i
+                ));
+                log.insert("host", "web-1");
+                log.insert("service", "api");
+                log.insert("status", "info");
+                log.insert("http.method", "GET");
+                log.insert("http.status_code", 200);
+                log.insert("timestamp", Utc::now());
+                Event::Log(log)
+            })
+            .collect::<Vec<_>>();
+
+        let size = |batch_sizing: BatchSizing| {
+            events
+                .iter()
+                .map(|event| batch_sizing.size_of(event))
+                .sum::<usize>()
+        };
+        let in_memory = size(BatchSizing::InMemory);
+        let projected = size(BatchSizing::EncodedJson);
+
+        let mut writer = Cursor::new(Vec::new());
+        let encoding = DatadogArchivesEncoding::new(Default::default());
+        encoding.encode_input(events, &mut writer).unwrap();
+        let actual = writer.into_inner().len();
+
+        // The projection doesn't account for the reshaping, but is still much closer to the actual
+        // size than the size in memory.
+        assert!(actual.abs_diff(projected) < actual.abs_diff(in_memory));
+        assert!(projected * 10 >= actual * 6 && projected * 10 <= actual * 14);
+    }
+
+    #[test]
+    fn encodes_single_member_gzip() {
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        );
+
+        // Enough events for the payload to span many of the compressor's internal buffers.
+        let events = (0..10_000)
+            .map(|i| Event::Log(LogEvent::from(format!("test message {}", i))))
+            .collect::<Vec<_>>();
+        let payload = request_builder
+            .encode_events(events)
+            .unwrap()
+            .into_payload();
+
+        // `GzDecoder` only decodes the first member of a gzip stream, so anything left over
+        // would belong to another member.
+        let mut decoder = flate2::bufread::GzDecoder::new(&payload[..]);
+        let mut decoded = String::new();
+        decoder.read_to_string(&mut decoded).unwrap();
+        assert!(decoder.into_inner().is_empty());
+        assert_eq!(decoded.lines().count(), 10_000);
+    }
+
+    #[test]
+    fn presizing_doesnt_change_the_payload() {
+        let encoder = DatadogArchivesEncoding::new(Default::default());
+        let events = || {
+            (0..1_000)
+                .map(|i| Event::Log(LogEvent::from(format!("test message {}", i))))
+                .collect::<Vec<_>>()
+        };
+
+        for compression in [Compression::None, DEFAULT_COMPRESSION] {
+            for uncompressed_fallback in [false, true] {
+                let encode = |presize_ratio| {
+                    encode_archive(
+                        &encoder,
+                        events(),
+                        compression,
+                        uncompressed_fallback,
+                        presize_ratio,
+                    )
+                    .unwrap()
+                    .into_payload()
+                };
+
+                let payload = encode(0.0);
+                assert_eq!(encode(default_compression_presize_ratio()), payload);
+                assert_eq!(encode(1.0), payload);
+            }
+        }
+    }
+
+    /// Counts how many times the buffer of a compressor is reallocated as it's written to.
+    struct ReallocationCounter {
+        compressor: Compressor,
+        reallocations: usize,
+    }
+
+    impl io::Write for ReallocationCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let capacity = self.compressor.get_ref().capacity();
+            #[allow(clippy::disallowed_methods)] // Caller handles the result of `write`.
+            let written = self.compressor.write(buf)?;
+            if self.compressor.get_ref().capacity() != capacity {
+                self.reallocations += 1;
+            }
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.compressor.flush()
+        }
+    }
+
+    #[test]
+    fn presizing_avoids_reallocations() {
+        let encoder = DatadogArchivesEncoding::new(Default::default());
+        let events = (0..10_000)
+            .map(|i| Event::Log(LogEvent::from(format!("test message {}", i))))
+            .collect::<Vec<_>>();
+        let estimated_size = events.estimated_json_encoded_size_of().get();
+
+        let reallocations = |compressor| {
+            let mut counter = ReallocationCounter {
+                compressor,
+                reallocations: 0,
+            };
+            encode_with_manifest(&encoder, events.clone(), &mut counter).unwrap();
+            counter.reallocations
+        };
+
+        let grown = reallocations(Compressor::from(Compression::None));
+        let presized = reallocations(Compressor::with_capacity(
+            Compression::None,
+            payload_capacity(estimated_size, Compression::None, 1.0),
+        ));
+
+        // Without pre-sizing, the buffer grows from 1KiB to over 1MiB, doubling at best.
+        assert!(grown >= 10, "grown {} times", grown);
+        // The estimate doesn't account for the reshaping, so the buffer may still grow once.
+        assert!(presized <= 1, "grown {} times", presized);
+    }
+
+    #[test]
+    fn payload_capacity_is_scaled_once_compressed() {
+        assert_eq!(payload_capacity(1_000, Compression::None, 0.2), 1_000);
+        assert_eq!(payload_capacity(1_000, DEFAULT_COMPRESSION, 0.2), 200);
+        assert_eq!(payload_capacity(1_000, DEFAULT_COMPRESSION, 5.0), 1_000);
+        assert_eq!(payload_capacity(1_000, DEFAULT_COMPRESSION, -1.0), 0);
+        assert_eq!(payload_capacity(1_000, DEFAULT_COMPRESSION, f64::NAN), 0);
+    }
+
+    #[test]
+    fn falls_back_to_uncompressed_on_compression_failure() {
+        let events = || {
+            (0..3)
+                .map(|i| Event::Log(LogEvent::from(format!("test message {}", i))))
+                .collect::<Vec<_>>()
+        };
+        let encode = || {
+            encode_with_fallback(
+                &DatadogArchivesEncoding::new(Default::default()),
+                events(),
+                |_| Err(io::Error::new(io::ErrorKind::OutOfMemory, "out of memory")),
+            )
+            .unwrap()
+        };
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+
+        let payload = encode();
+        assert!(payload.compressed_byte_size.is_none());
+        let body = payload.payload.clone();
+        let lines = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        for line in lines {
+            serde_json::from_str::<serde_json::Value>(line).expect("invalid JSON record");
+        }
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_uncompressed_fallback(true);
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), events()));
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert!(req.metadata.s3_key.ends_with(".json"));
+        assert_eq!(req.body, body);
+        assert_eq!(req.content_encoding, None);
+        assert_eq!(
+            req.options.content_type.as_deref(),
+            Some("application/x-ndjson")
+        );
+
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: Vec::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: false,
+            uncompressed_fallback: true,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), events()));
+        let payload = encode();
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert!(req.key.ends_with(".json"));
+        assert_eq!(req.settings.content_encoding, None);
+        assert_eq!(req.settings.content_type, "application/x-ndjson");
+
+        let request_builder = DatadogAzureRequestBuilder {
+            container_name: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            object_metadata: BTreeMap::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            content_disposition: false,
+            content_language: None,
+            custom_time: false,
+            uncompressed_fallback: true,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, events()));
+        let payload = encode();
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert!(req.metadata.partition_key.ends_with(".json"));
+        assert_eq!(req.content_encoding, None);
+        assert_eq!(req.content_type, "application/x-ndjson");
+    }
+
+    #[test]
+    fn compresses_when_fallback_is_enabled() {
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_uncompressed_fallback(true);
+
+        let events = vec![Event::Log(LogEvent::from("test message"))];
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let (metadata, metadata_request_builder, events) =
+            request_builder.split_input((key, events));
+        let payload = request_builder.encode_events(events).unwrap();
+        assert!(payload.compressed_byte_size.is_some());
+
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert!(req.metadata.s3_key.ends_with(".json.gz"));
+        assert_eq!(req.content_encoding, Some("gzip"));
+
+        let mut decoded = String::new();
+        flate2::bufread::GzDecoder::new(&req.body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded.lines().count(), 1);
+    }
+
+    /// check that _id is:
+    /// - 18 bytes,
+    /// - base64-encoded,
+    /// - first 6 bytes - a "now" timestamp in millis
+    fn validate_event_id(id: &str) {
+        let bytes = BASE64_STANDARD
+            .decode(id)
+            .expect("_id is not base64-encoded");
+        assert_eq!(bytes.len(), 18);
+        let mut timestamp: [u8; 8] = [0; 8];
+        for (i, b) in bytes[..6].iter().enumerate() {
+            timestamp[i + 2] = *b;
+        }
+        let timestamp = i64::from_be_bytes(timestamp);
+        // check that it is a recent timestamp in millis
+        assert!(Utc::now().timestamp_millis() - timestamp < 1000);
+    }
+
+    #[test]
+    fn s3_build_request() {
+        let fake_buf = Bytes::new();
+        let mut log = Event::Log(LogEvent::from("test message"));
+        let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+        log.as_mut_log().insert("timestamp", timestamp);
+        let partitioner = DatadogArchivesPartitioner::new(
+            Template::try_from(KEY_TEMPLATE).expect("invalid object key format"),
+            false,
+        );
+        let key = partitioner.partition(&log).expect("key wasn't provided");
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator {
+                key_prefix: Some("audit".into()),
+                ..Default::default()
+            },
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        );
+
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![log]));
+
+        let payload = EncodeResult::uncompressed(fake_buf.clone());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+
+        let expected_key_prefix = "audit/dt=20210823/hour=16/archive_";
+        let expected_key_ext = ".json.gz";
+        println!("{}", req.metadata.s3_key);
+        assert!(req.metadata.s3_key.starts_with(expected_key_prefix));
+        assert!(req.metadata.s3_key.ends_with(expected_key_ext));
+        let uuid1 = &req.metadata.s3_key
+            [expected_key_prefix.len()..req.metadata.s3_key.len() - expected_key_ext.len()];
+        assert_eq!(uuid1.len(), 36);
+
+        // check that the second batch has a different UUID
+        let log2 = LogEvent::default().into();
+
+        let key = partitioner.partition(&log2).expect("key wasn't provided");
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![log2]));
+        let payload = EncodeResult::uncompressed(fake_buf);
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+
+        let uuid2 = &req.metadata.s3_key
+            [expected_key_prefix.len()..req.metadata.s3_key.len() - expected_key_ext.len()];
+
+        assert_ne!(uuid1, uuid2);
+    }
+
+    #[test]
+    fn s3_part_size() {
+        let part_size = |part_size: Option<usize>| {
+            S3Config {
+                part_size: part_size.and_then(NonZeroUsize::new),
+                ..Default::default()
+            }
+            .part_size()
+            .map(NonZeroUsize::get)
+        };
+
+        assert_eq!(part_size(None), Ok(8 * 1024 * 1024));
+        assert_eq!(part_size(Some(MIN_PART_SIZE)), Ok(MIN_PART_SIZE));
+        assert_eq!(
+            part_size(Some(1024)),
+            Err(ConfigError::S3PartTooSmall {
+                part_size: 1024,
+                min: MIN_PART_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn s3_failover_region() {
+        let config = |region: Option<&str>, endpoint: Option<&str>| S3Config {
+            region: RegionOrEndpoint::with_region("us-east-1".to_owned()),
+            failover_region: region.map(str::to_owned),
+            failover_endpoint: endpoint.map(str::to_owned),
+            ..Default::default()
+        };
+
+        assert_eq!(config(None, None).failover_region(), None);
+        assert_eq!(
+            config(Some("us-west-2"), None).failover_region(),
+            Some(RegionOrEndpoint::with_region("us-west-2".to_owned()))
+        );
+        // The configured region is used with a failover endpoint alone.
+        assert_eq!(
+            config(None, Some("http://localhost:9000")).failover_region(),
+            Some(RegionOrEndpoint::with_both(
+                "us-east-1",
+                "http://localhost:9000"
+            ))
+        );
+        assert_eq!(config(None, None).failover_after().get(), 5);
+    }
+
+    #[test]
+    fn s3_build_request_with_bucket_key() {
+        for (server_side_encryption, bucket_key_enabled, expected) in [
+            (Some(S3ServerSideEncryption::AwsKms), Some(true), Some(true)),
+            (Some(S3ServerSideEncryption::AwsKms), Some(false), None),
+            (Some(S3ServerSideEncryption::AwsKms), None, None),
+            (Some(S3ServerSideEncryption::Aes256), Some(true), None),
+            (None, Some(true), None),
+        ] {
+            let request_builder = DatadogS3RequestBuilder::new(
+                "dd-logs".into(),
+                ObjectKeyGenerator::default(),
+                S3Config {
+                    options: S3Options {
+                        server_side_encryption,
+                        bucket_key_enabled,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                Box::new(DatadogArchivesEncoding::new(Default::default())),
+            );
+
+            let log = Event::Log(LogEvent::from("test message"));
+            let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+            let (metadata, metadata_request_builder, _events) =
+                request_builder.split_input((key, vec![log]));
+            let payload = EncodeResult::uncompressed(Bytes::new());
+            let request_metadata = metadata_request_builder.build(&payload);
+            let req = request_builder.build_request(metadata, request_metadata, payload);
+
+            assert_eq!(req.options.bucket_key_enabled, expected);
+        }
+    }
+
+    #[test]
+    fn s3_build_request_with_retention_tag() {
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config {
+                options: S3Options {
+                    tags: Some(BTreeMap::from([
+                        ("team".to_owned(), "audit".to_owned()),
+                        ("expires".to_owned(), "never".to_owned()),
+                    ])),
+                    retention_tag: Some(RetentionTagConfig {
+                        key: default_retention_tag_key(),
+                        days: NonZeroU32::new(30).unwrap(),
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        );
+
+        let tags = |prefix: &str| {
+            let log = Event::Log(LogEvent::from("test message"));
+            let key = ArchivePartition::new(prefix.to_owned());
+            let (metadata, metadata_request_builder, _events) =
+                request_builder.split_input((key, vec![log]));
+            let payload = EncodeResult::uncompressed(Bytes::new());
+            let request_metadata = metadata_request_builder.build(&payload);
+            let req = request_builder.build_request(metadata, request_metadata, payload);
+            req.options.tags.expect("tags not set")
+        };
+
+        let expected = |expires: &str| {
+            vec![
+                ("expires".to_owned(), expires.to_owned()),
+                ("team".to_owned(), "audit".to_owned()),
+            ]
+        };
+        assert_eq!(
+            tags("/dt=20210823/hour=16/")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            expected("2021-09-22")
+        );
+        assert_eq!(
+            tags("/dt=20211231/hour=23/source=nginx/")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            expected("2022-01-30")
+        );
+
+        // Partitions without a date keep the static tags only.
+        assert_eq!(
+            tags("/dt=unknown/").into_iter().collect::<Vec<_>>(),
+            expected("never")
+        );
+    }
+
+    #[test]
+    fn s3_build_request_with_partition_metadata() {
+        let request_builder = |partition_metadata| {
+            DatadogS3RequestBuilder::new(
+                "dd-logs".into(),
+                ObjectKeyGenerator::default(),
+                S3Config {
+                    options: S3Options {
+                        partition_metadata,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                Box::new(DatadogArchivesEncoding::new(Default::default())),
+            )
+            .with_object_metadata(BTreeMap::from([(
+                "vector-config-hash".to_owned(),
+                "abc".to_owned(),
+            )]))
+        };
+        let user_metadata = |request_builder: DatadogS3RequestBuilder| {
+            let mut log = Event::Log(LogEvent::from("test message"));
+            let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+                .expect("invalid test case")
+                .with_timezone(&Utc);
+            log.as_mut_log().insert("timestamp", timestamp);
+            let partitioner = DatadogArchivesPartitioner::new(
+                Template::try_from(KEY_TEMPLATE).expect("invalid object key format"),
+                true,
+            );
+            let key = partitioner.partition(&log).expect("key wasn't provided");
+            let (metadata, metadata_request_builder, _events) =
+                request_builder.split_input((key, vec![log]));
+            let payload = EncodeResult::uncompressed(Bytes::new());
+            let request_metadata = metadata_request_builder.build(&payload);
+            let req = request_builder.build_request(metadata, request_metadata, payload);
+            req.user_metadata
+                .expect("user metadata not set")
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        assert_eq!(
+            user_metadata(request_builder(true)),
+            BTreeMap::from([
+                ("dt".to_owned(), "20210823".to_owned()),
+                ("hour".to_owned(), "16".to_owned()),
+                ("vector-config-hash".to_owned(), "abc".to_owned()),
+            ])
+        );
+        assert_eq!(
+            user_metadata(request_builder(false)),
+            BTreeMap::from([("vector-config-hash".to_owned(), "abc".to_owned())])
+        );
+    }
+
+    #[test]
+    fn partition_metadata_from_prefixes() {
+        assert_eq!(
+            partition_metadata("/index=main/dt=20210823/hour=16/source=nginx/"),
+            BTreeMap::from([
+                ("dt".to_owned(), "20210823".to_owned()),
+                ("hour".to_owned(), "16".to_owned()),
+            ])
+        );
+        assert_eq!(
+            partition_metadata("/dt=unknown/"),
+            BTreeMap::from([("dt".to_owned(), "unknown".to_owned())])
+        );
+        assert!(partition_metadata("/overflow/").is_empty());
+    }
+
+    #[test]
+    fn rolls_up_finalized_objects() {
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_object_rollups(true);
+        let key = ArchivePartition::new("/index=main/dt=20210823/hour=16/".to_owned());
+        let events = ["first", "second", "third"]
+            .into_iter()
+            .map(|message| Event::Log(LogEvent::from(message)))
+            .collect::<Vec<_>>();
+
+        let ((metadata, _), metadata_request_builder, events) =
+            request_builder.split_input((key, events));
+        let payload = request_builder.encode_events(events).unwrap();
+        let request_metadata = metadata_request_builder.build(&payload);
+        let rollup = object_rollup(
+            &metadata.partition_key.key_prefix,
+            &request_metadata,
+            &payload,
+        );
+
+        assert_eq!(rollup.partition, "/index=main/");
+        assert_eq!(rollup.events, 3);
+        assert_eq!(rollup.uncompressed_bytes, payload.uncompressed_byte_size);
+        assert_eq!(rollup.compressed_bytes, Some(payload.into_payload().len()));
+        assert!(rollup.uncompressed_bytes > 0);
+    }
+
+    #[test]
+    fn rollup_partitions_leave_out_the_time() {
+        assert_eq!(
+            rollup_partition("/index=main/dt=20210823/hour=16/source=nginx/"),
+            "/index=main/source=nginx/"
+        );
+        assert_eq!(rollup_partition("/dt=20210823/hour=16/"), "/");
+        assert_eq!(rollup_partition("/dt=unknown/"), "/");
+        assert_eq!(rollup_partition("/overflow/"), "/overflow/");
+    }
+
+    #[test]
+    fn stamps_uncompressed_size() {
+        let events = || {
+            ["first", "second", "third"]
+                .into_iter()
+                .map(|message| Event::Log(LogEvent::from(message)))
+                .collect::<Vec<_>>()
+        };
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let decoded_len = |body: &[u8]| {
+            let mut decoded = Vec::new();
+            flate2::bufread::GzDecoder::new(body)
+                .read_to_end(&mut decoded)
+                .unwrap();
+            decoded.len().to_string()
+        };
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_uncompressed_size(true);
+        let (metadata, metadata_request_builder, events) =
+            request_builder.split_input((key.clone(), events()));
+        let payload = request_builder.encode_events(events).unwrap();
+        assert!(payload.compressed_byte_size.is_some());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(
+            req.user_metadata.unwrap().get("uncompressed_size"),
+            Some(&decoded_len(&req.body))
+        );
+
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: Vec::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: true,
+            crc32c: false,
+        };
+        let (metadata, metadata_request_builder, events) =
+            request_builder.split_input((key.clone(), events()));
+        let payload = request_builder.encode_events(events).unwrap();
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        let uncompressed_size = req
+            .settings
+            .headers
+            .iter()
+            .find(|(name, _)| name == "x-goog-meta-uncompressed-size")
+            .map(|(_, value)| value.to_str().unwrap().to_owned());
+        assert_eq!(uncompressed_size, Some(decoded_len(&req.body)));
+
+        let request_builder = DatadogAzureRequestBuilder {
+            container_name: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            object_metadata: BTreeMap::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            content_disposition: false,
+            content_language: None,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: true,
+        };
+        let (metadata, metadata_request_builder, events) =
+            request_builder.split_input((key, events()));
+        let payload = request_builder.encode_events(events).unwrap();
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(
+            req.user_metadata.unwrap().get("uncompressed_size"),
+            Some(&decoded_len(&req.blob_data))
+        );
+    }
+
+    #[test]
+    fn sets_content_disposition() {
+        let log = Event::Log(LogEvent::from("test message"));
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            ..Default::default()
+        };
+        let expected = |key: &str| {
+            let filename = key.rsplit('/').next().unwrap();
+            assert!(filename.starts_with("archive_"));
+            assert!(filename.ends_with(".json.gz"));
+            format!("attachment; filename=\"{}\"", filename)
+        };
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            object_key.clone(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_content_disposition(true);
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), vec![log.clone()]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(
+            req.content_disposition,
+            Some(expected(&req.metadata.s3_key))
+        );
+
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: object_key.clone(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: Vec::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: true,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), vec![log.clone()]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        let header = req
+            .settings
+            .headers
+            .iter()
+            .find(|(name, _)| name == CONTENT_DISPOSITION)
+            .map(|(_, value)| value.to_str().unwrap().to_owned());
+        assert_eq!(header, Some(expected(&req.key)));
+
+        let request_builder = DatadogAzureRequestBuilder {
+            container_name: "dd-logs".into(),
+            object_key,
+            object_metadata: BTreeMap::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            content_disposition: true,
+            content_language: None,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![log]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(
+            req.content_disposition,
+            Some(expected(&req.metadata.partition_key))
+        );
+    }
+
+    #[test]
+    fn sets_content_language() {
+        let log = Event::Log(LogEvent::from("test message"));
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_content_language(Some("en-US".to_owned()));
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), vec![log.clone()]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(req.content_language.as_deref(), Some("en-US"));
+
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "gcp_cloud_storage"
+            bucket = "vector-datadog-archives"
+            content_language = "en-US"
+
+            [gcp_cloud_storage.metadata]
+            team = "logs"
+        "#})
+        .unwrap();
+        let headers = config
+            .build_gcs_headers(config.gcp_cloud_storage.as_ref().unwrap())
+            .unwrap();
+        let header = headers
+            .iter()
+            .find(|(name, _)| name == CONTENT_LANGUAGE)
+            .map(|(_, value)| value.to_str().unwrap());
+        assert_eq!(header, Some("en-US"));
+
+        let request_builder = DatadogAzureRequestBuilder {
+            container_name: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            object_metadata: BTreeMap::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            content_disposition: false,
+            content_language: Some("en-US".to_owned()),
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![log]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(req.content_language.as_deref(), Some("en-US"));
+    }
+
+    #[test]
+    fn rejects_too_long_object_keys() {
+        let config = |extra: &str| {
+            toml::from_str::<DatadogArchivesSinkConfig>(&format!(
+                "service = \"gcp_cloud_storage\"\nbucket = \"vector-datadog-archives\"\n{}",
+                extra
+            ))
+            .unwrap()
+        };
+        let check = |config: DatadogArchivesSinkConfig| {
+            config.check_object_keys(&config.object_key_generator(None))
+        };
+
+        assert_eq!(check(config("key_prefix = \"audit/\"")), Ok(()));
+
+        let config_with_long_prefix = config(&format!("key_prefix = \"{}/\"", "a".repeat(1000)));
+        assert!(matches!(
+            check(config_with_long_prefix),
+            Err(ConfigError::ObjectKeyTooLong { max: 1024, .. })
+        ));
+
+        // `audit/logs/dt=<date>/hour=<hour>/source=<source>/archive_<uuid>.json.gz`
+        let nested = "key_prefix = \"audit/logs\"\npartition_by_source = true\n";
+        assert_eq!(
+            check(config(&format!("{}max_key_segments = 6", nested))),
+            Ok(())
+        );
+        assert!(matches!(
+            check(config(&format!("{}max_key_segments = 5", nested))),
+            Err(ConfigError::TooManyKeySegments {
+                segments: 6,
+                max: 5,
+                ..
+            })
+        ));
+
+        // The literal partition fallback may produce longer keys than the time partitions.
+        let fallback = "max_key_segments = 4\n[partition_fallback]\nstrategy = \"literal\"\nprefix = \"/unknown/a/b/\"";
+        assert!(matches!(
+            check(config(fallback)),
+            Err(ConfigError::TooManyKeySegments { segments: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn config_hash_is_stable() {
+        let config = || {
+            toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+                service = "gcp_cloud_storage"
+                bucket = "vector-datadog-archives"
+                include_config_hash = true
+
+                [gcp_cloud_storage.metadata]
+                one = "1"
+                two = "2"
+                three = "3"
+            "#})
+            .unwrap()
+        };
+
+        let hash = config().config_hash();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(config().config_hash(), hash);
+
+        let mut changed = config();
+        changed.key_prefix = Some("audit/".to_owned());
+        assert_ne!(changed.config_hash(), hash);
+
+        let metadata = config().build_object_metadata();
+        assert_eq!(metadata.get("vector-config-hash"), Some(&hash));
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_object_metadata(metadata);
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![Event::Log(LogEvent::from("test message"))]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+
+        assert_eq!(
+            req.user_metadata.unwrap().get("vector-config-hash"),
+            Some(&hash)
+        );
+    }
+
+    #[test]
+    fn adds_object_key_to_records() {
+        let events = (0..3)
+            .map(|i| Event::Log(LogEvent::from(format!("test message {}", i))))
+            .collect::<Vec<_>>();
+        let object_key = ObjectKeyGenerator {
+            key_field: Some("_archive_key".into()),
+            ..Default::default()
+        };
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            object_key,
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        );
+
+        let ((metadata, _), _metadata_request_builder, events) = request_builder.split_input((
+            ArchivePartition::new("/dt=20210823/hour=16/".to_owned()),
+            events,
+        ));
+        let mut writer = Cursor::new(Vec::new());
+        DatadogArchivesEncoding::new(Default::default())
+            .encode_input(events, &mut writer)
+            .unwrap();
+
+        let records = writer
+            .into_inner()
+            .split(|&b| b == b'\n')
+            .map(|line| serde_json::from_slice::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        for record in records {
+            assert_eq!(
+                record["attributes"]["_archive_key"],
+                json!(metadata.s3_key.clone())
+            );
+        }
+        assert!(metadata.s3_key.starts_with("/dt=20210823/hour=16/archive_"));
+    }
+
+    #[test]
+    fn generates_object_key_with_hostname() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            hostname: Some("vector-0".into()),
+            ..Default::default()
+        };
+
+        let key = object_key.generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()));
+
+        let expected_key_prefix = "audit/dt=20210823/hour=16/archive_vector-0_";
+        let expected_key_ext = ".json.gz";
+        assert!(key.starts_with(expected_key_prefix));
+        assert!(key.ends_with(expected_key_ext));
+        assert!(!key.contains("//"));
+        let uuid = &key[expected_key_prefix.len()..key.len() - expected_key_ext.len()];
+        assert!(Uuid::parse_str(uuid).is_ok());
+    }
+
+    #[test]
+    fn generates_time_ordered_object_keys_with_ulid() {
+        let partition = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let keys = |object_key: ObjectKeyGenerator| {
+            (0..100)
+                .map(|_| object_key.generate(&partition))
+                .collect::<Vec<_>>()
+        };
+        let sorted = |keys: &[String]| {
+            let mut sorted = keys.to_vec();
+            sorted.sort();
+            sorted
+        };
+
+        let ulid_keys = keys(ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            ulid: Some(Default::default()),
+            ..Default::default()
+        });
+        assert!(ulid_keys[0].starts_with("audit/dt=20210823/hour=16/archive_"));
+        assert!(ulid_keys[0].ends_with(".json.gz"));
+        assert_eq!(sorted(&ulid_keys), ulid_keys);
+
+        let uuid_keys = keys(ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            ..Default::default()
+        });
+        assert_ne!(sorted(&uuid_keys), uuid_keys);
+    }
+
+    #[test]
+    fn generates_object_keys_with_consecutive_sequence_numbers() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            hostname: Some("vector-0".into()),
+            sequence: Some(Default::default()),
+            ..Default::default()
+        };
+        let partition = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+
+        let expected_key_prefix = "audit/dt=20210823/hour=16/archive_";
+        let sequences = (0..3)
+            .map(|_| {
+                let key = object_key.generate(&partition);
+                let filename = &key[expected_key_prefix.len()..];
+                let (sequence, rest) = filename.split_once('_').unwrap();
+                assert!(rest.starts_with("vector-0_"));
+                assert_eq!(sequence.len(), 20);
+                sequence.parse::<u64>().unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![0, 1, 2]);
+
+        // The sequence is shared by the clones of the generator, such as those of each request
+        // builder, and by the error objects.
+        let clone = object_key.clone();
+        let mut errors = partition.clone();
+        errors.errors = true;
+        assert!(clone
+            .generate(&partition)
+            .starts_with("audit/dt=20210823/hour=16/archive_00000000000000000003_"));
+        assert!(object_key
+            .generate(&errors)
+            .starts_with("audit/dt=20210823/hour=16/errors_00000000000000000004_"));
+    }
+
+    #[test]
+    fn generates_separate_object_key_for_errors() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            ..Default::default()
+        };
+
+        let mut partition = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        assert!(object_key
+            .generate(&partition)
+            .starts_with("audit/dt=20210823/hour=16/archive_"));
+
+        partition.errors = true;
+        let key = object_key.generate(&partition);
+        assert!(key.starts_with("audit/dt=20210823/hour=16/errors_"));
+        assert!(key.ends_with(".json.gz"));
+    }
+
+    #[test]
+    fn generates_sanitized_object_key() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("Audit Logs".into()),
+            hostname: Some("Vector-0.Example".into()),
+            sanitize: true,
+            ..Default::default()
+        };
+
+        let key = object_key.generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()));
+
+        let expected_key_prefix = "audit_logs/dt=20210823/hour=16/archive_vector-0.example_";
+        assert!(key.starts_with(expected_key_prefix));
+        assert!(key.ends_with(".json.gz"));
+
+        // Keys are left as is, unless sanitization is enabled.
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("Audit Logs".into()),
+            ..Default::default()
+        };
+        assert!(object_key
+            .generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()))
+            .starts_with("Audit Logs/dt=20210823/hour=16/archive_"));
+    }
+
+    #[test]
+    fn collapses_double_slashes() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit//logs".into()),
+            ..Default::default()
+        };
+        assert!(object_key
+            .generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()))
+            .starts_with("audit/logs/dt=20210823/hour=16/archive_"));
+
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit//logs".into()),
+            preserve_slashes: true,
+            ..Default::default()
+        };
+        assert!(object_key
+            .generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()))
+            .starts_with("audit//logs/dt=20210823/hour=16/archive_"));
+
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit/".into()),
+            preserve_slashes: true,
+            ..Default::default()
+        };
+        assert!(object_key
+            .generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()))
+            .starts_with("audit//dt=20210823/hour=16/archive_"));
+    }
+
+    #[test]
+    fn uppercases_time_segments() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            casing: PartitionKeyCasing::Uppercase,
+            ..Default::default()
+        };
+        let key = object_key.generate(&ArchivePartition::new(
+            "/index=main/dt=20210823/hour=16/source=nginx/".to_owned(),
+        ));
+        assert!(key.starts_with("audit/index=main/DT=20210823/HOUR=16/source=nginx/archive_"));
+
+        // The segments are uppercased after the key is sanitized, and with any separator.
+        let object_key = ObjectKeyGenerator {
+            sanitize: true,
+            separator: Some("-".into()),
+            ..object_key
+        };
+        let key = object_key.generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()));
+        assert!(key.starts_with("audit-DT=20210823-HOUR=16-archive_"));
+
+        assert_eq!(
+            uppercase_time_segments("audit/dt=20210823/hour=16/birthdt=1/", "/"),
+            "audit/DT=20210823/HOUR=16/birthdt=1/"
+        );
+    }
+
+    #[test]
+    fn generates_object_key_with_custom_separator() {
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit".into()),
+            separator: Some("-".into()),
+            ..Default::default()
+        };
+        let key = object_key.generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()));
+        assert!(key.starts_with("audit-dt=20210823-hour=16-archive_"));
+        assert!(key.ends_with(".json.gz"));
+        assert!(!key.contains('/'));
+
+        // The key prefix is used as is, and doubled separators are collapsed.
+        let object_key = ObjectKeyGenerator {
+            key_prefix: Some("audit/app--logs".into()),
+            separator: Some("-".into()),
+            ..Default::default()
+        };
+        assert!(object_key
+            .generate(&ArchivePartition::new("/dt=20210823/hour=16/".to_owned()))
+            .starts_with("audit/app-logs-dt=20210823-hour=16-archive_"));
+    }
+
+    #[test]
+    fn gcs_build_request_with_batch_metadata() {
+        let events = [
+            "2021-08-23T16:00:27.879Z",
+            "2021-08-23T16:10:00Z",
+            "2021-08-23T16:05:00Z",
+        ]
+        .into_iter()
+        .map(|timestamp| {
+            let mut log = LogEvent::from("test message");
+            let timestamp = DateTime::parse_from_rfc3339(timestamp)
+                .expect("invalid test case")
+                .with_timezone(&Utc);
+            log.insert("timestamp", timestamp);
+            Event::Log(log)
+        })
+        .chain(std::iter::once(Event::Log(LogEvent::from("no timestamp"))))
+        .collect::<Vec<_>>();
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: vec![(
+                HeaderName::from_static("x-goog-meta-team"),
+                HeaderValue::from_static("logs"),
+            )],
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, events));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+
+        let headers = req
+            .settings
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            vec![
+                ("x-goog-meta-team", "logs"),
+                ("x-goog-meta-event-count", "4"),
+                ("x-goog-meta-time-min", "2021-08-23T16:00:27.879Z"),
+                ("x-goog-meta-time-max", "2021-08-23T16:10:00.000Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn gcs_batch_metadata_defers_to_user_metadata() {
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: vec![(
+                HeaderName::from_static("x-goog-meta-event-count"),
+                HeaderValue::from_static("custom"),
+            )],
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: false,
+        };
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![Event::Log(LogEvent::from("no timestamp"))]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+
+        // Batches without timestamps have no time bounds.
+        let headers = req
+            .settings
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(headers, vec![("x-goog-meta-event-count", "custom")]);
+    }
+
+    #[test]
+    fn gcs_sends_the_crc32c_of_the_payload() {
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: Vec::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: true,
+        };
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![Event::Log(LogEvent::from("no timestamp"))]));
+        let payload = EncodeResult::uncompressed(Bytes::from_static(b"123456789"));
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+
+        // The CRC32C of "123456789" is 0xE3069283.
+        let hash = req
+            .settings
+            .headers
+            .iter()
+            .find(|(name, _)| name == "x-goog-hash")
+            .map(|(_, value)| value.to_str().unwrap());
+        assert_eq!(hash, Some("crc32c=4waSgw=="));
+    }
+
+    /// Encodes each event as is, as a line of JSON.
+    #[derive(Debug)]
+    struct NoopEncoder;
+
+    impl crate::sinks::util::encoding::Encoder<Vec<Event>> for NoopEncoder {
+        fn encode_input(&self, input: Vec<Event>, writer: &mut dyn Write) -> io::Result<usize> {
+            let mut written = 0;
+            for event in input {
+                let line = format!("{}\n", serde_json::to_string(event.as_log()).unwrap());
+                writer.write_all(line.as_bytes())?;
+                written += line.len();
+            }
+            Ok(written)
+        }
+    }
+
+    impl ArchiveEncoder for NoopEncoder {
+        fn content_type(&self) -> &'static str {
+            "application/x-ndjson"
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_the_sink_with_a_custom_encoder() {
+        let addr = crate::test_util::next_addr();
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "gcp_cloud_storage"
+            bucket = "dd-logs"
+
+            [gcp_cloud_storage]
+        "#})
+        .unwrap();
+        let client = HttpClient::new(TlsSettings::default(), &Default::default()).unwrap();
+        let encoder: Arc<dyn ArchiveEncoder> = Arc::new(NoopEncoder);
+        let sink = config
+            .build_gcs_sink(
+                client,
+                format!("http://{}/dd-logs/", addr),
+                GcpAuthenticator::None,
+                Some(&encoder),
+            )
+            .unwrap();
+        let (mut rx, trigger, server) = crate::sinks::util::test::build_test_server(addr);
+        tokio::spawn(server);
+
+        let (batch, mut receiver) = BatchNotifier::new_with_receiver();
+        let events = ["first", "second"]
+            .map(|message| Event::Log(LogEvent::from(message).with_batch_notifier(&batch)));
+        drop(batch);
+        sink.run_events(events).await.unwrap();
+        drop(trigger);
+        assert_eq!(receiver.try_recv(), Ok(BatchStatus::Delivered));
+
+        // The events are archived as encoded by the custom encoder, rather than reshaped.
+        let (_parts, body) = futures::StreamExt::next(&mut rx).await.unwrap();
+        let mut archive = String::new();
+        flate2::bufread::GzDecoder::new(&body[..])
+            .read_to_string(&mut archive)
+            .unwrap();
+        assert_eq!(
+            archive,
+            "{\"message\":\"first\"}\n{\"message\":\"second\"}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn times_out_the_healthcheck_of_a_single_service() {
+        // The listener never accepts connections, so requests to it never get a response.
+        let addr = crate::test_util::next_addr();
+        let _listener = std::net::TcpListener::bind(addr).unwrap();
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(&format!(
+            indoc! {r#"
+                service = "azure_blob"
+                bucket = "dd-logs"
+                healthcheck_timeout_secs = 1
+
+                [azure_blob]
+                connection_string = "AccountName=archives;AccountKey=a2V5;BlobEndpoint=http://{}/archives"
+            "#},
+            addr
+        ))
+        .unwrap();
+
+        let (_sink, healthcheck) = config
+            .build_sink(SinkContext::new_test(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            healthcheck.await.unwrap_err().to_string(),
+            "Healthcheck of azure_blob timed out after 1s"
+        );
+    }
+
+    #[test]
+    fn records_the_keys_of_built_objects() {
+        let path = crate::test_util::temp_file();
+        let inventory = KeyInventory::open(&path).unwrap();
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: Vec::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: false,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: false,
+        };
+
+        let mut keys = Vec::new();
+        for partition in [
+            "/dt=20210823/hour=16/",
+            "/dt=20210823/hour=16/",
+            "/dt=20210823/hour=17/",
+        ] {
+            let key = ArchivePartition::new(partition.to_owned());
+            let (metadata, metadata_request_builder, events) = request_builder
+                .split_input((key, vec![Event::Log(LogEvent::from("test message"))]));
+            let payload = request_builder.encode_events(events).unwrap();
+            let request_metadata = metadata_request_builder.build(&payload);
+            let req = request_builder.build_request(metadata, request_metadata, payload);
+            assert!(req.key.starts_with(&format!("{}archive_", partition)));
+            inventory.record(&req.key);
+            keys.push(req.key);
+        }
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(recorded.lines().collect::<Vec<_>>(), keys);
+    }
+
+    #[test]
+    fn stamps_custom_time() {
+        let events = || {
+            ["2021-08-23T18:10:00+02:00", "2021-08-23T18:00:27.879+02:00"]
+                .into_iter()
+                .map(|timestamp| {
+                    let mut log = LogEvent::from("test message");
+                    let timestamp = DateTime::parse_from_rfc3339(timestamp)
+                        .expect("invalid test case")
+                        .with_timezone(&Utc);
+                    log.insert("timestamp", timestamp);
+                    Event::Log(log)
+                })
+                .chain(std::iter::once(Event::Log(LogEvent::from("no timestamp"))))
+                .collect::<Vec<_>>()
+        };
+        let key = ArchivePartition::new("/dt=20210823/hour=16/".to_owned());
+        let latest = "2021-08-23T16:10:00.000Z";
+
+        let request_builder = DatadogS3RequestBuilder::new(
+            "dd-logs".into(),
+            ObjectKeyGenerator::default(),
+            S3Config::default(),
+            Box::new(DatadogArchivesEncoding::new(Default::default())),
+        )
+        .with_custom_time(true);
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), events()));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(
+            req.user_metadata
+                .unwrap()
+                .get("custom_time")
+                .map(String::as_str),
+            Some(latest)
+        );
+
+        let request_builder = DatadogGcsRequestBuilder {
+            bucket: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            acl: None,
+            storage_class: HeaderValue::from_static("STANDARD"),
+            metadata: Vec::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            compression: DEFAULT_COMPRESSION,
+            content_disposition: false,
+            custom_time: true,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+            crc32c: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), events()));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        let custom_time = req
+            .settings
+            .headers
+            .iter()
+            .find(|(name, _)| name == "x-goog-custom-time")
+            .map(|(_, value)| value.to_str().unwrap());
+        assert_eq!(custom_time, Some(latest));
+
+        let request_builder = DatadogAzureRequestBuilder {
+            container_name: "dd-logs".into(),
+            object_key: ObjectKeyGenerator::default(),
+            object_metadata: BTreeMap::new(),
+            encoding: Box::new(DatadogArchivesEncoding::new(Default::default())),
+            content_disposition: false,
+            content_language: None,
+            custom_time: true,
+            uncompressed_fallback: false,
+            compression_presize_ratio: default_compression_presize_ratio(),
+            object_rollups: false,
+            uncompressed_size: false,
+        };
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key.clone(), events()));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert_eq!(
+            req.user_metadata
+                .unwrap()
+                .get("custom_time")
+                .map(String::as_str),
+            Some(latest)
+        );
+
+        // Batches without timestamps aren't stamped.
+        let (metadata, metadata_request_builder, _events) =
+            request_builder.split_input((key, vec![Event::Log(LogEvent::from("no timestamp"))]));
+        let payload = EncodeResult::uncompressed(Bytes::new());
+        let request_metadata = metadata_request_builder.build(&payload);
+        let req = request_builder.build_request(metadata, request_metadata, payload);
+        assert!(req.user_metadata.is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_service_fields() {
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+
+            [aws_s3]
+            region = "us-east-1"
+            storage_class = "STANDARD"
+            object_lock_mode = "GOVERNANCE"
+        "#})
+        .unwrap();
+
+        assert_eq!(
+            config.check_unknown_fields(),
+            Err(ConfigError::UnknownField {
+                config: "aws_s3",
+                field: "object_lock_mode".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn accepts_unknown_service_fields_when_lenient() {
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "gcp_cloud_storage"
+            bucket = "vector-datadog-archives"
+            lenient_parsing = true
+
+            [gcp_cloud_storage]
+            storage_class = "NEARLINE"
+            soft_delete_days = 7
+        "#})
+        .unwrap();
+
+        assert_eq!(config.check_unknown_fields(), Ok(()));
+        let gcs = config.gcp_cloud_storage.unwrap();
+        assert_eq!(gcs.storage_class, Some(GcsStorageClass::Nearline));
+        assert_eq!(
+            gcs.unknown_fields.keys().collect::<Vec<_>>(),
+            vec!["soft_delete_days"]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_top_level_fields_even_when_lenient() {
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            lenient_parsing = true
+            unknown_option = true
+        "#});
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn rejects_misspelled_service_fields() {
+        let config = toml::from_str::<DatadogArchivesSinkConfig>(indoc! {r#"
+            service = "azure_blob"
+            bucket = "vector-datadog-archives"
+
+            [azure_blob]
+            connection_string = "DefaultEndpointsProtocol=https;AccountName=archives;AccountKey=key"
+            blok_size = 8388608
+        "#})
+        .unwrap();
+
+        let error = config.check_unknown_fields().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown field `azure_blob.blok_size`, which is either misspelled or not supported by \
+             this version of Vector. Set `lenient_parsing` to ignore it"
+        );
+        assert_eq!(config.azure_blob.unwrap().block_size, None);
+    }
+
+    #[test]
+    fn checks_gcs_acl_grants() {
+        let grant = |entity: &str| GcsAclGrant {
+            entity: entity.to_owned(),
+            role: GcsAclRole::Reader,
+        };
+
+        for entity in [
+            "user-reader@example.com",
+            "group-security@example.com",
+            "domain-example.com",
+            "project-viewers-123456789",
+            "allUsers",
+            "allAuthenticatedUsers",
+        ] {
+            let config = GcsConfig {
+                grants: vec![grant(entity)],
+                ..Default::default()
+            };
+            assert!(config.acl_grants().is_ok(), "{}", entity);
+        }
+
+        for entity in [
+            "reader@example.com",
+            "user-",
+            "http://acs.amazonaws.com/groups/global/AllUsers",
+        ] {
+            let config = GcsConfig {
+                grants: vec![grant(entity)],
+                ..Default::default()
+            };
+            assert_eq!(
+                config.acl_grants().unwrap_err(),
+                ConfigError::InvalidGcsGrant {
+                    entity: entity.to_owned()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn gcs_grants_conflict_with_predefined_acl() {
+        let config = GcsConfig {
+            acl: Some(GcsPredefinedAcl::Private),
+            grants: vec![GcsAclGrant {
+                entity: "user-reader@example.com".to_owned(),
+                role: GcsAclRole::Reader,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.acl_grants().unwrap_err(),
+            ConfigError::ConflictingGcsAcl
+        );
+
+        let config = GcsConfig {
+            acl: Some(GcsPredefinedAcl::Private),
+            ..Default::default()
+        };
+        assert!(config.acl_grants().unwrap().is_empty());
+    }
+
+    #[test]
+    fn gcs_base_url_with_private_endpoint() {
+        let base_url = |private_endpoint: Option<&str>| {
+            GcsConfig {
+                private_endpoint: private_endpoint.map(str::to_owned),
+                ..Default::default()
+            }
+            .base_url("vector-datadog-archives")
+        };
+
+        assert_eq!(
+            base_url(None),
+            Ok("https://storage.googleapis.com/vector-datadog-archives/".to_owned())
+        );
+        assert_eq!(
+            base_url(Some("archives1")),
+            Ok("https://storage-archives1.p.googleapis.com/vector-datadog-archives/".to_owned())
+        );
+        // The healthcheck probes the same host as the objects are written to.
+        assert_eq!(
+            format!(
+                "{}{}",
+                base_url(Some("archives1")).unwrap(),
+                healthcheck::probe_key(Some("audit"))
+            ),
+            "https://storage-archives1.p.googleapis.com/vector-datadog-archives/audit/.vector-healthcheck"
+        );
+
+        for invalid in [
+            "",
+            "1archives",
+            "Archives",
+            "archives.example",
+            "a".repeat(21).as_str(),
+        ] {
+            assert_eq!(
+                base_url(Some(invalid)),
+                Err(ConfigError::InvalidGcsPrivateEndpoint {
+                    endpoint: invalid.to_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn generates_object_key_with_source_index() {
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            key_prefix = "lake"
+
+            [source_index]
+            default_index = "misc"
+        "#})
+        .unwrap();
+        let partitioner = config.build_partitioner();
+        let object_key = config.object_key_generator(None);
+
+        let mut event = Event::Log(LogEvent::from("test message"));
+        event.as_mut_log().insert(
+            "timestamp",
+            DateTime::parse_from_rfc3339("2021-08-23T16:00:27.879Z")
+                .expect("invalid test case")
+                .with_timezone(&Utc),
+        );
+        event.as_mut_log().insert("source", "Payments API");
+        let key = object_key.generate(&partitioner.partition(&event).unwrap());
+        assert!(key.starts_with("lake/index=payments_api/dt=20210823/hour=16/archive_"));
+
+        event.as_mut_log().remove("source");
+        let key = object_key.generate(&partitioner.partition(&event).unwrap());
+        assert!(key.starts_with("lake/index=misc/dt=20210823/hour=16/archive_"));
+    }
+
+    #[test]
+    fn previews_object_key() {
+        let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            key_prefix = "audit"
+        "#})
+        .unwrap();
+        assert_eq!(
+            config.preview_key(timestamp),
+            "audit/dt=20210823/hour=16/archive_00000000-0000-0000-0000-000000000000.json.gz"
+        );
+        // The preview is deterministic.
+        assert_eq!(config.preview_key(timestamp), config.preview_key(timestamp));
+
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            key_prefix = "audit"
+            include_hostname = true
+            partition_by_source = true
+            object_id = "ulid"
+        "#})
+        .unwrap();
+        assert_eq!(
+            config.preview_key(timestamp),
+            "audit/dt=20210823/hour=16/source=unknown/archive_hostname_00000000000000000000000000.json.gz"
+        );
+    }
+
+    #[test]
+    fn separates_the_components_of_the_date() {
+        let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            key_prefix = "audit"
+            partition_date_separator = "-"
+        "#})
+        .unwrap();
+        assert_eq!(config.check_date_separator(), Ok(()));
+        assert_eq!(
+            config.preview_key(timestamp),
+            "audit/dt=2021-08-23/hour=16/archive_00000000-0000-0000-0000-000000000000.json.gz"
+        );
+        // The separated dates are still read back from the keys.
+        assert_eq!(
+            parse_partition_date("2021-08-23"),
+            NaiveDate::from_ymd_opt(2021, 8, 23)
+        );
+
+        for separator in ["", "/", "1", "-%"] {
+            let config = DatadogArchivesSinkConfig {
+                partition_date_separator: Some(separator.to_owned()),
+                ..config.clone()
+            };
+            assert_eq!(
+                config.check_date_separator(),
+                Err(ConfigError::InvalidDateSeparator {
+                    separator: separator.to_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_key_directory_options_with_another_key_separator() {
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            key_separator = "_"
+        "#})
+        .unwrap();
+        assert_eq!(config.check_key_separator(), Ok(()));
+
+        let config = DatadogArchivesSinkConfig {
+            success_markers: true,
+            ..config
+        };
+        assert_eq!(
+            config.check_key_separator(),
+            Err(ConfigError::UnsupportedKeySeparator {
+                option: "success_markers",
+                separator: "_".to_owned(),
+            })
+        );
+
+        let config = DatadogArchivesSinkConfig {
+            key_separator: "/".to_owned(),
+            ..config
+        };
+        assert_eq!(config.check_key_separator(), Ok(()));
+    }
+
+    #[test]
+    fn parses_healthcheck_mode() {
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+        "#})
+        .unwrap();
+        assert_eq!(config.healthcheck_mode, HealthcheckMode::Read);
+
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            bucket = "vector-datadog-archives"
+            healthcheck_mode = "write"
+        "#})
+        .unwrap();
+        assert_eq!(config.healthcheck_mode, HealthcheckMode::Write);
+    }
+
+    #[tokio::test]
+    async fn error_if_duplicate_service() {
+        let config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+            service = "aws_s3"
+            services = ["aws_s3"]
+            bucket = "vector-datadog-archives"
+        "#})
+        .unwrap();
+
+        let res = config.build_sink(SinkContext::new_test(), None).await;
+        assert_eq!(res.err().unwrap().to_string(), "Duplicate service: aws_s3");
+    }
+
+    #[tokio::test]
+    async fn error_if_unsupported_s3_storage_class() {
+        for (class, supported) in [
+            (S3StorageClass::Standard, true),
+            (S3StorageClass::StandardIa, true),
+            (S3StorageClass::IntelligentTiering, true),
+            (S3StorageClass::OnezoneIa, true),
+            (S3StorageClass::ReducedRedundancy, true),
+            (S3StorageClass::DeepArchive, false),
+            (S3StorageClass::Glacier, false),
+        ] {
+            let mut config: DatadogArchivesSinkConfig = toml::from_str(indoc! {r#"
+                service = "aws_s3"
+                bucket = "vector-datadog-archives"
+                key_prefix = "logs/"
+
+                [aws_s3]
+                region = "us-east-1"
+            "#})
+            .unwrap();
+            config.aws_s3.as_mut().unwrap().options.storage_class = class;
+
+            let res = config.build_sink(SinkContext::new_test(), None).await;
+
+            if supported {
+                assert!(res.is_ok());
+            } else {
+                assert_eq!(
+                    res.err().unwrap().to_string(),
+                    format!(r#"Unsupported storage class: {:?}"#, class)
+                );
+            }
+        }
+    }
+}
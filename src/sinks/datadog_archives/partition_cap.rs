@@ -0,0 +1,106 @@
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use crate::internal_events::DatadogArchivesPartitionOverflow;
+
+/// A partition which events can be redirected from once too many partitions are open.
+pub trait OverflowPartition {
+    /// The catch-all partition receiving the events of this partition once the cap is reached.
+    fn overflow(&self) -> Self;
+}
+
+/// Limits the number of partitions buffered by the batcher at once.
+///
+/// A partition is open from its first event until its batch is flushed. Once `max` partitions are
+/// open, events of any other partition are redirected to its catch-all partition, which doesn't
+/// count towards the cap, so that a partition key of unexpectedly high cardinality can't open an
+/// unbounded number of batches.
+#[derive(Debug)]
+pub struct PartitionCap<K> {
+    max: usize,
+    open: Arc<Mutex<HashSet<K>>>,
+}
+
+impl<K> Clone for PartitionCap<K> {
+    fn clone(&self) -> Self {
+        Self {
+            max: self.max,
+            open: Arc::clone(&self.open),
+        }
+    }
+}
+
+impl<K> PartitionCap<K>
+where
+    K: OverflowPartition + Eq + Hash + Clone,
+{
+    pub fn new(max: NonZeroUsize) -> Self {
+        Self {
+            max: max.get(),
+            open: Arc::default(),
+        }
+    }
+
+    /// The partition to buffer an event of partition `key` into.
+    pub fn admit(&self, key: K) -> K {
+        let mut open = self.open.lock().expect("poisoned lock");
+        if open.contains(&key) || open.len() < self.max {
+            open.insert(key.clone());
+            key
+        } else {
+            drop(open);
+            emit!(DatadogArchivesPartitionOverflow { max: self.max });
+            key.overflow()
+        }
+    }
+
+    /// Closes a partition whose batch was flushed by the batcher.
+    pub fn close(&self, key: &K) {
+        self.open.lock().expect("poisoned lock").remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl OverflowPartition for &'static str {
+        fn overflow(&self) -> Self {
+            "overflow"
+        }
+    }
+
+    #[test]
+    fn redirects_partitions_over_the_cap() {
+        let cap = PartitionCap::new(NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(cap.admit("a"), "a");
+        assert_eq!(cap.admit("b"), "b");
+        assert_eq!(cap.admit("c"), "overflow");
+
+        // Partitions which are already open keep receiving their events.
+        assert_eq!(cap.admit("a"), "a");
+        assert_eq!(cap.admit("b"), "b");
+        assert_eq!(cap.admit("d"), "overflow");
+    }
+
+    #[test]
+    fn reopens_partitions_once_flushed() {
+        let cap = PartitionCap::new(NonZeroUsize::new(1).unwrap());
+
+        assert_eq!(cap.admit("a"), "a");
+        assert_eq!(cap.admit("b"), "overflow");
+
+        cap.close(&"a");
+        assert_eq!(cap.admit("b"), "b");
+        assert_eq!(cap.admit("a"), "overflow");
+
+        // Closing the catch-all partition has no effect.
+        cap.close(&"overflow");
+        assert_eq!(cap.admit("a"), "overflow");
+    }
+}
@@ -0,0 +1,963 @@
+use std::num::NonZeroU32;
+
+use chrono::{
+    format::{Fixed, Item, Numeric, Pad},
+    DateTime, Duration, Local, NaiveDate, Utc,
+};
+use lookup::path;
+use vector_common::TimeZone;
+use vector_config::configurable_component;
+use vector_core::{
+    event::{Event, Value},
+    internal_event::{ComponentEventsDropped, INTENTIONAL},
+    partition::Partitioner,
+};
+
+use super::{
+    aggregate::{mark_partition, AggregatePartition},
+    grace_period::ClosingPartition,
+    partition_cap::OverflowPartition,
+};
+use crate::{internal_events::TemplateRenderingError, template::Template};
+
+/// The source segment used for events which don't have a `source`.
+const UNKNOWN_SOURCE: &str = "unknown";
+
+/// The directory of the catch-all partition, used once too many partitions are open.
+const OVERFLOW_PARTITION: &str = "overflow";
+
+/// The directory of the aggregated objects, within the directory shared by their partitions.
+const AGGREGATE_PARTITION: &str = "aggregate";
+
+/// The time by which the archives are partitioned.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionTime {
+    /// Events are partitioned by their timestamp.
+    #[default]
+    EventTime,
+
+    /// Events are partitioned by the time at which they were ingested.
+    ///
+    /// This places late events in the partition of the time at which they were processed. The
+    /// ingest time is taken from the `vector.ingest_timestamp` metadata of the events, which is set
+    /// by the sources using the `vector` log namespace. Events without it are partitioned by the
+    /// time at which they're archived, and `partition_fallback` doesn't apply.
+    IngestTime,
+}
+
+/// What to do with events which don't have a timestamp to be partitioned by.
+#[configurable_component]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[serde(tag = "strategy", rename_all = "snake_case", deny_unknown_fields)]
+#[configurable(metadata(
+    docs::enum_tag_description = "What to do with events which don't have a timestamp."
+))]
+pub enum PartitionFallback {
+    /// Events are partitioned by the time at which they are archived.
+    #[default]
+    Now,
+
+    /// Events are dropped.
+    Drop,
+
+    /// Events are archived under a fixed partition.
+    Literal {
+        /// The partition under which events are archived, such as `/dt=unknown/`.
+        ///
+        /// It's used in place of the `dt=<date>/hour=<hour>/` part of the object keys.
+        #[configurable(metadata(docs::examples = "/dt=unknown/"))]
+        prefix: String,
+    },
+}
+
+/// What to do with events whose timestamp is in the future.
+///
+/// Sources with a skewed clock can produce events timestamped in the future, which are otherwise
+/// archived under future partitions, where they're easily missed when rehydrating. Timestamps are
+/// in the future when they're later than the current time by more than
+/// `future_timestamp_tolerance_secs`.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FutureTimestamps {
+    /// Events are archived with their timestamp.
+    #[default]
+    Leave,
+
+    /// Events are partitioned by the current time, which becomes their `date`.
+    Clamp,
+
+    /// Events are dropped, and an error is reported.
+    ///
+    /// The events are partitioned by the current time, so that their batch isn't written to a
+    /// future partition, and are rejected once they're encoded.
+    Reject,
+}
+
+/// The default index of the events which don't have a `source`.
+const UNKNOWN_INDEX: &str = "unknown";
+
+/// The maximum length of an index name.
+const MAX_INDEX_LENGTH: usize = 255;
+
+/// Partitioning of the archives by index, derived from the `source` of the events.
+///
+/// The objects are written under an `index=<source>/` directory, ahead of the time partition, as
+/// in `index=nginx/dt=20230801/hour=12/`. The name of the index is the `source` of the events,
+/// lowercased, with any character which isn't an ASCII letter, an ASCII digit, or one of `-`, `_`,
+/// and `.` replaced with `_`, and without leading `-`, `_`, and `.`.
+#[configurable_component]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SourceIndexConfig {
+    /// The index of the events which don't have a `source`, or whose `source` can't be used as an
+    /// index name.
+    #[serde(default = "default_index")]
+    #[configurable(metadata(docs::examples = "unknown"))]
+    pub default_index: String,
+}
+
+fn default_index() -> String {
+    UNKNOWN_INDEX.to_owned()
+}
+
+impl Default for SourceIndexConfig {
+    fn default() -> Self {
+        Self {
+            default_index: default_index(),
+        }
+    }
+}
+
+/// The statuses of the events which are routed to the error objects.
+const ERROR_STATUSES: [&str; 8] = [
+    "emerg",
+    "emergency",
+    "alert",
+    "crit",
+    "critical",
+    "err",
+    "error",
+    "fatal",
+];
+
+/// The partition of a batch of events.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ArchivePartition {
+    /// The directory of the objects, relative to the `key_prefix`.
+    pub prefix: String,
+
+    /// Whether the events are errors, which are written to separate objects.
+    pub errors: bool,
+}
+
+impl ArchivePartition {
+    pub const fn new(prefix: String) -> Self {
+        Self {
+            prefix,
+            errors: false,
+        }
+    }
+}
+
+impl AggregatePartition for ArchivePartition {
+    /// The aggregated objects are written to the deepest directory shared by all of the partitions,
+    /// which is usually the directory of their hour. Error partitions are only aggregated with each
+    /// other, so the aggregated objects hold error events if their partitions do.
+    fn aggregate(batches: Vec<(Self, Vec<Event>)>) -> (Self, Vec<Event>) {
+        let mut directory: Option<&str> = None;
+        for (partition, _) in &batches {
+            directory = Some(match directory {
+                None => &partition.prefix,
+                Some(directory) => common_directory(directory, &partition.prefix),
+            });
+        }
+        let partition = ArchivePartition {
+            prefix: format!("{}{}/", directory.unwrap_or("/"), AGGREGATE_PARTITION),
+            errors: batches.iter().all(|(partition, _)| partition.errors),
+        };
+
+        let events = batches
+            .into_iter()
+            .flat_map(|(partition, mut events)| {
+                mark_partition(&mut events, &partition.prefix);
+                events
+            })
+            .collect();
+
+        (partition, events)
+    }
+
+    fn errors(&self) -> bool {
+        self.errors
+    }
+}
+
+impl ClosingPartition for ArchivePartition {
+    /// The end of the hour of the partition, from its `dt=` and `hour=` segments.
+    fn end(&self) -> Option<DateTime<Utc>> {
+        let segment = |name: &str| {
+            self.prefix
+                .split('/')
+                .find_map(|segment| segment.strip_prefix(name))
+        };
+        let date = parse_partition_date(segment("dt=")?)?;
+        let hour = segment("hour=")?.parse().ok()?;
+
+        let start = DateTime::<Utc>::from_utc(date.and_hms_opt(hour, 0, 0)?, Utc);
+
+        Some(start + Duration::hours(1))
+    }
+}
+
+impl OverflowPartition for ArchivePartition {
+    fn overflow(&self) -> Self {
+        // Error events are still kept apart from the others in the catch-all partition.
+        Self {
+            prefix: format!("/{}/", OVERFLOW_PARTITION),
+            errors: self.errors,
+        }
+    }
+}
+
+/// Partitions events into the directories of the archives.
+///
+/// Events are always partitioned by time, and optionally by their `source` within each hour. Error
+/// events can also be partitioned apart from the others.
+pub struct DatadogArchivesPartitioner {
+    time_template: Template,
+    partition_by_source: bool,
+    partition_time: PartitionTime,
+    fallback: PartitionFallback,
+    route_errors: bool,
+    source_index: Option<SourceIndexConfig>,
+    hash_buckets: Option<NonZeroU32>,
+    timezone: Option<TimeZone>,
+    future_tolerance: Option<Duration>,
+}
+
+impl DatadogArchivesPartitioner {
+    pub const fn new(time_template: Template, partition_by_source: bool) -> Self {
+        Self {
+            time_template,
+            partition_by_source,
+            partition_time: PartitionTime::EventTime,
+            fallback: PartitionFallback::Now,
+            route_errors: false,
+            source_index: None,
+            hash_buckets: None,
+            timezone: None,
+            future_tolerance: None,
+        }
+    }
+
+    /// Sets whether error events are partitioned apart from the others.
+    pub const fn with_error_routing(mut self, route_errors: bool) -> Self {
+        self.route_errors = route_errors;
+        self
+    }
+
+    /// Sets whether events are partitioned by an index derived from their `source`.
+    pub fn with_source_index(mut self, source_index: Option<SourceIndexConfig>) -> Self {
+        self.source_index = source_index;
+        self
+    }
+
+    /// Sets the number of hash buckets events are spread across, if any.
+    pub const fn with_hash_buckets(mut self, hash_buckets: Option<NonZeroU32>) -> Self {
+        self.hash_buckets = hash_buckets;
+        self
+    }
+
+    /// Sets the time by which events are partitioned.
+    pub const fn with_partition_time(mut self, partition_time: PartitionTime) -> Self {
+        self.partition_time = partition_time;
+        self
+    }
+
+    /// Sets the time zone the time of the partitions is rendered in, rather than UTC.
+    pub const fn with_timezone(mut self, timezone: Option<TimeZone>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Partitions events with a timestamp later than the current time by more than `tolerance` by
+    /// the current time, unless future timestamps are left as is.
+    pub fn with_future_timestamps(
+        mut self,
+        future_timestamps: FutureTimestamps,
+        tolerance: Duration,
+    ) -> Self {
+        self.future_tolerance = (future_timestamps != FutureTimestamps::Leave).then_some(tolerance);
+        self
+    }
+
+    /// Sets what to do with events which don't have a timestamp.
+    pub fn with_fallback(mut self, fallback: PartitionFallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    fn render_time(&self, item: &Event) -> Option<String> {
+        if self.partition_time == PartitionTime::IngestTime {
+            return Some(self.format_time(ingest_time(item)));
+        }
+
+        let has_timestamp = item.maybe_as_log().map_or(true, |log| {
+            log.get_timestamp().and_then(Value::as_timestamp).is_some()
+        });
+
+        match &self.fallback {
+            PartitionFallback::Drop if !has_timestamp => {
+                emit!(ComponentEventsDropped::<INTENTIONAL> {
+                    count: 1,
+                    reason: "Event has no timestamp to be partitioned by.",
+                });
+                return None;
+            }
+            PartitionFallback::Literal { prefix } if !has_timestamp => {
+                return Some(prefix.clone());
+            }
+            // The template renders the current time for events without a timestamp.
+            _ => {}
+        }
+
+        if let Some(tolerance) = self.future_tolerance {
+            let now = Utc::now();
+            if event_time(item).map_or(false, |timestamp| is_future(timestamp, now, tolerance)) {
+                return Some(self.format_time(now));
+            }
+        }
+
+        if self.timezone.is_some() {
+            let timestamp = event_time(item).unwrap_or_else(Utc::now);
+            return Some(self.format_time(timestamp));
+        }
+
+        self.time_template
+            .render_string(item)
+            .map_err(|error| {
+                emit!(TemplateRenderingError {
+                    error,
+                    field: Some("key_prefix"),
+                    drop_event: true,
+                });
+            })
+            .ok()
+    }
+
+    /// Renders the time template for `timestamp`, in the time zone of the partitions.
+    fn format_time(&self, timestamp: DateTime<Utc>) -> String {
+        let format = self.time_template.get_ref();
+        match self.timezone {
+            None => timestamp.format(format).to_string(),
+            Some(TimeZone::Local) => timestamp.with_timezone(&Local).format(format).to_string(),
+            Some(TimeZone::Named(tz)) => timestamp.with_timezone(&tz).format(format).to_string(),
+        }
+    }
+}
+
+impl Partitioner for DatadogArchivesPartitioner {
+    type Item = Event;
+    type Key = Option<ArchivePartition>;
+
+    fn partition(&self, item: &Self::Item) -> Self::Key {
+        let mut prefix = self.render_time(item)?;
+
+        if let Some(source_index) = &self.source_index {
+            prefix = format!(
+                "/index={}{}",
+                index_segment(item, &source_index.default_index),
+                prefix
+            );
+        }
+
+        if let Some(buckets) = self.hash_buckets {
+            prefix = format!("/{}{}", hash_bucket_segment(item, buckets), prefix);
+        }
+
+        if self.partition_by_source {
+            prefix.push_str("source=");
+            prefix.push_str(&source_segment(item));
+            prefix.push('/');
+        }
+
+        Some(ArchivePartition {
+            prefix,
+            errors: self.route_errors && is_error(item),
+        })
+    }
+}
+
+/// Whether `timestamp` is later than `now` by more than `tolerance`.
+pub fn is_future(timestamp: DateTime<Utc>, now: DateTime<Utc>, tolerance: Duration) -> bool {
+    now.checked_add_signed(tolerance)
+        .map_or(false, |latest| timestamp > latest)
+}
+
+/// The timestamp of the event, if it has one.
+fn event_time(event: &Event) -> Option<DateTime<Utc>> {
+    event
+        .maybe_as_log()
+        .and_then(|log| log.get_timestamp())
+        .and_then(Value::as_timestamp)
+        .copied()
+}
+
+/// The time at which the event was ingested, or the current time if it isn't known.
+fn ingest_time(event: &Event) -> DateTime<Utc> {
+    event
+        .maybe_as_log()
+        .and_then(|log| {
+            log.metadata()
+                .value()
+                .get(path!("vector", "ingest_timestamp"))
+        })
+        .and_then(Value::as_timestamp)
+        .copied()
+        .unwrap_or_else(Utc::now)
+}
+
+/// The deepest directory shared by both prefixes, with its trailing `/`.
+fn common_directory<'a>(a: &'a str, b: &str) -> &'a str {
+    let common = a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count();
+    a[..common].rfind('/').map_or("", |end| &a[..=end])
+}
+
+/// Whether the `status` of the event is an error status.
+fn is_error(event: &Event) -> bool {
+    event
+        .maybe_as_log()
+        .and_then(|log| log.get("status"))
+        .map_or(false, |status| {
+            let status = status.to_string_lossy();
+            ERROR_STATUSES
+                .iter()
+                .any(|error| status.eq_ignore_ascii_case(error))
+        })
+}
+
+/// The key segment for the `source` of the event, which can't contain any `/`.
+fn source_segment(event: &Event) -> String {
+    event
+        .maybe_as_log()
+        .and_then(|log| log.get("source"))
+        .map(|source| source.to_string_lossy().replace('/', "_"))
+        .filter(|source| !source.is_empty())
+        .unwrap_or_else(|| UNKNOWN_SOURCE.to_owned())
+}
+
+/// The key segment for the hash bucket of the event, out of `buckets`.
+///
+/// The bucket is a stable hash of the `message` of the event, so that an event is always assigned
+/// the same bucket.
+fn hash_bucket_segment(event: &Event, buckets: NonZeroU32) -> String {
+    let message = event
+        .maybe_as_log()
+        .and_then(|log| log.get_message())
+        .map(Value::to_string_lossy)
+        .unwrap_or_default();
+    let bucket = seahash::hash(message.as_bytes()) % u64::from(buckets.get());
+
+    format!(
+        "bucket={:0width$}",
+        bucket,
+        width = hash_bucket_width(buckets)
+    )
+}
+
+/// The width of the numbers of `buckets` hash buckets, which are zero-padded to at least two digits
+/// so that they sort in order.
+pub fn hash_bucket_width(buckets: NonZeroU32) -> usize {
+    (buckets.get() - 1).to_string().len().max(2)
+}
+
+/// The index name for the `source` of the event, or `default_index` if it has none.
+fn index_segment(event: &Event, default_index: &str) -> String {
+    event
+        .maybe_as_log()
+        .and_then(|log| log.get("source"))
+        .and_then(|source| sanitize_index(&source.to_string_lossy()))
+        .unwrap_or_else(|| default_index.to_owned())
+}
+
+/// Turns `name` into a valid index name, or returns `None` if nothing is left of it.
+fn sanitize_index(name: &str) -> Option<String> {
+    let mut index = name
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect::<String>()
+        .trim_start_matches(['-', '_', '.'])
+        .to_owned();
+    // Only ASCII characters are left, so the index can be truncated anywhere.
+    index.truncate(MAX_INDEX_LENGTH);
+
+    (!index.is_empty()).then_some(index)
+}
+
+/// Parses the date of a `dt=` segment, whatever the separator between its components.
+pub fn parse_partition_date(date: &str) -> Option<NaiveDate> {
+    let digits = date
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+    NaiveDate::parse_from_str(&digits, "%Y%m%d").ok()
+}
+
+/// The time segments of a key template which don't sort in the order of their time.
+///
+/// Objects are listed in the lexical order of their keys, which only matches the order of their
+/// time if every time segment is a zero-padded number, as with `%H`. Numbers which aren't padded,
+/// such as `%-H` or `%k`, hours on a 12-hour clock, and names of months or weekdays are returned
+/// with a description of why they don't sort. Unix timestamps aren't padded, but are returned as
+/// sortable.
+pub fn unsortable_time_segments(template: &Template) -> Vec<String> {
+    template
+        .strftime_items()
+        .filter_map(|item| match item {
+            Item::Numeric(Numeric::Timestamp, _) => None,
+            Item::Numeric(Numeric::Hour12, _) => {
+                Some("`Hour12` doesn't sort across AM and PM".to_owned())
+            }
+            Item::Numeric(numeric, pad) if *pad != Pad::Zero => {
+                Some(format!("`{:?}` isn't zero-padded", numeric))
+            }
+            Item::Fixed(
+                fixed @ (Fixed::ShortMonthName
+                | Fixed::LongMonthName
+                | Fixed::ShortWeekdayName
+                | Fixed::LongWeekdayName
+                | Fixed::LowerAmPm
+                | Fixed::UpperAmPm
+                | Fixed::RFC2822),
+            ) => Some(format!("`{:?}` isn't numeric", fixed)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use lookup::metadata_path;
+
+    use super::*;
+    use crate::{event::LogEvent, sinks::datadog_archives::KEY_TEMPLATE};
+
+    fn event(source: Option<&str>) -> Event {
+        let mut log = LogEvent::from("test message");
+        let timestamp = DateTime::parse_from_rfc3339("2021-08-23T18:00:27.879+02:00")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+        log.insert("timestamp", timestamp);
+        if let Some(source) = source {
+            log.insert("source", source);
+        }
+        log.into()
+    }
+
+    #[test]
+    fn partitions_by_source() {
+        let partitioner =
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), true);
+
+        assert_eq!(
+            partitioner.partition(&event(Some("nginx"))).unwrap().prefix,
+            "/dt=20210823/hour=16/source=nginx/"
+        );
+        assert_eq!(
+            partitioner
+                .partition(&event(Some("app/worker")))
+                .unwrap()
+                .prefix,
+            "/dt=20210823/hour=16/source=app_worker/"
+        );
+        assert_eq!(
+            partitioner.partition(&event(None)).unwrap().prefix,
+            "/dt=20210823/hour=16/source=unknown/"
+        );
+    }
+
+    fn event_without_timestamp() -> Event {
+        let mut log = LogEvent::default();
+        log.insert("message", "test message");
+        log.into()
+    }
+
+    fn partitioner(fallback: PartitionFallback) -> DatadogArchivesPartitioner {
+        DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+            .with_fallback(fallback)
+    }
+
+    #[test]
+    fn falls_back_to_now() {
+        let before = Utc::now();
+        let key = partitioner(PartitionFallback::Now)
+            .partition(&event_without_timestamp())
+            .unwrap()
+            .prefix;
+        let after = Utc::now();
+
+        let expected = [before, after].map(|now| now.format("/dt=%Y%m%d/hour=%H/").to_string());
+        assert!(expected.contains(&key));
+    }
+
+    #[test]
+    fn falls_back_to_drop() {
+        let partitioner = partitioner(PartitionFallback::Drop);
+        assert!(partitioner.partition(&event_without_timestamp()).is_none());
+
+        // Events with a timestamp are partitioned as usual.
+        assert_eq!(
+            partitioner.partition(&event(None)).unwrap().prefix,
+            "/dt=20210823/hour=16/"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_literal() {
+        let partitioner = partitioner(PartitionFallback::Literal {
+            prefix: "/dt=unknown/".to_owned(),
+        });
+        assert_eq!(
+            partitioner
+                .partition(&event_without_timestamp())
+                .unwrap()
+                .prefix,
+            "/dt=unknown/"
+        );
+        assert_eq!(
+            partitioner.partition(&event(None)).unwrap().prefix,
+            "/dt=20210823/hour=16/"
+        );
+    }
+
+    #[test]
+    fn partitions_future_events_by_now() {
+        let future = Utc::now() + Duration::days(365);
+        let mut future_event = event(None);
+        future_event.as_mut_log().insert("timestamp", future);
+        let partitioner = |future_timestamps| {
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+                .with_future_timestamps(future_timestamps, Duration::minutes(5))
+        };
+
+        for future_timestamps in [FutureTimestamps::Clamp, FutureTimestamps::Reject] {
+            let partitioner = partitioner(future_timestamps);
+            let before = Utc::now();
+            let key = partitioner.partition(&future_event).unwrap().prefix;
+            let after = Utc::now();
+
+            let expected = [before, after].map(|now| now.format("/dt=%Y%m%d/hour=%H/").to_string());
+            assert!(expected.contains(&key));
+            // Past events are partitioned as usual.
+            assert_eq!(
+                partitioner.partition(&event(None)).unwrap().prefix,
+                "/dt=20210823/hour=16/"
+            );
+        }
+
+        assert_eq!(
+            partitioner(FutureTimestamps::Leave)
+                .partition(&future_event)
+                .unwrap()
+                .prefix,
+            future.format("/dt=%Y%m%d/hour=%H/").to_string()
+        );
+    }
+
+    #[test]
+    fn routes_errors() {
+        let partitioner = |route_errors| {
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+                .with_error_routing(route_errors)
+        };
+
+        for (status, errors) in [("info", false), ("error", true), ("CRITICAL", true)] {
+            let mut event = event(None);
+            event.as_mut_log().insert("status", status);
+
+            assert!(!partitioner(false).partition(&event).unwrap().errors);
+
+            let partition = partitioner(true).partition(&event).unwrap();
+            assert_eq!(partition.prefix, "/dt=20210823/hour=16/");
+            assert_eq!(partition.errors, errors);
+        }
+    }
+
+    #[test]
+    fn partitions_by_source_index() {
+        let partitioner =
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+                .with_source_index(Some(SourceIndexConfig::default()));
+
+        assert_eq!(
+            partitioner.partition(&event(Some("nginx"))).unwrap().prefix,
+            "/index=nginx/dt=20210823/hour=16/"
+        );
+        assert_eq!(
+            partitioner
+                .partition(&event(Some("_My App/Worker #1")))
+                .unwrap()
+                .prefix,
+            "/index=my_app_worker__1/dt=20210823/hour=16/"
+        );
+        assert_eq!(
+            partitioner.partition(&event(None)).unwrap().prefix,
+            "/index=unknown/dt=20210823/hour=16/"
+        );
+
+        // Sources with nothing left once sanitized go to the default index.
+        let partitioner = partitioner.with_source_index(Some(SourceIndexConfig {
+            default_index: "logs".to_owned(),
+        }));
+        assert_eq!(
+            partitioner.partition(&event(Some("__"))).unwrap().prefix,
+            "/index=logs/dt=20210823/hour=16/"
+        );
+    }
+
+    #[test]
+    fn partitions_by_hash_bucket() {
+        let buckets = NonZeroU32::new(16).unwrap();
+        let partitioner =
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+                .with_source_index(Some(SourceIndexConfig::default()))
+                .with_hash_buckets(Some(buckets));
+        let event = |message: &str| {
+            let mut log = LogEvent::from(message);
+            log.insert(
+                "timestamp",
+                Utc.with_ymd_and_hms(2021, 8, 23, 16, 0, 0).unwrap(),
+            );
+            log.insert("source", "nginx");
+            Event::Log(log)
+        };
+
+        // The bucket of an event is deterministic, and composed with the other segments.
+        let prefix = partitioner.partition(&event("first")).unwrap().prefix;
+        assert_eq!(
+            partitioner.partition(&event("first")).unwrap().prefix,
+            prefix
+        );
+        let bucket = prefix
+            .strip_prefix("/bucket=")
+            .and_then(|prefix| prefix.strip_suffix("/index=nginx/dt=20210823/hour=16/"))
+            .unwrap();
+        assert_eq!(bucket.len(), 2);
+        assert!(bucket.parse::<u32>().unwrap() < 16);
+
+        // A sample of events is spread across all of the buckets.
+        let mut counts = [0; 16];
+        for i in 0..1600 {
+            let segment = hash_bucket_segment(&event(&format!("event {}", i)), buckets);
+            let bucket = segment["bucket=".len()..].parse::<usize>().unwrap();
+            counts[bucket] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 50), "{:?}", counts);
+
+        assert_eq!(hash_bucket_width(NonZeroU32::new(1).unwrap()), 2);
+        assert_eq!(hash_bucket_width(NonZeroU32::new(100).unwrap()), 2);
+        assert_eq!(hash_bucket_width(NonZeroU32::new(101).unwrap()), 3);
+    }
+
+    #[test]
+    fn partitions_in_a_time_zone() {
+        let partitioner = |timezone: &str| {
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+                .with_timezone(Some(TimeZone::parse(timezone).unwrap()))
+        };
+        // 2021-08-23T16:00:27.879Z, in the evening in Paris and in the morning in Los Angeles.
+        let event = event(None);
+
+        assert_eq!(
+            partitioner("Europe/Paris")
+                .partition(&event)
+                .unwrap()
+                .prefix,
+            "/dt=20210823/hour=18/"
+        );
+        assert_eq!(
+            partitioner("America/Los_Angeles")
+                .partition(&event)
+                .unwrap()
+                .prefix,
+            "/dt=20210823/hour=09/"
+        );
+        assert_eq!(
+            partitioner("Asia/Tokyo").partition(&event).unwrap().prefix,
+            "/dt=20210824/hour=01/"
+        );
+        assert_eq!(
+            partitioner("Etc/UTC").partition(&event).unwrap().prefix,
+            "/dt=20210823/hour=16/"
+        );
+    }
+
+    #[test]
+    fn sanitizes_index_names() {
+        assert_eq!(sanitize_index("nginx").as_deref(), Some("nginx"));
+        assert_eq!(
+            sanitize_index("Web.Server-1").as_deref(),
+            Some("web.server-1")
+        );
+        assert_eq!(sanitize_index("a:b*c?d").as_deref(), Some("a_b_c_d"));
+        assert_eq!(sanitize_index("-_.kafka").as_deref(), Some("kafka"));
+        assert_eq!(sanitize_index("é").as_deref(), None);
+        assert_eq!(
+            sanitize_index(&"a".repeat(300)).map(|index| index.len()),
+            Some(255)
+        );
+    }
+
+    #[test]
+    fn partitions_by_ingest_time() {
+        // An event from 16:00, ingested late at 19:30.
+        let mut late = event(None);
+        let ingested = DateTime::parse_from_rfc3339("2021-08-23T19:30:00Z")
+            .expect("invalid test case")
+            .with_timezone(&Utc);
+        late.as_mut_log()
+            .insert(metadata_path!("vector", "ingest_timestamp"), ingested);
+
+        let partitioner = |partition_time| {
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+                .with_partition_time(partition_time)
+        };
+        assert_eq!(
+            partitioner(PartitionTime::EventTime)
+                .partition(&late)
+                .unwrap()
+                .prefix,
+            "/dt=20210823/hour=16/"
+        );
+        assert_eq!(
+            partitioner(PartitionTime::IngestTime)
+                .partition(&late)
+                .unwrap()
+                .prefix,
+            "/dt=20210823/hour=19/"
+        );
+    }
+
+    #[test]
+    fn partitions_by_now_without_ingest_time() {
+        let before = Utc::now();
+        let key = DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false)
+            .with_partition_time(PartitionTime::IngestTime)
+            .with_fallback(PartitionFallback::Drop)
+            .partition(&event_without_timestamp())
+            .unwrap()
+            .prefix;
+        let after = Utc::now();
+
+        let expected = [before, after].map(|now| now.format("/dt=%Y%m%d/hour=%H/").to_string());
+        assert!(expected.contains(&key));
+    }
+
+    #[test]
+    fn overflows_to_a_catch_all_partition() {
+        let partitioner = DatadogArchivesPartitioner::new(
+            Template::try_from(KEY_TEMPLATE).expect("invalid key template"),
+            true,
+        );
+        let partition = partitioner.partition(&event(Some("nginx"))).unwrap();
+
+        assert_eq!(
+            partition.overflow(),
+            ArchivePartition::new("/overflow/".into())
+        );
+
+        let errors = ArchivePartition {
+            errors: true,
+            ..partition
+        };
+        assert!(errors.overflow().errors);
+    }
+
+    #[test]
+    fn aggregates_into_the_shared_directory() {
+        let partition = |prefix: &str| ArchivePartition::new(prefix.to_owned());
+        let (aggregate, events) = ArchivePartition::aggregate(vec![
+            (
+                partition("/dt=20210823/hour=16/source=nginx/"),
+                vec![event(Some("nginx"))],
+            ),
+            (
+                partition("/dt=20210823/hour=16/source=redis/"),
+                vec![event(Some("redis"))],
+            ),
+        ]);
+
+        assert_eq!(aggregate, partition("/dt=20210823/hour=16/aggregate/"));
+        assert_eq!(events.len(), 2);
+
+        let (aggregate, _) = ArchivePartition::aggregate(vec![
+            (partition("/dt=20210823/hour=16/"), Vec::new()),
+            (partition("/dt=20210823/hour=17/"), Vec::new()),
+        ]);
+        assert_eq!(aggregate, partition("/dt=20210823/aggregate/"));
+    }
+
+    #[test]
+    fn ends_at_the_end_of_the_hour() {
+        let end = |prefix: &str| ArchivePartition::new(prefix.to_owned()).end();
+
+        assert_eq!(
+            end("/index=main/dt=20210823/hour=23/source=nginx/"),
+            Some(
+                DateTime::parse_from_rfc3339("2021-08-24T00:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+        assert_eq!(end("/overflow/"), None);
+        assert_eq!(end("/dt=unknown/hour=16/"), None);
+    }
+
+    #[test]
+    fn accepts_sortable_key_templates() {
+        let segments = |template| unsortable_time_segments(&Template::try_from(template).unwrap());
+
+        assert!(segments(KEY_TEMPLATE).is_empty());
+        assert!(segments("/{{ service }}/%Y/%m/%d/%H%M%S/").is_empty());
+        assert!(segments("/epoch=%s/").is_empty());
+    }
+
+    #[test]
+    fn reports_unsortable_key_templates() {
+        let segments = |template| unsortable_time_segments(&Template::try_from(template).unwrap());
+
+        assert_eq!(
+            segments("/dt=%Y%m%d/hour=%-H/"),
+            vec!["`Hour` isn't zero-padded"]
+        );
+        assert_eq!(
+            segments("/%Y/%b/%e/"),
+            vec!["`ShortMonthName` isn't numeric", "`Day` isn't zero-padded"]
+        );
+        assert_eq!(
+            segments("/%Y%m%d/%I%p/"),
+            vec![
+                "`Hour12` doesn't sort across AM and PM",
+                "`UpperAmPm` isn't numeric"
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_source_unless_enabled() {
+        let partitioner =
+            DatadogArchivesPartitioner::new(Template::try_from(KEY_TEMPLATE).unwrap(), false);
+
+        assert_eq!(
+            partitioner.partition(&event(Some("nginx"))).unwrap().prefix,
+            "/dt=20210823/hour=16/"
+        );
+    }
+}
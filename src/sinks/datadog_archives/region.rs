@@ -0,0 +1,160 @@
+use aws_sdk_s3::Client as S3Client;
+use vector_config::configurable_component;
+
+use super::ConfigError;
+use crate::aws::RegionOrEndpoint;
+
+/// The region of the buckets without a location constraint.
+const DEFAULT_BUCKET_REGION: &str = "us-east-1";
+
+/// What to do when the S3 bucket is in another region than the configured one.
+///
+/// Writes to a bucket in another region are redirected by S3, and fail once the sink is running.
+/// Unless mismatches are ignored, the region of the bucket is looked up when the sink is built.
+/// The lookup is skipped when a custom `endpoint` is configured, and the sink is built as
+/// configured if the lookup fails, such as when the credentials can't read the location of the
+/// bucket.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionMismatch {
+    /// The region of the bucket isn't looked up.
+    #[default]
+    Ignore,
+
+    /// The sink fails to build.
+    Error,
+
+    /// The sink is built for the region of the bucket, and a warning is logged.
+    Correct,
+}
+
+/// The region of a bucket, from its location constraint.
+///
+/// Buckets in `us-east-1` have no location constraint, and the legacy `EU` constraint stands for
+/// `eu-west-1`.
+fn bucket_region(location_constraint: Option<&str>) -> &str {
+    match location_constraint {
+        None | Some("") => DEFAULT_BUCKET_REGION,
+        Some("EU") => "eu-west-1",
+        Some(region) => region,
+    }
+}
+
+/// Applies `policy` to a bucket in `actual` region, with the `configured` region.
+///
+/// Returns the region to build the sink for, if it's to be corrected.
+fn resolve_region(
+    policy: RegionMismatch,
+    bucket: &str,
+    configured: &RegionOrEndpoint,
+    actual: &str,
+) -> Result<Option<RegionOrEndpoint>, ConfigError> {
+    let configured_region = configured.region.as_deref().unwrap_or_default();
+    if configured_region == actual {
+        return Ok(None);
+    }
+
+    match policy {
+        RegionMismatch::Ignore => Ok(None),
+        RegionMismatch::Error => Err(ConfigError::BucketRegionMismatch {
+            bucket: bucket.to_owned(),
+            region: actual.to_owned(),
+            configured: configured_region.to_owned(),
+        }),
+        RegionMismatch::Correct => {
+            warn!(
+                message = "Bucket is in another region than the configured one, using its region.",
+                bucket,
+                region = actual,
+                configured = configured_region,
+            );
+            Ok(Some(RegionOrEndpoint::with_region(actual.to_owned())))
+        }
+    }
+}
+
+/// Looks up the region of `bucket`, and applies `policy` if it isn't the `configured` one.
+///
+/// Returns the region to build the sink for, if it's to be corrected.
+pub(super) async fn check_bucket_region(
+    policy: RegionMismatch,
+    bucket: &str,
+    configured: &RegionOrEndpoint,
+    client: &S3Client,
+) -> Result<Option<RegionOrEndpoint>, ConfigError> {
+    if policy == RegionMismatch::Ignore || configured.endpoint.is_some() {
+        return Ok(None);
+    }
+
+    match client.get_bucket_location().bucket(bucket).send().await {
+        Ok(output) => {
+            let location_constraint = output.location_constraint().map(|lc| lc.as_str());
+            resolve_region(
+                policy,
+                bucket,
+                configured,
+                bucket_region(location_constraint),
+            )
+        }
+        Err(error) => {
+            warn!(
+                message = "Couldn't look up the region of the bucket.",
+                bucket,
+                %error,
+            );
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured(region: &str) -> RegionOrEndpoint {
+        RegionOrEndpoint::with_region(region.to_owned())
+    }
+
+    #[test]
+    fn maps_location_constraints_to_regions() {
+        assert_eq!(bucket_region(None), "us-east-1");
+        assert_eq!(bucket_region(Some("")), "us-east-1");
+        assert_eq!(bucket_region(Some("EU")), "eu-west-1");
+        assert_eq!(bucket_region(Some("ap-south-1")), "ap-south-1");
+    }
+
+    #[test]
+    fn accepts_matching_regions() {
+        for policy in [
+            RegionMismatch::Ignore,
+            RegionMismatch::Error,
+            RegionMismatch::Correct,
+        ] {
+            assert_eq!(
+                resolve_region(policy, "logs", &configured("eu-west-1"), "eu-west-1"),
+                Ok(None)
+            );
+        }
+    }
+
+    #[test]
+    fn applies_the_policy_to_mismatching_regions() {
+        let resolve =
+            |policy| resolve_region(policy, "logs", &configured("us-east-1"), "eu-west-1");
+
+        assert_eq!(resolve(RegionMismatch::Ignore), Ok(None));
+        assert_eq!(
+            resolve(RegionMismatch::Error),
+            Err(ConfigError::BucketRegionMismatch {
+                bucket: "logs".to_owned(),
+                region: "eu-west-1".to_owned(),
+                configured: "us-east-1".to_owned(),
+            })
+        );
+        assert_eq!(
+            resolve(RegionMismatch::Correct),
+            Ok(Some(configured("eu-west-1")))
+        );
+    }
+}
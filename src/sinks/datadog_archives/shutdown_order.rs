@@ -0,0 +1,302 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+};
+
+use async_stream::stream;
+use futures::{future, future::BoxFuture, stream, Stream, StreamExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::PollSemaphore;
+use tower::Service;
+
+use super::grace_period::ClosingPartition;
+
+/// Orders the batches flushed on shutdown by the time of their partition.
+///
+/// Once the input of the sink ends, the batches still buffered are flushed in an arbitrary order.
+/// They're instead collected until the end of the stream, and written in the order of the end of
+/// their partition, so that the last objects written are those of the most recent partitions.
+/// Partitions which don't cover a period of time are written first. The requests writing them are
+/// sent one at a time by [`ShutdownOrderService`], so that they also complete in that order.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownOrder {
+    input_ended: Arc<AtomicBool>,
+}
+
+impl ShutdownOrder {
+    /// Watches `items` for the end of the input of the sink.
+    pub fn watch<S: Stream>(&self, items: S) -> impl Stream<Item = S::Item> {
+        let input_ended = Arc::clone(&self.input_ended);
+        let end = stream::once(async move {
+            input_ended.store(true, Ordering::Release);
+        })
+        .filter_map(|()| future::ready(None));
+
+        items.chain(end)
+    }
+
+    /// Applies the order to a stream of batches.
+    ///
+    /// Batches flushed before the input ends are passed through as they come.
+    pub fn order<'a, K, T>(
+        self,
+        batches: impl Stream<Item = (K, Vec<T>)> + Send + 'a,
+    ) -> impl Stream<Item = (K, Vec<T>)> + Send + 'a
+    where
+        K: ClosingPartition + Send + 'a,
+        T: Send + 'a,
+    {
+        let mut batches = Box::pin(batches);
+
+        stream! {
+            while let Some(batch) = batches.next().await {
+                if !self.input_ended.load(Ordering::Acquire) {
+                    yield batch;
+                    continue;
+                }
+
+                let mut remaining = vec![batch];
+                while let Some(batch) = batches.next().await {
+                    remaining.push(batch);
+                }
+                remaining.sort_by_key(|(key, _)| key.end());
+                for batch in remaining {
+                    yield batch;
+                }
+            }
+        }
+    }
+}
+
+/// Sends requests one at a time once the input of the sink ends, if the shutdown is ordered.
+///
+/// The driver sends requests concurrently, so that the objects ordered on shutdown would otherwise
+/// complete in an arbitrary order. Once the input ends, each request is instead only sent once the
+/// previous one completed, including its retries.
+pub struct ShutdownOrderService<S> {
+    inner: S,
+    input_ended: Option<Arc<AtomicBool>>,
+    permits: PollSemaphore,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<S> ShutdownOrderService<S> {
+    pub fn new(inner: S, shutdown_order: Option<&ShutdownOrder>) -> Self {
+        Self {
+            inner,
+            input_ended: shutdown_order.map(|order| Arc::clone(&order.input_ended)),
+            permits: PollSemaphore::new(Arc::new(Semaphore::new(1))),
+            permit: None,
+        }
+    }
+
+    fn input_ended(&self) -> bool {
+        self.input_ended
+            .as_ref()
+            .map_or(false, |input_ended| input_ended.load(Ordering::Acquire))
+    }
+}
+
+impl<S, Request> Service<Request> for ShutdownOrderService<S>
+where
+    S: Service<Request>,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.permit.is_none() && self.input_ended() {
+            let permit = ready!(self.permits.poll_acquire(cx));
+            self.permit = Some(permit.expect("semaphore is never closed"));
+        }
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let permit = self.permit.take();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let result = future.await;
+            drop(permit);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use chrono::{DateTime, TimeZone, Utc};
+    use vector_common::{
+        finalization::{EventFinalizers, EventStatus, Finalizable},
+        internal_event::CountByteSize,
+        json_size::JsonSize,
+        request_metadata::{MetaDescriptive, RequestMetadata},
+    };
+    use vector_core::stream::{Driver, DriverResponse};
+
+    use super::*;
+
+    /// A partition of the given hour of 2021-08-23, if any.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct Hour(Option<u32>);
+
+    impl ClosingPartition for Hour {
+        fn end(&self) -> Option<DateTime<Utc>> {
+            self.0
+                .map(|hour| Utc.with_ymd_and_hms(2021, 8, 23, hour + 1, 0, 0).unwrap())
+        }
+    }
+
+    fn batches() -> Vec<(Hour, Vec<u32>)> {
+        vec![
+            (Hour(Some(18)), vec![1]),
+            (Hour(None), vec![2]),
+            (Hour(Some(16)), vec![3]),
+            (Hour(Some(17)), vec![4]),
+            (Hour(Some(16)), vec![5]),
+        ]
+    }
+
+    async fn end_input(order: &ShutdownOrder) {
+        order.watch(stream::empty::<()>()).collect::<Vec<_>>().await;
+    }
+
+    /// The write of the `n`th object, which takes longer for the first objects.
+    struct Write(u64, EventFinalizers, RequestMetadata);
+
+    impl Write {
+        fn new(n: u64) -> Self {
+            Self(n, EventFinalizers::default(), RequestMetadata::default())
+        }
+    }
+
+    impl Finalizable for Write {
+        fn take_finalizers(&mut self) -> EventFinalizers {
+            std::mem::take(&mut self.1)
+        }
+    }
+
+    impl MetaDescriptive for Write {
+        fn get_metadata(&self) -> RequestMetadata {
+            self.2
+        }
+    }
+
+    struct Written;
+
+    impl DriverResponse for Written {
+        fn event_status(&self) -> EventStatus {
+            EventStatus::Delivered
+        }
+
+        fn events_sent(&self) -> CountByteSize {
+            CountByteSize(0, JsonSize::zero())
+        }
+    }
+
+    /// Records the objects in the order their write completes.
+    struct WriteService(Arc<Mutex<Vec<u64>>>);
+
+    impl Service<Write> for WriteService {
+        type Response = Written;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Written, ()>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, write: Write) -> Self::Future {
+            let written = Arc::clone(&self.0);
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(100 / write.0)).await;
+                written.lock().unwrap().push(write.0);
+                Ok(Written)
+            })
+        }
+    }
+
+    async fn write_objects(order: &ShutdownOrder) -> Vec<u64> {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let service = ShutdownOrderService::new(WriteService(Arc::clone(&written)), Some(order));
+
+        Driver::new(stream::iter((1..=3).map(Write::new)), service)
+            .run()
+            .await
+            .unwrap();
+
+        let written = written.lock().unwrap().clone();
+        written
+    }
+
+    #[tokio::test]
+    async fn orders_partitions_flushed_on_shutdown() {
+        let order = ShutdownOrder::default();
+        end_input(&order).await;
+
+        let flushed = order
+            .order(stream::iter(batches()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            flushed,
+            vec![
+                (Hour(None), vec![2]),
+                (Hour(Some(16)), vec![3]),
+                (Hour(Some(16)), vec![5]),
+                (Hour(Some(17)), vec![4]),
+                (Hour(Some(18)), vec![1]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn passes_batches_through_until_the_input_ends() {
+        let order = ShutdownOrder::default();
+
+        let flushed = order
+            .order(stream::iter(batches()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(flushed, batches());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_objects_one_at_a_time_once_the_input_ends() {
+        let order = ShutdownOrder::default();
+        end_input(&order).await;
+
+        assert_eq!(write_objects(&order).await, vec![1, 2, 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_objects_concurrently_until_the_input_ends() {
+        let order = ShutdownOrder::default();
+
+        assert_eq!(write_objects(&order).await, vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn detects_the_end_of_the_input() {
+        let order = ShutdownOrder::default();
+        let mut input = Box::pin(order.watch(stream::iter(vec![1, 2])));
+
+        assert_eq!(input.next().await, Some(1));
+        assert_eq!(input.next().await, Some(2));
+        assert!(!order.input_ended.load(Ordering::Acquire));
+        assert_eq!(input.next().await, None);
+        assert!(order.input_ended.load(Ordering::Acquire));
+    }
+}
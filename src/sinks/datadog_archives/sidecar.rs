@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue};
+use sha2::{Digest, Sha256};
+use vector_common::{json_size::JsonSize, request_metadata::RequestMetadata};
+use vector_core::event::EventFinalizers;
+
+use super::{
+    crc32c::gcs_crc32c_hash, CUSTOM_TIME_METADATA_KEY, GCS_CUSTOM_TIME_HEADER,
+    GCS_EVENT_COUNT_HEADER, GCS_HASH_HEADER, GCS_TIME_MAX_HEADER, GCS_TIME_MIN_HEADER,
+    GCS_UNCOMPRESSED_SIZE_HEADER, UNCOMPRESSED_SIZE_METADATA_KEY,
+};
+use crate::sinks::{
+    azure_common::config::{AzureBlobMetadata, AzureBlobRequest},
+    gcs_common::service::{GcsRequest, GcsRequestSettings},
+    s3_common::{
+        config::S3Options,
+        service::{S3Metadata, S3Request},
+    },
+};
+
+/// The extension of the checksum sidecar objects.
+const CHECKSUM_EXTENSION: &str = ".sha256";
+
+/// The content type of the checksum sidecar objects.
+const CHECKSUM_CONTENT_TYPE: &str = "text/plain";
+
+/// A request writing an archive, from which requests writing sidecar objects can be derived.
+///
+/// Sidecar objects are small objects written next to an archive, which describe it. They don't
+/// hold any events, so the requests writing them carry no finalizers.
+pub trait SidecarRequest: Sized {
+    /// The key of the object written by the request.
+    fn key(&self) -> &str;
+
+    /// The payload of the object written by the request, as it's sent.
+    fn body(&self) -> &Bytes;
+
+    /// A request writing `body` to `key`, with the same settings as this request.
+    fn sidecar(&self, key: String, body: Bytes, content_type: &'static str) -> Self;
+}
+
+/// The request writing the checksum of the archive written by `request`.
+///
+/// The sidecar object has the key of the archive with a `.sha256` extension, and holds the
+/// hex-encoded SHA-256 of the payload followed by the name of the archive, as written by
+/// `sha256sum`.
+pub fn checksum_sidecar<R: SidecarRequest>(request: &R) -> R {
+    let key = request.key();
+    let name = key.rsplit('/').next().unwrap_or(key);
+    let body = format!("{:x}  {}\n", Sha256::digest(request.body()), name);
+
+    request.sidecar(
+        format!("{}{}", key, CHECKSUM_EXTENSION),
+        Bytes::from(body),
+        CHECKSUM_CONTENT_TYPE,
+    )
+}
+
+/// The GCS headers which describe the payload of an archive, rather than the archive as a whole,
+/// and so don't apply to its sidecar objects.
+const GCS_PAYLOAD_HEADERS: [&str; 7] = [
+    "content-disposition",
+    GCS_HASH_HEADER,
+    GCS_EVENT_COUNT_HEADER,
+    GCS_TIME_MIN_HEADER,
+    GCS_TIME_MAX_HEADER,
+    GCS_UNCOMPRESSED_SIZE_HEADER,
+    GCS_CUSTOM_TIME_HEADER,
+];
+
+/// The S3 and Azure Blob Storage metadata keys which describe the payload of an archive.
+const PAYLOAD_METADATA_KEYS: [&str; 2] = [CUSTOM_TIME_METADATA_KEY, UNCOMPRESSED_SIZE_METADATA_KEY];
+
+fn sidecar_metadata(body: &Bytes) -> RequestMetadata {
+    RequestMetadata::new(0, 0, body.len(), body.len(), JsonSize::zero())
+}
+
+/// The user metadata of an archive which applies to its sidecar objects as well.
+fn sidecar_user_metadata(
+    user_metadata: &Option<HashMap<String, String>>,
+) -> Option<HashMap<String, String>> {
+    let user_metadata = user_metadata
+        .iter()
+        .flatten()
+        .filter(|(key, _)| !PAYLOAD_METADATA_KEYS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<HashMap<_, _>>();
+    (!user_metadata.is_empty()).then_some(user_metadata)
+}
+
+impl SidecarRequest for S3Request {
+    fn key(&self) -> &str {
+        &self.metadata.s3_key
+    }
+
+    fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    fn sidecar(&self, key: String, body: Bytes, content_type: &'static str) -> Self {
+        Self {
+            request_metadata: sidecar_metadata(&body),
+            body,
+            bucket: self.bucket.clone(),
+            metadata: S3Metadata {
+                partition_key: self.metadata.partition_key.clone(),
+                s3_key: key,
+                finalizers: EventFinalizers::default(),
+            },
+            content_encoding: None,
+            options: S3Options {
+                content_encoding: None,
+                content_type: Some(content_type.to_owned()),
+                ..self.options.clone()
+            },
+            user_metadata: sidecar_user_metadata(&self.user_metadata),
+            content_disposition: None,
+            content_language: self.content_language.clone(),
+        }
+    }
+}
+
+impl SidecarRequest for GcsRequest {
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    fn sidecar(&self, key: String, body: Bytes, content_type: &'static str) -> Self {
+        let mut headers = self
+            .settings
+            .headers
+            .iter()
+            .filter(|(name, _)| !GCS_PAYLOAD_HEADERS.contains(&name.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        // The checksum of the archive is replaced by that of the sidecar, which GCS validates the
+        // same way.
+        if self
+            .settings
+            .headers
+            .iter()
+            .any(|(name, _)| name == GCS_HASH_HEADER)
+        {
+            headers.push((
+                HeaderName::from_static(GCS_HASH_HEADER),
+                HeaderValue::from_str(&gcs_crc32c_hash(&body))
+                    .expect("base64 checksums are valid header values"),
+            ));
+        }
+
+        Self {
+            key,
+            metadata: sidecar_metadata(&body),
+            body,
+            settings: GcsRequestSettings {
+                content_type: HeaderValue::from_static(content_type),
+                content_encoding: None,
+                headers,
+                ..self.settings.clone()
+            },
+            finalizers: EventFinalizers::default(),
+        }
+    }
+}
+
+impl SidecarRequest for AzureBlobRequest {
+    fn key(&self) -> &str {
+        &self.metadata.partition_key
+    }
+
+    fn body(&self) -> &Bytes {
+        &self.blob_data
+    }
+
+    fn sidecar(&self, key: String, body: Bytes, content_type: &'static str) -> Self {
+        Self {
+            request_metadata: sidecar_metadata(&body),
+            blob_data: body,
+            content_encoding: None,
+            content_type,
+            metadata: AzureBlobMetadata {
+                partition_key: key,
+                count: 0,
+                byte_size: JsonSize::zero(),
+                finalizers: EventFinalizers::default(),
+            },
+            user_metadata: sidecar_user_metadata(&self.user_metadata),
+            content_disposition: None,
+            content_language: self.content_language.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::CONTENT_DISPOSITION;
+    use vector_core::event::{BatchNotifier, EventFinalizer};
+
+    use super::*;
+
+    fn gcs_request(key: &str, body: &'static [u8]) -> GcsRequest {
+        let (batch, _receiver) = BatchNotifier::new_with_receiver();
+        GcsRequest {
+            key: key.to_owned(),
+            body: Bytes::from_static(body),
+            settings: GcsRequestSettings {
+                acl: None,
+                content_type: HeaderValue::from_static("application/x-ndjson"),
+                content_encoding: Some(HeaderValue::from_static("gzip")),
+                storage_class: HeaderValue::from_static("STANDARD"),
+                headers: vec![
+                    (
+                        CONTENT_DISPOSITION,
+                        HeaderValue::from_static("attachment; filename=\"archive.json.gz\""),
+                    ),
+                    (
+                        "x-goog-meta-team".parse().unwrap(),
+                        HeaderValue::from_static("logs"),
+                    ),
+                    (
+                        HeaderName::from_static(GCS_EVENT_COUNT_HEADER),
+                        HeaderValue::from(1),
+                    ),
+                    (
+                        HeaderName::from_static(GCS_TIME_MAX_HEADER),
+                        HeaderValue::from_static("2021-08-23T16:10:00.000Z"),
+                    ),
+                    (
+                        HeaderName::from_static(GCS_CUSTOM_TIME_HEADER),
+                        HeaderValue::from_static("2021-08-23T16:10:00.000Z"),
+                    ),
+                ],
+            },
+            finalizers: EventFinalizers::new(EventFinalizer::new(batch)),
+            metadata: RequestMetadata::new(1, 1, body.len(), body.len(), JsonSize::new(1)),
+        }
+    }
+
+    #[test]
+    fn checksum_matches_the_payload() {
+        let request = gcs_request("dt=20210823/hour=16/archive.json.gz", b"hello world");
+        let sidecar = checksum_sidecar(&request);
+
+        // `echo -n "hello world" | sha256sum`
+        assert_eq!(
+            sidecar.body,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9  archive.json.gz\n"
+        );
+    }
+
+    #[test]
+    fn sidecar_key_is_derived_from_the_archive_key() {
+        let request = gcs_request("dt=20210823/hour=16/archive.json.gz", b"");
+        let sidecar = checksum_sidecar(&request);
+
+        assert_eq!(sidecar.key, "dt=20210823/hour=16/archive.json.gz.sha256");
+        assert_eq!(sidecar.settings.content_type, "text/plain");
+        assert!(sidecar.settings.content_encoding.is_none());
+        assert!(sidecar.finalizers.is_empty());
+        assert_eq!(sidecar.metadata.event_count(), 0);
+
+        // The sidecar keeps the metadata of the archive, but not that describing its payload.
+        let headers = sidecar
+            .settings
+            .headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(headers, vec!["x-goog-meta-team"]);
+    }
+
+    #[test]
+    fn sidecar_carries_its_own_crc32c() {
+        let mut request = gcs_request("dt=20210823/hour=16/archive.json.gz", b"hello world");
+        request.settings.headers.push((
+            HeaderName::from_static(GCS_HASH_HEADER),
+            HeaderValue::from_str(&gcs_crc32c_hash(&request.body)).unwrap(),
+        ));
+        let sidecar = checksum_sidecar(&request);
+
+        let hashes = sidecar
+            .settings
+            .headers
+            .iter()
+            .filter(|(name, _)| name == GCS_HASH_HEADER)
+            .map(|(_, value)| value.to_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(hashes, vec![gcs_crc32c_hash(&sidecar.body)]);
+        assert_ne!(hashes, vec![gcs_crc32c_hash(&request.body)]);
+    }
+}
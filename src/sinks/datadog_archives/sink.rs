@@ -0,0 +1,504 @@
+use std::{fmt, hash::Hash, iter, num::NonZeroUsize, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::{
+    future,
+    stream::{self, BoxStream},
+};
+use futures_util::StreamExt;
+use tower::Service;
+use vector_common::request_metadata::MetaDescriptive;
+use vector_config::configurable_component;
+use vector_core::{
+    event::Finalizable,
+    partition::Partitioner,
+    sink::StreamSink,
+    stream::{BatcherSettings, DriverResponse},
+    ByteSizeOf, EstimatedJsonEncodedSizeOf,
+};
+
+use super::{
+    adaptive::AdaptiveBatchSize,
+    aggregate::{aggregate_batches, AggregatePartition, Aggregator},
+    audit::AuditService,
+    buffer_limit::BufferLimit,
+    carry_over::{carry_over_batches, CarryOver},
+    compressed_cap::build_capped_requests,
+    grace_period::{grace_period_batches, ClosingPartition, GracePeriod},
+    inventory::KeyInventory,
+    partition_cap::{OverflowPartition, PartitionCap},
+    shutdown_order::{ShutdownOrder, ShutdownOrderService},
+    sidecar::{checksum_sidecar, SidecarRequest},
+    success_marker::{marker_requests, PartitionRollover},
+};
+use crate::{
+    event::Event,
+    internal_events::SinkRequestBuildError,
+    sinks::util::{RequestBuilder, SinkBuilderExt},
+};
+
+/// How the size of events is measured against the batch size limits.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchSizing {
+    /// Events are measured by their size in memory.
+    #[default]
+    InMemory,
+
+    /// Events are measured by their estimated size once encoded as JSON.
+    ///
+    /// This is much closer to the size of the archived objects before compression, so that
+    /// `batch.max_bytes` more tightly controls the size of the objects.
+    EncodedJson,
+}
+
+impl BatchSizing {
+    /// The size of the event, as accounted for against the batch size limits.
+    pub fn size_of(self, event: &Event) -> usize {
+        match self {
+            Self::InMemory => event.size_of(),
+            Self::EncodedJson => event.estimated_json_encoded_size_of().get(),
+        }
+    }
+}
+
+/// An event, along with its size as measured by the batch sizing.
+struct SizedEvent {
+    event: Event,
+    size: usize,
+}
+
+impl ByteSizeOf for SizedEvent {
+    fn size_of(&self) -> usize {
+        self.size
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        self.size
+    }
+}
+
+/// Partitions sized events with the partitioner of the events themselves, within the cap on open
+/// partitions, if any.
+struct SizedPartitioner<P, K> {
+    partitioner: P,
+    partition_cap: Option<PartitionCap<K>>,
+}
+
+impl<P, K> Partitioner for SizedPartitioner<P, K>
+where
+    P: Partitioner<Item = Event, Key = Option<K>>,
+    K: OverflowPartition + Eq + Hash + Clone,
+{
+    type Item = SizedEvent;
+    type Key = Option<K>;
+
+    fn partition(&self, item: &Self::Item) -> Self::Key {
+        let key = self.partitioner.partition(&item.event)?;
+        Some(match &self.partition_cap {
+            Some(partition_cap) => partition_cap.admit(key),
+            None => key,
+        })
+    }
+}
+
+/// The stream sink shared by all of the `datadog_archives` storage backends.
+///
+/// This is equivalent to the backend-specific sinks (`S3Sink`, `GcsSink`, `AzureBlobSink`), but is
+/// generic over the partitioner so that archive-specific batching behavior lives in a single place.
+pub struct DatadogArchivesSink<Svc, RB, P> {
+    service: Svc,
+    request_builder: RB,
+    partitioner: P,
+    batcher_settings: BatcherSettings,
+    protocol: Option<&'static str>,
+    adaptive_batch_size: Option<Arc<AdaptiveBatchSize>>,
+    batch_sizing: BatchSizing,
+    checksums: bool,
+    success_markers: bool,
+    min_events_per_object: Option<(NonZeroUsize, Duration)>,
+    max_buffered_bytes: Option<NonZeroUsize>,
+    max_open_partitions: Option<NonZeroUsize>,
+    aggregate_below_bytes: Option<NonZeroUsize>,
+    partition_grace_period: Option<Duration>,
+    ordered_shutdown: bool,
+    max_compressed_bytes: Option<NonZeroUsize>,
+    audit_writes: bool,
+    inventory: Option<Arc<KeyInventory>>,
+}
+
+impl<Svc, RB, P> DatadogArchivesSink<Svc, RB, P> {
+    pub const fn new(
+        service: Svc,
+        request_builder: RB,
+        partitioner: P,
+        batcher_settings: BatcherSettings,
+        protocol: Option<&'static str>,
+    ) -> Self {
+        Self {
+            service,
+            request_builder,
+            partitioner,
+            batcher_settings,
+            protocol,
+            adaptive_batch_size: None,
+            batch_sizing: BatchSizing::InMemory,
+            checksums: false,
+            success_markers: false,
+            min_events_per_object: None,
+            max_buffered_bytes: None,
+            max_open_partitions: None,
+            aggregate_below_bytes: None,
+            partition_grace_period: None,
+            ordered_shutdown: false,
+            max_compressed_bytes: None,
+            audit_writes: false,
+            inventory: None,
+        }
+    }
+
+    /// Splits batches according to the given adaptive batch size, if any.
+    pub fn with_adaptive_batch_size(
+        mut self,
+        adaptive_batch_size: Option<Arc<AdaptiveBatchSize>>,
+    ) -> Self {
+        self.adaptive_batch_size = adaptive_batch_size;
+        self
+    }
+
+    /// Sets how the size of events is measured against the batch size limits.
+    pub const fn with_batch_sizing(mut self, batch_sizing: BatchSizing) -> Self {
+        self.batch_sizing = batch_sizing;
+        self
+    }
+
+    /// Sets whether the checksum of each archive is written to a sidecar object.
+    pub const fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Sets whether a `_SUCCESS` marker is written to each partition once it's complete.
+    pub const fn with_success_markers(mut self, success_markers: bool) -> Self {
+        self.success_markers = success_markers;
+        self
+    }
+
+    /// Carries batches with fewer than `min_events` events over to the next batch of their
+    /// partition, for up to `max_age`.
+    pub fn with_min_events_per_object(
+        mut self,
+        min_events: Option<NonZeroUsize>,
+        max_age: Duration,
+    ) -> Self {
+        self.min_events_per_object = min_events.map(|min_events| (min_events, max_age));
+        self
+    }
+
+    /// Limits the bytes of events buffered by the batcher across all partitions, if any.
+    pub const fn with_max_buffered_bytes(
+        mut self,
+        max_buffered_bytes: Option<NonZeroUsize>,
+    ) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self
+    }
+
+    /// Limits the number of partitions buffered by the batcher at once, if any.
+    pub const fn with_max_open_partitions(
+        mut self,
+        max_open_partitions: Option<NonZeroUsize>,
+    ) -> Self {
+        self.max_open_partitions = max_open_partitions;
+        self
+    }
+
+    /// Aggregates batches smaller than `aggregate_below_bytes` with those of other partitions, if
+    /// any.
+    pub const fn with_aggregate_below_bytes(
+        mut self,
+        aggregate_below_bytes: Option<NonZeroUsize>,
+    ) -> Self {
+        self.aggregate_below_bytes = aggregate_below_bytes;
+        self
+    }
+
+    /// Holds the batches of each partition until `partition_grace_period` has elapsed past its
+    /// end, if any.
+    pub const fn with_partition_grace_period(
+        mut self,
+        partition_grace_period: Option<Duration>,
+    ) -> Self {
+        self.partition_grace_period = partition_grace_period;
+        self
+    }
+
+    /// Sets whether the batches flushed on shutdown are written in the order of their partition.
+    pub const fn with_ordered_shutdown(mut self, ordered_shutdown: bool) -> Self {
+        self.ordered_shutdown = ordered_shutdown;
+        self
+    }
+
+    /// Splits batches whose payload, once compressed, exceeds `max_compressed_bytes`, if any.
+    pub const fn with_max_compressed_bytes(
+        mut self,
+        max_compressed_bytes: Option<NonZeroUsize>,
+    ) -> Self {
+        self.max_compressed_bytes = max_compressed_bytes;
+        self
+    }
+
+    /// Sets whether each archive written is reported for the audit trail of the archives.
+    pub const fn with_audit_writes(mut self, audit_writes: bool) -> Self {
+        self.audit_writes = audit_writes;
+        self
+    }
+
+    /// Records the key of each object in the given inventory, if any.
+    pub fn with_inventory(mut self, inventory: Option<Arc<KeyInventory>>) -> Self {
+        self.inventory = inventory;
+        self
+    }
+}
+
+impl<Svc, RB, P, K> DatadogArchivesSink<Svc, RB, P>
+where
+    Svc: Service<RB::Request> + Send + 'static,
+    Svc::Future: Send + 'static,
+    Svc::Response: DriverResponse + Send + 'static,
+    Svc::Error: fmt::Debug + Into<crate::Error> + Send + 'static,
+    RB: RequestBuilder<(K, Vec<Event>)> + Send + Sync + 'static,
+    RB::Error: fmt::Display + Send,
+    RB::Request: Finalizable + MetaDescriptive + SidecarRequest + Send,
+    P: Partitioner<Item = Event, Key = Option<K>> + Unpin + Send,
+    K: AggregatePartition
+        + ClosingPartition
+        + OverflowPartition
+        + Eq
+        + Hash
+        + Clone
+        + Send
+        + 'static,
+{
+    async fn run_inner(self: Box<Self>, input: BoxStream<'_, Event>) -> Result<(), ()> {
+        let partitioner = self.partitioner;
+        let settings = self.batcher_settings;
+        let adaptive_batch_size = self.adaptive_batch_size;
+        let batch_sizing = self.batch_sizing;
+        let checksums = self.checksums;
+        let mut rollover = self.success_markers.then(PartitionRollover::default);
+        let min_events_per_object = self.min_events_per_object;
+        let aggregate_below_bytes = self.aggregate_below_bytes;
+        let partition_grace_period = self.partition_grace_period;
+        let buffer_limit = self.max_buffered_bytes.map(BufferLimit::new);
+        let partition_cap = self.max_open_partitions.map(PartitionCap::new);
+        let shutdown_order = self.ordered_shutdown.then(ShutdownOrder::default);
+        let max_compressed_bytes = self.max_compressed_bytes;
+        let inventory = self.inventory;
+
+        let builder_limit = NonZeroUsize::new(64);
+        let request_builder = self.request_builder;
+
+        let input = match &shutdown_order {
+            Some(shutdown_order) => shutdown_order.watch(input).boxed(),
+            None => input,
+        };
+        let events = input.map(move |event| SizedEvent {
+            size: batch_sizing.size_of(&event),
+            event,
+        });
+        let events = match &buffer_limit {
+            Some(buffer_limit) => buffer_limit
+                .limit(events, |event: &SizedEvent| event.size)
+                .boxed(),
+            None => events.boxed(),
+        };
+        let batches = events
+            .batched_partitioned(
+                SizedPartitioner {
+                    partitioner,
+                    partition_cap: partition_cap.clone(),
+                },
+                settings,
+            )
+            .filter_map(move |(key, batch)| {
+                if let (Some(partition_cap), Some(key)) = (&partition_cap, &key) {
+                    partition_cap.close(key);
+                }
+                let size = batch.iter().map(|sized| sized.size).sum();
+                let mut events = batch
+                    .into_iter()
+                    .map(|sized| sized.event)
+                    .collect::<Vec<_>>();
+                if let Some(buffer_limit) = &buffer_limit {
+                    events = buffer_limit.release_when_finalized(events, size);
+                }
+                // A `TemplateRenderingError` will have been emitted by the partitioner if the key here is `None`,
+                // thus no further `EventsDropped` event needs emitting at this stage.
+                future::ready(key.map(move |k| (k, events)))
+            });
+        let batches = match min_events_per_object {
+            Some((min_events, max_age)) => {
+                carry_over_batches(batches, CarryOver::new(min_events, max_age)).boxed()
+            }
+            None => batches.boxed(),
+        };
+        let batches = match partition_grace_period {
+            Some(grace_period) => grace_period_batches(
+                batches,
+                GracePeriod::new(grace_period, settings.size_limit),
+                move |event| batch_sizing.size_of(event),
+            )
+            .boxed(),
+            None => batches,
+        };
+        let batches = match aggregate_below_bytes {
+            Some(threshold) => {
+                aggregate_batches(batches, Aggregator::new(threshold), batch_sizing).boxed()
+            }
+            None => batches,
+        };
+        let service = ShutdownOrderService::new(
+            AuditService::new(self.service, self.audit_writes),
+            shutdown_order.as_ref(),
+        );
+        let batches = match shutdown_order {
+            Some(shutdown_order) => shutdown_order.order(batches).boxed(),
+            None => batches,
+        };
+
+        let batches = batches.flat_map(move |(key, batch)| {
+            let batches = split_batch(adaptive_batch_size.as_deref(), batch_sizing, batch);
+            stream::iter(batches.into_iter().map(move |batch| (key.clone(), batch)))
+        });
+        let requests = match max_compressed_bytes {
+            Some(max_bytes) => {
+                let request_builder = Arc::new(request_builder);
+                batches
+                    .concurrent_map(builder_limit, move |(key, batch)| {
+                        let request_builder = Arc::clone(&request_builder);
+                        Box::pin(async move {
+                            build_capped_requests(&*request_builder, key, batch, max_bytes)
+                        })
+                    })
+                    .flat_map(stream::iter)
+                    .boxed()
+            }
+            None => batches
+                .request_builder(builder_limit, request_builder)
+                .boxed(),
+        };
+
+        let driver = requests
+            .filter_map(|request| async move {
+                match request {
+                    Err(error) => {
+                        emit!(SinkRequestBuildError { error });
+                        None
+                    }
+                    Ok(req) => Some(req),
+                }
+            })
+            .flat_map(move |request| {
+                // The markers of the partitions completed by this archive are enqueued ahead of it,
+                // and behind the last archives of those partitions.
+                let markers = rollover
+                    .as_mut()
+                    .map(|rollover| marker_requests(rollover, &request))
+                    .unwrap_or_default();
+                // The sidecar is enqueued right behind its archive. Requests are sent concurrently,
+                // so it may still be written before the archive completes.
+                let sidecar = checksums.then(|| checksum_sidecar(&request));
+                stream::iter(
+                    markers
+                        .into_iter()
+                        .chain(iter::once(request))
+                        .chain(sidecar),
+                )
+            })
+            .inspect(move |request| {
+                if let Some(inventory) = &inventory {
+                    inventory.record(request.key());
+                }
+            })
+            .into_driver(service);
+
+        match self.protocol {
+            Some(protocol) => driver.protocol(protocol).run().await,
+            None => driver.run().await,
+        }
+    }
+}
+
+/// Splits a batch into the batches to build requests from.
+///
+/// Empty batches are dropped here, so that no empty object is ever written. An empty batch holds no
+/// events, and therefore no finalizers that would need to be acknowledged.
+fn split_batch(
+    adaptive_batch_size: Option<&AdaptiveBatchSize>,
+    batch_sizing: BatchSizing,
+    batch: Vec<Event>,
+) -> Vec<Vec<Event>> {
+    if batch.is_empty() {
+        return Vec::new();
+    }
+
+    match adaptive_batch_size {
+        Some(adaptive_batch_size) => adaptive_batch_size.split(batch, batch_sizing),
+        None => vec![batch],
+    }
+}
+
+#[async_trait]
+impl<Svc, RB, P, K> StreamSink<Event> for DatadogArchivesSink<Svc, RB, P>
+where
+    Svc: Service<RB::Request> + Send + 'static,
+    Svc::Future: Send + 'static,
+    Svc::Response: DriverResponse + Send + 'static,
+    Svc::Error: fmt::Debug + Into<crate::Error> + Send + 'static,
+    RB: RequestBuilder<(K, Vec<Event>)> + Send + Sync + 'static,
+    RB::Error: fmt::Display + Send,
+    RB::Request: Finalizable + MetaDescriptive + SidecarRequest + Send,
+    P: Partitioner<Item = Event, Key = Option<K>> + Unpin + Send,
+    K: AggregatePartition
+        + ClosingPartition
+        + OverflowPartition
+        + Eq
+        + Hash
+        + Clone
+        + Send
+        + 'static,
+{
+    async fn run(mut self: Box<Self>, input: BoxStream<'_, Event>) -> Result<(), ()> {
+        self.run_inner(input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::LogEvent;
+
+    #[test]
+    fn drops_empty_batches() {
+        assert!(split_batch(None, BatchSizing::InMemory, Vec::new()).is_empty());
+
+        let batches = split_batch(
+            None,
+            BatchSizing::InMemory,
+            vec![Event::Log(LogEvent::from("test message"))],
+        );
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 1);
+    }
+
+    #[test]
+    fn sizes_events_as_encoded_json() {
+        let event = Event::Log(LogEvent::from("test message"));
+
+        assert_eq!(BatchSizing::InMemory.size_of(&event), event.size_of());
+        // `{"message":"test message"}`
+        assert_eq!(BatchSizing::EncodedJson.size_of(&event), 26);
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+
+use super::{concurrency::partition_of, sidecar::SidecarRequest};
+
+/// The name of the marker objects.
+const SUCCESS_MARKER: &str = "_SUCCESS";
+
+/// The content type of the marker objects.
+const SUCCESS_MARKER_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The key of the marker object of a partition.
+fn marker_key(partition: &str) -> String {
+    if partition.is_empty() {
+        SUCCESS_MARKER.to_owned()
+    } else {
+        format!("{}/{}", partition, SUCCESS_MARKER)
+    }
+}
+
+/// The time window of a partition, made of its `dt=` and `hour=` segments.
+///
+/// Partitions without a date and an hour, such as those of the literal partition fallback, have
+/// no window.
+fn window_of(partition: &str) -> Option<String> {
+    let segment = |name: &str| {
+        partition.split('/').find(|segment| {
+            segment.strip_prefix(name).map_or(false, |value| {
+                !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+            })
+        })
+    };
+
+    Some(format!("{}/{}", segment("dt=")?, segment("hour=")?))
+}
+
+/// Detects the partitions which are complete, from the keys of the archives being written.
+///
+/// Archives are written to partitions by the hour of their events. Once an archive is written for
+/// a later hour than any before, no more data is expected for the partitions of the earlier hours,
+/// which are then complete. Partitions receiving late events after being completed are completed
+/// again at the next rollover.
+///
+/// Partitions which are still open when the sink stops aren't completed, since more data may be
+/// written to them after a restart.
+#[derive(Debug, Default)]
+pub struct PartitionRollover {
+    latest: Option<String>,
+    open: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl PartitionRollover {
+    /// Records that an archive is written to `key`.
+    ///
+    /// Returns the partitions which are complete as a result.
+    pub fn observe(&mut self, key: &str) -> Vec<String> {
+        let partition = partition_of(key);
+        let Some(window) = window_of(partition) else {
+            return Vec::new();
+        };
+
+        self.open
+            .entry(window.clone())
+            .or_default()
+            .insert(partition.to_owned());
+
+        if self
+            .latest
+            .as_ref()
+            .map_or(false, |latest| window <= *latest)
+        {
+            return Vec::new();
+        }
+
+        // The windows sort in time order, since their segments have a fixed width.
+        let open = self.open.split_off(&window);
+        let completed = std::mem::replace(&mut self.open, open);
+        self.latest = Some(window);
+
+        completed.into_values().flatten().collect()
+    }
+}
+
+/// The requests writing the markers of the partitions completed by `request`, if any.
+///
+/// The markers are written with the settings of `request`.
+pub fn marker_requests<R: SidecarRequest>(rollover: &mut PartitionRollover, request: &R) -> Vec<R> {
+    rollover
+        .observe(request.key())
+        .into_iter()
+        .map(|partition| {
+            request.sidecar(
+                marker_key(&partition),
+                Bytes::new(),
+                SUCCESS_MARKER_CONTENT_TYPE,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_marker_key_from_the_partition() {
+        assert_eq!(
+            marker_key(partition_of("audit/dt=20210823/hour=16/archive_1.json.gz")),
+            "audit/dt=20210823/hour=16/_SUCCESS"
+        );
+        assert_eq!(
+            marker_key(partition_of(
+                "dt=20210823/hour=16/source=nginx/archive_1.json.gz"
+            )),
+            "dt=20210823/hour=16/source=nginx/_SUCCESS"
+        );
+        assert_eq!(marker_key(partition_of("archive_1.json.gz")), "_SUCCESS");
+    }
+
+    #[test]
+    fn derives_window_from_the_partition() {
+        assert_eq!(
+            window_of("audit/dt=20210823/hour=16/source=nginx").as_deref(),
+            Some("dt=20210823/hour=16")
+        );
+        assert_eq!(window_of("audit/dt=unknown"), None);
+        assert_eq!(window_of("audit/dt=20210823"), None);
+    }
+
+    #[test]
+    fn completes_partitions_on_rollover() {
+        let mut rollover = PartitionRollover::default();
+
+        assert!(rollover
+            .observe("dt=20210823/hour=16/source=nginx/archive_1.json.gz")
+            .is_empty());
+        assert!(rollover
+            .observe("dt=20210823/hour=16/source=redis/archive_2.json.gz")
+            .is_empty());
+        assert!(rollover
+            .observe("dt=20210823/hour=16/source=nginx/archive_3.json.gz")
+            .is_empty());
+
+        assert_eq!(
+            rollover.observe("dt=20210823/hour=17/source=nginx/archive_4.json.gz"),
+            vec![
+                "dt=20210823/hour=16/source=nginx".to_owned(),
+                "dt=20210823/hour=16/source=redis".to_owned(),
+            ]
+        );
+        assert!(rollover
+            .observe("dt=20210823/hour=17/source=redis/archive_5.json.gz")
+            .is_empty());
+    }
+
+    #[test]
+    fn completes_late_partitions_at_the_next_rollover() {
+        let mut rollover = PartitionRollover::default();
+
+        rollover.observe("dt=20210823/hour=16/archive_1.json.gz");
+        assert_eq!(
+            rollover.observe("dt=20210823/hour=17/archive_2.json.gz"),
+            vec!["dt=20210823/hour=16".to_owned()]
+        );
+
+        // Late events don't roll the partitions over, but are completed at the next rollover.
+        assert!(rollover
+            .observe("dt=20210823/hour=16/archive_3.json.gz")
+            .is_empty());
+        assert_eq!(
+            rollover.observe("dt=20210824/hour=00/archive_4.json.gz"),
+            vec![
+                "dt=20210823/hour=16".to_owned(),
+                "dt=20210823/hour=17".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn never_completes_partitions_without_a_window() {
+        let mut rollover = PartitionRollover::default();
+
+        assert!(rollover.observe("dt=unknown/archive_1.json.gz").is_empty());
+        assert!(rollover
+            .observe("dt=20210823/hour=16/archive_2.json.gz")
+            .is_empty());
+        assert_eq!(
+            rollover.observe("dt=20210823/hour=17/archive_3.json.gz"),
+            vec!["dt=20210823/hour=16".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use async_trait::async_trait;
+use futures::{future, stream::BoxStream, StreamExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use vector_core::{
+    event::{EventArray, EventStatus, Finalizable},
+    sink::{StreamSink, VectorSink},
+};
+
+/// The number of event arrays buffered for each of the sinks, so that a slower sink doesn't
+/// immediately hold back the others.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Writes every event to each of several sinks.
+///
+/// The events sent to each sink share their finalizers, so that the batch an event belongs to is
+/// only acknowledged once every sink has delivered it. If any of the sinks fails to deliver an
+/// event, the event is reported as errored.
+pub struct TeeSink {
+    sinks: Vec<VectorSink>,
+}
+
+impl TeeSink {
+    pub fn new(sinks: Vec<VectorSink>) -> Self {
+        Self { sinks }
+    }
+}
+
+#[async_trait]
+impl StreamSink<EventArray> for TeeSink {
+    async fn run(self: Box<Self>, mut input: BoxStream<'_, EventArray>) -> Result<(), ()> {
+        let (senders, runs): (Vec<_>, Vec<_>) = self
+            .sinks
+            .into_iter()
+            .map(|sink| {
+                let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+                (Some(tx), sink.run(ReceiverStream::new(rx)))
+            })
+            .unzip();
+
+        let forward = async move {
+            let mut senders = senders;
+            while let Some(events) = input.next().await {
+                for sender in senders.iter_mut() {
+                    let Some(tx) = sender else {
+                        // The sink has stopped, so it won't be able to deliver the events.
+                        events.clone().take_finalizers().update_status(EventStatus::Errored);
+                        continue;
+                    };
+                    if let Err(mut error) = tx.send(events.clone()).await {
+                        error
+                            .0
+                            .take_finalizers()
+                            .update_status(EventStatus::Errored);
+                        *sender = None;
+                    }
+                }
+            }
+            // Dropping the senders ends the input of each sink.
+        };
+
+        let (_, results) = future::join(forward, future::join_all(runs)).await;
+        results.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use vector_core::event::{BatchNotifier, BatchStatus, Event, LogEvent};
+
+    use super::*;
+
+    /// A sink which collects the events it receives, and marks them with the given status.
+    struct StatusSink {
+        status: EventStatus,
+        received: mpsc::UnboundedSender<Event>,
+    }
+
+    #[async_trait]
+    impl StreamSink<Event> for StatusSink {
+        async fn run(self: Box<Self>, mut input: BoxStream<'_, Event>) -> Result<(), ()> {
+            while let Some(mut event) = input.next().await {
+                event.take_finalizers().update_status(self.status);
+                _ = self.received.send(event);
+            }
+            Ok(())
+        }
+    }
+
+    fn status_sink(status: EventStatus) -> (VectorSink, mpsc::UnboundedReceiver<Event>) {
+        let (received, rx) = mpsc::unbounded_channel();
+        let sink = VectorSink::from_event_streamsink(StatusSink { status, received });
+        (sink, rx)
+    }
+
+    async fn run_tee(statuses: &[EventStatus]) -> (BatchStatus, Vec<usize>) {
+        let (sinks, mut receivers): (Vec<_>, Vec<_>) =
+            statuses.iter().map(|status| status_sink(*status)).unzip();
+
+        let (batch, receiver) = BatchNotifier::new_with_receiver();
+        let events = (0..5)
+            .map(|i| {
+                Event::Log(
+                    LogEvent::from(format!("test message {}", i)).with_batch_notifier(&batch),
+                )
+            })
+            .collect::<Vec<_>>();
+        drop(batch);
+
+        let tee = VectorSink::Stream(Box::new(TeeSink::new(sinks)));
+        tee.run(stream::iter(events).map(Into::into))
+            .await
+            .expect("tee sink failed");
+
+        let counts = receivers
+            .iter_mut()
+            .map(|rx| {
+                let mut count = 0;
+                while rx.try_recv().is_ok() {
+                    count += 1;
+                }
+                count
+            })
+            .collect();
+
+        (receiver.await, counts)
+    }
+
+    #[tokio::test]
+    async fn writes_to_every_sink() {
+        let (status, counts) = run_tee(&[EventStatus::Delivered, EventStatus::Delivered]).await;
+        assert_eq!(status, BatchStatus::Delivered);
+        assert_eq!(counts, vec![5, 5]);
+    }
+
+    #[tokio::test]
+    async fn fails_finalizers_when_any_sink_fails() {
+        let (status, counts) = run_tee(&[EventStatus::Delivered, EventStatus::Errored]).await;
+        assert_eq!(status, BatchStatus::Errored);
+        assert_eq!(counts, vec![5, 5]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::Mutex;
+
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+
+/// The Crockford base32 alphabet ULIDs are encoded with.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The number of characters of an encoded ULID.
+const ULID_LEN: usize = 26;
+
+/// The mask of the 80 random bits of a ULID.
+const RANDOM_MASK: u128 = (1 << 80) - 1;
+
+/// Generates [ULIDs][ulid], which sort lexicographically by the time at which they were generated.
+///
+/// ULIDs generated within the same millisecond increment the random part of the previous one, so
+/// they still sort in the order in which they were generated. This also holds when the clock goes
+/// backwards.
+///
+/// [ulid]: https://github.com/ulid/spec
+#[derive(Debug, Default)]
+pub struct UlidGenerator {
+    last: Mutex<Option<(u64, u128)>>,
+}
+
+impl UlidGenerator {
+    pub fn generate(&self) -> String {
+        let millis = u64::try_from(Utc::now().timestamp_millis()).unwrap_or_default();
+        self.generate_at(millis)
+    }
+
+    fn generate_at(&self, millis: u64) -> String {
+        let mut last = self.last.lock().expect("poisoned lock");
+
+        let (millis, random) = match *last {
+            Some((last_millis, last_random)) if millis <= last_millis => {
+                if last_random < RANDOM_MASK {
+                    (last_millis, last_random + 1)
+                } else {
+                    (last_millis + 1, 0)
+                }
+            }
+            _ => (millis, thread_rng().gen::<u128>() & RANDOM_MASK),
+        };
+        *last = Some((millis, random));
+
+        encode((u128::from(millis) << 80) | random)
+    }
+}
+
+fn encode(value: u128) -> String {
+    (0..ULID_LEN)
+        .rev()
+        .map(|i| char::from(ALPHABET[((value >> (i * 5)) & 0x1f) as usize]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_timestamp_first() {
+        assert_eq!(encode(0), "00000000000000000000000000");
+        assert_eq!(encode(u128::MAX), "7ZZZZZZZZZZZZZZZZZZZZZZZZZ");
+
+        // The timestamp of the example in the specification.
+        let ulid = UlidGenerator::default().generate_at(1_469_918_176_385);
+        assert_eq!(ulid.len(), ULID_LEN);
+        assert!(ulid.starts_with("01ARYZ6S41"));
+    }
+
+    #[test]
+    fn sorts_in_generation_order() {
+        let generator = UlidGenerator::default();
+        let ulids = [100, 100, 100, 99, 101, 101]
+            .map(|millis| generator.generate_at(millis))
+            .to_vec();
+
+        let mut sorted = ulids.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, ulids);
+    }
+}
@@ -1,5 +1,8 @@
+use std::time::Duration;
+
+use chrono::Utc;
 use futures::FutureExt;
-use http::{StatusCode, Uri};
+use http::{header::RETRY_AFTER, StatusCode, Uri};
 use hyper::Body;
 use snafu::Snafu;
 use vector_config::configurable_component;
@@ -9,7 +12,7 @@ use crate::{
     http::HttpClient,
     sinks::{
         gcs_common::service::GcsResponse,
-        util::retries::{RetryAction, RetryLogic},
+        util::retries::{parse_retry_after, RetryAction, RetryLogic},
         Healthcheck, HealthcheckError,
     },
 };
@@ -161,4 +164,9 @@ impl RetryLogic for GcsRetryLogic {
             _ => RetryAction::DontRetry(format!("response status: {}", status).into()),
         }
     }
+
+    fn retry_after(&self, response: &Self::Response) -> Option<Duration> {
+        let value = response.inner.headers().get(RETRY_AFTER)?.to_str().ok()?;
+        parse_retry_after(value, Utc::now())
+    }
 }
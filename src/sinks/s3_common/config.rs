@@ -93,6 +93,13 @@ pub struct S3Options {
     #[configurable(metadata(docs::templateable))]
     pub ssekms_key_id: Option<String>,
 
+    /// Whether or not to use an S3 Bucket Key for the created objects.
+    ///
+    /// This isn't configurable on the `aws_s3` sink. It's only set by the sinks which enable it
+    /// along with KMS encryption.
+    #[serde(skip)]
+    pub bucket_key_enabled: Option<bool>,
+
     /// The storage class for the created objects.
     ///
     /// See the [S3 Storage Classes][s3_storage_classes] for more details.
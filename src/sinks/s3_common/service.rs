@@ -1,7 +1,14 @@
-use std::task::{Context, Poll};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    ops::Range,
+    task::{Context, Poll},
+    time::Duration,
+};
 
 use aws_sdk_s3::{
-    error::PutObjectError,
+    error::{PutObjectError, UploadPartError},
+    model::{CompletedMultipartUpload, CompletedPart},
     types::{ByteStream, SdkError},
     Client as S3Client,
 };
@@ -23,6 +30,7 @@ use vector_core::{
 
 use super::config::S3Options;
 use super::partitioner::S3PartitionKey;
+use crate::sinks::util::retries::ExponentialBackoff;
 
 #[derive(Debug, Clone)]
 pub struct S3Request {
@@ -32,6 +40,12 @@ pub struct S3Request {
     pub request_metadata: RequestMetadata,
     pub content_encoding: Option<&'static str>,
     pub options: S3Options,
+    /// User-defined metadata for the created object, sent as `x-amz-meta-*` headers.
+    pub user_metadata: Option<HashMap<String, String>>,
+    /// The `Content-Disposition` of the created object.
+    pub content_disposition: Option<String>,
+    /// The `Content-Language` of the created object.
+    pub content_language: Option<String>,
 }
 
 impl Finalizable for S3Request {
@@ -78,16 +92,150 @@ impl DriverResponse for S3Response {
 #[derive(Clone)]
 pub struct S3Service {
     client: S3Client,
+    multipart: Option<(NonZeroUsize, NonZeroUsize)>,
+}
+
+/// The number of times the upload of a single part is attempted, when objects are uploaded in parts.
+const PART_ATTEMPTS: usize = 3;
+
+/// The delays between the attempts to upload a part.
+///
+/// Parts are mostly retried when requests are throttled, so attempts back off rather than being
+/// retried right away.
+fn part_backoff() -> ExponentialBackoff {
+    ExponentialBackoff::from_millis(2)
+        .factor(250)
+        .max_delay(Duration::from_secs(5))
 }
 
+/// The maximum number of parts of a multipart upload.
+const MAX_PARTS: usize = 10_000;
+
+/// The minimum size of the parts of a multipart upload, but the last one.
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
 impl S3Service {
     pub const fn new(client: S3Client) -> S3Service {
-        S3Service { client }
+        S3Service {
+            client,
+            multipart: None,
+        }
     }
 
     pub fn client(&self) -> S3Client {
         self.client.clone()
     }
+
+    /// Uploads objects larger than `threshold` bytes with a multipart upload, in parts of
+    /// `part_size` bytes.
+    ///
+    /// Each part is retried on its own, so that a failure doesn't require uploading the whole
+    /// object again. Smaller objects are still uploaded in a single request.
+    pub const fn with_multipart(
+        mut self,
+        threshold: Option<NonZeroUsize>,
+        part_size: NonZeroUsize,
+    ) -> Self {
+        self.multipart = match threshold {
+            Some(threshold) => Some((threshold, part_size)),
+            None => None,
+        };
+        self
+    }
+}
+
+/// The size of the parts of an object of `len` bytes, if it's uploaded in parts.
+fn multipart_part_size(
+    multipart: Option<(NonZeroUsize, NonZeroUsize)>,
+    len: usize,
+) -> Option<NonZeroUsize> {
+    multipart
+        .filter(|(threshold, _)| len > threshold.get())
+        .map(|(_, part_size)| part_size)
+}
+
+/// The byte ranges of the parts an object of `len` bytes is uploaded in.
+///
+/// The parts are larger than `part_size` if the object would otherwise have more parts than allowed.
+fn part_ranges(len: usize, part_size: NonZeroUsize) -> Vec<Range<usize>> {
+    let part_size = part_size.get().max((len + MAX_PARTS - 1) / MAX_PARTS);
+    (0..len)
+        .step_by(part_size)
+        .map(|start| start..len.min(start + part_size))
+        .collect()
+}
+
+/// Converts the error of a request of a multipart upload into that of a single upload, keeping the
+/// raw response so that it's retried the same way.
+fn into_put_object_error<E>(error: SdkError<E>) -> SdkError<PutObjectError>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match error {
+        SdkError::ConstructionFailure(error) => SdkError::ConstructionFailure(error),
+        SdkError::TimeoutError(error) => SdkError::TimeoutError(error),
+        SdkError::DispatchFailure(error) => SdkError::DispatchFailure(error),
+        SdkError::ResponseError { err, raw } => SdkError::ResponseError { err, raw },
+        SdkError::ServiceError { err, raw } => SdkError::ResponseError {
+            err: Box::new(err),
+            raw,
+        },
+    }
+}
+
+/// Uploads the parts of `data`, and returns the parts to complete the upload with.
+async fn upload_parts(
+    client: &S3Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    data: &Bytes,
+    part_size: NonZeroUsize,
+) -> Result<Vec<CompletedPart>, SdkError<UploadPartError>> {
+    let mut parts = Vec::new();
+    for (index, range) in part_ranges(data.len(), part_size).into_iter().enumerate() {
+        let part = data.slice(range);
+        let part_number = index as i32 + 1;
+        let content_md5 = BASE64_STANDARD.encode(md5::Md5::digest(&part));
+        let mut attempt = 1;
+        let mut backoff = part_backoff();
+        let output = loop {
+            match client
+                .upload_part()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(bytes_to_bytestream(part.clone()))
+                .content_md5(content_md5.clone())
+                .send()
+                .await
+            {
+                Ok(output) => break output,
+                Err(error) if attempt < PART_ATTEMPTS => {
+                    let delay = backoff.next().expect("backoff never ends");
+                    warn!(
+                        message = "Failed to upload part, retrying.",
+                        part = part_number,
+                        attempt,
+                        delay_ms = %delay.as_millis(),
+                        %error,
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(output.e_tag().map(ToOwned::to_owned))
+                .build(),
+        );
+    }
+
+    Ok(parts)
 }
 
 impl Service<S3Request> for S3Service {
@@ -128,8 +276,82 @@ impl Service<S3Request> for S3Service {
         });
 
         let client = self.client.clone();
+        let part_size = multipart_part_size(self.multipart, request.body.len());
 
         Box::pin(async move {
+            if let Some(part_size) = part_size {
+                let upload = client
+                    .create_multipart_upload()
+                    .bucket(request.bucket.clone())
+                    .key(request.metadata.s3_key.clone())
+                    .set_content_encoding(content_encoding)
+                    .set_content_type(content_type)
+                    .set_acl(options.acl.map(Into::into))
+                    .set_grant_full_control(options.grant_full_control)
+                    .set_grant_read(options.grant_read)
+                    .set_grant_read_acp(options.grant_read_acp)
+                    .set_grant_write_acp(options.grant_write_acp)
+                    .set_server_side_encryption(options.server_side_encryption.map(Into::into))
+                    .set_ssekms_key_id(options.ssekms_key_id)
+                    .set_bucket_key_enabled(options.bucket_key_enabled)
+                    .set_storage_class(Some(options.storage_class.into()))
+                    .set_tagging(tagging)
+                    .set_metadata(request.user_metadata)
+                    .set_content_disposition(request.content_disposition)
+                    .set_content_language(request.content_language)
+                    .send()
+                    .in_current_span()
+                    .await
+                    .map_err(into_put_object_error)?;
+                let upload_id = upload.upload_id().ok_or_else(|| {
+                    SdkError::ConstructionFailure("Multipart upload has no upload ID.".into())
+                })?;
+
+                let bucket = request.bucket.as_str();
+                let key = request.metadata.s3_key.as_str();
+                let parts =
+                    match upload_parts(&client, bucket, key, upload_id, &request.body, part_size)
+                        .in_current_span()
+                        .await
+                    {
+                        Ok(parts) => parts,
+                        Err(error) => {
+                            // The parts uploaded so far are billed until the upload is aborted.
+                            if let Err(error) = client
+                                .abort_multipart_upload()
+                                .bucket(bucket)
+                                .key(key)
+                                .upload_id(upload_id)
+                                .send()
+                                .await
+                            {
+                                warn!(message = "Failed to abort multipart upload.", %error);
+                            }
+                            return Err(into_put_object_error(error));
+                        }
+                    };
+
+                client
+                    .complete_multipart_upload()
+                    .bucket(bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .multipart_upload(
+                        CompletedMultipartUpload::builder()
+                            .set_parts(Some(parts))
+                            .build(),
+                    )
+                    .send()
+                    .in_current_span()
+                    .await
+                    .map_err(into_put_object_error)?;
+
+                return Ok(S3Response {
+                    count,
+                    events_byte_size,
+                });
+            }
+
             let request = client
                 .put_object()
                 .body(bytes_to_bytestream(request.body))
@@ -144,8 +366,12 @@ impl Service<S3Request> for S3Service {
                 .set_grant_write_acp(options.grant_write_acp)
                 .set_server_side_encryption(options.server_side_encryption.map(Into::into))
                 .set_ssekms_key_id(options.ssekms_key_id)
+                .set_bucket_key_enabled(options.bucket_key_enabled)
                 .set_storage_class(Some(options.storage_class.into()))
                 .set_tagging(tagging)
+                .set_metadata(request.user_metadata)
+                .set_content_disposition(request.content_disposition)
+                .set_content_language(request.content_language)
                 .content_md5(content_md5);
 
             let result = request.send().in_current_span().await;
@@ -161,3 +387,55 @@ impl Service<S3Request> for S3Service {
 fn bytes_to_bytestream(buf: Bytes) -> ByteStream {
     ByteStream::from(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(size: usize) -> NonZeroUsize {
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    #[test]
+    fn backs_off_between_part_attempts() {
+        assert_eq!(
+            part_backoff().take(PART_ATTEMPTS - 1).collect::<Vec<_>>(),
+            [Duration::from_millis(500), Duration::from_secs(1)]
+        );
+        assert_eq!(part_backoff().nth(10), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn uploads_objects_over_the_threshold_in_parts() {
+        let multipart = Some((size(100), size(10)));
+
+        assert_eq!(multipart_part_size(multipart, 101), Some(size(10)));
+        assert_eq!(multipart_part_size(multipart, 100), None);
+        assert_eq!(multipart_part_size(None, usize::MAX), None);
+    }
+
+    #[test]
+    fn splits_objects_into_parts() {
+        assert_eq!(part_ranges(10, size(4)), vec![0..4, 4..8, 8..10]);
+        assert_eq!(part_ranges(8, size(4)), vec![0..4, 4..8]);
+        assert_eq!(part_ranges(3, size(4)), vec![0..3]);
+        assert!(part_ranges(0, size(4)).is_empty());
+    }
+
+    #[test]
+    fn counts_parts_of_a_full_batch() {
+        let ranges = part_ranges(100_000_000, size(8 * 1024 * 1024));
+
+        assert_eq!(ranges.len(), 12);
+        assert_eq!(ranges.last().unwrap().end, 100_000_000);
+    }
+
+    #[test]
+    fn enlarges_parts_beyond_the_maximum_count() {
+        let ranges = part_ranges(MAX_PARTS * 10 + 1, size(5));
+
+        assert!(ranges.len() <= MAX_PARTS);
+        assert_eq!(ranges[0], 0..11);
+        assert_eq!(ranges.last().unwrap().end, MAX_PARTS * 10 + 1);
+    }
+}
@@ -5,6 +5,9 @@ use flate2::write::{GzEncoder, ZlibEncoder};
 
 use super::{zstd::ZstdEncoder, Compression};
 
+/// The initial capacity of the buffer of a compressor, unless it's sized upfront.
+const DEFAULT_CAPACITY: usize = 1_024;
+
 enum Writer {
     Plain(bytes::buf::Writer<BytesMut>),
     Gzip(GzEncoder<bytes::buf::Writer<BytesMut>>),
@@ -21,11 +24,9 @@ impl Writer {
             Writer::Zstd(inner) => inner.get_ref().get_ref(),
         }
     }
-}
 
-impl From<Compression> for Writer {
-    fn from(compression: Compression) -> Self {
-        let writer = BytesMut::with_capacity(1_024).writer();
+    fn with_capacity(compression: Compression, capacity: usize) -> Self {
+        let writer = BytesMut::with_capacity(capacity).writer();
         match compression {
             Compression::None => Writer::Plain(writer),
             Compression::Gzip(level) => Writer::Gzip(GzEncoder::new(writer, level.as_flate2())),
@@ -39,6 +40,12 @@ impl From<Compression> for Writer {
     }
 }
 
+impl From<Compression> for Writer {
+    fn from(compression: Compression) -> Self {
+        Writer::with_capacity(compression, DEFAULT_CAPACITY)
+    }
+}
+
 impl io::Write for Writer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         #[allow(clippy::disallowed_methods)] // Caller handles the result of `write`.
@@ -69,6 +76,17 @@ pub struct Compressor {
 }
 
 impl Compressor {
+    /// Creates a compressor whose buffer is allocated with the given capacity upfront.
+    ///
+    /// Callers which can estimate the size of the payload ahead of time can avoid the buffer being
+    /// reallocated repeatedly as it grows.
+    pub fn with_capacity(compression: Compression, capacity: usize) -> Self {
+        Compressor {
+            compression,
+            inner: Writer::with_capacity(compression, capacity),
+        }
+    }
+
     /// Gets a mutable reference to the underlying buffer.
     pub fn get_ref(&self) -> &BytesMut {
         self.inner.get_ref()
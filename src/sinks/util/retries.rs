@@ -7,6 +7,7 @@ use std::{
     time::Duration,
 };
 
+use chrono::{DateTime, Utc};
 use futures::FutureExt;
 use tokio::time::{sleep, Sleep};
 use tower::{retry::Policy, timeout::error::Elapsed};
@@ -32,6 +33,21 @@ pub trait RetryLogic: Clone + Send + Sync + 'static {
         // Treat the default as the request is successful
         RetryAction::Successful
     }
+
+    /// The delay requested by the server before retrying the request, such as with `Retry-After`.
+    ///
+    /// This is only used for responses which are retried, in place of the regular backoff.
+    fn retry_after(&self, _response: &Self::Response) -> Option<Duration> {
+        None
+    }
+
+    /// The delay requested by the server before retrying the request after the error, such as
+    /// with a header of the failed response.
+    ///
+    /// This is only used for errors which are retried, in place of the regular backoff.
+    fn error_retry_after(&self, _error: &Self::Error) -> Option<Duration> {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -80,12 +96,24 @@ impl<L: RetryLogic> FixedRetryPolicy<L> {
         self.current_duration
     }
 
-    fn build_retry(&self) -> RetryPolicyFuture<L> {
+    /// The delay before the next retry, which is the delay requested by the server if any, capped
+    /// by the maximum backoff.
+    fn delay(&self, retry_after: Option<Duration>) -> Duration {
+        retry_after.map_or_else(
+            || self.backoff(),
+            |retry_after| cmp::min(retry_after, self.max_duration),
+        )
+    }
+
+    fn build_retry(&self, retry_after: Option<Duration>) -> RetryPolicyFuture<L> {
         let policy = self.advance();
-        let delay = Box::pin(sleep(self.backoff()));
+        let delay = self.delay(retry_after);
 
-        debug!(message = "Retrying request.", delay_ms = %self.backoff().as_millis());
-        RetryPolicyFuture { delay, policy }
+        debug!(message = "Retrying request.", delay_ms = %delay.as_millis());
+        RetryPolicyFuture {
+            delay: Box::pin(sleep(delay)),
+            policy,
+        }
     }
 }
 
@@ -112,7 +140,7 @@ where
                     }
 
                     warn!(message = "Retrying after response.", reason = %reason, internal_log_rate_limit = true);
-                    Some(self.build_retry())
+                    Some(self.build_retry(self.logic.retry_after(response)))
                 }
 
                 RetryAction::DontRetry(reason) => {
@@ -131,7 +159,7 @@ where
                 if let Some(expected) = error.downcast_ref::<L::Error>() {
                     if self.logic.is_retriable_error(expected) {
                         warn!(message = "Retrying after error.", error = %expected, internal_log_rate_limit = true);
-                        Some(self.build_retry())
+                        Some(self.build_retry(self.logic.error_retry_after(expected)))
                     } else {
                         error!(
                             message = "Non-retriable error; dropping the request.",
@@ -145,7 +173,7 @@ where
                         message = "Request timed out. If this happens often while the events are actually reaching their destination, try decreasing `batch.max_bytes` and/or using `compression` if applicable. Alternatively `request.timeout_secs` can be increased.",
                         internal_log_rate_limit = true
                     );
-                    Some(self.build_retry())
+                    Some(self.build_retry(None))
                 } else {
                     error!(
                         message = "Unexpected error type; dropping the request.",
@@ -176,6 +204,24 @@ impl<L: RetryLogic> Future for RetryPolicyFuture<L> {
     }
 }
 
+/// Parses the value of a `Retry-After` header, in either its delay in seconds or its HTTP-date form.
+///
+/// A date in the past results in no delay.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
 impl RetryAction {
     pub const fn is_retryable(&self) -> bool {
         matches!(self, RetryAction::Retry(_))
@@ -361,6 +407,40 @@ mod tests {
         assert_eq!(fut.await.unwrap(), "world");
     }
 
+    #[tokio::test]
+    async fn service_error_retry_after() {
+        trace_init();
+
+        time::pause();
+
+        let policy = FixedRetryPolicy::new(
+            5,
+            Duration::from_secs(1),
+            Duration::from_secs(10),
+            ThrottledRetryLogic,
+        );
+
+        let (mut svc, mut handle) = mock::spawn_layer(RetryLayer::new(policy));
+
+        assert_ready_ok!(svc.poll_ready());
+
+        let mut fut = task::spawn(svc.call("hello"));
+        assert_request_eq!(handle, "hello").send_error(Error(true));
+        assert_pending!(fut.poll());
+
+        // The delay requested by the server is waited for, rather than the backoff.
+        time::advance(Duration::from_secs(2)).await;
+        assert_pending!(fut.poll());
+        assert_pending!(task::spawn(handle.next_request()).poll());
+
+        // The delay is capped by the maximum backoff.
+        time::advance(Duration::from_secs(9)).await;
+        assert_pending!(fut.poll());
+
+        assert_request_eq!(handle, "hello").send_response("world");
+        assert_eq!(fut.await.unwrap(), "world");
+    }
+
     #[test]
     fn backoff_grows_to_max() {
         let mut policy = FixedRetryPolicy::new(
@@ -393,6 +473,48 @@ mod tests {
         assert_eq!(Duration::from_secs(10), policy.backoff());
     }
 
+    #[test]
+    fn retry_after_is_capped() {
+        let policy = FixedRetryPolicy::new(
+            10,
+            Duration::from_secs(1),
+            Duration::from_secs(10),
+            SvcRetryLogic,
+        );
+
+        assert_eq!(policy.delay(None), Duration::from_secs(1));
+        assert_eq!(
+            policy.delay(Some(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            policy.delay(Some(Duration::from_secs(120))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-1", now), None);
+    }
+
     #[derive(Debug, Clone)]
     struct SvcRetryLogic;
 
@@ -405,6 +527,23 @@ mod tests {
         }
     }
 
+    /// Requests a delay of two minutes before each retry.
+    #[derive(Debug, Clone)]
+    struct ThrottledRetryLogic;
+
+    impl RetryLogic for ThrottledRetryLogic {
+        type Error = Error;
+        type Response = &'static str;
+
+        fn is_retriable_error(&self, error: &Self::Error) -> bool {
+            error.0
+        }
+
+        fn error_retry_after(&self, _error: &Self::Error) -> Option<Duration> {
+            Some(Duration::from_secs(120))
+        }
+    }
+
     #[derive(Debug)]
     struct Error(bool);
 
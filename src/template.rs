@@ -221,6 +221,17 @@ impl Template {
     pub const fn is_dynamic(&self) -> bool {
         !self.is_static
     }
+
+    /// Returns the time format items of this template, in order.
+    pub fn strftime_items(&self) -> impl Iterator<Item = &Item<'static>> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Strftime(parsed) => Some(parsed),
+                Part::Literal(_) | Part::Reference(_) => None,
+            })
+            .flat_map(ParsedStrftime::as_items)
+    }
 }
 
 /// One part of the template string after parsing.
@@ -27,6 +27,46 @@ base: components: sinks: amqp: configuration: {
 			type: bool: {}
 		}
 	}
+	channel_pool_size: {
+		description: """
+			The number of channels to publish messages to.
+
+			Messages are published to the channels in turn, each of which has a connection of its own
+			and receives the publisher confirms of its messages. A single channel waits for the confirm
+			of each message in order, so more channels raise the throughput of brokers with slow
+			confirms. By default, a single channel is used.
+			"""
+		required: false
+		type: uint: {
+			examples: [4]
+			unit: "channels"
+		}
+	}
+	confirm_retries: {
+		description: """
+			The number of times a message is published again when its publisher confirm times out.
+
+			The broker may still have received the message, so retrying can deliver it more than once.
+			Only applies when `confirm_timeout_ms` is set.
+			"""
+		required: false
+		type: uint: unit: "retries"
+	}
+	confirm_timeout_ms: {
+		description: """
+			The time to wait for the publisher confirm of a message, in milliseconds.
+
+			A broker that never confirms a message would otherwise leave its events pending forever.
+			When no confirm is received in time, the message is published again up to
+			`confirm_retries` times, after which its events are rejected. By default, the sink waits
+			for confirms indefinitely.
+			"""
+		required: false
+		type: uint: {
+			examples: [30000]
+			unit: "milliseconds"
+		}
+	}
 	connection_string: {
 		description: """
 			URI for the AMQP server.
@@ -187,6 +227,20 @@ base: components: sinks: amqp: configuration: {
 		required:    true
 		type: string: syntax: "template"
 	}
+	max_message_bytes: {
+		description: """
+			The maximum size of the encoded body of a message, in bytes.
+
+			Brokers refuse messages over their maximum message size. Messages whose body exceeds this
+			size are dropped, and their events rejected, rather than being published. By default, the
+			size of the messages isn't checked.
+			"""
+		required: false
+		type: uint: {
+			examples: [134217728]
+			unit: "bytes"
+		}
+	}
 	properties: {
 		description: """
 			Configure the AMQP message properties.
@@ -195,6 +249,19 @@ base: components: sinks: amqp: configuration: {
 			"""
 		required: false
 		type: object: options: {
+			app_id: {
+				description: """
+					App-Id for the AMQP messages, identifying the application which published them.
+
+					Either a constant, or a template rendering the App-Id of each message. Messages whose App-Id
+					fails to render are published without one.
+					"""
+				required: false
+				type: string: {
+					examples: ["vector", "{{ service }}"]
+					syntax: "template"
+				}
+			}
 			content_encoding: {
 				description: "Content-Encoding for the AMQP messages."
 				required:    false
@@ -205,6 +272,64 @@ base: components: sinks: amqp: configuration: {
 				required:    false
 				type: string: {}
 			}
+			priority: {
+				description: "Priority for the AMQP messages."
+				required:    false
+				type: {
+					int: {}
+					string: syntax: "template"
+				}
+			}
+			type: {
+				description: """
+					Type for the AMQP messages, such as the name of the event they carry.
+
+					Either a constant, or a template rendering the Type of each message. Messages whose Type
+					fails to render are published without one.
+					"""
+				required: false
+				type: string: {
+					examples: ["log", "{{ event_type }}"]
+					syntax: "template"
+				}
+			}
+		}
+	}
+	reconnect: {
+		description: """
+			Reconnection configuration.
+
+			When the connection to the AMQP server is lost, the sink waits before each attempt to
+			reconnect. The wait doubles after each failed attempt, up to `max_backoff_ms`.
+			"""
+		required: false
+		type: object: options: {
+			initial_backoff_ms: {
+				description: "The time to wait before the first attempt to reconnect, in milliseconds."
+				required:    false
+				type: uint: {
+					default: 1000
+					unit:    "milliseconds"
+				}
+			}
+			max_attempts: {
+				description: """
+					The maximum number of attempts to reconnect before giving up.
+
+					When the sink gives up, the events being published are rejected, and the next events
+					to be published start a new series of attempts. By default, the sink never gives up.
+					"""
+				required: false
+				type: uint: unit: "attempts"
+			}
+			max_backoff_ms: {
+				description: "The maximum time to wait between two attempts to reconnect, in milliseconds."
+				required:    false
+				type: uint: {
+					default: 30000
+					unit:    "milliseconds"
+				}
+			}
 		}
 	}
 	routing_key: {
@@ -0,0 +1,23 @@
+package metadata
+
+releases: "0.31.0": {
+	date:     "2023-07-05"
+	codename: ""
+
+	known_issues: []
+
+	changelog: [
+		{
+			type: "fix"
+			scopes: ["azure_blob sink"]
+			description: """
+				The `azure_blob` sink now retries requests that fail with a server error (5xx), that
+				are throttled (429), or that Azure Storage rejects with the `ServerBusy` error code,
+				waiting for the delay requested in the `x-ms-retry-after-ms` header when there is
+				one. Previously the errors returned by the Azure SDK were never classified as
+				retriable, so these requests were dropped on the first failure. Requests rejected
+				for other reasons, such as failed authentication, are still not retried.
+				"""
+		},
+	]
+}